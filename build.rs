@@ -0,0 +1,15 @@
+// build.rs
+// Embed the git commit of the source tree (GIT_COMMIT, empty outside a git checkout), for the provenance file
+// written by each run.
+
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git").args(["rev-parse", "HEAD"]).output().ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
@@ -0,0 +1,47 @@
+/* kminmer2dhisto.h
+ * C API of the k-min-mer counting core, in libkminmer2dhisto (cargo build --release --features ffi).
+ * Indexes can be shared between threads, except for kmh_index_free.
+ */
+
+#ifndef KMINMER2DHISTO_H
+#define KMINMER2DHISTO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct KmhIndex KmhIndex;
+
+/* New empty index for k-min-mers of k minimizers of length l with the given density, or NULL if invalid. */
+KmhIndex *kmh_index_new(size_t k, size_t l, double density);
+void kmh_index_free(KmhIndex *idx);
+
+/* Count the records of a FASTA/FASTQ buffer, as reads or, if reference is nonzero, as reference sequences.
+ * Returns the number of records, or -1 if the buffer isn't FASTA/FASTQ. */
+int64_t kmh_index_add_fastx(const KmhIndex *idx, const char *buf, size_t len, int reference);
+
+/* Count a single sequence. Returns its number of k-min-mers, or -1 on invalid arguments. */
+int64_t kmh_index_add_sequence(const KmhIndex *idx, const char *seq, size_t len, int reference);
+
+/* Add n to the count of a k-min-mer hash. */
+void kmh_index_increment(const KmhIndex *idx, uint64_t hash, uint64_t n);
+
+/* Count of a k-min-mer hash, 0 if absent. */
+uint64_t kmh_index_get(const KmhIndex *idx, uint64_t hash);
+
+/* Number of distinct k-min-mers. */
+size_t kmh_index_len(const KmhIndex *idx);
+
+/* Fill out, a row-major rows x cols matrix, with the 2D histogram of a read and a reference index:
+ * out[x * cols + y] k-min-mers seen x times in the reads and y times in the reference, the last row and
+ * column holding higher abundances. Returns 0, or -1 on invalid arguments or indexes of different parameters. */
+int kmh_histogram(const KmhIndex *reads, const KmhIndex *reference, uint64_t *out, size_t rows, size_t cols);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,140 @@
+// aligned.rs
+// Reads from aligned read files: BAM and CRAM (files named .bam or .cram), decoded with htslib and streamed to
+// the FASTQ parser of the query path on their own thread, so that archives stored as BAM or CRAM can be counted
+// without converting them first. Decoding CRAM needs the reference it was written against: --cram-reference,
+// or the REF_PATH and REF_CACHE environment variables of htslib. Secondary and supplementary records are
+// skipped, so that each read is counted once; the others can be selected on their flags and MAPQ, as with
+// samtools view (--unmapped-only, --min-mapq, --require-flags, --exclude-flags), e.g. to count only the reads
+// that didn't map. Reads are streamed in the orientation they are stored in, which doesn't change their
+// canonical k-min-mers. Records without qualities get Phred 40.
+// Only available when built with the "htslib" feature.
+
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use crate::error::Error;
+
+// SAM flag of unmapped records.
+pub const UNMAPPED: u16 = 0x4;
+
+// SAM flags of the records always skipped: secondary and supplementary alignments.
+const SKIPPED: u16 = 0x900;
+
+// Records of aligned read files to count: those with all of require_flags, none of exclude_flags, and a MAPQ of
+// at least min_mapq.
+#[derive(Clone, Copy, Default)]
+pub struct RecordFilter {
+    pub require_flags: u16,
+    pub exclude_flags: u16,
+    pub min_mapq: u8,
+}
+impl RecordFilter {
+
+    pub fn keep(&self, flags: u16, mapq: u8) -> bool {
+        flags & SKIPPED == 0 && flags & self.require_flags == self.require_flags && flags & self.exclude_flags == 0 && mapq >= self.min_mapq
+    }
+}
+
+static CRAM_REFERENCE: OnceLock<PathBuf> = OnceLock::new();
+static RECORD_FILTER: OnceLock<RecordFilter> = OnceLock::new();
+
+// Whether a read file is an aligned read file, from its name.
+pub fn is_aligned(path: &PathBuf) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".bam") || name.ends_with(".cram")
+}
+
+// Set the reference to decode CRAM files with (--cram-reference), once, before reading them.
+pub fn set_cram_reference(path: PathBuf) {
+    let _ = CRAM_REFERENCE.set(path);
+}
+
+// Set the records of aligned read files to count, once, before reading them.
+pub fn set_record_filter(filter: RecordFilter) {
+    let _ = RECORD_FILTER.set(filter);
+}
+
+// Fail early if aligned read input was asked for but isn't compiled in.
+pub fn check_available() -> Result<(), Error> {
+    if cfg!(feature = "htslib") {Ok(())}
+    else {Err(Error::Usage("BAM and CRAM input need a build with the \"htslib\" feature (cargo build --release --features htslib).".to_string()))}
+}
+
+#[cfg(feature = "htslib")]
+mod imp {
+    use std::io::{self, BufRead, Read};
+    use std::path::PathBuf;
+    use rust_htslib::bam::{self, Read as _, Record};
+    use crate::error::{Context, Error};
+    use crate::pipe::PipeReader;
+    use super::RecordFilter;
+
+    fn other(e: rust_htslib::errors::Error) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+
+    // Records of an aligned read file, as FASTQ text.
+    struct FastqRecords {
+        reader: bam::Reader,
+        record: Record,
+        filter: RecordFilter,
+        buf: Vec<u8>, // FASTQ text of the current record
+        pos: usize,
+    }
+    impl FastqRecords {
+
+        // Put the FASTQ text of the next primary record passing the filter in buf. Returns false at the end of the file.
+        fn next_record(&mut self) -> io::Result<bool> {
+            loop {
+                match self.reader.read(&mut self.record) {
+                    None => return Ok(false),
+                    Some(Err(e)) => return Err(other(e)),
+                    Some(Ok(())) => {}
+                }
+                let record = &self.record;
+                if !self.filter.keep(record.flags(), record.mapq()) || record.seq_len() == 0 {continue;}
+                self.buf.clear();
+                self.buf.push(b'@');
+                self.buf.extend_from_slice(record.qname());
+                self.buf.push(b'\n');
+                self.buf.extend_from_slice(&record.seq().as_bytes());
+                self.buf.extend_from_slice(b"\n+\n");
+                let qual = record.qual();
+                if qual.first() == Some(&255) {self.buf.extend(std::iter::repeat(33 + 40).take(qual.len()));}
+                else {self.buf.extend(qual.iter().map(|q| 33 + q.min(&93)));}
+                self.buf.push(b'\n');
+                self.pos = 0;
+                return Ok(true);
+            }
+        }
+    }
+    impl Read for FastqRecords {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            if self.pos == self.buf.len() && !self.next_record()? {return Ok(0);}
+            let n = out.len().min(self.buf.len() - self.pos);
+            out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    pub fn open(path: &PathBuf, reference: Option<&PathBuf>, filter: RecordFilter) -> Result<Box<dyn BufRead + Send>, Error> {
+        let mut reader = bam::Reader::from_path(path).map_err(other).input(path)?;
+        if let Some(reference) = reference {
+            reader.set_reference(reference).map_err(other).input(reference)?;
+        }
+        Ok(Box::new(PipeReader::new(FastqRecords {reader, record: Record::new(), filter, buf: Vec::new(), pos: 0})))
+    }
+}
+
+// Open an aligned read file as a stream of FASTQ records.
+#[cfg(feature = "htslib")]
+pub fn open(path: &PathBuf) -> Result<Box<dyn BufRead + Send>, Error> {
+    imp::open(path, CRAM_REFERENCE.get(), RECORD_FILTER.get().copied().unwrap_or_default())
+}
+
+#[cfg(not(feature = "htslib"))]
+pub fn open(path: &PathBuf) -> Result<Box<dyn BufRead + Send>, Error> {
+    check_available()?;
+    unreachable!()
+}
@@ -0,0 +1,128 @@
+// bgzf.rs
+// Reader for BGZF files (as written by bgzip): a series of independent gzip members of at most 64 KB,
+// each recording its compressed size in a "BC" extra field. Blocks are read in batches and
+// decompressed on several threads.
+
+use std::io::{self, BufRead, Read};
+use std::thread;
+use flate2::read::GzDecoder;
+
+// Number of blocks decompressed by each thread per batch.
+const BLOCKS_PER_THREAD: usize = 16;
+
+// Check whether a gzip stream starts with a BGZF block header.
+pub fn is_bgzf(header: &[u8]) -> bool {
+    header.len() >= 16
+        && header[0..4] == [0x1f, 0x8b, 8, 4] // gzip magic, deflate, FEXTRA flag
+        && header[12] == b'B' && header[13] == b'C'
+}
+
+pub struct BgzfReader<R: Read> {
+    inner: R,
+    threads: usize,
+    buf: Vec<u8>, // decompressed data of the current batch
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> BgzfReader<R> {
+
+    pub fn new(inner: R, threads: usize) -> Self {
+        BgzfReader {inner, threads: threads.max(1), buf: Vec::new(), pos: 0, eof: false}
+    }
+
+    // Read the next compressed block, or None at the end of the stream.
+    fn next_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0u8; 12];
+        match self.inner.read_exact(&mut header[..1]) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            res => res?,
+        }
+        self.inner.read_exact(&mut header[1..])?;
+        if header[0..4] != [0x1f, 0x8b, 8, 4] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid BGZF block header"));
+        }
+        let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let mut extra = vec![0u8; xlen];
+        self.inner.read_exact(&mut extra)?;
+
+        // look for the BC subfield holding the total block size minus 1
+        let mut bsize = None;
+        let mut i = 0;
+        while i + 4 <= xlen {
+            let slen = u16::from_le_bytes([extra[i + 2], extra[i + 3]]) as usize;
+            if extra[i] == b'B' && extra[i + 1] == b'C' && slen == 2 && i + 6 <= xlen {
+                bsize = Some(u16::from_le_bytes([extra[i + 4], extra[i + 5]]) as usize + 1);
+            }
+            i += 4 + slen;
+        }
+        let bsize = match bsize {
+            Some(b) if b >= 12 + xlen => b,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "BGZF block without a valid BC field")),
+        };
+
+        let mut block = Vec::with_capacity(bsize);
+        block.extend_from_slice(&header);
+        block.extend_from_slice(&extra);
+        block.resize(bsize, 0);
+        self.inner.read_exact(&mut block[12 + xlen..])?;
+        Ok(Some(block))
+    }
+
+    // Decompress the next batch of blocks into buf.
+    fn fill(&mut self) -> io::Result<()> {
+        let mut blocks = Vec::new();
+        while blocks.len() < self.threads * BLOCKS_PER_THREAD {
+            match self.next_block()? {
+                Some(block) => blocks.push(block),
+                None => {self.eof = true; break;}
+            }
+        }
+        let chunk_size = (blocks.len() + self.threads - 1) / self.threads;
+        let decompressed: Vec<io::Result<Vec<u8>>> = if chunk_size == 0 {Vec::new()} else {
+            thread::scope(|s| {
+                let handles: Vec<_> = blocks.chunks(chunk_size).map(|chunk| s.spawn(move || {
+                    let mut out = Vec::new();
+                    for block in chunk {
+                        GzDecoder::new(&block[..]).read_to_end(&mut out)?;
+                    }
+                    Ok(out)
+                })).collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            })
+        };
+        self.buf.clear();
+        self.pos = 0;
+        for out in decompressed {
+            self.buf.extend_from_slice(&out?);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let data = self.fill_buf()?;
+            let n = data.len().min(out.len());
+            out[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // empty blocks (such as the EOF marker) decompress to nothing, so keep going until data or end of stream
+        while self.pos >= self.buf.len() && !self.eof {
+            self.fill()?;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
@@ -0,0 +1,109 @@
+// bloom.rs
+// Bloom filter pre-pass over the reads (--prefilter), to keep the k-min-mers seen only once, mostly sequencing
+// errors in long reads, out of the read Index. A first pass records every read k-min-mer in a Bloom filter of
+// those seen once and one of those seen twice. The second pass counts exactly the k-min-mers of the second
+// filter, along with those of the reference, and only tallies the others: a k-min-mer absent from the second
+// filter was seen exactly once (Bloom filters have no false negatives), so the tally is the number of distinct
+// singletons left out, and the histogram stays exact. False positives only let a few singletons in.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::histo::ReadCounts;
+use crate::index::Index;
+use crate::mers::mix;
+
+const NB_HASHES: u64 = 3;
+
+// Bloom filter of k-min-mer hashes.
+struct Bloom {
+    words: Vec<AtomicU64>,
+    nb_bits: u64,
+}
+impl Bloom {
+
+    fn new(nb_bits: usize) -> Self {
+        let nb_words = (nb_bits + 63) / 64;
+        Bloom {words: (0..nb_words).map(|_| AtomicU64::new(0)).collect(), nb_bits: nb_words as u64 * 64}
+    }
+
+    // Bits of the hash h, by double hashing.
+    fn bits(&self, h: u64) -> impl Iterator<Item = u64> + '_ {
+        let m = mix(h);
+        let step = m.rotate_left(32) | 1;
+        (0..NB_HASHES).map(move |i| m.wrapping_add(i.wrapping_mul(step)) % self.nb_bits)
+    }
+
+    // Add h, and return whether it was (maybe) already in the filter.
+    fn insert(&self, h: u64) -> bool {
+        let mut present = true;
+        for bit in self.bits(h) {
+            let mask = 1 << (bit % 64);
+            present &= self.words[(bit / 64) as usize].fetch_or(mask, Ordering::Relaxed) & mask != 0;
+        }
+        present
+    }
+
+    fn contains(&self, h: u64) -> bool {
+        self.bits(h).all(|bit| self.words[(bit / 64) as usize].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0)
+    }
+}
+
+pub struct Prefilter {
+    once: Bloom,
+    twice: Bloom,
+    counting: AtomicBool, // false during the first pass
+    nb_dropped: AtomicU64, // singletons left out of the Index in the second pass
+}
+impl Prefilter {
+
+    // Filters taking bytes of memory in total.
+    pub fn new(bytes: usize) -> Self {
+        Prefilter {once: Bloom::new(bytes * 4), twice: Bloom::new(bytes * 4), counting: AtomicBool::new(false), nb_dropped: AtomicU64::new(0)}
+    }
+
+    // Whether n occurrences of the hash h should be counted in the Index. During the first pass they are
+    // only recorded; during the second one, singletons are tallied instead.
+    pub fn admit(&self, h: u64, n: u64) -> bool {
+        if !self.counting.load(Ordering::Relaxed) {
+            if self.once.insert(h) || n > 1 {self.twice.insert(h);}
+            return false;
+        }
+        if self.twice.contains(h) {return true;}
+        self.nb_dropped.fetch_add(n, Ordering::Relaxed);
+        false
+    }
+
+    // End the first pass. Reference k-min-mers are always counted, so that the histogram of those seen once
+    // in the reads is exact.
+    pub fn start_counting(&self, ref_mers_index: &Index) {
+        for (h, _) in ref_mers_index.iter() {
+            self.twice.insert(h);
+        }
+        self.counting.store(true, Ordering::Relaxed);
+    }
+
+    pub fn nb_dropped(&self) -> u64 {
+        self.nb_dropped.load(Ordering::Relaxed)
+    }
+
+    // Read counts against the reference Index, from the k-min-mers counted in read_mers_index and the
+    // singletons left out, which are all absent from the reference.
+    pub fn read_counts(&self, read_mers_index: &Index, ref_mers_index: &Index, threads: usize) -> ReadCounts {
+        let read_in_ref = read_mers_index.empty_like();
+        let mut not_in_ref = BTreeMap::new();
+        let mut total = self.nb_dropped();
+        for (h, count) in read_mers_index.iter() {
+            total += count;
+            if ref_mers_index.get(&h).is_some() {
+                read_in_ref.increment_by(h, count);
+            }
+            else {
+                *not_in_ref.entry(count).or_insert(0) += 1;
+            }
+        }
+        if self.nb_dropped() > 0 {
+            *not_in_ref.entry(1).or_insert(0) += self.nb_dropped();
+        }
+        ReadCounts::new(read_in_ref, not_in_ref, total, ref_mers_index, threads)
+    }
+}
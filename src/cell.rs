@@ -0,0 +1,52 @@
+// cell.rs
+// Drill-down into a cell of the 2D histogram (--inspect-cell i,j): the k-min-mers counted in row i (read
+// abundance) and column j (reference abundance), binned as histo::compute does (the last row and column hold
+// all higher abundances), are written to prefix.cell<i>_<j> with their exact counts, so that an anomalous cell
+// can be traced back to sequences. With --cell-read-ids, the reads are read again to add the ID of a read
+// containing each k-min-mer; row 0 holds reference k-min-mers absent from the reads, which have none.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::histo::{NB_READ_BINS, NB_REF_BINS};
+use crate::index::Index;
+use log::info;
+
+// The k-min-mers of cell (i, j), as (hash, read count, reference count), sorted by hash.
+pub fn cell_kminmers(read_mers_index: &Index, ref_mers_index: &Index, (i, j): (usize, usize)) -> Vec<(u64, u64, u64)> {
+    let in_bin = |count: u64, bin: usize, nb_bins: usize| if bin == nb_bins - 1 {count >= bin as u64} else {count == bin as u64};
+    let mut kminmers: Vec<_> = if i == 0 {
+        ref_mers_index.iter()
+            .filter(|(h, ref_count)| in_bin(*ref_count, j, NB_REF_BINS) && read_mers_index.get(h).is_none())
+            .map(|(h, ref_count)| (h, 0, ref_count))
+            .collect()
+    }
+    else {
+        read_mers_index.iter()
+            .filter(|(_, count)| in_bin(*count, i, NB_READ_BINS))
+            .map(|(h, count)| (h, count, ref_mers_index.get(&h).unwrap_or(0)))
+            .filter(|(_, _, ref_count)| in_bin(*ref_count, j, NB_REF_BINS))
+            .collect()
+    };
+    kminmers.sort_unstable();
+    kminmers
+}
+
+// Write prefix.cell<i>_<j>: the k-min-mers of the cell, with the ID of a read containing each if examples are
+// given.
+pub fn write_cell(kminmers: &[(u64, u64, u64)], (i, j): (usize, usize), examples: Option<&HashMap<u64, String>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}.cell{}_{}", output_prefix.to_str().unwrap(), i, j);
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    writeln!(file, "# hash\tread_count\tref_count{}", if examples.is_some() {"\tread_id"} else {""}).output(&path)?;
+    for (h, count, ref_count) in kminmers {
+        match examples {
+            Some(examples) => writeln!(file, "{}\t{}\t{}\t{}", h, count, ref_count, examples.get(h).map_or("NA", |id| id.as_str())),
+            None => writeln!(file, "{}\t{}\t{}", h, count, ref_count),
+        }.output(&path)?;
+    }
+    file.flush().output(&path)?;
+    info!("{} k-min-mers of histogram cell ({}, {}) written to {}.", kminmers.len(), i, j, path);
+    Ok(())
+}
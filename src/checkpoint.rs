@@ -0,0 +1,86 @@
+// checkpoint.rs
+// Snapshots of the read Index, so that an interrupted run can be resumed instead of restarted.
+// A checkpoint stores the parameters, the number of reads already counted and all (hash, count) pairs.
+// Version 2 also stores the minimizer scheme; version 1 files have the density-based one.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::index::Index;
+use super::Params;
+use super::mers;
+
+const MAGIC_V1: &[u8; 8] = b"KM2DCKP1";
+const MAGIC: &[u8; 8] = b"KM2DCKP2";
+
+pub fn write_u64(w: &mut impl Write, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// Strings are stored as their length followed by their UTF-8 bytes.
+pub fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_u64(w, s.len() as u64)?;
+    w.write_all(s.as_bytes())
+}
+
+pub fn read_str(r: &mut impl Read) -> io::Result<String> {
+    let len = read_u64(r)?;
+    let mut buf = Vec::new();
+    r.by_ref().take(len).read_to_end(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_checkpoint(w: &mut impl Write, params: &Params, nb_reads: u64, read_mers_index: &Index) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_u64(w, params.k as u64)?;
+    write_u64(w, params.l as u64)?;
+    write_u64(w, params.density.to_bits())?;
+    write_str(w, &mers::scheme(params))?;
+    write_u64(w, nb_reads)?;
+    write_u64(w, read_mers_index.len() as u64)?;
+    for (h, count) in read_mers_index.iter() {
+        write_u64(w, h)?;
+        write_u64(w, count)?;
+    }
+    w.flush()
+}
+
+// Write a checkpoint of the read Index after nb_reads reads. The file is written under a temporary
+// name and then renamed, so that a crash during the snapshot leaves the previous checkpoint intact.
+pub fn save(path: &PathBuf, params: &Params, nb_reads: u64, read_mers_index: &Index) -> Result<(), Error> {
+    let tmp_path = PathBuf::from(format!("{}{}", path.to_str().unwrap(), ".tmp"));
+    let file = File::create(&tmp_path).output(&tmp_path)?;
+    write_checkpoint(&mut BufWriter::new(file), params, nb_reads, read_mers_index).output(&tmp_path)?;
+    fs::rename(&tmp_path, path).output(path)
+}
+
+// Load a checkpoint into the (empty) read Index, returning the number of reads it covers.
+pub fn load(path: &PathBuf, params: &Params, read_mers_index: &Index) -> Result<u64, Error> {
+    let file = File::open(path).input(path)?;
+    let mut r = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic).corrupt(path)?;
+    if &magic != MAGIC && &magic != MAGIC_V1 {return Err(Error::corrupt(path, "not a checkpoint file"));}
+    let k = read_u64(&mut r).corrupt(path)? as usize;
+    let l = read_u64(&mut r).corrupt(path)? as usize;
+    let density = f64::from_bits(read_u64(&mut r).corrupt(path)?);
+    let scheme = if &magic == MAGIC {read_str(&mut r).corrupt(path)?} else {String::new()};
+    if k != params.k || l != params.l || density != params.density || scheme != mers::scheme(params) {
+        return Err(Error::Usage(format!("checkpoint {} was made with k={} l={} density={} {}, which differs from the current parameters", path.to_str().unwrap(), k, l, density, scheme)));
+    }
+    let nb_reads = read_u64(&mut r).corrupt(path)?;
+    let nb_entries = read_u64(&mut r).corrupt(path)?;
+    for _ in 0..nb_entries {
+        let h = read_u64(&mut r).corrupt(path)?;
+        let count = read_u64(&mut r).corrupt(path)?;
+        read_mers_index.add(h, count);
+    }
+    Ok(nb_reads)
+}
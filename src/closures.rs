@@ -2,37 +2,331 @@
 // Functions for FASTA parsing and invoking all main functions 
 
 use std::io::{self};
-use std::error::Error;
+use crate::error::{Context, Error};
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use crate::BufReadDecompressor;
-use std::fs::{File};
+use std::fs::{self, File};
 use std::sync::{Arc};
 use seq_io::BaseRecord;
 use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
 use dashmap::DashMap;
+use super::aligned;
 use super::mers;
+use super::spectrum;
+use super::histo::{self, ReadCounts};
+use super::qv;
+use super::quality;
+use super::dump;
+use super::columnar;
+use super::h5;
+use super::gfa;
+use super::mmap;
+use super::sqlite;
+use super::html;
+use super::checkpoint;
+use super::cell;
+use super::contaminants;
+use super::missing;
+use super::nbfit;
+use super::ploidy;
+use super::top;
+use super::signals;
+use super::positional::{self, RefPositions};
+use super::gc::{self, GcMap};
+use super::readids::ReadIds;
+use super::dedup::Duplicates;
+use super::report::{self, ReadReport};
+use super::unmapped::UnmappedReads;
+use super::spill::{self, Spill};
+use super::snapshot::Snapshots;
+use super::summary::{self, Summary};
 use std::path::PathBuf;
 use super::Params;
 use crate::get_reader;
 use indicatif::ProgressBar;
 use std::time::Instant;
+use std::collections::{HashMap, HashSet};
 use dashmap::DashSet;
 use crate::index::{Entry, Index};
 use std::borrow::Cow;
 use std::io::Write;
+use log::{debug, info, warn};
 
 
-// Main function for all FASTA parsing + mapping / alignment functions.
-pub fn run_mers(filename: &PathBuf, ref_filename: &PathBuf, params: &Params, ref_threads: usize, threads: usize, ref_queue_len: usize, queue_len: usize, reads_are_fasta: bool, ref_is_fasta: bool, output_prefix: &PathBuf) {
+// Open a sequence file and tell whether it is FASTA (true) or FASTQ (false) from its first
+// non-whitespace byte, after decompression. Aligned read files are read as FASTQ.
+pub fn open_sequences(path: &PathBuf) -> Result<(Box<dyn BufRead + Send>, bool), Error> {
+    if aligned::is_aligned(path) {return Ok((aligned::open(path)?, false));}
+    let mut buf = get_reader(path)?;
+    loop {
+        let data = buf.fill_buf().corrupt(path)?;
+        if data.is_empty() {return Ok((buf, true));} // empty file: no records either way
+        let (skip, first) = match data.iter().position(|c| !c.is_ascii_whitespace()) {
+            Some(i) => (i, Some(data[i])),
+            None => (data.len(), None),
+        };
+        buf.consume(skip);
+        match first {
+            Some(b'>') => return Ok((buf, true)),
+            Some(b'@') => return Ok((buf, false)),
+            Some(c) => return Err(Error::corrupt(path, format!("not a FASTA or FASTQ file (starts with {:?})", c as char))),
+            None => continue,
+        }
+    }
+}
+
+// Stream a read file through process_read, counting its k-min-mers into read_mers_index.
+// If gc is given, the GC content of each k-min-mer is recorded there.
+// If report is given, a line per read is written to it.
+// If unmapped is given, the reads with few k-min-mers in the reference are written to it.
+// If dups is given, duplicate reads are skipped (and left out of the report).
+// If spill is given, read_mers_index is spilled to disk whenever it exceeds the memory budget.
+// If restrict is given (--restrict-to-reference), only the k-min-mers present in it are counted.
+// Returns the number of reads, and the signal number if processing was interrupted.
+pub fn index_reads(filename: &PathBuf, threads: usize, queue_len: usize, params: &Params, lens: &DashMap<String, usize>, read_mers_index: &Index, restrict: Option<&Index>, gc: Option<&GcMap>, read_ids: Option<&ReadIds>, mut report: Option<&mut ReadReport>, mut unmapped: Option<&mut UnmappedReads>, dups: Option<&Duplicates>, mut spill: Option<&mut Spill>, mut snapshots: Option<&mut Snapshots>) -> Result<(u64, Option<i32>), Error> {
+
+    let ref_mers_index = restrict.or(report.as_ref().map(|r| r.ref_mers_index)).or(unmapped.as_ref().map(|u| u.ref_mers_index));
+
+    // Closures for mapping queries to references
+    // Each record yields (length, number of k-min-mers, number of them in the reference), or None if it is a duplicate
+
+    let query_process_read_aux_mer = |seq_str: &[u8], qual: Option<&[u8]>, seq_id: &str| -> Option<(usize, usize, usize)> {
+        if dups.map_or(false, |dups| dups.is_duplicate(&[seq_str])) {return None;}
+        let (nb, nb_in_ref) = mers::process_read(&seq_id, seq_str.len(), &seq_str, qual, lens, read_mers_index, params, gc, read_ids, ref_mers_index);
+        Some((report::seq_len(seq_str), nb, nb_in_ref))
+    };
+    let query_process_read_fasta_mer = |record: seq_io::fasta::RefRecord, found: &mut Option<(usize, usize, usize)>| {
+        let seq_str = record.seq(); 
+        let seq_id = record.id().unwrap().to_string();
+        *found = query_process_read_aux_mer(&seq_str, None, &seq_id);
+    
+    };
+    let query_process_read_fastq_mer = |record: seq_io::fastq::RefRecord, found: &mut Option<(usize, usize, usize)>| {
+        let seq_str = record.seq(); 
+        let seq_id = record.id().unwrap().to_string();
+        *found = query_process_read_aux_mer(&seq_str, Some(record.qual()), &seq_id);
+    };
+    let mut nb_reads: u64 = 0;
+    let mut main_thread_mer = |seq_id: &str, record: (&[u8], &[u8], Option<&[u8]>), found: &mut Option<(usize, usize, usize)>| { // runs in main thread
+        nb_reads += 1;
+        if let (Some(report), Some(found)) = (report.as_mut(), &found) {
+            if let Err(e) = report.write(seq_id, found.0, found.1, found.2) {
+                return Some(Err(e));
+            }
+        }
+        if let (Some(unmapped), Some(found)) = (unmapped.as_mut(), &found) {
+            if let Err(e) = unmapped.write(record.0, record.1, record.2, found.1, found.2) {
+                return Some(Err(e));
+            }
+        }
+        if let Some(spill) = spill.as_mut().filter(|_| nb_reads % spill::CHECK_EVERY == 0) {
+            if let Err(e) = spill.check(read_mers_index) {
+                return Some(Err(e));
+            }
+        }
+        if let Some(snapshots) = snapshots.as_mut() {
+            if let Err(e) = snapshots.check(nb_reads, read_mers_index) {
+                return Some(Err(e));
+            }
+        }
+        signals::pending().map(Ok)
+    };
+
+    let query_start = Instant::now();
+    let (buf, reads_are_fasta) = open_sequences(&filename)?;
+    debug!("Input file: {} ({})", filename.to_str().unwrap(), if reads_are_fasta {"FASTA"} else {"FASTQ"});
+    let interrupted = if reads_are_fasta {
+        let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fasta_records(reader, threads as u32, queue_len, query_process_read_fasta_mer, |record, found| {main_thread_mer(record.id().unwrap_or(""), (record.head(), record.seq(), record.opt_qual()), found)})
+    }
+    else {
+        let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fastq_records(reader, threads as u32, queue_len, query_process_read_fastq_mer, |record, found| {main_thread_mer(record.id().unwrap_or(""), (record.head(), record.seq(), record.opt_qual()), found)})
+    }.map_err(|e| Error::corrupt(filename, e))?.transpose()?;
+    if params.quality_weights {
+        quality::rescale(read_mers_index);
+    }
+    let query_duration = query_start.elapsed();
+    info!("Counted {} reads of {} in {:?}.", nb_reads, filename.to_str().unwrap(), query_duration);
+    Ok((nb_reads, interrupted))
+}
+
+// Pairs of mates read per thread in each batch of index_pairs.
+const PAIRS_PER_THREAD: usize = 256;
+
+// Reader over a FASTA or FASTQ file, for reading records one at a time (e.g. two files in lockstep).
+pub enum SeqReader {
+    Fasta(seq_io::fasta::Reader<Box<dyn BufRead + Send>>),
+    Fastq(seq_io::fastq::Reader<Box<dyn BufRead + Send>>),
+}
+impl SeqReader {
+
+    pub fn open(path: &PathBuf) -> Result<Self, Error> {
+        let (buf, is_fasta) = open_sequences(path)?;
+        Ok(if is_fasta {SeqReader::Fasta(seq_io::fasta::Reader::new(buf))} else {SeqReader::Fastq(seq_io::fastq::Reader::new(buf))})
+    }
+
+    // Sequence of the next record, or None at the end of the file.
+    pub fn next_seq(&mut self, path: &PathBuf) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            SeqReader::Fasta(reader) => reader.next().transpose().map(|r| r.map(|record| record.seq().to_vec())),
+            SeqReader::Fastq(reader) => reader.next().transpose().map(|r| r.map(|record| record.seq().to_vec())),
+        }.map_err(|e| Error::corrupt(path, e))
+    }
+}
+
+// Count paired-end reads into read_mers_index: mates are the records of filename and mates_filename in the
+// same order, or consecutive records of filename if mates_filename isn't given (interleaved file).
+// If dups is given, duplicate pairs are skipped.
+// Returns the number of reads (twice the number of pairs), and the signal number if processing was interrupted.
+pub fn index_pairs(filename: &PathBuf, mates_filename: Option<&PathBuf>, threads: usize, params: &Params, read_mers_index: &Index, gc: Option<&GcMap>, dups: Option<&Duplicates>) -> Result<(u64, Option<i32>), Error> {
+    let query_start = Instant::now();
+    let mut reader1 = SeqReader::open(filename)?;
+    let mut reader2 = match mates_filename {
+        Some(path) => Some(SeqReader::open(path)?),
+        None => None,
+    };
+    let mates_path = mates_filename.unwrap_or(filename);
+    let threads = threads.max(1);
+    let mut nb_reads: u64 = 0;
+    let mut interrupted = None;
+    loop {
+        // read a batch of pairs on the main thread, then count them on all threads
+        let mut batch = Vec::with_capacity(threads * PAIRS_PER_THREAD);
+        while batch.len() < threads * PAIRS_PER_THREAD {
+            let mate1 = match reader1.next_seq(filename)? {
+                Some(seq) => seq,
+                None => break,
+            };
+            let mate2 = match &mut reader2 {
+                Some(reader) => reader.next_seq(mates_path)?,
+                None => reader1.next_seq(filename)?,
+            };
+            match mate2 {
+                Some(mate2) => batch.push((mate1, mate2)),
+                None => return Err(Error::corrupt(mates_path, format!("no mate for read {} of {}", nb_reads / 2 + batch.len() as u64 + 1, filename.to_str().unwrap()))),
+            }
+        }
+        let last = batch.len() < threads * PAIRS_PER_THREAD;
+        if !batch.is_empty() {
+            let chunk_size = (batch.len() + threads - 1) / threads;
+            std::thread::scope(|s| {
+                for chunk in batch.chunks(chunk_size) {
+                    s.spawn(move || {
+                        for (mate1, mate2) in chunk {
+                            if dups.map_or(false, |dups| dups.is_duplicate(&[mate1, mate2])) {continue;}
+                            mers::process_pair(mate1, mate2, read_mers_index, params, gc);
+                        }
+                    });
+                }
+            });
+        }
+        nb_reads += 2 * batch.len() as u64;
+        interrupted = signals::pending();
+        if last || interrupted.is_some() {break;}
+    }
+    if interrupted.is_none() {
+        if let Some(reader) = &mut reader2 {
+            if reader.next_seq(mates_path)?.is_some() {
+                return Err(Error::corrupt(mates_path, format!("more records than {}", filename.to_str().unwrap())));
+            }
+        }
+    }
+    info!("Counted {} read pairs of {} in {:?}.", nb_reads / 2, filename.to_str().unwrap(), query_start.elapsed());
+    Ok((nb_reads, interrupted))
+}
 
-    let ref_mers_index = Index::new(); // Index of reference k-min-mer entries
-    let read_mers_index = Index::new(); // Index of read k-min-mer entries
+// Same as index_reads, but periodically checkpoints the read Index to checkpoint_path, and resumes from it if asked to.
+// Workers only extract k-min-mer hashes; they are counted by the main thread, which sees reads in input order,
+// so that a checkpoint covers exactly the first nb_reads reads.
+pub fn index_reads_checkpointed(filename: &PathBuf, threads: usize, queue_len: usize, params: &Params, read_mers_index: &Index, gc: Option<&GcMap>, mut report: Option<&mut ReadReport>, checkpoint_path: &PathBuf) -> Result<(u64, Option<i32>), Error> {
+    let mut nb_reads: u64 = 0;
+    if params.resume && checkpoint_path.exists() {
+        nb_reads = checkpoint::load(checkpoint_path, params, read_mers_index)?;
+        info!("Resuming from checkpoint {}: {} reads already counted.", checkpoint_path.to_str().unwrap(), nb_reads);
+    }
+    let skip = nb_reads;
+    let mut last_checkpoint = nb_reads;
+
+    let query_extract_fasta_mer = |record: seq_io::fasta::RefRecord, hashes: &mut Vec<u64>| {
+        let seq_id = record.id().unwrap().to_string();
+        *hashes = mers::read_hashes(&seq_id, record.seq(), params, gc);
+    };
+    let query_extract_fastq_mer = |record: seq_io::fastq::RefRecord, hashes: &mut Vec<u64>| {
+        let seq_id = record.id().unwrap().to_string();
+        *hashes = mers::read_hashes(&seq_id, record.seq(), params, gc);
+    };
+    let mut main_thread_mer = |seq_id: &str, seq: &[u8], hashes: &mut Vec<u64>| { // runs in main thread
+        let mut seen = HashSet::new();
+        for h in hashes.iter() {
+            if !params.distinct_per_read || seen.insert(*h) {
+                read_mers_index.increment(*h);
+            }
+        }
+        nb_reads += 1;
+        if let Some(report) = report.as_mut() {
+            let nb_in_ref = hashes.iter().filter(|h| report.ref_mers_index.get(h).is_some()).count();
+            if let Err(e) = report.write(seq_id, report::seq_len(seq), hashes.len(), nb_in_ref) {
+                return Some(Err(e));
+            }
+        }
+        if let Some(every) = params.checkpoint_every {
+            if nb_reads - last_checkpoint >= every {
+                if let Err(e) = checkpoint::save(checkpoint_path, params, nb_reads, read_mers_index) {
+                    return Some(Err(e));
+                }
+                last_checkpoint = nb_reads;
+                debug!("Checkpoint: {} reads counted.", nb_reads);
+            }
+        }
+        signals::pending().map(Ok)
+    };
+
+    let query_start = Instant::now();
+    let (buf, reads_are_fasta) = open_sequences(&filename)?;
+    debug!("Input file: {} ({})", filename.to_str().unwrap(), if reads_are_fasta {"FASTA"} else {"FASTQ"});
+    let interrupted = if reads_are_fasta {
+        let mut reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+        for _ in 0..skip {
+            if reader.next().is_none() {break;}
+        }
+        read_process_fasta_records(reader, threads as u32, queue_len, query_extract_fasta_mer, |record, hashes| {main_thread_mer(record.id().unwrap_or(""), record.seq(), hashes)})
+    }
+    else {
+        let mut reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+        for _ in 0..skip {
+            if reader.next().is_none() {break;}
+        }
+        read_process_fastq_records(reader, threads as u32, queue_len, query_extract_fastq_mer, |record, hashes| {main_thread_mer(record.id().unwrap_or(""), record.seq(), hashes)})
+    }.map_err(|e| Error::corrupt(filename, e))?.transpose()?;
+    if params.quality_weights {
+        quality::rescale(read_mers_index);
+    }
+    let query_duration = query_start.elapsed();
+    info!("Counted {} reads of {} in {:?}.", nb_reads - skip, filename.to_str().unwrap(), query_duration);
+
+    if interrupted.is_some() {
+        // keep a checkpoint of everything counted so far, to resume from
+        checkpoint::save(checkpoint_path, params, nb_reads, read_mers_index)?;
+    }
+    else if checkpoint_path.exists() {
+        // the run went through, the checkpoint isn't needed anymore
+        fs::remove_file(checkpoint_path).output(checkpoint_path)?;
+    }
+    Ok((nb_reads, interrupted))
+}
+
+// Index the k-min-mers of all reference files into a single Index.
+// Also returns the signal number if indexing was interrupted.
+// If positions is given, the positions of reference k-min-mers are recorded there.
+pub fn index_reference(ref_filenames: &[PathBuf], params: &Params, ref_threads: usize, ref_queue_len: usize, positions: Option<&RefPositions>) -> Result<(Index, Option<i32>), Error> {
+
+    let ref_mers_index = Index::with_backend(params.counter_width, &params.index_backend).with_filter(params.hash_filter.clone()); // Index of reference k-min-mer entries
     let lens : DashMap<String, usize> = DashMap::new(); // Sequence lengths per reference
 
     // Closure for indexing reference k-min-mers
     let index_mers = |seq_id: &str, seq: &[u8], params: &Params| -> usize {
-        let nb_mers = mers::ref_extract(seq_id, seq, params, &ref_mers_index);
+        let nb_mers = mers::ref_extract_chunked(seq_id, seq, params, &ref_mers_index, positions, ref_threads);
         lens.insert(seq_id.to_string(), seq.len());
         nb_mers
     };
@@ -41,12 +335,12 @@ pub fn run_mers(filename: &PathBuf, ref_filename: &PathBuf, params: &Params, ref
 
     let ref_process_read_aux_mer = |ref_str: &[u8], ref_id: &str| -> Option<u64> {
         let nb_mers = index_mers(ref_id, ref_str, params);
-        println!("Indexed reference {}: {} k-min-mers.", ref_id, nb_mers);
+        debug!("Indexed reference {}: {} k-min-mers.", ref_id, nb_mers);
         return Some(1)
     };
 
     let ref_process_read_fasta_mer = |record: seq_io::fasta::RefRecord, found: &mut Option<u64>| {
-        let ref_str = record.seq().to_vec(); 
+        let ref_str = record.seq(); 
         let ref_id = record.id().unwrap().to_string();
         *found = ref_process_read_aux_mer(&ref_str, &ref_id);
 
@@ -57,108 +351,470 @@ pub fn run_mers(filename: &PathBuf, ref_filename: &PathBuf, params: &Params, ref
         *found = ref_process_read_aux_mer(&ref_str, &ref_id);
     };
     let ref_main_thread_mer = |found: &mut Option<u64>| { // runs in main thread
-        None::<()>
-    };
-
-    // Closures for mapping queries to references
-
-    let query_process_read_aux_mer = |seq_str: &[u8], seq_id: &str| -> bool {
-        mers::process_read(&seq_id, seq_str.len(), &seq_str, &lens, &read_mers_index, params);
-        return true;
-    };
-    let query_process_read_fasta_mer = |record: seq_io::fasta::RefRecord, found: &mut bool| {
-        let seq_str = record.seq(); 
-        let seq_id = record.id().unwrap().to_string();
-        *found = query_process_read_aux_mer(&seq_str, &seq_id);
-    
-    };
-    let query_process_read_fastq_mer = |record: seq_io::fastq::RefRecord, found: &mut bool| {
-        let seq_str = record.seq(); 
-        let seq_id = record.id().unwrap().to_string();
-        *found = query_process_read_aux_mer(&seq_str, &seq_id);
-    };
-    let mut main_thread_mer = |found: &mut bool| { // runs in main thread
-        None::<()>
+        signals::pending()
     };
 
     // Start processing references
 
+    // All reference files go into the same Index
     let start = Instant::now();
-    let buf = get_reader(&ref_filename);
-    if ref_is_fasta {
-        let reader = seq_io::fasta::Reader::new(buf);
-        read_process_fasta_records(reader, ref_threads as u32, ref_queue_len, ref_process_read_fasta_mer, |record, found| {ref_main_thread_mer(found)});
-    }
-    else {
-        let reader = seq_io::fastq::Reader::new(buf);
-        read_process_fastq_records(reader, ref_threads as u32, ref_queue_len, ref_process_read_fastq_mer, |record, found| {ref_main_thread_mer(found)});
+    let mut interrupted = None;
+    for ref_filename in ref_filenames {
+        if gfa::is_gfa(ref_filename) {
+            debug!("Reference file: {} (GFA)", ref_filename.to_str().unwrap());
+            interrupted = gfa::index_gfa(ref_filename, params, &ref_mers_index, positions, ref_threads)?;
+            if interrupted.is_some() {break;}
+            continue;
+        }
+        if let Some(mapping) = mmap::map_fasta(ref_filename)? {
+            debug!("Reference file: {} (FASTA, memory-mapped)", ref_filename.to_str().unwrap());
+            interrupted = mmap::index_fasta(&mapping, ref_threads, |ref_id, ref_str| {ref_process_read_aux_mer(ref_str, ref_id);});
+            if interrupted.is_some() {break;}
+            continue;
+        }
+        let (buf, ref_is_fasta) = open_sequences(ref_filename)?;
+        debug!("Reference file: {} ({})", ref_filename.to_str().unwrap(), if ref_is_fasta {"FASTA"} else {"FASTQ"});
+        interrupted = if ref_is_fasta {
+            let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+            read_process_fasta_records(reader, ref_threads as u32, ref_queue_len, ref_process_read_fasta_mer, |record, found| {ref_main_thread_mer(found)})
+        }
+        else {
+            let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+            read_process_fastq_records(reader, ref_threads as u32, ref_queue_len, ref_process_read_fastq_mer, |record, found| {ref_main_thread_mer(found)})
+        }.map_err(|e| Error::corrupt(ref_filename, e))?;
+        if interrupted.is_some() {break;}
     }
     let duration = start.elapsed();
-    println!("Indexed references in {:?}.", duration);
+    info!("Indexed {} reference file(s) in {:?}: {} k-min-mers.", ref_filenames.len(), duration, ref_mers_index.len());
+    Ok((ref_mers_index, interrupted))
+}
+
+// Index a second reference and write the copy-number matrix of both references (prefix.ref-vs-ref2.hist2D),
+// to spot repeats collapsed or expanded from one assembly to the other. The BED regions and softmasking of the
+// primary reference aren't applied to the second one.
+pub fn compare_references(ref2_filenames: &[PathBuf], ref_mers_index: &Index, params: &Params, ref_threads: usize, ref_queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let (ref2_mers_index, interrupted) = index_reference(ref2_filenames, &params.for_other_reference(), ref_threads, ref_queue_len, None)?;
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {} while indexing the second reference.", sig);
+        std::process::exit(128 + sig);
+    }
+    let hist = histo::compute_ref_pair(ref_mers_index, &ref2_mers_index);
+    let only_ref2: u64 = hist[0].iter().sum();
+    let only_ref: u64 = hist.iter().map(|row| row[0]).sum();
+    let different: u64 = (1..hist.len()).flat_map(|i| (1..hist.len()).filter(move |&j| j != i).map(move |j| (i, j))).map(|(i, j)| hist[i][j]).sum();
+    info!("{} k-min-mers only in the reference, {} only in the second one, {} in both with different copy numbers.", only_ref, only_ref2, different);
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".ref-vs-ref2.hist2D");
+    histo::write_matrix(&hist, &path, params.sparse, &[histo::metadata(params)])
+}
+
+// Main function for counting reads against the reference Index and writing the outputs.
+// ref_summary holds the stages that produced the reference Index.
+pub fn run_mers(filename: Option<&PathBuf>, filename2: Option<&PathBuf>, samples: &[(String, PathBuf)], ref_mers_index: &Index, ref_positions: Option<&RefPositions>, ref_summary: &Summary, params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+
+    let read_mers_index = new_read_index(params); // Index of read k-min-mer entries
+    let read2_mers_index = Index::with_backend(params.counter_width, &params.index_backend).with_filter(params.hash_filter.clone()); // Index of k-min-mer entries of the second read set, if any
+    let read_gc = if params.gc_histo {Some(GcMap::new())} else {None}; // GC content of read k-min-mers, if asked for
+    let read_ids = params.track_read_ids.map(ReadIds::new); // example reads of each read k-min-mer, if asked for
+    let read_dups = if params.dedup_reads {Some(Duplicates::new())} else {None}; // signatures of the reads seen, if duplicates are skipped
+    let mut read_report = if params.per_read_report {Some(ReadReport::create(ref_mers_index, output_prefix)?)} else {None};
+    let mut read_unmapped = match &params.unmapped_reads {Some(path) => Some(UnmappedReads::create(ref_mers_index, path, params.unmapped_max_fraction)?), None => None};
+    let lens : DashMap<String, usize> = DashMap::new(); // Sequence lengths per reference
+    let restrict = Some(ref_mers_index).filter(|_| params.restrict_to_reference); // only count the read k-min-mers of the reference
+    let panel = match &params.contaminants {Some(path) => Some(contaminants::load_panel(path, params, threads, queue_len)?), None => None}; // references to screen the reads against, if any
+    let excluded = match params.max_ref_count_filter {Some(max) => exclude_repeats(ref_mers_index, max), None => HashSet::new()}; // frequent reference k-min-mers, left out of both indexes
 
     // Done, start processing reads
 
-    let query_start = Instant::now();
-    let buf = get_reader(&filename);
-    if reads_are_fasta {
-        let reader = seq_io::fasta::Reader::new(buf);
-        read_process_fasta_records(reader, threads as u32, queue_len, query_process_read_fasta_mer, |record, found| {main_thread_mer(found)});
-        let query_duration = query_start.elapsed();
-        println!("Processed reads in {:?}.", query_duration);
+    if let Some(filename) = filename.filter(|_| params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some()) {
+        // counted under a memory budget, into a sketch or after a prefilter pass, without the outputs that need
+        // all read k-min-mers in memory
+        let mut summary = ref_summary.clone();
+        let mut spill = match params.max_memory {
+            Some(max_memory) => Some(Spill::new(max_memory, ref_mers_index, output_prefix)?),
+            None => None,
+        };
+        prefilter_reads(filename, threads, queue_len, params, &lens, &read_mers_index, ref_mers_index, &mut summary)?;
+        let start = Instant::now();
+        let (nb_reads, interrupted) = index_reads(filename, threads, queue_len, params, &lens, &read_mers_index, restrict, None, None, read_report.as_mut(), read_unmapped.as_mut(), read_dups.as_ref(), spill.as_mut(), None)?;
+        if let Some(report) = read_report.take() {
+            report.finish()?;
+        }
+        log_unmapped(read_unmapped.take(), params)?;
+        summary.nb_reads = nb_reads;
+        summary.nb_duplicates = log_duplicates(read_dups.as_ref(), filename);
+        summary.add_stage("count_reads", start.elapsed());
+        drop_excluded(&read_mers_index, &excluded);
+        write_budgeted_outputs(spill.as_mut(), &read_mers_index, interrupted, ref_mers_index, ref_positions, &mut summary, params, output_prefix)?;
     }
-    else {
-        let reader = seq_io::fastq::Reader::new(buf);
-        read_process_fastq_records(reader, threads as u32, queue_len, query_process_read_fastq_mer, |record, found| {main_thread_mer(found)});
-        let query_duration = query_start.elapsed();
-        println!("Processed reads in {:?}.", query_duration);
+    else if let Some(filename) = filename {
+        let mut summary = ref_summary.clone();
+        let start = Instant::now();
+        let (nb_reads, interrupted) = if params.mates.is_some() || params.interleaved {
+            index_pairs(filename, params.mates.as_ref(), threads, params, &read_mers_index, read_gc.as_ref(), read_dups.as_ref())?
+        }
+        else if params.checkpoint_every.is_some() || params.resume {
+            let checkpoint_path = PathBuf::from(format!("{}{}", output_prefix.to_str().unwrap(), ".ckpt"));
+            index_reads_checkpointed(filename, threads, queue_len, params, &read_mers_index, read_gc.as_ref(), read_report.as_mut(), &checkpoint_path)?
+        }
+        else {
+            let mut snapshots = params.snapshot_every.map(|every| Snapshots::new(every, ref_mers_index, params, output_prefix));
+            index_reads(filename, threads, queue_len, params, &lens, &read_mers_index, restrict, read_gc.as_ref(), read_ids.as_ref(), read_report.as_mut(), read_unmapped.as_mut(), read_dups.as_ref(), None, snapshots.as_mut())?
+        };
+        if let Some(report) = read_report.take() {
+            report.finish()?;
+        }
+        log_unmapped(read_unmapped.take(), params)?;
+        exit_if_interrupted(interrupted, &read_mers_index, &ref_mers_index, params, output_prefix)?;
+        summary.nb_reads = nb_reads;
+        summary.nb_duplicates = log_duplicates(read_dups.as_ref(), filename);
+        summary.add_stage("count_reads", start.elapsed());
+        drop_excluded(&read_mers_index, &excluded);
+        if let Some(filename2) = filename2 {
+            let start = Instant::now();
+            let read2_dups = if params.dedup_reads {Some(Duplicates::new())} else {None};
+            let (_, interrupted) = index_reads(filename2, threads, queue_len, params, &lens, &read2_mers_index, restrict, None, None, None, None, read2_dups.as_ref(), None, None)?;
+            log_duplicates(read2_dups.as_ref(), filename2);
+            exit_if_interrupted(interrupted, &read_mers_index, &ref_mers_index, params, output_prefix)?;
+            drop_excluded(&read2_mers_index, &excluded);
+            summary.add_stage("count_reads2", start.elapsed());
+        }
+
+        info!("{} distinct k-min-mers in the reads, {} in the reference.", read_mers_index.len(), ref_mers_index.len());
+
+        let start = Instant::now();
+        write_outputs(&read_mers_index, &ref_mers_index, ref_positions, read_gc.as_ref(), params, output_prefix)?;
+        summary.add_stage("write_outputs", start.elapsed());
+        if let Some(dump_path) = &params.dump_kminmers {
+            let start = Instant::now();
+            dump::dump_kminmers(&read_mers_index, &ref_mers_index, dump_path, params.deterministic)?;
+            summary.add_stage("dump_kminmers", start.elapsed());
+        }
+        if let Some(sqlite_path) = &params.sqlite {
+            let start = Instant::now();
+            sqlite::write_sqlite(&read_mers_index, &ref_mers_index, &histo::compute(&read_mers_index, &ref_mers_index, params.threads), params, sqlite_path)?;
+            info!("Wrote k-min-mer counts to {} in {:?}.", sqlite_path.to_str().unwrap(), start.elapsed());
+            summary.add_stage("write_sqlite", start.elapsed());
+        }
+        if let Some(min_abundance) = params.report_novel {
+            let start = Instant::now();
+            report_novel(filename, &read_mers_index, ref_mers_index, read_ids.as_ref(), min_abundance, params, threads, queue_len, output_prefix)?;
+            summary.add_stage("report_novel", start.elapsed());
+        }
+        if let Some(n) = params.top_kminmers {
+            let start = Instant::now();
+            report_top(filename, &read_mers_index, ref_mers_index, read_ids.as_ref(), n, params, threads, queue_len, output_prefix)?;
+            summary.add_stage("report_top", start.elapsed());
+        }
+        if let Some(cell) = params.inspect_cell {
+            let start = Instant::now();
+            inspect_cell(filename, &read_mers_index, ref_mers_index, read_ids.as_ref(), cell, params, threads, queue_len, output_prefix)?;
+            summary.add_stage("inspect_cell", start.elapsed());
+        }
+        if let Some(panel) = &panel {
+            let start = Instant::now();
+            contaminants::screen(filename, &read_mers_index, ref_mers_index, panel, params, threads, queue_len, output_prefix)?;
+            summary.add_stage("screen_contaminants", start.elapsed());
+        }
+
+        // Second read set: its own histogram against the reference, and reads1 vs reads2 histograms
+        if filename2.is_some() {
+            info!("{} distinct k-min-mers in the second read set.", read2_mers_index.len());
+            let prefix_str = output_prefix.to_str().unwrap();
+            let hist2 = histo::compute(&read2_mers_index, &ref_mers_index, params.threads);
+            histo::write_hist2d(&hist2, params, &PathBuf::from(format!("{}{}", prefix_str, ".reads2")))?;
+            let (pair_in_ref, pair_not_in_ref) = histo::compute_pair(&read_mers_index, &read2_mers_index, &ref_mers_index);
+            histo::write_matrix(&pair_in_ref, &format!("{}{}", prefix_str, ".reads1-vs-reads2.in-ref.hist2D"), params.sparse, &[histo::metadata(params)])?;
+            histo::write_matrix(&pair_not_in_ref, &format!("{}{}", prefix_str, ".reads1-vs-reads2.not-in-ref.hist2D"), params.sparse, &[histo::metadata(params)])?;
+        }
+        summary::write_summary(&summary, &read_mers_index, &ref_mers_index, params, output_prefix)?;
+    }
+
+    // Samples from the manifest are processed one at a time, so that a single read Index is in memory
+    for (sample, sample_filename) in samples {
+        info!("Processing sample {}.", sample);
+        let mut summary = ref_summary.clone();
+        let sample_mers_index = new_read_index(params);
+        let sample_gc = if params.gc_histo {Some(GcMap::new())} else {None};
+        let sample_read_ids = params.track_read_ids.map(ReadIds::new);
+        let sample_prefix = PathBuf::from(format!("{}.{}", output_prefix.to_str().unwrap(), sample));
+        let mut sample_report = if params.per_read_report {Some(ReadReport::create(ref_mers_index, &sample_prefix)?)} else {None};
+        let sample_dups = if params.dedup_reads {Some(Duplicates::new())} else {None};
+        let mut sample_spill = match params.max_memory {
+            Some(max_memory) => Some(Spill::new(max_memory, ref_mers_index, &sample_prefix)?),
+            None => None,
+        };
+        prefilter_reads(sample_filename, threads, queue_len, params, &lens, &sample_mers_index, ref_mers_index, &mut summary)?;
+        let start = Instant::now();
+        let mut sample_snapshots = params.snapshot_every.map(|every| Snapshots::new(every, ref_mers_index, params, &sample_prefix));
+        let (nb_reads, interrupted) = index_reads(sample_filename, threads, queue_len, params, &lens, &sample_mers_index, restrict, sample_gc.as_ref(), sample_read_ids.as_ref(), sample_report.as_mut(), None, sample_dups.as_ref(), sample_spill.as_mut(), sample_snapshots.as_mut())?;
+        if let Some(report) = sample_report {
+            report.finish()?;
+        }
+        drop_excluded(&sample_mers_index, &excluded);
+        if params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() {
+            summary.nb_reads = nb_reads;
+            summary.nb_duplicates = log_duplicates(sample_dups.as_ref(), sample_filename);
+            summary.add_stage("count_reads", start.elapsed());
+            write_budgeted_outputs(sample_spill.as_mut(), &sample_mers_index, interrupted, ref_mers_index, ref_positions, &mut summary, params, &sample_prefix)?;
+            continue;
+        }
+        info!("{} distinct k-min-mers in sample {}.", sample_mers_index.len(), sample);
+        exit_if_interrupted(interrupted, &sample_mers_index, &ref_mers_index, params, &sample_prefix)?;
+        summary.nb_reads = nb_reads;
+        summary.nb_duplicates = log_duplicates(sample_dups.as_ref(), sample_filename);
+        summary.add_stage("count_reads", start.elapsed());
+        let start = Instant::now();
+        write_outputs(&sample_mers_index, &ref_mers_index, ref_positions, sample_gc.as_ref(), params, &sample_prefix)?;
+        summary.add_stage("write_outputs", start.elapsed());
+        if let Some(min_abundance) = params.report_novel {
+            let start = Instant::now();
+            report_novel(sample_filename, &sample_mers_index, ref_mers_index, sample_read_ids.as_ref(), min_abundance, params, threads, queue_len, &sample_prefix)?;
+            summary.add_stage("report_novel", start.elapsed());
+        }
+        if let Some(n) = params.top_kminmers {
+            let start = Instant::now();
+            report_top(sample_filename, &sample_mers_index, ref_mers_index, sample_read_ids.as_ref(), n, params, threads, queue_len, &sample_prefix)?;
+            summary.add_stage("report_top", start.elapsed());
+        }
+        if let Some(cell) = params.inspect_cell {
+            let start = Instant::now();
+            inspect_cell(sample_filename, &sample_mers_index, ref_mers_index, sample_read_ids.as_ref(), cell, params, threads, queue_len, &sample_prefix)?;
+            summary.add_stage("inspect_cell", start.elapsed());
+        }
+        if let Some(panel) = &panel {
+            let start = Instant::now();
+            contaminants::screen(sample_filename, &sample_mers_index, ref_mers_index, panel, params, threads, queue_len, &sample_prefix)?;
+            summary.add_stage("screen_contaminants", start.elapsed());
+        }
+        summary::write_summary(&summary, &sample_mers_index, &ref_mers_index, params, &sample_prefix)?;
+    }
+    Ok(())
+}
+
+// Remove the k-min-mers seen more than max times from the reference Index (--max-ref-count-filter), e.g. of
+// rDNA or centromeric repeats, and return their hashes to remove them from the read Indexes too.
+fn exclude_repeats(ref_mers_index: &Index, max: u64) -> HashSet<u64> {
+    let mut excluded = HashSet::new();
+    ref_mers_index.retain(|h, count| count <= max || !excluded.insert(h));
+    info!("Excluded {} reference k-min-mers seen more than {} times.", excluded.len(), max);
+    excluded
+}
+
+// Remove the excluded reference k-min-mers from a read Index, once its reads are counted.
+fn drop_excluded(read_mers_index: &Index, excluded: &HashSet<u64>) {
+    if !excluded.is_empty() {
+        read_mers_index.retain(|h, _| !excluded.contains(&h));
+    }
+}
+
+// Example reads of the given k-min-mers of filename: those tracked while counting if read_ids is given, else
+// found by reading filename again if second_pass is set.
+fn example_reads(filename: &PathBuf, kminmers: &[(u64, u64)], read_ids: Option<&ReadIds>, second_pass: bool, params: &Params, threads: usize, queue_len: usize) -> Result<Option<HashMap<u64, String>>, Error> {
+    match read_ids {
+        Some(read_ids) => Ok(Some(read_ids.examples(kminmers))),
+        None if second_pass => missing::example_reads(filename, kminmers, params, threads, queue_len).map(Some),
+        None => Ok(None),
     }
+}
 
+// Write prefix.novel, the read k-min-mers of filename absent from the reference, with example reads of each if
+// tracked or asked for.
+fn report_novel(filename: &PathBuf, read_mers_index: &Index, ref_mers_index: &Index, read_ids: Option<&ReadIds>, min_abundance: u64, params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let novel = missing::novel_kminmers(read_mers_index, ref_mers_index, min_abundance);
+    let examples = example_reads(filename, &novel, read_ids, params.novel_read_ids, params, threads, queue_len)?;
+    missing::write_novel(&novel, examples.as_ref(), min_abundance, output_prefix)
+}
+
+// Write prefix.top, the n most abundant read k-min-mers of filename, with example reads of each if tracked or
+// asked for.
+fn report_top(filename: &PathBuf, read_mers_index: &Index, ref_mers_index: &Index, read_ids: Option<&ReadIds>, n: usize, params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let top = top::top_kminmers(read_mers_index, n);
+    let examples = example_reads(filename, &top, read_ids, params.top_read_ids, params, threads, queue_len)?;
+    top::write_top(&top, ref_mers_index, examples.as_ref(), output_prefix)
+}
+
+// Write prefix.cell<i>_<j>, the k-min-mers of a cell of the histogram of filename, with example reads of each
+// if tracked or asked for (row 0 k-min-mers are in no read).
+fn inspect_cell(filename: &PathBuf, read_mers_index: &Index, ref_mers_index: &Index, read_ids: Option<&ReadIds>, cell: (usize, usize), params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let kminmers = cell::cell_kminmers(read_mers_index, ref_mers_index, cell);
+    let examples = match cell.0 {
+        0 => Some(HashMap::new()).filter(|_| params.cell_read_ids || read_ids.is_some()),
+        _ => example_reads(filename, &kminmers.iter().map(|&(h, count, _)| (h, count)).collect::<Vec<_>>(), read_ids, params.cell_read_ids, params, threads, queue_len)?,
+    };
+    cell::write_cell(&kminmers, cell, examples.as_ref(), output_prefix)
+}
 
+// Close the file of reads with few k-min-mers in the reference, if any, and log how many were written.
+fn log_unmapped(unmapped: Option<UnmappedReads>, params: &Params) -> Result<(), Error> {
+    if let (Some(unmapped), Some(path)) = (unmapped, &params.unmapped_reads) {
+        let nb_reads = unmapped.finish()?;
+        info!("Extracted {} reads with less than {} of their k-min-mers in the reference to {}.", nb_reads, params.unmapped_max_fraction, path.to_str().unwrap());
+    }
+    Ok(())
+}
+
+// Log the number of duplicate reads skipped in a read file, and return it.
+fn log_duplicates(dups: Option<&Duplicates>, filename: &PathBuf) -> u64 {
+    let nb_duplicates = dups.map_or(0, |dups| dups.nb_duplicates());
+    if dups.is_some() {
+        info!("Skipped {} duplicate reads in {}.", nb_duplicates, filename.to_str().unwrap());
+    }
+    nb_duplicates
+}
+
+// Empty Index for the k-min-mers of a read set, sketched with --sketch or prefiltered with --prefilter.
+pub fn new_read_index(params: &Params) -> Index {
+    match (params.sketch, params.prefilter) {
+        (Some((width, depth)), _) => Index::with_sketch(width, depth),
+        (None, Some(bytes)) => Index::with_prefilter(params.counter_width, &params.index_backend, bytes),
+        (None, None) => Index::with_backend(params.counter_width, &params.index_backend),
+    }.with_filter(params.hash_filter.clone())
+}
+
+// First pass over a read file for a prefiltered read Index: its k-min-mers are only recorded in the Bloom
+// filters, after which the Index counts those seen twice or present in the reference.
+fn prefilter_reads(filename: &PathBuf, threads: usize, queue_len: usize, params: &Params, lens: &DashMap<String, usize>, read_mers_index: &Index, ref_mers_index: &Index, summary: &mut Summary) -> Result<(), Error> {
+    let prefilter = match read_mers_index.prefilter() {
+        Some(prefilter) => prefilter,
+        None => return Ok(()),
+    };
+    let start = Instant::now();
+    let dups = if params.dedup_reads {Some(Duplicates::new())} else {None}; // the second pass sees the reads again
+    let (nb_reads, interrupted) = index_reads(filename, threads, queue_len, params, lens, read_mers_index, None, None, None, None, None, dups.as_ref(), None, None)?;
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {} during the prefilter pass.", sig);
+        std::process::exit(128 + sig);
+    }
+    prefilter.start_counting(ref_mers_index);
+    info!("Prefiltered {} reads of {} in {:?}.", nb_reads, filename.to_str().unwrap(), start.elapsed());
+    summary.add_stage("prefilter_reads", start.elapsed());
+    Ok(())
+}
+
+// Write the outputs and summary of reads counted under a memory budget (--max-memory, with spill), into
+// a count-min sketch or after a prefilter pass. Spilled runs are merged with what remains in read_mers_index,
+// and the outputs are computed from the merged k-min-mers, from the sketch, or from the prefiltered counts
+// and the singletons left out.
+fn write_budgeted_outputs(spill: Option<&mut Spill>, read_mers_index: &Index, interrupted: Option<i32>, ref_mers_index: &Index, ref_positions: Option<&RefPositions>, summary: &mut Summary, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let start = Instant::now();
+    let counts = match (read_mers_index.sketch(), read_mers_index.prefilter(), spill) {
+        (Some(sketch), _, _) => sketch.read_counts(ref_mers_index, read_mers_index.width, params.threads),
+        (None, Some(prefilter), _) => {
+            info!("Left {} read k-min-mers seen once out of the index.", prefilter.nb_dropped());
+            prefilter.read_counts(read_mers_index, ref_mers_index, params.threads)
+        }
+        (None, None, Some(spill)) if spill.nb_runs() > 0 => spill.finish(read_mers_index, ref_mers_index, params.threads)?,
+        _ => {
+            info!("{} distinct k-min-mers in the reads, {} in the reference.", read_mers_index.len(), ref_mers_index.len());
+            exit_if_interrupted(interrupted, read_mers_index, ref_mers_index, params, output_prefix)?;
+            write_outputs(read_mers_index, ref_mers_index, ref_positions, None, params, output_prefix)?;
+            summary.add_stage("write_outputs", start.elapsed());
+            return summary::write_summary(summary, read_mers_index, ref_mers_index, params, output_prefix);
+        }
+    };
+    write_read_counts_outputs(&counts, interrupted, ref_mers_index, ref_positions, summary, params, output_prefix, start)
+}
+
+// Write the outputs and summary of read k-min-mers that aren't all held in an Index, like write_outputs and
+// summary::write_summary. start is when the outputs started being computed.
+fn write_read_counts_outputs(counts: &ReadCounts, interrupted: Option<i32>, ref_mers_index: &Index, ref_positions: Option<&RefPositions>, summary: &mut Summary, params: &Params, output_prefix: &PathBuf, start: Instant) -> Result<(), Error> {
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {}, writing partial histogram.", sig);
+        histo::write_partial_hist2d(&counts.hist, sig, params, output_prefix)?;
+        std::process::exit(128 + sig);
+    }
+    info!("{} distinct k-min-mers in the reads, {} in the reference.", counts.totals.1, ref_mers_index.len());
+    let solid_threshold = qv::solid_threshold(&counts.hist, params);
+    let (solid, solid_in_ref) = counts.solid(solid_threshold);
+    let qv_report = qv::evaluate_solid(solid, solid_in_ref, &counts.read_in_ref, ref_mers_index, solid_threshold, params);
+    write_hist_outputs(&counts.hist, &qv_report, &counts.read_in_ref, ref_mers_index, ref_positions, None, params, output_prefix)?;
+    summary.add_stage("write_outputs", start.elapsed());
+    summary::write_summary_counts(summary, counts.totals, counts.read_in_ref.len(), ref_mers_index, params, output_prefix)
+}
+
+// If a signal was received, flush a partial histogram of what was counted so far and exit.
+pub fn exit_if_interrupted(interrupted: Option<i32>, read_mers_index: &Index, ref_mers_index: &Index, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {}, writing partial histogram.", sig);
+        let hist = histo::compute(read_mers_index, ref_mers_index, params.threads);
+        histo::write_partial_hist2d(&hist, sig, params, output_prefix)?;
+        std::process::exit(128 + sig);
+    }
+    Ok(())
+}
+
+// Produce the 2D histogram of a read Index against the reference Index, and write it along with the derived outputs.
+pub fn write_outputs(read_mers_index: &Index, ref_mers_index: &Index, ref_positions: Option<&RefPositions>, gc: Option<&GcMap>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
     // Now produce the 2D histogram by iterating read kmers
-    let mut hist = vec![vec![0u64; 10]; 10000];
-
-    let hist_path = format!("{}{}", output_prefix.to_str().unwrap(), ".hist2D");
-    let mut hist_file = match File::create(&hist_path) {
-        Err(why) => panic!("Couldn't create {}: {}", hist_path, why.description()),
-        Ok(hist_file) => hist_file,
-    };
-
-    println!("nb read kminmers {}",read_mers_index.index.len());
-    println!("nb ref kminmers {}",ref_mers_index.index.len());
-
-    for item in read_mers_index.index.iter() {
-        let (node, entry) = item.pair();
-        let kminmer_abundance = entry.counter;
-        let ref_e = ref_mers_index.get(node);
-        let ref_abundance = if let Some(m) = ref_e {
-           m.counter
-        } else {0};
-        let i = if kminmer_abundance > 9999 { 9999 } else { kminmer_abundance } as usize;
-        let j = if ref_abundance > 9 { 9 } else { ref_abundance } as usize;
-        hist[i][j] += 1;
-    } 
-
-    // now do the edge case where reference kminmers aren't found in the reads
-    for item in ref_mers_index.index.iter() {
-        let (node, entry) = item.pair();
-        let ref_abundance = entry.counter;
-        let read_e = read_mers_index.get(node);
-        let read_abundance = if let Some(m) = read_e {
-           m.counter
-        } else {0};
-        if read_abundance == 0
-        {
-            let i = 0;
-            let j = if ref_abundance > 9 { 9 } else { ref_abundance } as usize;
-            hist[i][j] += 1;
-        }
-    } 
- 
-    for i in 0..10000 {
-        for j in 0..10 {
-            write!(hist_file, "{}\t", hist[i][j]).expect("Error writing hist file.");
-        }
-        write!(hist_file, "\n").expect("Error writing hist file.");
+    let start = Instant::now();
+    let hist = histo::compute(read_mers_index, ref_mers_index, params.threads);
+    debug!("Computed 2D histogram in {:?}.", start.elapsed());
+    let qv_report = qv::evaluate(read_mers_index, ref_mers_index, qv::solid_threshold(&hist, params), params);
+    write_hist_outputs(&hist, &qv_report, read_mers_index, ref_mers_index, ref_positions, gc, params, output_prefix)?;
+    info!("Wrote outputs to {}.* in {:?}.", output_prefix.to_str().unwrap(), start.elapsed());
+    Ok(())
+}
+
+// Write the 2D histogram and the outputs derived from it. read_mers_index is only looked up for reference
+// k-min-mers, so without gc it may hold just the read k-min-mers present in the reference.
+fn write_hist_outputs(hist: &Vec<Vec<u64>>, qv_report: &qv::QvReport, read_mers_index: &Index, ref_mers_index: &Index, ref_positions: Option<&RefPositions>, gc: Option<&GcMap>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    if params.reads_only {
+        // without a reference, only the read spectrum and what is derived from it are meaningful
+        histo::write_read_marginal(&hist, output_prefix)?;
+        spectrum::write_stats(&hist, params, output_prefix)?;
+        if params.estimate_ploidy {
+            ploidy::write_ploidy(&hist, output_prefix)?;
+        }
+        if let Some(gc) = gc {
+            gc::write_gc_hist2d(gc, read_mers_index, output_prefix)?;
+        }
+        return Ok(());
+    }
+    histo::write_hist2d(&hist, params, output_prefix)?;
+    if params.annotated {
+        histo::write_annotated_hist2d(&hist, params, output_prefix)?;
+    }
+    if params.cumulative {
+        histo::write_cumulative_hist2d(&hist, params, output_prefix)?;
+    }
+    if let Some(normalize) = params.normalize {
+        histo::write_normalized_hist2d(&hist, normalize, params, output_prefix)?;
+    }
+    if params.parquet {
+        columnar::write_hist2d_parquet(&hist, output_prefix)?;
+    }
+    if params.hdf5 {
+        h5::write_h5(&hist, read_mers_index, ref_positions, params, output_prefix)?;
+    }
+    histo::write_marginals(&hist, output_prefix)?;
+    let similarity = histo::similarity(&hist);
+    info!("Jaccard index: {:.4}, containment of reads in reference: {:.4}, of reference in reads: {:.4}.", similarity.jaccard, similarity.reads_in_ref, similarity.ref_in_reads);
+    if params.spectra_cn {
+        histo::write_spectra_cn(&hist, params.k, output_prefix)?;
+    }
+    if params.html_report {
+        html::write_html_report(&hist, params, output_prefix)?;
     }
 
+    spectrum::write_stats(&hist, params, output_prefix)?;
+    if let Some(nb_components) = params.nb_mixture {
+        nbfit::write_nbfit(&hist, nb_components, params, output_prefix)?;
+    }
+    if params.estimate_ploidy {
+        ploidy::write_ploidy(&hist, output_prefix)?;
+    }
+    qv::write_qv(qv_report, params, output_prefix)?;
+    if params.report_missing {
+        missing::write_missing_ref(read_mers_index, ref_mers_index, ref_positions.filter(|_| params.positional_bin.is_some()), qv_report.solid_threshold, output_prefix)?;
+    }
+    if let (Some(positions), Some(bin_size)) = (ref_positions, params.positional_bin) {
+        positional::write_positional_hist2d(positions, read_mers_index, bin_size, output_prefix)?;
+    }
+    if let Some(positions) = ref_positions.filter(|_| params.breadth_report) {
+        positional::write_breadth(positions, read_mers_index, output_prefix)?;
+    }
+    if let Some(gc) = gc {
+        gc::write_gc_hist2d(gc, read_mers_index, output_prefix)?;
+    }
+    Ok(())
 }
@@ -0,0 +1,72 @@
+// cnbin.rs
+// Binning of reads by reference copy number (--bin-by-copy-number): each read is classified by the median
+// reference count of its k-min-mers, 0 (absent from the reference, e.g. contaminants), 1, 2, or more (repeats),
+// and written as it was read to the file of its class, prefix.cn0, prefix.cn1, prefix.cn2 or prefix.repeat,
+// with a .fastq or .fasta extension like the input. Reads too short to have a k-min-mer aren't written.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use seq_io::BaseRecord;
+use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
+use crate::closures::open_sequences;
+use crate::error::{Context, Error};
+use crate::index::Index;
+use crate::mers;
+use crate::signals;
+use crate::unmapped::write_record;
+use super::Params;
+use log::{info, warn};
+
+const CLASSES: [&str; 4] = ["cn0", "cn1", "cn2", "repeat"]; // by median reference count, 3 and more being repeats
+
+// Class of a read (an index in CLASSES), or None if it has no k-min-mer.
+fn classify(seq_id: &str, seq: &[u8], ref_mers_index: &Index, params: &Params) -> Option<usize> {
+    let mut counts: Vec<u64> = match mers::extract(seq_id, seq, params) {
+        Some(kminmers) => kminmers.map(|kminmer| ref_mers_index.get(&kminmer.get_hash_u64()).unwrap_or(0)).collect(),
+        None => return None,
+    };
+    if counts.is_empty() {return None;}
+    let mid = (counts.len() - 1) / 2; // lower median
+    let (_, median, _) = counts.select_nth_unstable(mid);
+    Some((*median as usize).min(CLASSES.len() - 1))
+}
+
+// Stream the reads, writing each one to the file of its class.
+pub fn run_binning(reads: &PathBuf, ref_mers_index: &Index, params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let start = Instant::now();
+    let (buf, is_fasta) = open_sequences(reads)?;
+    let paths: Vec<String> = CLASSES.iter().map(|class| format!("{}.{}.{}", output_prefix.to_str().unwrap(), class, if is_fasta {"fasta"} else {"fastq"})).collect();
+    let mut files = Vec::new();
+    for path in &paths {
+        files.push(BufWriter::new(File::create(path).output(path)?));
+    }
+    let mut nb_reads = [0u64; CLASSES.len()];
+    let mut main_thread = |record: (&[u8], &[u8], Option<&[u8]>), found: &mut Option<usize>| { // runs in main thread
+        if let Some(class) = *found {
+            nb_reads[class] += 1;
+            if let Err(e) = write_record(&mut files[class], record.0, record.1, record.2).output(&paths[class]) {
+                return Some(Err(e));
+            }
+        }
+        signals::pending().map(Ok)
+    };
+    let interrupted = if is_fasta {
+        let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fasta_records(reader, threads as u32, queue_len, |record, found: &mut Option<usize>| {*found = classify(record.id().unwrap_or(""), record.seq(), ref_mers_index, params)}, |record, found| {main_thread((record.head(), record.seq(), record.opt_qual()), found)})
+    }
+    else {
+        let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fastq_records(reader, threads as u32, queue_len, |record, found: &mut Option<usize>| {*found = classify(record.id().unwrap_or(""), record.seq(), ref_mers_index, params)}, |record, found| {main_thread((record.head(), record.seq(), record.opt_qual()), found)})
+    }.map_err(|e| Error::corrupt(reads, e))?.transpose()?;
+    for (file, path) in files.iter_mut().zip(&paths) {
+        file.flush().output(path)?;
+    }
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {}, the read bins are partial.", sig);
+        std::process::exit(128 + sig);
+    }
+    info!("Binned the reads of {} by reference copy number in {:?}: {} absent (cn0), {} single-copy (cn1), {} two-copy (cn2) and {} repeat reads.", reads.to_str().unwrap(), start.elapsed(), nb_reads[0], nb_reads[1], nb_reads[2], nb_reads[3]);
+    Ok(())
+}
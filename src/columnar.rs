@@ -0,0 +1,122 @@
+// columnar.rs
+// Apache Parquet output of the 2D histogram and of the k-min-mer dump, so that they load directly into
+// pandas, polars, duckdb or any Arrow reader. Only available when built with the "parquet" feature.
+
+use std::path::PathBuf;
+use crate::error::Error;
+use crate::index::Index;
+
+// Rows per Arrow record batch, which bounds the memory used on top of the indexes.
+#[cfg(feature = "parquet")]
+const BATCH_SIZE: usize = 1 << 20;
+
+// Check whether a path asks for Parquet output.
+pub fn is_parquet(path: &PathBuf) -> bool {
+    path.to_str().unwrap().ends_with(".parquet")
+}
+
+// Fail early if Parquet output was asked for but isn't compiled in.
+pub fn check_available() -> Result<(), Error> {
+    if cfg!(feature = "parquet") {Ok(())}
+    else {Err(Error::Usage("Parquet output needs a build with the \"parquet\" feature (cargo build --release --features parquet).".to_string()))}
+}
+
+#[cfg(feature = "parquet")]
+mod imp {
+    use std::fs::File;
+    use std::io;
+    use std::sync::Arc;
+    use arrow_array::{ArrayRef, RecordBatch, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use crate::error::{Context, Error};
+    use super::BATCH_SIZE;
+
+    fn other<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+
+    // Parquet file of u64 columns, written one batch of rows at a time.
+    pub struct Writer {
+        path: String,
+        schema: Arc<Schema>,
+        writer: ArrowWriter<File>,
+        columns: Vec<Vec<u64>>,
+    }
+    impl Writer {
+
+        pub fn create(path: &str, names: &[&str]) -> Result<Self, Error> {
+            let schema = Arc::new(Schema::new(names.iter().map(|n| Field::new(*n, DataType::UInt64, false)).collect::<Vec<_>>()));
+            let file = File::create(path).output(path)?;
+            let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let writer = ArrowWriter::try_new(file, schema.clone(), Some(props)).map_err(other).output(path)?;
+            Ok(Writer {path: path.to_string(), schema, writer, columns: vec![Vec::new(); names.len()]})
+        }
+
+        pub fn push(&mut self, row: &[u64]) -> Result<(), Error> {
+            for (column, value) in self.columns.iter_mut().zip(row) {
+                column.push(*value);
+            }
+            if self.columns[0].len() >= BATCH_SIZE {self.flush_batch()?;}
+            Ok(())
+        }
+
+        fn flush_batch(&mut self) -> Result<(), Error> {
+            if self.columns[0].is_empty() {return Ok(());}
+            let arrays: Vec<ArrayRef> = self.columns.iter_mut().map(|c| Arc::new(UInt64Array::from(std::mem::take(c))) as ArrayRef).collect();
+            let batch = RecordBatch::try_new(self.schema.clone(), arrays).map_err(other).output(&self.path)?;
+            self.writer.write(&batch).map_err(other).output(&self.path)
+        }
+
+        pub fn finish(mut self) -> Result<(), Error> {
+            self.flush_batch()?;
+            self.writer.close().map_err(other).output(&self.path)?;
+            Ok(())
+        }
+    }
+}
+
+// Write the nonzero cells of the 2D histogram to prefix.hist2D.parquet, with columns
+// read_abundance, ref_abundance and count.
+#[cfg(feature = "parquet")]
+pub fn write_hist2d_parquet(hist: &Vec<Vec<u64>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".hist2D.parquet");
+    let mut writer = imp::Writer::create(&path, &["read_abundance", "ref_abundance", "count"])?;
+    for (i, row) in hist.iter().enumerate() {
+        for (j, cell) in row.iter().enumerate() {
+            if *cell > 0 {
+                writer.push(&[i as u64, j as u64, *cell])?;
+            }
+        }
+    }
+    writer.finish()
+}
+
+// Write the counts of every k-min-mer seen in the reads or the reference to a Parquet file,
+// with columns hash, read_count and ref_count, in hash order if ordered.
+#[cfg(feature = "parquet")]
+pub fn dump_kminmers_parquet(read_mers_index: &Index, ref_mers_index: &Index, dump_path: &PathBuf, ordered: bool) -> Result<(), Error> {
+    let mut writer = imp::Writer::create(dump_path.to_str().unwrap(), &["hash", "read_count", "ref_count"])?;
+    for (node, read_abundance) in read_mers_index.iter_ordered(ordered) {
+        writer.push(&[node, read_abundance, ref_mers_index.get(&node).unwrap_or(0)])?;
+    }
+    // reference k-min-mers absent from the reads
+    for (node, ref_abundance) in ref_mers_index.iter_ordered(ordered) {
+        if read_mers_index.get(&node).is_none() {
+            writer.push(&[node, 0, ref_abundance])?;
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn write_hist2d_parquet(hist: &Vec<Vec<u64>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    check_available()
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn dump_kminmers_parquet(read_mers_index: &Index, ref_mers_index: &Index, dump_path: &PathBuf, ordered: bool) -> Result<(), Error> {
+    check_available()
+}
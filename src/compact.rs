@@ -0,0 +1,197 @@
+// compact.rs
+// Compact hash table backend of the Index (--index-backend compact): open addressing with linear probing over
+// plain arrays, instead of DashMap's buckets of (key, entry) pairs with a control byte each. Keys are mixed
+// (invertibly) and split: their top SHARD_BITS bits select a shard, and only the remaining 52 bits are stored,
+// in 7 bytes. Slots hold a counter of the Index width and an occupancy bit, and shards grow by half when 9/10
+// full instead of doubling, so that the memory per entry is well below DashMap's on large tables.
+
+use std::sync::RwLock;
+use crate::index::Counter;
+use crate::mers::{mix, unmix};
+
+const SHARD_BITS: u32 = 12;
+const NB_SHARDS: usize = 1 << SHARD_BITS;
+const KEY_MASK: u64 = (1 << (64 - SHARD_BITS)) - 1; // bits of a mixed key stored in its shard
+
+// A shard of a CompactTable: slots i hold keys[i] and counters[i] if bit i of occupied is set.
+struct Shard<C> {
+    keys: Vec<[u8; 7]>,
+    counters: Vec<C>,
+    occupied: Vec<u64>,
+    len: usize,
+}
+impl<C: Counter> Shard<C> {
+
+    fn new() -> Self {
+        Shard {keys: Vec::new(), counters: Vec::new(), occupied: Vec::new(), len: 0}
+    }
+
+    fn is_occupied(&self, i: usize) -> bool {
+        self.occupied[i / 64] >> (i % 64) & 1 != 0
+    }
+
+    fn key(&self, i: usize) -> u64 {
+        let k = self.keys[i];
+        u64::from_le_bytes([k[0], k[1], k[2], k[3], k[4], k[5], k[6], 0])
+    }
+
+    // Slot holding key, and whether it does; otherwise the empty slot where key would be inserted.
+    // None if the shard has no slots.
+    fn find(&self, key: u64) -> Option<(usize, bool)> {
+        let capacity = self.counters.len();
+        if capacity == 0 {return None;}
+        let mut i = (key % capacity as u64) as usize;
+        loop {
+            if !self.is_occupied(i) {return Some((i, false));}
+            if self.key(i) == key {return Some((i, true));}
+            i = if i + 1 == capacity {0} else {i + 1};
+        }
+    }
+
+    // Insert key, which isn't in the shard, with a counter holding v. Returns its slot.
+    fn insert(&mut self, key: u64, v: u64) -> usize {
+        if (self.len + 1) * 10 > self.counters.len() * 9 {
+            self.grow();
+        }
+        let (i, _) = self.find(key).unwrap();
+        let bytes = key.to_le_bytes();
+        self.keys[i].copy_from_slice(&bytes[..7]);
+        self.counters[i] = C::new(v);
+        self.occupied[i / 64] |= 1 << (i % 64);
+        self.len += 1;
+        i
+    }
+
+    fn grow(&mut self) {
+        let capacity = (self.counters.len() * 3 / 2).max(16);
+        let old = std::mem::replace(self, Shard {
+            keys: vec![[0; 7]; capacity],
+            counters: (0..capacity).map(|_| C::new(0)).collect(),
+            occupied: vec![0; (capacity + 63) / 64],
+            len: 0,
+        });
+        for (key, v) in old.entries() {
+            self.insert(key, v);
+        }
+    }
+
+    // (stored key, count) pairs of the occupied slots.
+    fn entries(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        (0..self.counters.len()).filter(move |&i| self.is_occupied(i)).map(move |i| (self.key(i), self.counters[i].get()))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.counters.len() * (7 + std::mem::size_of::<C>()) + self.occupied.len() * 8
+    }
+}
+
+pub struct CompactTable<C> {
+    shards: Box<[RwLock<Shard<C>>]>,
+}
+impl<C: Counter> CompactTable<C> {
+
+    pub fn new() -> Self {
+        CompactTable {shards: (0..NB_SHARDS).map(|_| RwLock::new(Shard::new())).collect()}
+    }
+
+    // Shard and stored key of the hash h.
+    fn locate(h: u64) -> (usize, u64) {
+        let m = mix(h);
+        ((m >> (64 - SHARD_BITS)) as usize, m & KEY_MASK)
+    }
+
+    // Hash of a stored key of a shard.
+    fn hash(shard: usize, key: u64) -> u64 {
+        unmix((shard as u64) << (64 - SHARD_BITS) | key)
+    }
+
+    // Value of the counter of h, or None if h isn't in the table.
+    pub fn get(&self, h: u64) -> Option<u64> {
+        let (shard, key) = Self::locate(h);
+        let guard = self.shards[shard].read().unwrap();
+        match guard.find(key) {
+            Some((i, true)) => Some(guard.counters[i].get()),
+            _ => None,
+        }
+    }
+
+    // Apply f to the counter of h, inserting it if needed. Existing keys only take a read lock on their shard,
+    // like with DashMap.
+    fn update<R>(&self, h: u64, f: impl Fn(&C) -> R) -> R {
+        let (shard, key) = Self::locate(h);
+        {
+            let guard = self.shards[shard].read().unwrap();
+            if let Some((i, true)) = guard.find(key) {
+                return f(&guard.counters[i]);
+            }
+        }
+        let mut guard = self.shards[shard].write().unwrap();
+        let i = match guard.find(key) {
+            Some((i, true)) => i,
+            _ => guard.insert(key, 0),
+        };
+        f(&guard.counters[i])
+    }
+
+    // Add n to the counter of h, inserting it if needed. Returns the part of n beyond the counter's capacity.
+    pub fn increment_by(&self, h: u64, n: u64) -> u64 {
+        self.update(h, |c| c.saturating_add(n))
+    }
+
+    // Increment the counter of h unless it already holds cap, inserting it if needed.
+    pub fn increment_capped(&self, h: u64, cap: u64) -> bool {
+        self.update(h, |c| c.increment_below(cap))
+    }
+
+    // Set the counter of h to v. Returns whether h was already in the table.
+    pub fn set(&self, h: u64, v: u64) -> bool {
+        let (shard, key) = Self::locate(h);
+        let mut guard = self.shards[shard].write().unwrap();
+        match guard.find(key) {
+            Some((i, true)) => {
+                guard.counters[i] = C::new(v);
+                true
+            }
+            _ => {
+                guard.insert(key, v);
+                false
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().unwrap().len).sum()
+    }
+
+    pub fn nb_shards(&self) -> usize {
+        NB_SHARDS
+    }
+
+    // Call f on the (hash, count) pairs of one shard, under its read lock.
+    pub fn for_each_in_shard(&self, shard: usize, mut f: impl FnMut(u64, u64)) {
+        let guard = self.shards[shard].read().unwrap();
+        for (key, count) in guard.entries() {
+            f(Self::hash(shard, key), count);
+        }
+    }
+
+    // Remove the entries of one shard and return their (hash, count) pairs.
+    pub fn drain_shard(&self, shard: usize) -> Vec<(u64, u64)> {
+        let old = std::mem::replace(&mut *self.shards[shard].write().unwrap(), Shard::new());
+        old.entries().map(|(key, count)| (Self::hash(shard, key), count)).collect()
+    }
+
+    // Iterate over (hash, count) pairs, copying one shard at a time.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        (0..NB_SHARDS).flat_map(move |shard| {
+            let mut pairs = Vec::new();
+            self.for_each_in_shard(shard, |h, count| pairs.push((h, count)));
+            pairs
+        })
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let slots: usize = self.shards.iter().map(|shard| shard.read().unwrap().memory_usage()).sum();
+        slots + NB_SHARDS * std::mem::size_of::<RwLock<Shard<C>>>()
+    }
+}
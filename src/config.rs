@@ -0,0 +1,46 @@
+// config.rs
+// Run configuration files (--config run.toml), so that a set of parameters can be versioned and rerun. Each key
+// is the long name of an option of the count, index or histo command line, with dashes or underscores (e.g.
+// positional_histo = 1000), or "reads" for the input file. Values are strings, numbers, booleans for flags, or
+// arrays for repeated options such as reference. Settings become command-line arguments, added only for the
+// options the command line doesn't give, so that the command line overrides the file.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use clap::{ArgMatches, Command};
+use clap::parser::ValueSource;
+use crate::error::{Context, Error};
+
+// Arguments for the settings of the config file at path that aren't already on the command line of cmd.
+pub fn config_args(path: &PathBuf, cmd: &Command, matches: &ArgMatches) -> Result<Vec<OsString>, Error> {
+    let table: toml::Table = fs::read_to_string(path).input(path)?.parse().map_err(|e| Error::corrupt(path, e))?;
+    let mut args = Vec::new();
+    for (key, value) in &table {
+        let name = key.replace('_', "-");
+        let arg = cmd.get_arguments()
+            .find(|arg| arg.get_long() == Some(name.as_str()) || (arg.is_positional() && arg.get_id() == key.as_str()))
+            .filter(|arg| arg.get_id() != "config")
+            .ok_or_else(|| Error::corrupt(path, format!("unknown setting {}", key)))?;
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {continue;}
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) if !arg.get_action().takes_values() => {
+                    if *b {args.push(format!("--{}", name).into());}
+                    continue;
+                }
+                toml::Value::Boolean(b) => b.to_string(),
+                _ => return Err(Error::corrupt(path, format!("setting {} should be a string, number, boolean or array", key))),
+            };
+            args.push(if arg.is_positional() {value.into()} else {format!("--{}={}", name, value).into()});
+        }
+    }
+    Ok(args)
+}
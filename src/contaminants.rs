@@ -0,0 +1,160 @@
+// contaminants.rs
+// Contamination screening against a panel of references (--contaminants), e.g. human, E. coli, phiX and cloning
+// vectors: each contaminant is indexed into its own Index, and once the reads are counted, the read k-min-mers
+// found in each are reported in prefix.contaminants.tsv: the containment of the read k-min-mers in the contaminant
+// (distinct ones and occurrences), how many of them aren't in the reference, and the fraction of the contaminant's
+// k-min-mers seen in the reads. The panel is a directory with one sequence file per contaminant, named after the
+// file, or a manifest of "name<TAB>file" lines. With --classify-contaminant-reads, the reads are read again and
+// each is assigned to the contaminant (or the reference) sharing the most k-min-mers with it, in
+// prefix.contaminants.reads.tsv.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use seq_io::BaseRecord;
+use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
+use crate::closures::{index_reference, open_sequences};
+use crate::error::{Context, Error};
+use crate::index::Index;
+use crate::mers;
+use crate::report;
+use crate::signals;
+use super::{expand_references, read_manifest, Params};
+use log::{info, warn};
+
+// A reference of the panel.
+pub struct Contaminant {
+    pub name: String,
+    pub index: Index,
+}
+
+// Name of a contaminant from its file name, without compression and sequence extensions.
+fn name_of(path: &PathBuf) -> String {
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    let name = name.trim_end_matches(".gz").trim_end_matches(".bgz").trim_end_matches(".lz4");
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem).to_string()
+}
+
+// Index each contaminant of a panel: a directory of sequence files, or a manifest of names and files (each of
+// which can be a directory too). The BED regions, softmasking and count cap of the primary reference don't apply.
+pub fn load_panel(path: &PathBuf, params: &Params, threads: usize, queue_len: usize) -> Result<Vec<Contaminant>, Error> {
+    let start = Instant::now();
+    let entries: Vec<(String, Vec<PathBuf>)> = if path.is_dir() {
+        expand_references(vec![path.clone()])?.into_iter().map(|file| (name_of(&file), vec![file])).collect()
+    }
+    else {
+        read_manifest(path)?.into_iter().map(|(name, file)| Ok((name, expand_references(vec![file])?))).collect::<Result<_, Error>>()?
+    };
+    if entries.is_empty() {return Err(Error::Usage(format!("No contaminants in {}.", path.to_str().unwrap())));}
+    let panel_params = params.for_other_reference();
+    let mut panel = Vec::new();
+    for (name, filenames) in entries {
+        let (index, interrupted) = index_reference(&filenames, &panel_params, threads, queue_len, None)?;
+        if let Some(sig) = interrupted {
+            warn!("Interrupted by signal {} while indexing the contaminants.", sig);
+            std::process::exit(128 + sig);
+        }
+        info!("Contaminant {}: {} k-min-mers.", name, index.len());
+        panel.push(Contaminant {name, index});
+    }
+    info!("Indexed {} contaminants in {:?}.", panel.len(), start.elapsed());
+    Ok(panel)
+}
+
+// Classify each read of filename by the candidate (contaminants, then the reference if any) sharing the most
+// k-min-mers with it, and write them to prefix.contaminants.reads.tsv. Returns the number of reads assigned to each
+// candidate, then of ambiguous reads (tied candidates) and of reads matching none.
+fn classify_reads(filename: &PathBuf, candidates: &[(&str, &Index)], params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<Vec<u64>, Error> {
+    // Each record yields (length, number of k-min-mers, k-min-mers found in each candidate)
+    let count_read = |seq: &[u8], seq_id: &str| -> (usize, usize, Vec<usize>) {
+        let (mut nb, mut hits) = (0, vec![0; candidates.len()]);
+        if let Some(kminmers) = mers::extract(seq_id, seq, params) {
+            for kminmer in kminmers {
+                let h = kminmer.get_hash_u64();
+                for (c, (_, index)) in candidates.iter().enumerate() {
+                    if index.get(&h).is_some() {hits[c] += 1;}
+                }
+                nb += 1;
+            }
+        }
+        (report::seq_len(seq), nb, hits)
+    };
+
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".contaminants.reads.tsv");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    let names: Vec<&str> = candidates.iter().map(|(name, _)| *name).collect();
+    writeln!(file, "read_id\tlength\tnb_kminmers\t{}\tbest_match", names.join("\t")).output(&path)?;
+    let mut nb_reads = vec![0u64; candidates.len() + 2];
+    let mut main_thread = |seq_id: &str, found: &mut Option<(usize, usize, Vec<usize>)>| { // runs in main thread
+        let (length, nb, hits) = found.take().unwrap();
+        let best = hits.iter().max().cloned().unwrap_or(0);
+        let class = if best == 0 {candidates.len() + 1}
+            else if hits.iter().filter(|&&n| n == best).count() > 1 {candidates.len()}
+            else {hits.iter().position(|&n| n == best).unwrap()};
+        nb_reads[class] += 1;
+        let best_match = if class < candidates.len() {candidates[class].0} else if class == candidates.len() {"ambiguous"} else {"none"};
+        let hits: Vec<String> = hits.iter().map(|n| n.to_string()).collect();
+        if let Err(e) = writeln!(file, "{}\t{}\t{}\t{}\t{}", seq_id, length, nb, hits.join("\t"), best_match).output(&path) {
+            return Some(Err(e));
+        }
+        signals::pending().map(Ok)
+    };
+    let (buf, is_fasta) = open_sequences(filename)?;
+    let interrupted = if is_fasta {
+        let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fasta_records(reader, threads as u32, queue_len, |record, found: &mut Option<_>| {*found = Some(count_read(record.seq(), record.id().unwrap_or("")))}, |record, found| {main_thread(record.id().unwrap_or(""), found)})
+    }
+    else {
+        let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fastq_records(reader, threads as u32, queue_len, |record, found: &mut Option<_>| {*found = Some(count_read(record.seq(), record.id().unwrap_or("")))}, |record, found| {main_thread(record.id().unwrap_or(""), found)})
+    }.map_err(|e| Error::corrupt(filename, e))?.transpose()?;
+    file.flush().output(&path)?;
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {}, the read classification is partial.", sig);
+        std::process::exit(128 + sig);
+    }
+    info!("Classified the reads of {}: {} ambiguous, {} matching none.", filename.to_str().unwrap(), nb_reads[candidates.len()], nb_reads[candidates.len() + 1]);
+    Ok(nb_reads)
+}
+
+// Write prefix.contaminants.tsv: the read k-min-mers of filename found in each contaminant, and with
+// params.classify_contaminant_reads, the number of reads best matching each.
+pub fn screen(filename: &PathBuf, read_mers_index: &Index, ref_mers_index: &Index, panel: &[Contaminant], params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    // distinct read k-min-mers and occurrences found in each contaminant, and those not in the reference
+    let (mut shared, mut occurrences, mut not_in_ref) = (vec![0u64; panel.len()], vec![0u64; panel.len()], vec![0u64; panel.len()]);
+    let (mut nb_distinct, mut nb_occurrences) = (0u64, 0u64);
+    for (h, count) in read_mers_index.iter() {
+        nb_distinct += 1;
+        nb_occurrences += count;
+        for (c, contaminant) in panel.iter().enumerate() {
+            if contaminant.index.get(&h).is_none() {continue;}
+            shared[c] += 1;
+            occurrences[c] += count;
+            if ref_mers_index.get(&h).is_none() {not_in_ref[c] += 1;}
+        }
+    }
+    let reads = if params.classify_contaminant_reads {
+        let mut candidates: Vec<(&str, &Index)> = panel.iter().map(|c| (c.name.as_str(), &c.index)).collect();
+        if !params.reads_only {candidates.push(("reference", ref_mers_index));}
+        Some(classify_reads(filename, &candidates, params, threads, queue_len, output_prefix)?)
+    }
+    else {None};
+
+    let fraction = |n: u64, total: u64| if total == 0 {0.0} else {n as f64 / total as f64};
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".contaminants.tsv");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    writeln!(file, "# contaminant\tkminmers\tshared_kminmers\tcontainment\toccurrence_fraction\tnot_in_reference\tcontaminant_coverage{}", if reads.is_some() {"\tbest_match_reads"} else {""}).output(&path)?;
+    for (c, contaminant) in panel.iter().enumerate() {
+        let nb_kminmers = contaminant.index.len() as u64;
+        write!(file, "{}\t{}\t{}\t{:.6}\t{:.6}\t{}\t{:.6}", contaminant.name, nb_kminmers, shared[c], fraction(shared[c], nb_distinct), fraction(occurrences[c], nb_occurrences), not_in_ref[c], fraction(shared[c], nb_kminmers)).output(&path)?;
+        match &reads {
+            Some(reads) => writeln!(file, "\t{}", reads[c]),
+            None => writeln!(file),
+        }.output(&path)?;
+        info!("Contaminant {}: {:.2}% of the read k-min-mer occurrences, {:.2}% of its k-min-mers seen in the reads{}.", contaminant.name, 100.0 * fraction(occurrences[c], nb_occurrences), 100.0 * fraction(shared[c], nb_kminmers), reads.as_ref().map_or(String::new(), |reads| format!(", best match of {} reads", reads[c])));
+    }
+    file.flush().output(&path)?;
+    info!("Contamination screening of {} contaminants written to {}.", panel.len(), path);
+    Ok(())
+}
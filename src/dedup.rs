@@ -0,0 +1,73 @@
+// dedup.rs
+// Detection of duplicate reads (PCR or optical duplicates), so that each set of duplicates is counted once
+// and doesn't inflate read abundances. Reads are compared by a sketch of their prefix: the minimizer of each
+// block of BLOCK_LEN k-mer positions, with its offset in the read, so that only reads starting at the same
+// position match. Blocks are grouped into bands, and a read whose band matches a band of an earlier read is a
+// duplicate: exact duplicates match on all bands, and near-duplicates on the bands without sequencing errors.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const SKETCH_K: usize = 21;        // k-mer length of the sketch
+const BLOCK_LEN: usize = 40;       // k-mer positions per block
+const NB_BLOCKS: usize = 8;        // blocks sketched, i.e. the first 320 k-mers of a read
+const BLOCKS_PER_BAND: usize = 2;
+
+fn hash<T: Hash>(value: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Band hashes of the prefix of a read (line breaks of multi-line FASTA records are skipped).
+// Reads too short for a band are hashed whole, so only exact duplicates of them match.
+pub fn signatures(seq: &[u8]) -> Vec<u64> {
+    let prefix: Vec<u8> = seq.iter().filter(|c| !c.is_ascii_whitespace()).take(NB_BLOCKS * BLOCK_LEN + SKETCH_K - 1)
+        .map(|c| c.to_ascii_uppercase()).collect();
+    if prefix.len() < BLOCKS_PER_BAND * BLOCK_LEN + SKETCH_K - 1 {
+        return vec![hash(&prefix)];
+    }
+    let kmer_hashes: Vec<u64> = prefix.windows(SKETCH_K).map(hash).collect();
+    let minimizers: Vec<(u64, usize)> = kmer_hashes.chunks_exact(BLOCK_LEN).enumerate()
+        .map(|(b, block)| {
+            let (i, h) = block.iter().enumerate().min_by_key(|(_, h)| **h).unwrap();
+            (*h, b * BLOCK_LEN + i)
+        })
+        .collect();
+    minimizers.chunks_exact(BLOCKS_PER_BAND).map(hash).collect()
+}
+
+// Band hashes of the reads seen so far.
+pub struct Duplicates {
+    seen: Mutex<HashSet<u64>>,
+    nb_duplicates: AtomicU64,
+}
+impl Duplicates {
+
+    pub fn new() -> Self {
+        Duplicates {seen: Mutex::new(HashSet::new()), nb_duplicates: AtomicU64::new(0)}
+    }
+
+    // Whether a read (or a pair, given both mates) shares a band with one seen before; its bands are recorded either way.
+    pub fn is_duplicate(&self, seqs: &[&[u8]]) -> bool {
+        let bands = match seqs {
+            [seq] => signatures(seq),
+            [mate1, mate2] => signatures(mate1).into_iter().zip(signatures(mate2)).map(hash).collect(),
+            _ => unreachable!(),
+        };
+        // bands are checked and recorded under a single lock, so that of two concurrent copies of a read, one is kept
+        let mut seen = self.seen.lock().unwrap();
+        let duplicate = bands.into_iter().fold(false, |duplicate, band| !seen.insert(band) || duplicate);
+        if duplicate {
+            self.nb_duplicates.fetch_add(1, Ordering::Relaxed);
+        }
+        duplicate
+    }
+
+    pub fn nb_duplicates(&self) -> u64 {
+        self.nb_duplicates.load(Ordering::Relaxed)
+    }
+}
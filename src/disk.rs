@@ -0,0 +1,162 @@
+// disk.rs
+// On-disk backend of the Index (--index-backend disk), for k-min-mer sets that don't fit in memory: counts are
+// kept in a sled database under --index-dir, one per Index, with 8-byte big-endian hashes as keys so that the
+// first byte of a key gives its shard. Counters are 64-bit and updated atomically by sled, and only a bounded
+// page cache stays in memory. Databases are temporary, and removed when their Index is dropped.
+// Only available when built with the "sled" feature.
+
+use crate::error::Error;
+
+// Fail early if the disk backend was asked for but isn't compiled in.
+pub fn check_available() -> Result<(), Error> {
+    if cfg!(feature = "sled") {Ok(())}
+    else {Err(Error::Usage("--index-backend disk needs a build with the \"sled\" feature (cargo build --release --features sled).".to_string()))}
+}
+
+#[cfg(feature = "sled")]
+mod imp {
+    use std::io;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::error::Error;
+
+    const NB_SHARDS: usize = 256;          // one per first byte of the keys
+    const CACHE_BYTES: u64 = 256 << 20;    // page cache of each database
+    static NB_TABLES: AtomicUsize = AtomicUsize::new(0); // databases created so far, to name their directories
+
+    // Table of (hash, count) pairs in a sled database.
+    pub struct DiskTable {
+        db: sled::Db,
+        dir: PathBuf,
+        len: AtomicUsize,
+    }
+    impl DiskTable {
+
+        // Open a new database in a subdirectory of dir.
+        pub fn new(dir: &PathBuf) -> Self {
+            let dir = dir.join(format!("index{}", NB_TABLES.fetch_add(1, Ordering::Relaxed)));
+            let db = sled::Config::new()
+                .path(&dir)
+                .temporary(true)
+                .cache_capacity(CACHE_BYTES)
+                .flush_every_ms(None)
+                .open();
+            match db {
+                Ok(db) => DiskTable {db, dir, len: AtomicUsize::new(0)},
+                Err(e) => fail(&dir, e),
+            }
+        }
+
+        // Value of the counter of h, or None if h isn't in the table.
+        pub fn get(&self, h: u64) -> Option<u64> {
+            self.db.get(h.to_be_bytes()).unwrap_or_else(|e| fail(&self.dir, e)).map(|v| decode(&v))
+        }
+
+        // Add n to the counter of h, inserting it if needed. Counters are 64-bit, so nothing is left over.
+        pub fn increment_by(&self, h: u64, n: u64) -> u64 {
+            let prev = self.db.fetch_and_update(h.to_be_bytes(), |v| Some(encode(v.map_or(0, decode) + n)))
+                .unwrap_or_else(|e| fail(&self.dir, e));
+            if prev.is_none() {self.len.fetch_add(1, Ordering::Relaxed);}
+            0
+        }
+
+        // Increment the counter of h unless it already holds cap, inserting it if needed. Returns whether it was
+        // incremented.
+        pub fn increment_capped(&self, h: u64, cap: u64) -> bool {
+            let prev = self.db.fetch_and_update(h.to_be_bytes(), |v| {
+                let count = v.map_or(0, decode);
+                Some(encode(if count < cap {count + 1} else {count}))
+            }).unwrap_or_else(|e| fail(&self.dir, e));
+            if prev.is_none() {self.len.fetch_add(1, Ordering::Relaxed);}
+            prev.map_or(0, |v| decode(&v)) < cap
+        }
+
+        // Set the counter of h to v. Returns whether h was already in the table.
+        pub fn set(&self, h: u64, v: u64) -> bool {
+            let prev = self.db.insert(h.to_be_bytes(), encode(v)).unwrap_or_else(|e| fail(&self.dir, e));
+            if prev.is_none() {self.len.fetch_add(1, Ordering::Relaxed);}
+            prev.is_some()
+        }
+
+        pub fn len(&self) -> usize {
+            self.len.load(Ordering::Relaxed)
+        }
+
+        pub fn nb_shards(&self) -> usize {
+            NB_SHARDS
+        }
+
+        // Call f on the (hash, count) pairs of one shard.
+        pub fn for_each_in_shard(&self, shard: usize, mut f: impl FnMut(u64, u64)) {
+            for r in self.db.scan_prefix([shard as u8]) {
+                let (key, v) = r.unwrap_or_else(|e| fail(&self.dir, e));
+                f(decode(&key).swap_bytes(), decode(&v));
+            }
+        }
+
+        // Remove the entries of one shard and return their (hash, count) pairs. Each entry is removed on its own,
+        // so that increments made meanwhile are either returned or kept in the table.
+        pub fn drain_shard(&self, shard: usize) -> Vec<(u64, u64)> {
+            let mut pairs = Vec::new();
+            for r in self.db.scan_prefix([shard as u8]).keys() {
+                let key = r.unwrap_or_else(|e| fail(&self.dir, e));
+                if let Some(v) = self.db.remove(&key).unwrap_or_else(|e| fail(&self.dir, e)) {
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    pairs.push((decode(&key).swap_bytes(), decode(&v)));
+                }
+            }
+            pairs
+        }
+
+        // Iterate over (hash, count) pairs, in increasing hash order.
+        pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+            self.db.iter().map(move |r| {
+                let (key, v) = r.unwrap_or_else(|e| fail(&self.dir, e));
+                (decode(&key).swap_bytes(), decode(&v))
+            })
+        }
+
+        // Entries are on disk, and the page cache is bounded by CACHE_BYTES: they don't count against --max-memory.
+        pub fn memory_usage(&self) -> usize {
+            0
+        }
+    }
+
+    fn encode(count: u64) -> Vec<u8> {
+        count.to_le_bytes().to_vec()
+    }
+
+    // Counts are little-endian; keys are big-endian and decoded with swap_bytes.
+    fn decode(bytes: &[u8]) -> u64 {
+        u64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    // The Index interface can't return errors: a failing database ends the run, like errors of the entry points.
+    fn fail(dir: &PathBuf, e: sled::Error) -> ! {
+        let e = Error::Output {path: dir.display().to_string(), source: io::Error::from(e)};
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use imp::DiskTable;
+
+// Placeholder when the "sled" feature is off: check_available rejects --index-backend disk before any is created.
+#[cfg(not(feature = "sled"))]
+pub enum DiskTable {}
+
+#[cfg(not(feature = "sled"))]
+impl DiskTable {
+    pub fn new(_dir: &std::path::PathBuf) -> Self {unreachable!()}
+    pub fn get(&self, _h: u64) -> Option<u64> {match *self {}}
+    pub fn increment_by(&self, _h: u64, _n: u64) -> u64 {match *self {}}
+    pub fn increment_capped(&self, _h: u64, _cap: u64) -> bool {match *self {}}
+    pub fn set(&self, _h: u64, _v: u64) -> bool {match *self {}}
+    pub fn len(&self) -> usize {match *self {}}
+    pub fn nb_shards(&self) -> usize {match *self {}}
+    pub fn for_each_in_shard(&self, _shard: usize, _f: impl FnMut(u64, u64)) {match *self {}}
+    pub fn drain_shard(&self, _shard: usize) -> Vec<(u64, u64)> {match *self {}}
+    pub fn iter(&self) -> std::iter::Empty<(u64, u64)> {match *self {}}
+    pub fn memory_usage(&self) -> usize {match *self {}}
+}
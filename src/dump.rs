@@ -0,0 +1,35 @@
+// dump.rs
+// Dump of the read and reference k-min-mer counts, for downstream joins and custom analyses.
+
+use std::io::Write;
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::get_writer;
+use crate::columnar;
+use crate::index::Index;
+use log::info;
+
+// Write a "hash<TAB>read_count<TAB>ref_count" line for every k-min-mer of either index
+// (or a Parquet file with these columns, if dump_path ends with .parquet), in hash order if ordered.
+pub fn dump_kminmers(read_mers_index: &Index, ref_mers_index: &Index, dump_path: &PathBuf, ordered: bool) -> Result<(), Error> {
+    if columnar::is_parquet(dump_path) {
+        columnar::dump_kminmers_parquet(read_mers_index, ref_mers_index, dump_path, ordered)?;
+        info!("Dumped k-min-mers to {}.", dump_path.to_str().unwrap());
+        return Ok(());
+    }
+    let mut dump_file = get_writer(dump_path)?;
+    writeln!(dump_file, "hash\tread_count\tref_count").output(dump_path)?;
+    for (node, read_abundance) in read_mers_index.iter_ordered(ordered) {
+        let ref_abundance = ref_mers_index.get(&node).unwrap_or(0);
+        writeln!(dump_file, "{}\t{}\t{}", node, read_abundance, ref_abundance).output(dump_path)?;
+    }
+    // reference k-min-mers absent from the reads
+    for (node, ref_abundance) in ref_mers_index.iter_ordered(ordered) {
+        if read_mers_index.get(&node).is_none() {
+            writeln!(dump_file, "{}\t0\t{}", node, ref_abundance).output(dump_path)?;
+        }
+    }
+    dump_file.flush().output(dump_path)?;
+    info!("Dumped k-min-mers to {}.", dump_path.to_str().unwrap());
+    Ok(())
+}
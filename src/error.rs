@@ -0,0 +1,83 @@
+// error.rs
+// Error type for the command-line entry points. Each kind of failure maps to its own exit code,
+// so that pipeline managers can tell a bad input path from a corrupt input or a full disk.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),                                // invalid command line or parameters
+    Input {path: String, source: io::Error},      // input file can't be opened
+    Corrupt {path: String, msg: String},          // input can't be decompressed or parsed
+    Output {path: String, source: io::Error},     // output file can't be created or written
+}
+
+impl Error {
+
+    // Process exit code for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => 1,
+            Error::Input {..} => 2,
+            Error::Corrupt {..} => 3,
+            Error::Output {source, ..} if is_out_of_disk(source) => 5,
+            Error::Output {..} => 4,
+        }
+    }
+
+    pub fn corrupt<P: AsRef<Path>>(path: P, msg: impl fmt::Display) -> Self {
+        Error::Corrupt {path: path.as_ref().display().to_string(), msg: msg.to_string()}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn is_out_of_disk(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENOSPC) | Some(libc::EDQUOT))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn is_out_of_disk(_e: &io::Error) -> bool {
+    false
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(msg) => write!(f, "{}", msg),
+            Error::Input {path, source} => write!(f, "cannot open input file {}: {}", path, source),
+            Error::Corrupt {path, msg} => write!(f, "corrupt or malformed input {}: {}", path, msg),
+            Error::Output {path, source} if is_out_of_disk(source) => write!(f, "out of disk space writing {}: {}", path, source),
+            Error::Output {path, source} => write!(f, "cannot write output file {}: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Input {source, ..} | Error::Output {source, ..} => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// Attach the file an I/O error relates to, and whether it happened on input or output.
+pub trait Context<T> {
+    fn input<P: AsRef<Path>>(self, path: P) -> Result<T, Error>;
+    fn corrupt<P: AsRef<Path>>(self, path: P) -> Result<T, Error>;
+    fn output<P: AsRef<Path>>(self, path: P) -> Result<T, Error>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn input<P: AsRef<Path>>(self, path: P) -> Result<T, Error> {
+        self.map_err(|source| Error::Input {path: path.as_ref().display().to_string(), source})
+    }
+    fn corrupt<P: AsRef<Path>>(self, path: P) -> Result<T, Error> {
+        self.map_err(|e| Error::corrupt(path, e))
+    }
+    fn output<P: AsRef<Path>>(self, path: P) -> Result<T, Error> {
+        self.map_err(|source| Error::Output {path: path.as_ref().display().to_string(), source})
+    }
+}
@@ -0,0 +1,172 @@
+// estimate.rs
+// Quick estimate of the size of a run (--estimate-only), without building any Index: the k-min-mers of the
+// reference and the reads go through HyperLogLog sketches, which estimate the number of distinct k-min-mers
+// (and, from their union, the number of shared ones) in a few KB. A 1/2^SAMPLE_BITS sample of the read
+// k-min-mers, selected by hash as in ntCard, is counted exactly, and its abundance spectrum scaled up gives
+// a coarse histogram of read k-min-mer abundances. Results go to prefix.estimate.json.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::time::Instant;
+use dashmap::DashMap;
+use seq_io::BaseRecord;
+use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
+use serde_json::json;
+use crate::closures::open_sequences;
+use crate::error::{Context, Error};
+use crate::mers::{self, mix};
+use crate::signals;
+use super::Params;
+use log::{info, warn};
+
+const HLL_BITS: u32 = 14;    // 2^14 registers, about 0.8% standard error
+const SAMPLE_BITS: u32 = 8;  // one read k-min-mer in 256 is counted exactly
+const NB_BINS: usize = 16;   // abundance bins 1, 2-3, 4-7, ..., 2^15 and above
+
+// HyperLogLog sketch of a set of hashes.
+struct Hll {
+    registers: Vec<AtomicU8>,
+}
+impl Hll {
+
+    fn new() -> Self {
+        Hll {registers: (0..1 << HLL_BITS).map(|_| AtomicU8::new(0)).collect()}
+    }
+
+    // Add a mixed hash: its top bits select a register, which keeps the longest run of leading zeros seen
+    // in the other bits.
+    fn add(&self, m: u64) {
+        let rank = ((m << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() as u8 + 1;
+        self.registers[(m >> (64 - HLL_BITS)) as usize].fetch_max(rank, Ordering::Relaxed);
+    }
+
+    // Sketch of the union of both sets.
+    fn union(&self, other: &Hll) -> Hll {
+        Hll {registers: self.registers.iter().zip(&other.registers)
+            .map(|(a, b)| AtomicU8::new(a.load(Ordering::Relaxed).max(b.load(Ordering::Relaxed))))
+            .collect()}
+    }
+
+    // Estimated number of distinct hashes, with linear counting for small sets.
+    fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let (sum, zeros) = self.registers.iter().map(|r| r.load(Ordering::Relaxed)).fold((0.0, 0), |(sum, zeros), r| {
+            (sum + 2f64.powi(-(r as i32)), zeros + (r == 0) as usize)
+        });
+        let raw = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        if raw <= 2.5 * m && zeros > 0 {m * (m / zeros as f64).ln()} else {raw}
+    }
+}
+
+// Estimates for one set of sequences.
+struct Estimator {
+    hll: Hll,
+    sample: DashMap<u64, u64>, // abundance of the sampled k-min-mers
+    total: AtomicU64,
+}
+impl Estimator {
+
+    fn new() -> Self {
+        Estimator {hll: Hll::new(), sample: DashMap::new(), total: AtomicU64::new(0)}
+    }
+
+    fn add(&self, h: u64, sampled: bool) {
+        let m = mix(h);
+        self.hll.add(m);
+        if sampled && m.trailing_zeros() >= SAMPLE_BITS {
+            *self.sample.entry(h).or_insert(0) += 1;
+        }
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Estimated number of distinct k-min-mers in each abundance bin.
+    fn abundance_bins(&self) -> Vec<f64> {
+        let mut bins = vec![0.0; NB_BINS];
+        for r in self.sample.iter() {
+            bins[(63 - r.value().leading_zeros() as usize).min(NB_BINS - 1)] += (1u64 << SAMPLE_BITS) as f64;
+        }
+        bins
+    }
+}
+
+// Add the k-min-mers of all sequences of files to estimator, sampling them if sampled is set.
+// Returns the number of sequences, and the signal number if reading was interrupted.
+fn stream_files(files: &[PathBuf], estimator: &Estimator, sampled: bool, params: &Params, threads: usize, queue_len: usize) -> Result<(u64, Option<i32>), Error> {
+    let add_seq = |seq_id: &str, seq: &[u8]| {
+        if let Some(kminmers) = mers::extract(seq_id, seq, params) {
+            for kminmer in kminmers {
+                estimator.add(kminmer.get_hash_u64(), sampled);
+            }
+        }
+    };
+    let mut nb_seqs: u64 = 0;
+    let mut main_thread = || { // runs in main thread
+        nb_seqs += 1;
+        signals::pending()
+    };
+    for path in files {
+        let (buf, is_fasta) = open_sequences(path)?;
+        let interrupted = if is_fasta {
+            let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+            read_process_fasta_records(reader, threads as u32, queue_len, |record, _: &mut ()| {add_seq(record.id().unwrap_or(""), record.seq())}, |_, _| {main_thread()})
+        }
+        else {
+            let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+            read_process_fastq_records(reader, threads as u32, queue_len, |record, _: &mut ()| {add_seq(record.id().unwrap_or(""), record.seq())}, |_, _| {main_thread()})
+        }.map_err(|e| Error::corrupt(path, e))?;
+        if interrupted.is_some() {return Ok((nb_seqs, interrupted));}
+    }
+    Ok((nb_seqs, None))
+}
+
+// Stream the reference and the reads once through the estimators, and write prefix.estimate.json.
+pub fn run_estimate(reads: &PathBuf, ref_filenames: &[PathBuf], params: &Params, threads: usize, queue_len: usize, ref_queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let start = Instant::now();
+    let ref_estimator = Estimator::new();
+    let (_, interrupted) = stream_files(ref_filenames, &ref_estimator, false, params, threads, ref_queue_len)?;
+    let read_estimator = Estimator::new();
+    let (nb_reads, interrupted) = match interrupted {
+        None => stream_files(&[reads.clone()], &read_estimator, true, params, threads, queue_len)?,
+        Some(_) => (0, interrupted),
+    };
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {}.", sig);
+        std::process::exit(128 + sig);
+    }
+    let ref_distinct = ref_estimator.hll.estimate();
+    let read_distinct = read_estimator.hll.estimate();
+    let shared = (ref_distinct + read_distinct - ref_estimator.hll.union(&read_estimator.hll).estimate()).max(0.0);
+    info!("Estimated {:.0} distinct read k-min-mers, {:.0} distinct reference k-min-mers and {:.0} shared ones in {:?}.", read_distinct, ref_distinct, shared, start.elapsed());
+
+    let bins: Vec<_> = read_estimator.abundance_bins().into_iter().enumerate()
+        .map(|(i, n)| json!({
+            "min_abundance": 1u64 << i,
+            "max_abundance": if i + 1 < NB_BINS {json!((1u64 << (i + 1)) - 1)} else {json!(null)},
+            "distinct_kminmers": n.round() as u64,
+        }))
+        .collect();
+    let doc = json!({
+        "k": params.k,
+        "l": params.l,
+        "density": params.density,
+        "reads": {
+            "nb_reads": nb_reads,
+            "total_kminmers": read_estimator.total.load(Ordering::Relaxed),
+            "distinct_kminmers": read_distinct.round() as u64,
+        },
+        "reference": {
+            "total_kminmers": ref_estimator.total.load(Ordering::Relaxed),
+            "distinct_kminmers": ref_distinct.round() as u64,
+        },
+        "shared_kminmers": shared.round() as u64,
+        "sampling_rate": 1.0 / (1u64 << SAMPLE_BITS) as f64,
+        "read_abundance_histogram": bins,
+    });
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".estimate.json");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    serde_json::to_writer_pretty(&mut file, &doc).map_err(std::io::Error::from).output(&path)?;
+    writeln!(file).output(&path)?;
+    file.flush().output(&path)
+}
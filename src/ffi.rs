@@ -0,0 +1,86 @@
+// ffi.rs
+// C API (the "ffi" feature), to link the counting core into C or C++ programs such as an assembler: indexes are
+// built from in-memory FASTA/FASTQ buffers or single sequences, counts can be incremented and queried by hash,
+// and the 2D histogram of a read and a reference index is written to a matrix allocated by the caller.
+// Declarations are in include/kminmer2dhisto.h. All functions taking an index can be called from several
+// threads on the same index, except kmh_index_free. Functions returning a count return -1 on invalid arguments.
+
+use std::os::raw::{c_char, c_int};
+use std::slice;
+use dashmap::DashMap;
+use crate::histo;
+use crate::index::Index;
+use crate::mers;
+use super::Params;
+
+pub struct KmhIndex {
+    index: Index,
+    params: Params,
+    lens: DashMap<String, usize>, // read lengths, as in closures::index_reads
+}
+
+// New empty index for the given parameters, or NULL if they are invalid. Free it with kmh_index_free.
+#[no_mangle]
+pub extern "C" fn kmh_index_new(k: usize, l: usize, density: f64) -> *mut KmhIndex {
+    if k == 0 || l == 0 || !(density > 0.0 && density <= 1.0) {return std::ptr::null_mut();}
+    Box::into_raw(Box::new(KmhIndex {index: Index::new(), params: Params::new(k, l, density), lens: DashMap::new()}))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kmh_index_free(idx: *mut KmhIndex) {
+    if !idx.is_null() {drop(Box::from_raw(idx));}
+}
+
+// Count the records of a FASTA or FASTQ buffer of len bytes. Returns the number of records, or -1 if the
+// buffer isn't FASTA/FASTQ.
+#[no_mangle]
+pub unsafe extern "C" fn kmh_index_add_fastx(idx: *const KmhIndex, buf: *const c_char, len: usize, reference: c_int) -> i64 {
+    if idx.is_null() || (buf.is_null() && len > 0) {return -1;}
+    let (idx, buf) = (&*idx, if len == 0 {&[][..]} else {slice::from_raw_parts(buf as *const u8, len)});
+    match mers::index_fastx(buf, reference != 0, &idx.index, &idx.lens, &idx.params) {
+        Some(nb_records) => nb_records as i64,
+        None => -1,
+    }
+}
+
+// Count the k-min-mers of a single sequence of len bases. Returns the number of k-min-mers.
+#[no_mangle]
+pub unsafe extern "C" fn kmh_index_add_sequence(idx: *const KmhIndex, seq: *const c_char, len: usize, reference: c_int) -> i64 {
+    if idx.is_null() || (seq.is_null() && len > 0) {return -1;}
+    let seq = if len == 0 {&[][..]} else {slice::from_raw_parts(seq as *const u8, len)};
+    let idx = &*idx;
+    mers::index_sequence("", seq, reference != 0, &idx.index, &idx.lens, &idx.params) as i64
+}
+
+// Add n to the count of a k-min-mer hash.
+#[no_mangle]
+pub unsafe extern "C" fn kmh_index_increment(idx: *const KmhIndex, hash: u64, n: u64) {
+    if !idx.is_null() && n > 0 {(*idx).index.increment_by(hash, n);}
+}
+
+// Count of a k-min-mer hash, 0 if absent.
+#[no_mangle]
+pub unsafe extern "C" fn kmh_index_get(idx: *const KmhIndex, hash: u64) -> u64 {
+    if idx.is_null() {return 0;}
+    (*idx).index.get(&hash).unwrap_or(0)
+}
+
+// Number of distinct k-min-mers.
+#[no_mangle]
+pub unsafe extern "C" fn kmh_index_len(idx: *const KmhIndex) -> usize {
+    if idx.is_null() {return 0;}
+    (*idx).index.len()
+}
+
+// Write the 2D histogram of a read and a reference index to out, a row-major matrix of rows x cols counts:
+// out[x * cols + y] is the number of k-min-mers seen x times in the reads and y times in the reference, the last
+// row and column holding all higher abundances (up to 9999 and 9, as in prefix.hist2D). Returns 0, or -1 if
+// an argument is invalid or the indexes were built with different parameters.
+#[no_mangle]
+pub unsafe extern "C" fn kmh_histogram(reads: *const KmhIndex, reference: *const KmhIndex, out: *mut u64, rows: usize, cols: usize) -> c_int {
+    if reads.is_null() || reference.is_null() || out.is_null() || rows == 0 || cols == 0 {return -1;}
+    let (reads, reference) = (&*reads, &*reference);
+    if histo::metadata(&reads.params) != histo::metadata(&reference.params) {return -1;}
+    slice::from_raw_parts_mut(out, rows * cols).copy_from_slice(&histo::compute_binned(&reads.index, &reference.index, rows, cols, reads.params.threads));
+    0
+}
@@ -0,0 +1,62 @@
+// gc.rs
+// GC content vs abundance histogram of read k-min-mers, to diagnose GC bias in minimizer space.
+// The GC fraction of a k-min-mer is taken over the read window it spans, the first time it is seen.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use dashmap::DashMap;
+use crate::error::{Context, Error};
+use crate::index::Index;
+
+pub const NB_GC_BINS: usize = 101;          // rows: GC percentage 0..100
+pub const NB_ABUNDANCE_BINS: usize = 1000;  // columns: read abundance 0..999 (last bin holds all higher abundances)
+
+// GC percentage of each distinct read k-min-mer.
+pub struct GcMap {
+    map: DashMap<u64, u8>,
+}
+impl GcMap {
+
+    pub fn new() -> Self {
+        GcMap {map: DashMap::new()}
+    }
+
+    // Record the GC percentage of the window spanned by the k-min-mer of hash h, unless already known.
+    pub fn record(&self, h: u64, window: &[u8]) {
+        if !self.map.contains_key(&h) {
+            self.map.insert(h, gc_percent(window));
+        }
+    }
+}
+
+// Percentage of G/C among the A/C/G/T bases of seq (newlines and Ns are ignored).
+pub fn gc_percent(seq: &[u8]) -> u8 {
+    let (mut gc, mut acgt) = (0usize, 0usize);
+    for c in seq {
+        match c {
+            b'G' | b'C' | b'g' | b'c' => {gc += 1; acgt += 1;}
+            b'A' | b'T' | b'a' | b't' => acgt += 1,
+            _ => {}
+        }
+    }
+    if acgt == 0 {0} else {((gc * 100 + acgt / 2) / acgt) as u8}
+}
+
+// Write prefix.gc.hist2D: h[g][x] is the number of distinct read k-min-mers with g% GC seen x times.
+pub fn write_gc_hist2d(gc: &GcMap, read_mers_index: &Index, output_prefix: &PathBuf) -> Result<(), Error> {
+    let mut hist = vec![vec![0u64; NB_ABUNDANCE_BINS]; NB_GC_BINS];
+    for r in gc.map.iter() {
+        let abundance = read_mers_index.get(r.key()).unwrap_or(0) as usize;
+        hist[*r.value() as usize][abundance.min(NB_ABUNDANCE_BINS - 1)] += 1;
+    }
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".gc.hist2D");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    for row in &hist {
+        for cell in row {
+            write!(file, "{}\t", cell).output(&path)?;
+        }
+        writeln!(file).output(&path)?;
+    }
+    file.flush().output(&path)
+}
@@ -0,0 +1,131 @@
+// gfa.rs
+// GFA assembly graphs as references (files named .gfa, possibly compressed), so that a pangenome graph can be
+// the reference axis of the histogram. The k-min-mers of each segment (S line) are indexed like those of a
+// FASTA record named after the segment. With --gfa-edges, those spanning a link (L line) are indexed too: for
+// each link, the end of the first segment holding its last k-1 minimizers is joined to the start of the second
+// one holding its first k-1 minimizers (after their overlap), in the orientations of the link, so that every
+// k-min-mer of the junction crosses it. K-min-mers spanning several links, through segments with fewer than k
+// minimizers, are not indexed.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::mpsc::sync_channel;
+use std::sync::Mutex;
+use std::thread;
+use bio::alphabets::dna::revcomp;
+use crate::error::{Context, Error};
+use crate::get_reader;
+use crate::index::Index;
+use crate::mers;
+use crate::positional::RefPositions;
+use crate::signals;
+use super::Params;
+use log::{debug, info};
+
+// Whether a reference file is a GFA graph, from its name.
+pub fn is_gfa(path: &PathBuf) -> bool {
+    let name = path.to_string_lossy();
+    name.trim_end_matches(".gz").trim_end_matches(".bgz").trim_end_matches(".lz4").ends_with(".gfa")
+}
+
+// A link between two oriented segments, with the length of their overlap.
+struct Link {
+    from: String,
+    from_forward: bool,
+    to: String,
+    to_forward: bool,
+    overlap: usize,
+}
+
+// Ends of a segment, in its forward orientation: the prefix holding its first k-1 minimizers (head) and the
+// suffix holding its last k-1 minimizers (tail). Both are the whole segment if it has fewer than k minimizers.
+struct Ends {
+    head: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+// Overlap length of a link from its CIGAR string: the length of an all-match overlap ("12M"), 0 otherwise ("*",
+// "0M" or other operations).
+fn overlap(cigar: &str) -> usize {
+    cigar.strip_suffix('M').and_then(|n| n.parse().ok()).unwrap_or(0)
+}
+
+// Index the segments of a GFA file, and the links with params.gfa_edges, on threads threads. Returns the signal
+// number if indexing was interrupted.
+pub fn index_gfa(path: &PathBuf, params: &Params, ref_mers_index: &Index, positions: Option<&RefPositions>, threads: usize) -> Result<Option<i32>, Error> {
+    let ends: Mutex<HashMap<String, Ends>> = Mutex::new(HashMap::new());
+    let mut links = Vec::new();
+    let (mut nb_segments, mut interrupted) = (0, None);
+    let (sender, receiver) = sync_channel::<(String, Vec<u8>)>(threads * 2);
+    let receiver = Mutex::new(receiver);
+    thread::scope(|s| -> Result<(), Error> {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| loop {
+                let (name, seq) = match receiver.lock().unwrap().recv() {
+                    Ok(segment) => segment,
+                    Err(_) => break,
+                };
+                let nb_mers = mers::ref_extract(&name, &seq, params, ref_mers_index, positions);
+                debug!("Indexed segment {}: {} k-min-mers.", name, nb_mers);
+                if params.gfa_edges {
+                    let segment_ends = segment_ends(&name, &seq, params);
+                    ends.lock().unwrap().insert(name, segment_ends);
+                }
+            });
+        }
+        for line in get_reader(path)?.lines() {
+            let line = line.input(path)?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["S", name, seq, ..] if *seq != "*" => {
+                    nb_segments += 1;
+                    if sender.send((name.to_string(), seq.as_bytes().to_vec())).is_err() {break;}
+                    if nb_segments % 1024 == 0 {
+                        interrupted = signals::pending();
+                        if interrupted.is_some() {break;}
+                    }
+                }
+                ["L", from, from_orient, to, to_orient, rest @ ..] if params.gfa_edges => {
+                    links.push(Link {from: from.to_string(), from_forward: *from_orient != "-", to: to.to_string(), to_forward: *to_orient != "-", overlap: rest.first().map_or(0, |cigar| overlap(cigar))});
+                }
+                _ => {}
+            }
+        }
+        drop(sender);
+        Ok(())
+    })?;
+    if interrupted.is_some() {return Ok(interrupted);}
+
+    let ends = ends.into_inner().unwrap();
+    let mut nb_junction_mers = 0;
+    for link in &links {
+        let (from, to) = match (ends.get(&link.from), ends.get(&link.to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Err(Error::corrupt(path, format!("link between {} and {}: no such segment with a sequence", link.from, link.to))),
+        };
+        // the end of the first oriented segment, then the start of the second one
+        let mut junction = if link.from_forward {from.tail.clone()} else {revcomp(&from.head)};
+        let to_start = if link.to_forward {to.head.clone()} else {revcomp(&to.tail)};
+        junction.extend_from_slice(to_start.get(link.overlap..).unwrap_or(&[]));
+        nb_junction_mers += mers::ref_extract(&format!("{}>{}", link.from, link.to), &junction, params, ref_mers_index, None);
+    }
+    info!("Indexed {} GFA segments of {}{}.", nb_segments, path.to_str().unwrap(),
+        if params.gfa_edges {format!(" and {} links ({} junction k-min-mers)", links.len(), nb_junction_mers)} else {String::new()});
+    Ok(None)
+}
+
+// The ends of a segment, from the positions of its first and last k-min-mers: the last k-1 minimizers start
+// after the start of the last k-min-mer, and the first k-1 end before the end of the first one.
+fn segment_ends(name: &str, seq: &[u8], params: &Params) -> Ends {
+    let mut kminmers = match mers::extract(name, seq, params) {
+        Some(kminmers) => kminmers,
+        None => return Ends {head: seq.to_vec(), tail: seq.to_vec()},
+    };
+    let first = match kminmers.next() {
+        Some(first) => first,
+        None => return Ends {head: seq.to_vec(), tail: seq.to_vec()},
+    };
+    let last_start = kminmers.last().map_or(first.start, |last| last.start);
+    Ends {head: seq[..(first.end - 1).min(seq.len())].to_vec(), tail: seq[(last_start + 1).min(seq.len())..].to_vec()}
+}
@@ -0,0 +1,89 @@
+// h5.rs
+// HDF5 output: the 2D histogram, its marginals and the per-reference positional histograms as datasets
+// of a single prefix.h5 file, with axis labels as attributes, for HDF5-aware viewers (napari, HDFView).
+// Only available when built with the "hdf5" feature, which links to the HDF5 library.
+
+use std::path::PathBuf;
+use crate::error::Error;
+use crate::index::Index;
+use crate::positional::RefPositions;
+use super::Params;
+
+// Fail early if HDF5 output was asked for but isn't compiled in.
+pub fn check_available() -> Result<(), Error> {
+    if cfg!(feature = "hdf5") {Ok(())}
+    else {Err(Error::Usage("HDF5 output needs a build with the \"hdf5\" feature (cargo build --release --features hdf5).".to_string()))}
+}
+
+#[cfg(feature = "hdf5")]
+mod imp {
+    use std::io;
+    use hdf5::types::VarLenUnicode;
+    use hdf5::{Group, Location};
+    use crate::error::{Context, Error};
+
+    pub fn other(e: hdf5::Error) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, e.to_string())
+    }
+
+    pub fn set_str_attr(loc: &Location, name: &str, value: &str, path: &str) -> Result<(), Error> {
+        let value: VarLenUnicode = value.parse().unwrap();
+        loc.new_attr::<VarLenUnicode>().shape(()).create(name)
+            .and_then(|a| a.write_scalar(&value)).map_err(other).output(path)
+    }
+
+    pub fn set_num_attr<T: hdf5::H5Type>(loc: &Location, name: &str, value: T, path: &str) -> Result<(), Error> {
+        loc.new_attr::<T>().shape(()).create(name)
+            .and_then(|a| a.write_scalar(&value)).map_err(other).output(path)
+    }
+
+    // Create a 2D u64 dataset from a matrix, labelling its axes.
+    pub fn write_matrix(group: &Group, name: &str, hist: &Vec<Vec<u64>>, row_axis: &str, col_axis: &str, path: &str) -> Result<(), Error> {
+        let nb_cols = hist.first().map_or(0, |row| row.len());
+        let flat: Vec<u64> = hist.iter().flatten().copied().collect();
+        let dataset = group.new_dataset::<u64>().shape([hist.len(), nb_cols]).create(name).map_err(other).output(path)?;
+        dataset.write_raw(&flat).map_err(other).output(path)?;
+        set_str_attr(&dataset, "row_axis", row_axis, path)?;
+        set_str_attr(&dataset, "column_axis", col_axis, path)
+    }
+
+    // Create a 1D u64 dataset, labelling its axis.
+    pub fn write_vector(group: &Group, name: &str, values: &[u64], axis: &str, path: &str) -> Result<(), Error> {
+        let dataset = group.new_dataset::<u64>().shape([values.len()]).create(name).map_err(other).output(path)?;
+        dataset.write_raw(values).map_err(other).output(path)?;
+        set_str_attr(&dataset, "axis", axis, path)
+    }
+}
+
+// Write prefix.h5 with the datasets /hist2D, /hist1D_reads, /hist1D_ref and, if reference positions
+// were recorded, one /positional/<ref_id> dataset per reference sequence.
+#[cfg(feature = "hdf5")]
+pub fn write_h5(hist: &Vec<Vec<u64>>, read_mers_index: &Index, ref_positions: Option<&RefPositions>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    use crate::error::Context;
+    use crate::{histo, positional};
+
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".h5");
+    let file = hdf5::File::create(&path).map_err(imp::other).output(&path)?;
+    imp::set_num_attr(&file, "k", params.k as u64, &path)?;
+    imp::set_num_attr(&file, "l", params.l as u64, &path)?;
+    imp::set_num_attr(&file, "density", params.density, &path)?;
+
+    imp::write_matrix(&file, "hist2D", hist, "read abundance", "reference abundance", &path)?;
+    imp::write_vector(&file, "hist1D_reads", &histo::read_marginal(hist), "read abundance", &path)?;
+    imp::write_vector(&file, "hist1D_ref", &histo::ref_marginal(hist), "reference abundance", &path)?;
+
+    if let (Some(positions), Some(bin_size)) = (ref_positions, params.positional_bin) {
+        let group = file.create_group("positional").map_err(imp::other).output(&path)?;
+        imp::set_num_attr(&group, "bin_size", bin_size as u64, &path)?;
+        for (seq_id, seq_hist) in positional::compute(positions, read_mers_index, bin_size) {
+            // '/' separates path components in HDF5
+            imp::write_matrix(&group, &seq_id.replace('/', "_"), &seq_hist, "reference position bin", "read abundance", &path)?;
+        }
+    }
+    file.close().map_err(imp::other).output(&path)
+}
+
+#[cfg(not(feature = "hdf5"))]
+pub fn write_h5(hist: &Vec<Vec<u64>>, read_mers_index: &Index, ref_positions: Option<&RefPositions>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    check_available()
+}
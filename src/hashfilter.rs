@@ -0,0 +1,64 @@
+// hashfilter.rs
+// Lists of k-min-mer hashes to count exclusively (--include-hashes), e.g. a targeted panel, or to leave out
+// (--exclude-hashes), e.g. known artifacts. The Index consults them on every increment, so both the reads and
+// the reference are filtered. A list is either text, one hash per line as its first field (so that the
+// outputs of --dump-kminmers, --report-missing and --report-novel can be used as is; a header line and lines
+// starting with '#' are skipped), or a binary set: the 8 bytes "KMHASHES" followed by little-endian u64
+// hashes. Both may be compressed.
+
+use std::collections::HashSet;
+use std::io::{BufRead, Read};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::get_reader;
+use log::info;
+
+const MAGIC: &[u8; 8] = b"KMHASHES";
+
+pub struct HashFilter {
+    include: Option<HashSet<u64>>,
+    exclude: HashSet<u64>,
+}
+impl HashFilter {
+
+    // Load the lists given, if any.
+    pub fn load(include: Option<&PathBuf>, exclude: Option<&PathBuf>) -> Result<Option<HashFilter>, Error> {
+        if include.is_none() && exclude.is_none() {return Ok(None);}
+        let include = match include {Some(path) => Some(read_hashes(path)?), None => None};
+        let exclude = match exclude {Some(path) => read_hashes(path)?, None => HashSet::new()};
+        Ok(Some(HashFilter {include, exclude}))
+    }
+
+    // Whether the hash h may be counted.
+    pub fn admit(&self, h: u64) -> bool {
+        self.include.as_ref().map_or(true, |include| include.contains(&h)) && !self.exclude.contains(&h)
+    }
+}
+
+fn read_hashes(path: &PathBuf) -> Result<HashSet<u64>, Error> {
+    let mut reader = get_reader(path)?;
+    let mut hashes = HashSet::new();
+    if reader.fill_buf().input(path)?.starts_with(MAGIC) {
+        reader.consume(MAGIC.len());
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).input(path)?;
+        if bytes.len() % 8 != 0 {return Err(Error::corrupt(path, "truncated binary hash set"));}
+        hashes.extend(bytes.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().unwrap())));
+    }
+    else {
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.input(path)?;
+            let field = match line.split_whitespace().next() {
+                Some(field) if !field.starts_with('#') => field,
+                _ => continue,
+            };
+            match field.parse() {
+                Ok(h) => {hashes.insert(h);}
+                Err(_) if i == 0 => continue, // a header line, as in --dump-kminmers outputs
+                Err(_) => return Err(Error::corrupt(path, format!("line {}: {:?} is not a k-min-mer hash", i + 1, field))),
+            }
+        }
+    }
+    info!("Loaded {} k-min-mer hashes from {}.", hashes.len(), path.to_str().unwrap());
+    Ok(hashes)
+}
@@ -0,0 +1,213 @@
+// histfile.rs
+// Reading back .hist2D files (dense or sparse, and transposed with --axis-order ref-major), and the subcommands working on them: merge sums the
+// histograms of runs over shards of a read set, diff compares two runs (e.g. library preps, or an
+// assembly before and after polishing), plot and stats write the HTML report and genome statistics
+// of a run again.
+
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::get_reader;
+use super::histo;
+use super::html;
+use super::spectrum;
+use super::Params;
+use log::{info, warn};
+
+// A 2D histogram read from a file, with the parameters recorded in its header, if any.
+pub struct HistFile {
+    pub hist: Vec<Vec<u64>>,
+    pub metadata: Option<String>, // "k=.. l=.. density=.." and the minimizer scheme, if not the default one
+    pub partial: bool,                         // written by an interrupted run
+}
+
+impl HistFile {
+
+    pub fn nb_cols(&self) -> usize {
+        self.hist.first().map_or(0, |row| row.len())
+    }
+
+    // Header line for the recorded parameters.
+    pub fn metadata_line(&self) -> Option<String> {
+        self.metadata.clone()
+    }
+
+    // Parameters recorded in the header, for the outputs that depend on k, l and density.
+    pub fn params(&self, path: &PathBuf) -> Result<Params, Error> {
+        let line = self.metadata.as_deref().ok_or_else(|| Error::Usage(format!("{} doesn't record k, l and density.", path.to_str().unwrap())))?;
+        // the values were checked when reading the header
+        Ok(Params::new(field(line, "k").unwrap().parse().unwrap(), field(line, "l").unwrap().parse().unwrap(), field(line, "density").unwrap().parse().unwrap()))
+    }
+}
+
+// Value of a "name=value" field of a header line.
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    line.split_whitespace().find_map(|f| f.strip_prefix(name).and_then(|v| v.strip_prefix('=')))
+}
+
+// Read a .hist2D file written as a dense matrix or with --sparse.
+pub fn read_hist2d(path: &PathBuf) -> Result<HistFile, Error> {
+    let reader = get_reader(path)?;
+    let mut res = HistFile {hist: Vec::new(), metadata: None, partial: false};
+    let mut sparse_dims: Option<(usize, usize)> = None;
+    let mut ref_major = false;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.corrupt(path)?;
+        let bad_line = || Error::corrupt(path, format!("line {}: unexpected {:?}", i + 1, line));
+        if let Some(header) = line.strip_prefix('#') {
+            let header = header.trim();
+            if header.starts_with("sparse") {
+                let rows = field(header, "rows").and_then(|v| v.parse().ok()).ok_or_else(bad_line)?;
+                let cols = field(header, "cols").and_then(|v| v.parse().ok()).ok_or_else(bad_line)?;
+                sparse_dims = Some((rows, cols));
+                res.hist = vec![vec![0u64; cols]; rows];
+            }
+            else if header.starts_with("PARTIAL") {
+                res.partial = true;
+            }
+            else if header == histo::REF_MAJOR_HEADER {
+                ref_major = true;
+            }
+            else if let (Some(k), Some(l), Some(density)) = (field(header, "k"), field(header, "l"), field(header, "density")) {
+                if k.parse::<usize>().is_err() || l.parse::<usize>().is_err() || density.parse::<f64>().is_err() {return Err(bad_line());}
+                res.metadata = Some(header.to_string());
+            }
+            continue;
+        }
+        if line.trim().is_empty() {continue;}
+        let values: Vec<u64> = line.split('\t').filter(|v| !v.is_empty()).map(|v| v.trim().parse()).collect::<Result<_, _>>().map_err(|_| bad_line())?;
+        match sparse_dims {
+            Some((rows, cols)) => {
+                if values.len() != 3 || values[0] as usize >= rows || values[1] as usize >= cols {return Err(bad_line());}
+                res.hist[values[0] as usize][values[1] as usize] += values[2];
+            }
+            None => {
+                if !res.hist.is_empty() && values.len() != res.nb_cols() {return Err(bad_line());}
+                res.hist.push(values);
+            }
+        }
+    }
+    if res.hist.is_empty() {
+        return Err(Error::corrupt(path, "empty histogram"));
+    }
+    if ref_major {
+        res.hist = histo::transpose(&res.hist);
+    }
+    Ok(res)
+}
+
+// Check that two histograms can be combined: same dimensions, and same parameters when both record them.
+pub fn check_compatible(first: &HistFile, first_path: &PathBuf, other: &HistFile, other_path: &PathBuf) -> Result<(), Error> {
+    if (other.hist.len(), other.nb_cols()) != (first.hist.len(), first.nb_cols()) {
+        return Err(Error::Usage(format!("{} is a {}x{} histogram, but {} is {}x{}.", other_path.to_str().unwrap(), other.hist.len(), other.nb_cols(),
+            first_path.to_str().unwrap(), first.hist.len(), first.nb_cols())));
+    }
+    match (first.metadata_line(), other.metadata_line()) {
+        (Some(a), Some(b)) if a != b => Err(Error::Usage(format!("{} was computed with {}, but {} with {}.", other_path.to_str().unwrap(), b, first_path.to_str().unwrap(), a))),
+        (Some(_), Some(_)) => Ok(()),
+        _ => {
+            warn!("{} or {} doesn't record k, l and density; they are assumed to be the same.", first_path.to_str().unwrap(), other_path.to_str().unwrap());
+            Ok(())
+        }
+    }
+}
+
+// Sum the histograms of several .hist2D files into output.
+pub fn merge(inputs: &[PathBuf], output: &PathBuf, sparse: bool) -> Result<(), Error> {
+    let first_path = &inputs[0];
+    let mut merged = read_hist2d(first_path)?;
+    for path in &inputs[1..] {
+        let hist_file = read_hist2d(path)?;
+        check_compatible(&merged, first_path, &hist_file, path)?;
+        if merged.metadata.is_none() {merged.metadata = hist_file.metadata.clone();}
+        merged.partial |= hist_file.partial;
+        for (row, other_row) in merged.hist.iter_mut().zip(&hist_file.hist) {
+            for (cell, other_cell) in row.iter_mut().zip(other_row) {
+                *cell += other_cell;
+            }
+        }
+    }
+    let mut header: Vec<String> = merged.metadata_line().into_iter().collect();
+    if merged.partial {
+        warn!("Some of the merged histograms are partial, counts are incomplete.");
+        header.push("PARTIAL histogram: merged from interrupted runs, counts are incomplete".to_string());
+    }
+    histo::write_matrix(&merged.hist, output.to_str().unwrap(), sparse, &header)?;
+    info!("Merged {} histograms into {}.", inputs.len(), output.to_str().unwrap());
+    Ok(())
+}
+
+// Distinct k-min-mers by presence class: (read-only, reference-only, shared) = (column 0, row 0, the rest).
+fn classes(hist: &Vec<Vec<u64>>) -> (u64, u64, u64) {
+    let read_only = hist.iter().skip(1).map(|row| row[0]).sum();
+    let ref_only = hist[0].iter().skip(1).sum();
+    let shared = hist.iter().skip(1).map(|row| row.iter().skip(1).sum::<u64>()).sum();
+    (read_only, ref_only, shared)
+}
+
+// Write second - first of two .hist2D files to prefix.diff.hist2D, and summary statistics of both
+// and of their difference to prefix.diff.stats.
+pub fn diff(first_path: &PathBuf, second_path: &PathBuf, output_prefix: &PathBuf, sparse: bool) -> Result<(), Error> {
+    let first = read_hist2d(first_path)?;
+    let second = read_hist2d(second_path)?;
+    check_compatible(&first, first_path, &second, second_path)?;
+
+    let diff: Vec<Vec<i64>> = first.hist.iter().zip(&second.hist)
+        .map(|(a, b)| a.iter().zip(b).map(|(x, y)| *y as i64 - *x as i64).collect())
+        .collect();
+    let prefix = output_prefix.to_str().unwrap();
+    let mut header: Vec<String> = first.metadata_line().into_iter().collect();
+    header.push(format!("{} - {}", second_path.to_str().unwrap(), first_path.to_str().unwrap()));
+    histo::write_matrix(&diff, &format!("{}{}", prefix, ".diff.hist2D"), sparse, &header)?;
+
+    let l1: u64 = diff.iter().flatten().map(|d| d.unsigned_abs()).sum();
+    let total = |h: &Vec<Vec<u64>>| -> u64 {h.iter().flatten().sum()};
+    let (total1, total2) = (total(&first.hist), total(&second.hist));
+    let (c1, c2) = (classes(&first.hist), classes(&second.hist));
+    let (s1, s2) = (histo::similarity(&first.hist), histo::similarity(&second.hist));
+
+    let stats_path = format!("{}{}", prefix, ".diff.stats");
+    let mut stats_file = File::create(&stats_path).output(&stats_path)?;
+    writeln!(stats_file, "# first: {}", first_path.to_str().unwrap()).output(&stats_path)?;
+    writeln!(stats_file, "# second: {}", second_path.to_str().unwrap()).output(&stats_path)?;
+    writeln!(stats_file, "statistic\tfirst\tsecond\tdifference").output(&stats_path)?;
+    for (name, a, b) in [("distinct_kminmers", total1, total2), ("read_only", c1.0, c2.0), ("reference_only", c1.1, c2.1), ("shared", c1.2, c2.2)] {
+        writeln!(stats_file, "{}\t{}\t{}\t{}", name, a, b, b as i64 - a as i64).output(&stats_path)?;
+    }
+    for (name, a, b) in [("jaccard", s1.jaccard, s2.jaccard), ("containment_reads_in_ref", s1.reads_in_ref, s2.reads_in_ref), ("containment_ref_in_reads", s1.ref_in_reads, s2.ref_in_reads)] {
+        writeln!(stats_file, "{}\t{:.4}\t{:.4}\t{:+.4}", name, a, b, b - a).output(&stats_path)?;
+    }
+    // L1 distance, normalized so that 0 is identical and 1 is disjoint
+    let normalized = if total1 + total2 > 0 {l1 as f64 / (total1 + total2) as f64} else {0.0};
+    writeln!(stats_file, "l1_distance\t\t\t{}", l1).output(&stats_path)?;
+    writeln!(stats_file, "normalized_l1_distance\t\t\t{:.4}", normalized).output(&stats_path)?;
+    info!("Wrote {}.diff.hist2D and {}: normalized L1 distance {:.4}.", prefix, stats_path, normalized);
+    Ok(())
+}
+
+// Read a .hist2D file, warning if it was written by an interrupted run.
+fn read_complete_hist2d(path: &PathBuf) -> Result<HistFile, Error> {
+    let hist_file = read_hist2d(path)?;
+    if hist_file.partial {
+        warn!("{} is a partial histogram, counts are incomplete.", path.to_str().unwrap());
+    }
+    Ok(hist_file)
+}
+
+// Write prefix.report.html for a .hist2D file.
+pub fn plot(input: &PathBuf, output_prefix: &PathBuf) -> Result<(), Error> {
+    let hist_file = read_complete_hist2d(input)?;
+    html::write_html_report(&hist_file.hist, &hist_file.params(input)?, output_prefix)?;
+    info!("Wrote {}.report.html.", output_prefix.to_str().unwrap());
+    Ok(())
+}
+
+// Write prefix.stats for a .hist2D file, and log the similarity of its read and reference k-min-mer sets.
+pub fn stats(input: &PathBuf, output_prefix: &PathBuf) -> Result<(), Error> {
+    let hist_file = read_complete_hist2d(input)?;
+    spectrum::write_stats(&hist_file.hist, &hist_file.params(input)?, output_prefix)?;
+    let similarity = histo::similarity(&hist_file.hist);
+    info!("Jaccard index: {:.4}, containment of reads in reference: {:.4}, of reference in reads: {:.4}.", similarity.jaccard, similarity.reads_in_ref, similarity.ref_in_reads);
+    Ok(())
+}
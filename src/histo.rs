@@ -0,0 +1,473 @@
+// histo.rs
+// Construction of the 2D histogram from the read and reference indexes, and its output files.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use crate::error::{Context, Error};
+use crate::index::Index;
+use super::Params;
+use super::mers;
+use log::info;
+
+pub const NB_READ_BINS: usize = 10000; // rows: read abundance 0..9999 (last bin holds all higher abundances)
+pub const NB_REF_BINS: usize = 10;     // columns: reference abundance 0..9 (same)
+pub const NB_PAIR_BINS: usize = 1000;  // both axes of reads1 vs reads2 histograms
+
+// Orientation of the written read vs reference histograms (--axis-order): read abundances as rows (the
+// default), or reference abundances as rows, which some plotting conventions expect. Transposed files record it
+// in a header line, and are transposed back when read again (merge, diff, plot, stats).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AxisOrder {
+    ReadMajor,
+    RefMajor,
+}
+impl AxisOrder {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AxisOrder::ReadMajor => "read-major",
+            AxisOrder::RefMajor => "ref-major",
+        }
+    }
+}
+impl FromStr for AxisOrder {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read-major" => Ok(AxisOrder::ReadMajor),
+            "ref-major" => Ok(AxisOrder::RefMajor),
+            _ => Err(format!("invalid axis order '{}' (expected read-major or ref-major)", s)),
+        }
+    }
+}
+
+// Header line of the histograms written with reference abundances as rows.
+pub const REF_MAJOR_HEADER: &str = "ref-major: rows are reference abundances, columns read abundances";
+
+// Transpose a matrix.
+pub fn transpose<T: Copy>(hist: &Vec<Vec<T>>) -> Vec<Vec<T>> {
+    let nb_cols = hist.first().map_or(0, |row| row.len());
+    (0..nb_cols).map(|j| hist.iter().map(|row| row[j]).collect()).collect()
+}
+
+// Write a read vs reference histogram with write_matrix, in the orientation of params.axis_order.
+pub fn write_oriented<T: Display + Default + PartialEq + Copy>(hist: &Vec<Vec<T>>, hist_path: &str, params: &Params, header: &[String]) -> Result<(), Error> {
+    match params.axis_order {
+        AxisOrder::ReadMajor => write_matrix(hist, hist_path, params.sparse, header),
+        AxisOrder::RefMajor => {
+            let header: Vec<String> = header.iter().cloned().chain([REF_MAJOR_HEADER.to_string()]).collect();
+            write_matrix(&transpose(hist), hist_path, params.sparse, &header)
+        }
+    }
+}
+
+// Normalization of the additional 2D histogram written with --normalize: cells as a fraction of all distinct
+// k-min-mers, per million or as joint probabilities summing to 1, comparable across sequencing depths.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalize {
+    PerMillion,
+    Probability,
+}
+impl Normalize {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Normalize::PerMillion => "per-million",
+            Normalize::Probability => "probability",
+        }
+    }
+
+    // Scale of the normalized cells: their sum.
+    fn scale(&self) -> f64 {
+        match self {
+            Normalize::PerMillion => 1e6,
+            Normalize::Probability => 1.0,
+        }
+    }
+}
+impl FromStr for Normalize {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-million" => Ok(Normalize::PerMillion),
+            "probability" => Ok(Normalize::Probability),
+            _ => Err(format!("invalid normalization '{}' (expected per-million or probability)", s)),
+        }
+    }
+}
+
+// Fill a nb_rows x nb_cols histogram by calling f on every (hash, count) pair of index. Shards of the index are
+// processed by up to threads threads, each into its own histogram, and the per-thread histograms are summed.
+fn par_fill(index: &Index, nb_rows: usize, nb_cols: usize, threads: usize, f: impl Fn(u64, u64, &mut Vec<Vec<u64>>) + Sync) -> Vec<Vec<u64>> {
+    let nb_shards = index.nb_shards();
+    let nb_threads = threads.min(nb_shards).max(1);
+    let next_shard = AtomicUsize::new(0);
+    let fill = || {
+        let mut hist = vec![vec![0u64; nb_cols]; nb_rows];
+        loop {
+            let shard = next_shard.fetch_add(1, Ordering::Relaxed);
+            if shard >= nb_shards {break;}
+            index.for_each_in_shard(shard, |h, count| f(h, count, &mut hist));
+        }
+        hist
+    };
+    // no thread is spawned when there is a single one, e.g. in WebAssembly, which has no threads
+    if nb_threads == 1 {return fill();}
+    let partials: Vec<Vec<Vec<u64>>> = thread::scope(|s| {
+        let workers: Vec<_> = (0..nb_threads).map(|_| s.spawn(&fill)).collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+    let mut hist = vec![vec![0u64; nb_cols]; nb_rows];
+    for partial in partials {
+        for (row, partial_row) in hist.iter_mut().zip(partial) {
+            for (cell, c) in row.iter_mut().zip(partial_row) {
+                *cell += c;
+            }
+        }
+    }
+    hist
+}
+
+// Produce the 2D histogram h[x][y]: the number of distinct k-min-mers seen x times in the reads and y times in the reference,
+// on up to threads threads.
+pub fn compute(read_mers_index: &Index, ref_mers_index: &Index, threads: usize) -> Vec<Vec<u64>> {
+    let mut hist = par_fill(read_mers_index, NB_READ_BINS, NB_REF_BINS, threads, |node, kminmer_abundance, hist| {
+        let ref_abundance = ref_mers_index.get(&node).unwrap_or(0);
+        let i = if kminmer_abundance > 9999 { 9999 } else { kminmer_abundance } as usize;
+        let j = if ref_abundance > 9 { 9 } else { ref_abundance } as usize;
+        hist[i][j] += 1;
+    });
+
+    // now do the edge case where reference kminmers aren't found in the reads
+    let ref_only = par_fill(ref_mers_index, 1, NB_REF_BINS, threads, |node, ref_abundance, hist| {
+        if read_mers_index.get(&node).is_none() {
+            let j = if ref_abundance > 9 { 9 } else { ref_abundance } as usize;
+            hist[0][j] += 1;
+        }
+    });
+    for (cell, c) in hist[0].iter_mut().zip(&ref_only[0]) {
+        *cell += c;
+    }
+    hist
+}
+
+// The histogram of compute as a row-major nb_rows x nb_cols matrix, for the C and WebAssembly bindings: the
+// last row and column hold all higher abundances.
+pub fn compute_binned(read_mers_index: &Index, ref_mers_index: &Index, nb_rows: usize, nb_cols: usize, threads: usize) -> Vec<u64> {
+    let mut res = vec![0u64; nb_rows * nb_cols];
+    for (x, row) in compute(read_mers_index, ref_mers_index, threads).iter().enumerate() {
+        for (y, count) in row.iter().enumerate() {
+            res[x.min(nb_rows - 1) * nb_cols + y.min(nb_cols - 1)] += count;
+        }
+    }
+    res
+}
+
+// Histogram and statistics of read k-min-mers that aren't all held in an Index (spilled to disk or sketched):
+// only those present in the reference are, the others are summarized by their abundance spectrum.
+pub struct ReadCounts {
+    pub hist: Vec<Vec<u64>>,
+    pub read_in_ref: Index, // counts of the read k-min-mers present in the reference
+    pub totals: (u64, u64), // total and distinct read k-min-mers
+    spectrum: BTreeMap<u64, (u64, u64)>, // distinct read k-min-mers per abundance, and how many of them are in the reference
+}
+impl ReadCounts {
+
+    // From the read k-min-mers present in the reference, the number of other distinct read k-min-mers per
+    // abundance, and the total number of read k-min-mers.
+    pub fn new(read_in_ref: Index, not_in_ref: BTreeMap<u64, u64>, total: u64, ref_mers_index: &Index, threads: usize) -> Self {
+        // read k-min-mers in the reference, and reference k-min-mers absent from the reads, are counted as usual
+        let mut hist = compute(&read_in_ref, ref_mers_index, threads);
+        let mut spectrum: BTreeMap<u64, (u64, u64)> = not_in_ref.iter().map(|(&count, &n)| (count, (n, 0))).collect();
+        for (count, n) in not_in_ref {
+            hist[(count as usize).min(NB_READ_BINS - 1)][0] += n;
+        }
+        for (_, count) in read_in_ref.iter() {
+            let cell = spectrum.entry(count).or_insert((0, 0));
+            cell.0 += 1;
+            cell.1 += 1;
+        }
+        let distinct = spectrum.values().map(|c| c.0).sum();
+        ReadCounts {hist, read_in_ref, totals: (total, distinct), spectrum}
+    }
+
+    // Number of read k-min-mers seen at least solid_threshold times, and of those in the reference.
+    pub fn solid(&self, solid_threshold: u64) -> (u64, u64) {
+        self.spectrum.range(solid_threshold..).fold((0, 0), |(solid, in_ref), (_, c)| (solid + c.0, in_ref + c.1))
+    }
+}
+
+// Produce the reference vs reference2 copy-number matrix: for each k-min-mer of either reference, its count
+// in the first one (row) and in the second one (column), both capped at NB_REF_BINS-1.
+pub fn compute_ref_pair(ref_mers_index: &Index, ref2_mers_index: &Index) -> Vec<Vec<u64>> {
+    let mut hist = vec![vec![0u64; NB_REF_BINS]; NB_REF_BINS];
+    let cap = |x: u64| (x as usize).min(NB_REF_BINS - 1);
+    for (node, abundance1) in ref_mers_index.iter() {
+        hist[cap(abundance1)][cap(ref2_mers_index.get(&node).unwrap_or(0))] += 1;
+    }
+    // k-min-mers of the second reference only
+    for (node, abundance2) in ref2_mers_index.iter() {
+        if ref_mers_index.get(&node).is_none() {
+            hist[0][cap(abundance2)] += 1;
+        }
+    }
+    hist
+}
+
+// Produce the reads1 vs reads2 abundance histograms of k-min-mers present in the reference and
+// of those absent from it. Both axes are capped at NB_PAIR_BINS-1.
+pub fn compute_pair(read_mers_index: &Index, read2_mers_index: &Index, ref_mers_index: &Index) -> (Vec<Vec<u64>>, Vec<Vec<u64>>) {
+    let mut in_ref = vec![vec![0u64; NB_PAIR_BINS]; NB_PAIR_BINS];
+    let mut not_in_ref = vec![vec![0u64; NB_PAIR_BINS]; NB_PAIR_BINS];
+    let cap = |x: u64| if x > (NB_PAIR_BINS - 1) as u64 { NB_PAIR_BINS - 1 } else { x as usize };
+
+    for (node, abundance1) in read_mers_index.iter() {
+        let abundance2 = read2_mers_index.get(&node).unwrap_or(0);
+        let hist = if ref_mers_index.get(&node).is_some() {&mut in_ref} else {&mut not_in_ref};
+        hist[cap(abundance1)][cap(abundance2)] += 1;
+    }
+    // k-min-mers of the second read set only
+    for (node, abundance2) in read2_mers_index.iter() {
+        if read_mers_index.get(&node).is_some() {continue;}
+        let hist = if ref_mers_index.get(&node).is_some() {&mut in_ref} else {&mut not_in_ref};
+        hist[0][cap(abundance2)] += 1;
+    }
+    (in_ref, not_in_ref)
+}
+
+// Sum the 2D histogram over reference abundances, giving the read abundance spectrum.
+pub fn read_marginal(hist: &Vec<Vec<u64>>) -> Vec<u64> {
+    hist.iter().map(|row| row.iter().sum()).collect()
+}
+
+// Sum the 2D histogram over read abundances, giving the reference abundance spectrum.
+pub fn ref_marginal(hist: &Vec<Vec<u64>>) -> Vec<u64> {
+    (0..NB_REF_BINS).map(|j| hist.iter().map(|row| row[j]).sum()).collect()
+}
+
+// Mash-like similarity of the read and reference k-min-mer sets.
+#[derive(Clone, Copy, Debug)]
+pub struct Similarity {
+    pub jaccard: f64,      // shared / (read + reference - shared) distinct k-min-mers
+    pub reads_in_ref: f64, // fraction of distinct read k-min-mers present in the reference
+    pub ref_in_reads: f64, // fraction of distinct reference k-min-mers present in the reads
+}
+impl Similarity {
+
+    pub fn new(read_distinct: u64, ref_distinct: u64, shared: u64) -> Self {
+        let ratio = |a: u64, b: u64| if b == 0 {0.0} else {a as f64 / b as f64};
+        Similarity {
+            jaccard: ratio(shared, read_distinct + ref_distinct - shared),
+            reads_in_ref: ratio(shared, read_distinct),
+            ref_in_reads: ratio(shared, ref_distinct),
+        }
+    }
+}
+
+// Similarity of the read and reference k-min-mer sets, from the 2D histogram (row 0 and column 0 hold
+// the k-min-mers absent from the reads and from the reference).
+pub fn similarity(hist: &Vec<Vec<u64>>) -> Similarity {
+    let read_distinct: u64 = hist.iter().skip(1).map(|row| row.iter().sum::<u64>()).sum();
+    let ref_distinct: u64 = hist.iter().map(|row| row.iter().skip(1).sum::<u64>()).sum();
+    let shared: u64 = hist.iter().skip(1).map(|row| row.iter().skip(1).sum::<u64>()).sum();
+    Similarity::new(read_distinct, ref_distinct, shared)
+}
+
+fn create(path: &str) -> Result<File, Error> {
+    File::create(path).output(path)
+}
+
+// Header line recording the parameters a histogram was computed with, checked when histograms are merged.
+pub fn metadata(params: &Params) -> String {
+    let line = format!("k={} l={} density={}", params.k, params.l, params.density);
+    match mers::scheme(params) {
+        scheme if scheme.is_empty() => line,
+        scheme => format!("{} {}", line, scheme),
+    }
+}
+
+// Write a matrix with '#'-prefixed header lines (e.g. its metadata), as tab-separated rows or in sparse format.
+pub fn write_matrix<T: Display + Default + PartialEq>(hist: &Vec<Vec<T>>, hist_path: &str, sparse: bool, header: &[String]) -> Result<(), Error> {
+    if sparse {
+        write_sparse_matrix(hist, hist_path, header)
+    }
+    else {
+        write_matrix_with_header(hist, hist_path, header)
+    }
+}
+
+// Write the nonzero cells of a matrix as "i<TAB>j<TAB>count" lines, after a header giving its dimensions.
+pub fn write_sparse_matrix<T: Display + Default + PartialEq>(hist: &Vec<Vec<T>>, hist_path: &str, header: &[String]) -> Result<(), Error> {
+    let mut hist_file = BufWriter::new(create(hist_path)?);
+    let nb_cols = hist.first().map_or(0, |row| row.len());
+    writeln!(hist_file, "# sparse rows={} cols={}", hist.len(), nb_cols).output(hist_path)?;
+    for line in header {
+        writeln!(hist_file, "# {}", line).output(hist_path)?;
+    }
+    for (i, row) in hist.iter().enumerate() {
+        for (j, cell) in row.iter().enumerate() {
+            if *cell != T::default() {
+                writeln!(hist_file, "{}\t{}\t{}", i, j, cell).output(hist_path)?;
+            }
+        }
+    }
+    hist_file.flush().output(hist_path)
+}
+
+// Write a matrix as tab-separated rows, preceded by '#'-prefixed header lines.
+pub fn write_matrix_with_header<T: Display>(hist: &Vec<Vec<T>>, hist_path: &str, header: &[String]) -> Result<(), Error> {
+    let mut hist_file = BufWriter::new(create(hist_path)?);
+    for line in header {
+        writeln!(hist_file, "# {}", line).output(hist_path)?;
+    }
+    for row in hist {
+        for cell in row {
+            write!(hist_file, "{}\t", cell).output(hist_path)?;
+        }
+        write!(hist_file, "\n").output(hist_path)?;
+    }
+    hist_file.flush().output(hist_path)
+}
+
+// Write the 2D histogram to prefix.hist2D as a tab-separated matrix, or in sparse format.
+pub fn write_hist2d(hist: &Vec<Vec<u64>>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let hist_path = format!("{}{}", output_prefix.to_str().unwrap(), ".hist2D");
+    write_oriented(hist, &hist_path, params, &[metadata(params)])
+}
+
+// Write the 2D histogram normalized to a sum of normalize.scale() to prefix.<normalization>.hist2D.
+pub fn write_normalized_hist2d(hist: &Vec<Vec<u64>>, normalize: Normalize, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let total = hist.iter().flatten().sum::<u64>().max(1) as f64;
+    let normalized: Vec<Vec<f64>> = hist.iter().map(|row| row.iter().map(|&c| c as f64 * normalize.scale() / total).collect()).collect();
+    let hist_path = format!("{}.{}.hist2D", output_prefix.to_str().unwrap(), normalize.name());
+    write_oriented(&normalized, &hist_path, params, &[metadata(params), format!("normalized: {} of {} distinct k-min-mers", normalize.name(), total)])
+}
+
+// Write the cumulative distributions of read abundances to prefix.cumulative.hist2D: cell (i, j) is the fraction
+// of the k-min-mers of reference abundance j seen at most i times in the reads, e.g. to read off the abundance
+// covering 99% of the single-copy k-min-mers in column 1. Columns without k-min-mers are 0.
+pub fn write_cumulative_hist2d(hist: &Vec<Vec<u64>>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let col_sums = ref_marginal(hist);
+    let mut running = vec![0u64; NB_REF_BINS];
+    let cumulative: Vec<Vec<f64>> = hist.iter().map(|row| {
+        row.iter().zip(running.iter_mut()).zip(&col_sums).map(|((count, sum), total)| {
+            *sum += count;
+            if *total == 0 {0.0} else {(*sum as f64 / *total as f64 * 1e6).round() / 1e6}
+        }).collect()
+    }).collect();
+    let hist_path = format!("{}{}", output_prefix.to_str().unwrap(), ".cumulative.hist2D");
+    write_oriented(&cumulative, &hist_path, params, &[metadata(params), "cumulative: fraction of each reference abundance column at or below each read abundance".to_string()])
+}
+
+// Write a self-describing copy of the 2D histogram to prefix.annotated.hist2D: header lines give the
+// parameters and the meaning of the axes, each row ends with its sum, and a last row holds the column sums and
+// the grand total. Always dense, as the sums fill the last row and column.
+pub fn write_annotated_hist2d(hist: &Vec<Vec<u64>>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let nb_cols = hist.first().map_or(0, |row| row.len());
+    let mut annotated: Vec<Vec<u64>> = hist.iter().map(|row| {
+        let mut row = row.clone();
+        row.push(row.iter().sum());
+        row
+    }).collect();
+    let col_sums: Vec<u64> = (0..=nb_cols).map(|j| annotated.iter().map(|row| row[j]).sum()).collect();
+    annotated.push(col_sums);
+    let hist_path = format!("{}{}", output_prefix.to_str().unwrap(), ".annotated.hist2D");
+    write_matrix_with_header(&annotated, &hist_path, &[
+        metadata(params),
+        format!("rows: read abundance 0..{} (last row of counts: {} and more), columns: reference abundance 0..{} (last column of counts: {} and more)", hist.len() - 1, hist.len() - 1, nb_cols - 1, nb_cols - 1),
+        "cells: number of distinct k-min-mers; last column: row sums, last row: column sums and grand total".to_string(),
+    ])
+}
+
+// Write the 2D histogram of an interrupted run to prefix.partial.hist2D, laid out as prefix.hist2D, with a header
+// line stating that counts are incomplete after the metadata.
+pub fn write_partial_hist2d(hist: &Vec<Vec<u64>>, sig: i32, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let hist_path = format!("{}{}", output_prefix.to_str().unwrap(), ".partial.hist2D");
+    write_oriented(hist, &hist_path, params, &[metadata(params), format!("PARTIAL histogram: run interrupted by signal {}, counts are incomplete", sig)])?;
+    info!("Partial histogram written to {}.", hist_path);
+    Ok(())
+}
+
+// Write a 1D spectrum in the "abundance count" format used by jellyfish histo (nonzero bins only, abundance >= 1).
+fn write_hist1d(spectrum: &[u64], path: &str) -> Result<(), Error> {
+    let mut file = BufWriter::new(create(path)?);
+    for (x, count) in spectrum.iter().enumerate().skip(1) {
+        if *count > 0 {
+            writeln!(file, "{} {}", x, count).output(path)?;
+        }
+    }
+    file.flush().output(path)
+}
+
+// Write the row and column sums of the 2D histogram to prefix.hist1D.reads and prefix.hist1D.ref.
+pub fn write_marginals(hist: &Vec<Vec<u64>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    let prefix = output_prefix.to_str().unwrap();
+    write_hist1d(&read_marginal(hist), &format!("{}{}", prefix, ".hist1D.reads"))?;
+    write_hist1d(&ref_marginal(hist), &format!("{}{}", prefix, ".hist1D.ref"))
+}
+
+// Write the read abundance spectrum alone (prefix.hist1D.reads), for runs without a reference.
+pub fn write_read_marginal(hist: &Vec<Vec<u64>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    write_hist1d(&read_marginal(hist), &format!("{}{}", output_prefix.to_str().unwrap(), ".hist1D.reads"))
+}
+
+// Collapse the reference axis into copy-number classes 0, 1, 2, 3 and 4+.
+pub fn copy_number_matrix(hist: &Vec<Vec<u64>>) -> Vec<[u64; 5]> {
+    hist.iter().map(|row| {
+        let mut cn = [0u64; 5];
+        for (j, count) in row.iter().enumerate() {
+            cn[if j > 4 { 4 } else { j }] += count;
+        }
+        cn
+    }).collect()
+}
+
+// Write the copy-number matrix as a KAT matrix file (prefix.spectra-cn.mx), readable by `kat plot spectra-cn`.
+fn write_spectra_cn_kat(cn: &Vec<[u64; 5]>, k: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".spectra-cn.mx");
+    let mut file = create(&path)?;
+    let max_val = cn.iter().skip(1).flat_map(|row| row.iter()).max().unwrap_or(&0);
+    writeln!(file, "# Title:Spectra Copy Number Plot").output(&path)?;
+    writeln!(file, "# XLabel:k-min-mer multiplicity").output(&path)?;
+    writeln!(file, "# YLabel:Number of distinct k-min-mers").output(&path)?;
+    writeln!(file, "# ZLabel:Copy number").output(&path)?;
+    writeln!(file, "# Columns:5").output(&path)?;
+    writeln!(file, "# Rows:{}", cn.len()).output(&path)?;
+    writeln!(file, "# MaxVal:{}", max_val).output(&path)?;
+    writeln!(file, "# Transpose:0").output(&path)?;
+    writeln!(file, "# Kmer value:{}", k).output(&path)?;
+    writeln!(file, "###").output(&path)?;
+    for row in cn {
+        let line: Vec<String> = row.iter().map(|c| c.to_string()).collect();
+        writeln!(file, "{}", line.join(" ")).output(&path)?;
+    }
+    Ok(())
+}
+
+// Write the copy-number histogram in Merqury's long format (prefix.spectra-cn.hist), readable by plot_spectra_cn.R.
+// Reference-only k-min-mers (read multiplicity 0) are not part of this format and are left out.
+fn write_spectra_cn_merqury(hist: &Vec<Vec<u64>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".spectra-cn.hist");
+    let mut file = create(&path)?;
+    writeln!(file, "Copies\tkmer_multiplicity\tCount").output(&path)?;
+    let labels = ["read-only", "1", "2", "3", "4", ">4"];
+    for (c, label) in labels.iter().enumerate() {
+        for (i, row) in hist.iter().enumerate().skip(1) {
+            let count: u64 = if c == 5 { row[5..].iter().sum() } else { row[c] };
+            writeln!(file, "{}\t{}\t{}", label, i, count).output(&path)?;
+        }
+    }
+    Ok(())
+}
+
+// Write spectra-cn outputs compatible with KAT and Merqury plotting.
+pub fn write_spectra_cn(hist: &Vec<Vec<u64>>, k: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    write_spectra_cn_kat(&copy_number_matrix(hist), k, output_prefix)?;
+    write_spectra_cn_merqury(hist, output_prefix)
+}
@@ -0,0 +1,214 @@
+// html.rs
+// Self-contained HTML report (prefix.report.html): an interactive heatmap of the 2D histogram, the read
+// and reference spectra and the run parameters, viewable in any browser without installing anything.
+// Plots are drawn by a small embedded script, so the file doesn't depend on a CDN or plotting library.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use serde_json::json;
+use crate::error::{Context, Error};
+use super::Params;
+use super::histo;
+use super::spectrum;
+
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>k-min-mer 2D histogram report</title>
+<style>
+body {font-family: sans-serif; margin: 2em; color: #222;}
+h2 {margin-top: 1.5em;}
+table {border-collapse: collapse;}
+td {padding: 2px 12px 2px 0;}
+canvas {border: 1px solid #ccc; display: block;}
+#tip {position: fixed; background: #fff; border: 1px solid #888; padding: 2px 6px; font-size: 12px; pointer-events: none; display: none;}
+.controls {margin: 6px 0;}
+</style>
+</head>
+<body>
+<h1>k-min-mer 2D histogram</h1>
+<table id="params"></table>
+
+<h2>Read abundance &times; reference abundance</h2>
+<div class="controls">Max read abundance: <input id="maxx" type="number" min="2" style="width: 6em"> <label><input id="logc" type="checkbox" checked> log color scale</label></div>
+<canvas id="heatmap" width="1000" height="360"></canvas>
+
+<h2>Read spectrum</h2>
+<div class="controls"><label><input id="logy" type="checkbox" checked> log scale</label></div>
+<canvas id="reads" width="1000" height="300"></canvas>
+
+<h2>Reference spectrum</h2>
+<canvas id="ref" width="1000" height="300"></canvas>
+<div id="tip"></div>
+
+<script>
+const DATA = __DATA__;
+const M = {left: 70, right: 20, top: 10, bottom: 40};
+const tip = document.getElementById("tip");
+
+const params = document.getElementById("params");
+for (const [name, value] of DATA.params) {
+  const row = params.insertRow();
+  row.insertCell().textContent = name;
+  row.insertCell().textContent = value;
+}
+
+// default x range: up to the abundance below which 99.9% of read k-min-mer occurrences lie
+function defaultMax(spectrum) {
+  let total = 0, acc = 0;
+  spectrum.forEach((c, x) => total += c * x);
+  for (let x = 1; x < spectrum.length; x++) {
+    acc += spectrum[x] * x;
+    if (acc >= 0.999 * total) return Math.max(x + 1, 10);
+  }
+  return spectrum.length;
+}
+
+function viridis(t) {
+  const stops = [[68, 1, 84], [59, 82, 139], [33, 145, 140], [94, 201, 98], [253, 231, 37]];
+  const s = Math.min(Math.max(t, 0), 1) * (stops.length - 1);
+  const i = Math.min(Math.floor(s), stops.length - 2), f = s - i;
+  const c = stops[i].map((v, k) => Math.round(v + f * (stops[i + 1][k] - v)));
+  return "rgb(" + c.join(",") + ")";
+}
+
+function axes(ctx, w, h, xlabel, ylabel, xmax, ticksY) {
+  ctx.fillStyle = "#222";
+  ctx.font = "12px sans-serif";
+  ctx.textAlign = "center";
+  ctx.fillText(xlabel, M.left + (w - M.left - M.right) / 2, h - 5);
+  for (let i = 0; i <= 5; i++) {
+    const x = Math.round(i * xmax / 5);
+    ctx.fillText(x, M.left + x / xmax * (w - M.left - M.right), h - M.bottom + 15);
+  }
+  ctx.save();
+  ctx.translate(15, M.top + (h - M.top - M.bottom) / 2);
+  ctx.rotate(-Math.PI / 2);
+  ctx.fillText(ylabel, 0, 0);
+  ctx.restore();
+  ctx.textAlign = "right";
+  for (const [label, y] of ticksY) ctx.fillText(label, M.left - 5, y + 4);
+}
+
+function hover(canvas, lookup) {
+  canvas.onmousemove = (e) => {
+    const r = canvas.getBoundingClientRect();
+    const text = lookup(e.clientX - r.left, e.clientY - r.top);
+    if (text === null) {tip.style.display = "none"; return;}
+    tip.textContent = text;
+    tip.style.left = (e.clientX + 12) + "px";
+    tip.style.top = (e.clientY + 12) + "px";
+    tip.style.display = "block";
+  };
+  canvas.onmouseleave = () => tip.style.display = "none";
+}
+
+function drawHeatmap() {
+  const canvas = document.getElementById("heatmap"), ctx = canvas.getContext("2d");
+  const w = canvas.width, h = canvas.height, pw = w - M.left - M.right, ph = h - M.top - M.bottom;
+  const xmax = Math.min(Math.max(parseInt(document.getElementById("maxx").value) || 2, 2), DATA.hist.length);
+  const log = document.getElementById("logc").checked;
+  const ny = DATA.hist[0].length;
+  const scale = (c) => log ? Math.log10(c + 1) : c;
+  let vmax = 0;
+  for (let x = 0; x < xmax; x++) for (let y = 0; y < ny; y++) vmax = Math.max(vmax, scale(DATA.hist[x][y]));
+  ctx.clearRect(0, 0, w, h);
+  const cw = pw / xmax, ch = ph / ny;
+  for (let x = 0; x < xmax; x++) {
+    for (let y = 0; y < ny; y++) {
+      const c = DATA.hist[x][y];
+      ctx.fillStyle = c == 0 ? "#fff" : viridis(vmax > 0 ? scale(c) / vmax : 0);
+      ctx.fillRect(M.left + x * cw, M.top + (ny - 1 - y) * ch, Math.ceil(cw), Math.ceil(ch));
+    }
+  }
+  const ticks = [];
+  for (let y = 0; y < ny; y++) ticks.push([y == ny - 1 ? y + "+" : y, M.top + (ny - 1 - y + 0.5) * ch]);
+  axes(ctx, w, h, "read abundance", "reference abundance", xmax, ticks);
+  hover(canvas, (px, py) => {
+    const x = Math.floor((px - M.left) / cw), y = ny - 1 - Math.floor((py - M.top) / ch);
+    if (x < 0 || x >= xmax || y < 0 || y >= ny) return null;
+    return "read " + x + ", reference " + y + ": " + DATA.hist[x][y] + " k-min-mers";
+  });
+}
+
+function drawSpectrum(id, spectrum, xlabel, bars, log) {
+  const canvas = document.getElementById(id), ctx = canvas.getContext("2d");
+  const w = canvas.width, h = canvas.height, pw = w - M.left - M.right, ph = h - M.top - M.bottom;
+  const xmax = spectrum.length;
+  const scale = (c) => log ? Math.log10(c + 1) : c;
+  const vmax = Math.max(...spectrum.slice(1).map(scale), 1e-9);
+  const px = (x) => M.left + x / xmax * pw, py = (c) => M.top + ph - scale(c) / vmax * ph;
+  ctx.clearRect(0, 0, w, h);
+  ctx.strokeStyle = ctx.fillStyle = "#31688e";
+  if (bars) {
+    for (let x = 1; x < xmax; x++) ctx.fillRect(px(x) + 1, py(spectrum[x]), pw / xmax - 2, M.top + ph - py(spectrum[x]));
+  } else {
+    ctx.beginPath();
+    for (let x = 1; x < xmax; x++) x == 1 ? ctx.moveTo(px(x), py(spectrum[x])) : ctx.lineTo(px(x), py(spectrum[x]));
+    ctx.stroke();
+  }
+  const ticks = [];
+  for (let i = 0; i <= 4; i++) {
+    const v = vmax * i / 4;
+    ticks.push([log ? "1e" + v.toFixed(1) : Math.round(v), M.top + ph - i / 4 * ph]);
+  }
+  axes(ctx, w, h, xlabel, "distinct k-min-mers", xmax, ticks);
+  hover(canvas, (cx, cy) => {
+    const x = Math.floor((cx - M.left) / pw * xmax);
+    if (x < 1 || x >= xmax || cy < M.top || cy > M.top + ph) return null;
+    return "abundance " + x + ": " + spectrum[x] + " k-min-mers";
+  });
+}
+
+function drawReads() {
+  const xmax = Math.min(parseInt(document.getElementById("maxx").value) || DATA.reads.length, DATA.reads.length);
+  drawSpectrum("reads", DATA.reads.slice(0, xmax), "read abundance", false, document.getElementById("logy").checked);
+}
+
+document.getElementById("maxx").value = defaultMax(DATA.reads);
+document.getElementById("maxx").onchange = () => {drawHeatmap(); drawReads();};
+document.getElementById("logc").onchange = drawHeatmap;
+document.getElementById("logy").onchange = drawReads;
+drawHeatmap();
+drawReads();
+drawSpectrum("ref", DATA.ref, "reference abundance", true, false);
+</script>
+</body>
+</html>
+"##;
+
+// Write prefix.report.html for the 2D histogram of a run.
+pub fn write_html_report(hist: &Vec<Vec<u64>>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let read_spectrum = histo::read_marginal(hist);
+    let similarity = histo::similarity(hist);
+    let mut run_params = vec![
+        ("k".to_string(), params.k.to_string()),
+        ("l".to_string(), params.l.to_string()),
+        ("density".to_string(), params.density.to_string()),
+        ("Jaccard index".to_string(), format!("{:.4}", similarity.jaccard)),
+        ("containment of reads in reference".to_string(), format!("{:.4}", similarity.reads_in_ref)),
+        ("containment of reference in reads".to_string(), format!("{:.4}", similarity.ref_in_reads)),
+    ];
+    if let Some(m) = spectrum::fit(&read_spectrum, params) {
+        run_params.push(("estimated genome size".to_string(), format!("{:.0} bp", m.genome_size)));
+        run_params.push(("estimated heterozygosity".to_string(), format!("{:.4}%", m.heterozygosity * 100.0)));
+        run_params.push(("estimated repeat fraction".to_string(), format!("{:.2}%", m.repeat_fraction * 100.0)));
+    }
+    // rows past the last nonzero one carry no information
+    let nb_rows = hist.iter().rposition(|row| row.iter().any(|c| *c > 0)).map_or(2, |i| i + 1).max(2).min(hist.len());
+    let data = json!({
+        "params": run_params,
+        "hist": &hist[..nb_rows],
+        "reads": &read_spectrum[..nb_rows],
+        "ref": histo::ref_marginal(hist),
+    });
+
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".report.html");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    // the data is JSON, which is valid JavaScript; "</" is escaped so that it can't close the script element
+    let html = TEMPLATE.replace("__DATA__", &data.to_string().replace("</", "<\\/"));
+    file.write_all(html.as_bytes()).output(&path)?;
+    file.flush().output(&path)
+}
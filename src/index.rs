@@ -1,77 +1,484 @@
 // index.rs
-// Contains the "Index" and "Entry" structs, which describe how reference k-min-mers are stored. 
+// Contains the "Index" and "Entry" structs, which describe how reference k-min-mers are stored.
 
 use crate::Kminmer;
+use crate::bloom::Prefilter;
+use crate::compact::CompactTable;
+use crate::disk::DiskTable;
+use crate::hashfilter::HashFilter;
+use crate::sketch::CountMinSketch;
 use dashmap::DashMap;
 use std::sync::Arc;
 use std::hash::BuildHasherDefault;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, AtomicU16, AtomicU32, AtomicU64, Ordering};
 use fxhash::FxHasher64;
 
+type FxBuildHasher = BuildHasherDefault<FxHasher64>;
 
-// An Entry object holds information for a reference k-min-mer without storing the minimizer hashes themselves.
+// A counter cell of a given width. Increments saturate at MAX instead of wrapping around.
+pub trait Counter: Send + Sync + std::fmt::Debug {
+    const MAX: u64;
+
+    // Create a counter holding v (clamped to MAX).
+    fn new(v: u64) -> Self;
+
+    // Current value of the counter.
+    fn get(&self) -> u64;
+
+    // Increment the counter by one. Returns false if the counter was already saturated.
+    fn saturating_increment(&self) -> bool;
+
+    // Add n to the counter, saturating at MAX. Returns the part of n that didn't fit.
+    fn saturating_add(&self, n: u64) -> u64;
+
+    // Increment the counter by one unless it already holds cap (at most MAX). Returns whether it was incremented.
+    fn increment_below(&self, cap: u64) -> bool;
+}
+
+macro_rules! impl_counter {
+    ($atomic:ty, $int:ty) => {
+        impl Counter for $atomic {
+            const MAX: u64 = <$int>::MAX as u64;
+            fn new(v: u64) -> Self {
+                <$atomic>::new(v.min(Self::MAX) as $int)
+            }
+            fn get(&self) -> u64 {
+                self.load(Ordering::Relaxed) as u64
+            }
+            fn saturating_increment(&self) -> bool {
+                self.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| v.checked_add(1)).is_ok()
+            }
+            fn saturating_add(&self, n: u64) -> u64 {
+                let prev = self.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some((v as u64).saturating_add(n).min(Self::MAX) as $int)).unwrap() as u64;
+                prev.saturating_add(n).saturating_sub(Self::MAX)
+            }
+            fn increment_below(&self, cap: u64) -> bool {
+                self.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| if (v as u64) < cap {Some(v + 1)} else {None}).is_ok()
+            }
+        }
+    };
+}
+impl_counter!(AtomicU8, u8);
+impl_counter!(AtomicU16, u16);
+impl_counter!(AtomicU32, u32);
+impl_counter!(AtomicU64, u64);
+
+// Width of the counters stored in an Index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CounterWidth {
+    U8,
+    U16,
+    U32,
+    U64,
+}
+impl CounterWidth {
+
+    // Largest value a counter of this width can hold before spilling into the overflow table.
+    pub fn max(&self) -> u64 {
+        match self {
+            CounterWidth::U8 => <AtomicU8 as Counter>::MAX,
+            CounterWidth::U16 => <AtomicU16 as Counter>::MAX,
+            CounterWidth::U32 => <AtomicU32 as Counter>::MAX,
+            CounterWidth::U64 => <AtomicU64 as Counter>::MAX,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            CounterWidth::U8 => 8,
+            CounterWidth::U16 => 16,
+            CounterWidth::U32 => 32,
+            CounterWidth::U64 => 64,
+        }
+    }
+}
+impl FromStr for CounterWidth {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(CounterWidth::U8),
+            "16" => Ok(CounterWidth::U16),
+            "32" => Ok(CounterWidth::U32),
+            "64" => Ok(CounterWidth::U64),
+            _ => Err(format!("invalid counter width '{}' (expected 8, 16, 32 or 64)", s)),
+        }
+    }
+}
+
+// Hash table implementation of an Index: DashMap, the more compact table of compact.rs, or a database in a
+// directory (disk.rs).
 #[derive(Clone, Debug, PartialEq)]
-pub struct Entry {
+pub enum IndexBackend {
+    HashMap,
+    Compact,
+    Disk(PathBuf),
+}
+impl FromStr for IndexBackend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hashmap" => Ok(IndexBackend::HashMap),
+            "compact" => Ok(IndexBackend::Compact),
+            "disk" => Ok(IndexBackend::Disk(PathBuf::new())), // directory set from --index-dir
+            _ => Err(format!("invalid index backend '{}' (expected hashmap, compact or disk)", s)),
+        }
+    }
+}
+
+// An Entry object holds information for a reference k-min-mer without storing the minimizer hashes themselves.
+// The counter is atomic so that existing entries can be incremented under a shard read lock.
+#[derive(Debug)]
+pub struct Entry<C: Counter = AtomicU64> {
     //pub id: String, // Reference ID
-    pub counter: u64,
+    pub counter: C,
 }
-impl Entry {
+impl<C: Counter> Entry<C> {
 
     // Create a new Entry.
     pub fn new(counter: u64) -> Self {
-        Entry {counter: counter}
+        Entry {counter: C::new(counter)}
     }
 
     // An empty Entry.
     pub fn empty() -> Self {
-        Entry {counter: 0}
+        Entry::new(0)
     }
 
     // Check if this Entry is Empty.
     pub fn is_empty(&self) -> bool {
-        self.counter == 0
+        self.count() == 0
+    }
+
+    // Current value of the counter.
+    pub fn count(&self) -> u64 {
+        self.counter.get()
+    }
+
+    // Increment the counter by one. Returns false if the counter is saturated.
+    pub fn increment(&self) -> bool {
+        self.counter.saturating_increment()
+    }
+
+    // Add n to the counter. Returns the part of n beyond the counter's capacity.
+    pub fn increment_by(&self, n: u64) -> u64 {
+        self.counter.saturating_add(n)
+    }
+
+    // Increment the counter by one unless it already holds cap (at most the counter's capacity). Returns whether
+    // it was incremented.
+    pub fn increment_capped(&self, cap: u64) -> bool {
+        self.counter.increment_below(cap)
     }
 }
+impl<C: Counter> Clone for Entry<C> {
+    fn clone(&self) -> Self {
+        Entry::new(self.count())
+    }
+}
+impl<C: Counter> PartialEq for Entry<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.count() == other.count()
+    }
+}
+
+// k-min-mer hash stored unaligned, so that narrow counters aren't padded up to 8 bytes in the hash table.
+#[repr(C, packed)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key(u64);
+
+// Hash tables for each supported backend and counter width.
+enum Table {
+    U8(DashMap<Key, Entry<AtomicU8>, FxBuildHasher>),
+    U16(DashMap<Key, Entry<AtomicU16>, FxBuildHasher>),
+    U32(DashMap<Key, Entry<AtomicU32>, FxBuildHasher>),
+    U64(DashMap<Key, Entry<AtomicU64>, FxBuildHasher>),
+    CompactU8(CompactTable<AtomicU8>),
+    CompactU16(CompactTable<AtomicU16>),
+    CompactU32(CompactTable<AtomicU32>),
+    CompactU64(CompactTable<AtomicU64>),
+    Disk(DiskTable),
+}
+
+// Approximate heap size in bytes of a hash table: the allocated buckets of all shards, each holding an entry
+// and a control byte.
+fn table_bytes<V>(t: &DashMap<Key, V, FxBuildHasher>) -> usize {
+    let capacity: usize = t.shards().iter().map(|shard| shard.read().capacity()).sum();
+    capacity * (std::mem::size_of::<(Key, V)>() + 1)
+}
+
+// Run $body with $t bound to the hash table, whatever its counter width: the first body for DashMap
+// tables, the second one for compact and disk tables, which have the same methods.
+macro_rules! with_table {
+    ($self:expr, $t:ident => $body:expr, $c:ident => $compact:expr) => {
+        match &*$self.table {
+            Table::U8($t) => $body,
+            Table::U16($t) => $body,
+            Table::U32($t) => $body,
+            Table::U64($t) => $body,
+            Table::CompactU8($c) => $compact,
+            Table::CompactU16($c) => $compact,
+            Table::CompactU32($c) => $compact,
+            Table::CompactU64($c) => $compact,
+            Table::Disk($c) => $compact,
+        }
+    };
+}
 
 // An Index object is a mapping of k-min-mer hashes (see kminmer.rs) to a single Entry (multiple Entries are not allowed).
+// Counters saturate at the maximum value of their width; increments beyond that go to a small overflow side-table.
+// A sketched Index counts into a count-min sketch instead of the table: it can be incremented and looked up
+// (approximately), but not iterated, see sketch.rs. A prefiltered Index only counts the k-min-mers its Bloom
+// filters let through, see bloom.rs.
 pub struct Index {
-    pub index: Arc<DashMap<u64, Entry, BuildHasherDefault<FxHasher64>>>
+    table: Arc<Table>,
+    overflow: Arc<DashMap<u64, u64, FxBuildHasher>>,
+    sketch: Option<Arc<CountMinSketch>>,
+    prefilter: Option<Arc<Prefilter>>,
+    filter: Option<Arc<HashFilter>>,
+    pub width: CounterWidth,
+    pub backend: IndexBackend,
 }
 impl Index {
 
-    // Create a new Index.
+    // Create a new Index with 64-bit counters.
     pub fn new() -> Self {
-        let hasher = BuildHasherDefault::<FxHasher64>::default();
-        Index {index: Arc::new(DashMap::with_hasher(hasher))}
+        Index::with_width(CounterWidth::U64)
     }
 
+    // Create a new Index with counters of the given width.
+    pub fn with_width(width: CounterWidth) -> Self {
+        Index::with_backend(width, &IndexBackend::HashMap)
+    }
 
-    // Return the Entry associated with the k-min-mer hash h, or None if none.
-    pub fn get(&self, h: &u64) -> Option<Entry> {
-        let e = self.index.get(h);
-        if let Some(r) = e {
-            if !r.is_empty() {
-                return Some(r.clone());
-            }
+    // Create a new Index with counters of the given width, stored in a table of the given backend.
+    // Disk tables always have 64-bit counters.
+    pub fn with_backend(width: CounterWidth, backend: &IndexBackend) -> Self {
+        let width = if let IndexBackend::Disk(_) = backend {CounterWidth::U64} else {width};
+        let table = match (backend, width) {
+            (IndexBackend::HashMap, CounterWidth::U8) => Table::U8(DashMap::with_hasher(FxBuildHasher::default())),
+            (IndexBackend::HashMap, CounterWidth::U16) => Table::U16(DashMap::with_hasher(FxBuildHasher::default())),
+            (IndexBackend::HashMap, CounterWidth::U32) => Table::U32(DashMap::with_hasher(FxBuildHasher::default())),
+            (IndexBackend::HashMap, CounterWidth::U64) => Table::U64(DashMap::with_hasher(FxBuildHasher::default())),
+            (IndexBackend::Compact, CounterWidth::U8) => Table::CompactU8(CompactTable::new()),
+            (IndexBackend::Compact, CounterWidth::U16) => Table::CompactU16(CompactTable::new()),
+            (IndexBackend::Compact, CounterWidth::U32) => Table::CompactU32(CompactTable::new()),
+            (IndexBackend::Compact, CounterWidth::U64) => Table::CompactU64(CompactTable::new()),
+            (IndexBackend::Disk(dir), _) => Table::Disk(DiskTable::new(dir)),
+        };
+        Index {table: Arc::new(table), overflow: Arc::new(DashMap::with_hasher(FxBuildHasher::default())), sketch: None, prefilter: None, filter: None, width, backend: backend.clone()}
+    }
+
+    // Create a new empty Index with the counter width and backend of this one.
+    pub fn empty_like(&self) -> Self {
+        Index::with_backend(self.width, &self.backend)
+    }
+
+    // Create a sketched Index, with a count-min sketch of depth rows of width counters.
+    pub fn with_sketch(width: usize, depth: usize) -> Self {
+        Index {sketch: Some(Arc::new(CountMinSketch::new(width, depth))), ..Index::new()}
+    }
+
+    // The count-min sketch of a sketched Index.
+    pub fn sketch(&self) -> Option<&CountMinSketch> {
+        self.sketch.as_deref()
+    }
+
+    // Create a prefiltered Index, with Bloom filters taking bytes of memory.
+    pub fn with_prefilter(width: CounterWidth, backend: &IndexBackend, bytes: usize) -> Self {
+        Index {prefilter: Some(Arc::new(Prefilter::new(bytes))), ..Index::with_backend(width, backend)}
+    }
+
+    // The Bloom filters of a prefiltered Index.
+    pub fn prefilter(&self) -> Option<&Prefilter> {
+        self.prefilter.as_deref()
+    }
+
+    // This Index, only counting the k-min-mers admitted by filter if given (--include-hashes, --exclude-hashes).
+    pub fn with_filter(self, filter: Option<Arc<HashFilter>>) -> Self {
+        Index {filter, ..self}
+    }
+
+    // Whether increments of the hash h are left out by the hash filter.
+    fn filtered(&self, h: u64) -> bool {
+        self.filter.as_ref().map_or(false, |f| !f.admit(h))
+    }
+
+    // Full count for the hash h given the value c stored in its Entry.
+    fn total(&self, h: u64, c: u64) -> u64 {
+        if c < self.width.max() {return c;}
+        c + self.overflow.get(&h).map_or(0, |r| *r)
+    }
+
+    // Return the count associated with the k-min-mer hash h, or None if none.
+    pub fn get(&self, h: &u64) -> Option<u64> {
+        if let Some(sketch) = &self.sketch {
+            return Some(sketch.estimate(*h)).filter(|&c| c > 0);
+        }
+        let c = with_table!(self, t => t.get(&Key(*h)).map(|r| r.count()), t => t.get(*h));
+        match c {
+            Some(c) if c > 0 => Some(self.total(*h, c)),
+            _ => None,
         }
-        None
     }
 
     // Add an Entry to the Index. If an Entry for the hash h already exists, insert None to prevent duplicates.
     pub fn add(&self, h: u64, counter: u64) {
-        let e = self.index.insert(h, Entry::new(counter));
-        if e.is_some() {self.index.insert(h, Entry::empty());}
+        let existed = with_table!(self, t => t.insert(Key(h), Entry::new(counter)).is_some(), t => t.set(h, counter));
+        self.overflow.remove(&h);
+        if existed {
+            with_table!(self, t => {t.insert(Key(h), Entry::empty());}, t => {t.set(h, 0);});
+        }
+        else if counter > self.width.max() {
+            self.overflow.insert(h, counter - self.width.max());
+        }
     }
 
+    // Increment the counter for the hash h. Existing keys only take a read lock on their shard;
+    // the write lock is only taken when a new key has to be inserted.
     pub fn increment(&self, h: u64) {
-        let e_mut = self.index.get_mut(&h);
-        if let Some(mut r) = e_mut
-        {
-            r.counter += 1;
+        if self.filtered(h) {return;}
+        if let Some(sketch) = &self.sketch {
+            return sketch.increment_by(h, 1);
+        }
+        if self.prefilter.as_ref().map_or(false, |p| !p.admit(h, 1)) {return;}
+        let incremented = with_table!(self, t => {
+            // the read guard must be released before taking the entry (write) lock
+            let existing = t.get(&Key(h)).map(|r| r.increment());
+            match existing {
+                Some(ok) => ok,
+                None => t.entry(Key(h)).or_insert_with(Entry::empty).increment(),
+            }
+        }, t => t.increment_by(h, 1) == 0);
+        if !incremented {
+            *self.overflow.entry(h).or_insert(0) += 1;
+        }
+    }
+
+    // Add n to the counter for the hash h, like n calls to increment.
+    pub fn increment_by(&self, h: u64, n: u64) {
+        if n == 0 || self.filtered(h) {return;}
+        if let Some(sketch) = &self.sketch {
+            return sketch.increment_by(h, n);
+        }
+        if self.prefilter.as_ref().map_or(false, |p| !p.admit(h, n)) {return;}
+        let excess = with_table!(self, t => {
+            let existing = t.get(&Key(h)).map(|r| r.increment_by(n));
+            match existing {
+                Some(excess) => excess,
+                None => t.entry(Key(h)).or_insert_with(Entry::empty).increment_by(n),
+            }
+        }, t => t.increment_by(h, n));
+        if excess > 0 {
+            *self.overflow.entry(h).or_insert(0) += excess;
         }
-        else
-        {
-            self.index.insert(h, Entry::new(1));
+    }
+
+    // Increment the counter for the hash h unless it already reached cap, so that k-min-mers repeated more
+    // than cap times stay at cap (--ref-cap). A cap below the counter width also keeps the overflow table empty.
+    // The cap is checked atomically with the increment, so concurrent increments never go past it.
+    pub fn increment_capped(&self, h: u64, cap: u64) {
+        if self.filtered(h) {return;}
+        if let Some(sketch) = &self.sketch {
+            return sketch.increment_capped(h, cap);
+        }
+        if self.prefilter.as_ref().map_or(false, |p| !p.admit(h, 1)) {return;}
+        let max = self.width.max();
+        let incremented = with_table!(self, t => {
+            let existing = t.get(&Key(h)).map(|r| r.increment_capped(cap.min(max)));
+            match existing {
+                Some(ok) => ok,
+                None => t.entry(Key(h)).or_insert_with(Entry::empty).increment_capped(cap.min(max)),
+            }
+        }, t => t.increment_capped(h, cap.min(max)));
+        if !incremented && cap > max {
+            // full counter: the excess goes to the overflow table, capped under the lock of its entry
+            let mut excess = self.overflow.entry(h).or_insert(0);
+            if *excess < cap - max {*excess += 1;}
+        }
+    }
+
+    // Add the counts of other to this Index, key-wise. Both may have different counter widths.
+    // This combines Indexes of shards of a read set counted separately, e.g. on different nodes.
+    pub fn merge(&self, other: &Index) {
+        for (h, count) in other.iter() {
+            self.increment_by(h, count);
+        }
+    }
+
+    // Number of distinct k-min-mers in the Index.
+    pub fn len(&self) -> usize {
+        with_table!(self, t => t.len(), t => t.len())
+    }
+
+    // Iterate over (hash, count) pairs.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (u64, u64)> + '_> {
+        with_table!(self, t => Box::new(t.iter().map(move |r| {
+            let h = r.key().0;
+            (h, self.total(h, r.value().count()))
+        })), t => Box::new(t.iter().map(move |(h, c)| (h, self.total(h, c)))))
+    }
+
+    // Iterate over (hash, count) pairs, in increasing hash order if ordered (the table order depends on thread timing).
+    pub fn iter_ordered(&self, ordered: bool) -> Box<dyn Iterator<Item = (u64, u64)> + '_> {
+        if !ordered {return self.iter();}
+        let mut entries: Vec<(u64, u64)> = self.iter().collect();
+        entries.sort_unstable();
+        Box::new(entries.into_iter())
+    }
+
+    // Number of shards of the hash table; shards hold disjoint entries and can be processed in parallel.
+    pub fn nb_shards(&self) -> usize {
+        with_table!(self, t => t.shards().len(), t => t.nb_shards())
+    }
+
+    // Approximate memory used by the Index, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        with_table!(self, t => table_bytes(t), t => t.memory_usage()) + self.overflow.len() * 2 * std::mem::size_of::<u64>()
+    }
+
+    // Remove the entries of one shard and return their (hash, count) pairs. The other shards can be updated
+    // meanwhile, so that an Index being filled can be emptied a shard at a time.
+    pub fn drain_shard(&self, shard: usize) -> Vec<(u64, u64)> {
+        let entries: Vec<(u64, u64)> = with_table!(self, t => {
+            let entries = std::mem::take(&mut *t.shards()[shard].write());
+            entries.into_iter().map(|(key, value)| (key.0, value.into_inner().count())).collect()
+        }, t => t.drain_shard(shard));
+        entries.into_iter().map(|(h, c)| {
+            let excess = if c < self.width.max() {0} else {self.overflow.remove(&h).map_or(0, |(_, v)| v)};
+            (h, c + excess)
+        }).collect()
+    }
+
+    // Remove all entries and return their (hash, count) pairs. Overflow counts whose entry was drained while
+    // being incremented are returned as separate pairs, so a hash may occur twice.
+    pub fn drain(&self) -> Vec<(u64, u64)> {
+        let mut res: Vec<(u64, u64)> = (0..self.nb_shards()).flat_map(|shard| self.drain_shard(shard)).collect();
+        let leftover: Vec<u64> = self.overflow.iter().map(|r| *r.key()).collect();
+        res.extend(leftover.into_iter().filter_map(|h| self.overflow.remove(&h)));
+        res
+    }
+
+    // Keep only the entries for which f(hash, count) is true. A drained shard only gets back its own hashes, so
+    // each entry is looked at once.
+    pub fn retain(&self, mut f: impl FnMut(u64, u64) -> bool) {
+        for shard in 0..self.nb_shards() {
+            for (h, c) in self.drain_shard(shard) {
+                if f(h, c) {self.add(h, c);}
+            }
         }
     }
 
+    // Call f on the (hash, count) pairs of one shard, under its read lock.
+    pub fn for_each_in_shard(&self, shard: usize, mut f: impl FnMut(u64, u64)) {
+        with_table!(self, t => {
+            let guard = t.shards()[shard].read();
+            for (key, value) in guard.iter() {
+                let h = key.0;
+                f(h, self.total(h, value.get().count()));
+            }
+        }, t => t.for_each_in_shard(shard, |h, c| f(h, self.total(h, c))))
+    }
+
 }
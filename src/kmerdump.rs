@@ -0,0 +1,70 @@
+// kmerdump.rs
+// Import of exact k-mer counts from text dumps of Jellyfish, KMC or meryl databases, to fill the reference
+// Index without re-counting. A k-min-mer made of a single minimizer (k=1) is a selected l-mer, so a
+// database of l-mers determines the count of each such k-min-mer; other parameters can't be derived from k-mers.
+//
+// Accepted formats (optionally compressed):
+//   "KMER<whitespace>count" lines: jellyfish dump -c, kmc_tools transform db dump, meryl print
+//   FASTA-like ">count" / "KMER" pairs: jellyfish dump (default output)
+
+use std::io::BufRead;
+use std::path::PathBuf;
+use rust_seq2kminmers::KminmersIterator;
+use crate::error::{Context, Error};
+use crate::get_reader;
+use crate::index::Index;
+use super::Params;
+
+// Check that k-min-mers of the given parameters can be obtained from a database of kmer_len-mers.
+fn check_params(kmer_len: usize, params: &Params, path: &PathBuf) -> Result<(), Error> {
+    if params.k != 1 || kmer_len != params.l {
+        return Err(Error::Usage(format!("k-mer counts from {} ({}-mers) can only be imported with k=1 and l={} (got k={} l={}).",
+            path.to_str().unwrap(), kmer_len, kmer_len, params.k, params.l)));
+    }
+    Ok(())
+}
+
+// Add the count of a k-mer to the Index, if it is a selected minimizer.
+fn add_kmer(kmer: &[u8], count: u64, params: &Params, index: &Index) -> bool {
+    match KminmersIterator::new(kmer, params.l, 1, params.density, false).unwrap().next() {
+        Some(kminmer) => {index.increment_by(kminmer.get_hash_u64(), params.ref_cap.map_or(count, |cap| count.min(cap))); true}
+        None => false,
+    }
+}
+
+// Load a k-mer count dump into index. Returns the number of k-mers read and of those kept as k-min-mers.
+pub fn import(path: &PathBuf, params: &Params, index: &Index) -> Result<(u64, u64), Error> {
+    let reader = get_reader(path)?;
+    let (mut nb_kmers, mut nb_kept) = (0u64, 0u64);
+    let mut fasta_count: Option<u64> = None; // count from the last ">count" header
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.corrupt(path)?;
+        let line = line.trim();
+        if line.is_empty() {continue;}
+        let bad_line = || Error::corrupt(path, format!("line {}: expected a k-mer and its count, got {:?}", i + 1, line));
+
+        let (kmer, count) = if let Some(header) = line.strip_prefix('>') {
+            fasta_count = Some(header.trim().parse().map_err(|_| bad_line())?);
+            continue;
+        }
+        else if let Some(count) = fasta_count.take() {
+            (line, count)
+        }
+        else {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next().and_then(|c| c.parse().ok())) {
+                (Some(kmer), Some(count)) => (kmer, count),
+                _ => return Err(bad_line()),
+            }
+        };
+        if nb_kmers == 0 {
+            check_params(kmer.len(), params, path)?;
+        }
+        else if kmer.len() != params.l {
+            return Err(bad_line());
+        }
+        nb_kmers += 1;
+        if add_kmer(kmer.as_bytes(), count, params, index) {nb_kept += 1;}
+    }
+    Ok((nb_kmers, nb_kept))
+}
@@ -0,0 +1,1800 @@
+// 2D histograms using kminmers v0.1.0
+// Copyright 2020-2021 Baris Ekim, Rayan Chikhi.
+// Licensed under the MIT license (http://opensource.org/licenses/MIT).
+// This file may not be copied, modified, or distributed except according to those terms.
+
+#![allow(unused_variables)]
+#![allow(non_upper_case_globals)]
+#![allow(warnings)]
+use indicatif::ProgressBar;
+use std::io::stderr;
+use std::io::{Write, BufWriter};
+use std::io::{BufRead, BufReader};
+use std::collections::HashMap;
+use std::fs::{File};
+use std::fs;
+use clap::{ArgAction, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::sync::{Arc, Mutex};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::mem::{MaybeUninit};
+use seq_io::BaseRecord;
+#[cfg(not(target_arch = "wasm32"))]
+use lzzzz::lz4f::{WriteCompressor, BufReadDecompressor, Preferences};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use dashmap::DashMap;
+use std::cell::UnsafeCell;
+use core::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use crate::index::{CounterWidth, Entry, Index, IndexBackend};
+use crate::error::{Context, Error};
+use crate::summary::Summary;
+use crate::positional::RefPositions;
+use crate::seeds::SyncmerKind;
+use crate::mask::MaskPolicy;
+use crate::snapshot::SnapshotEvery;
+use crate::histo::{AxisOrder, Normalize};
+use rust_seq2kminmers::Kminmer;
+use log::{debug, info, warn, LevelFilter};
+mod index;
+mod aligned;
+mod bgzf;
+mod bloom;
+mod checkpoint;
+mod closures;
+mod cell;
+mod cnbin;
+mod compact;
+mod config;
+mod columnar;
+mod contaminants;
+mod dedup;
+mod disk;
+mod dump;
+mod error;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod estimate;
+mod gc;
+mod gfa;
+mod h5;
+mod hashfilter;
+mod html;
+mod kmerdump;
+mod missing;
+mod nbfit;
+mod mmap;
+mod histo;
+mod histfile;
+mod mask;
+mod mers;
+mod pipe;
+mod ploidy;
+mod positional;
+mod provenance;
+#[cfg(feature = "python")]
+mod python;
+mod objstore;
+mod quality;
+mod qv;
+mod refindex;
+mod regions;
+mod seeds;
+mod readids;
+mod remote;
+mod serve;
+mod report;
+mod signals;
+mod snapshot;
+mod simulate;
+mod sketch;
+mod smudge;
+mod spill;
+mod sqlite;
+mod spectrum;
+mod summary;
+mod sweep;
+mod trio;
+mod top;
+mod tune;
+mod unmapped;
+mod watch;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+type ThreadIdType = usize;
+#[derive(Clone)]
+pub struct Params {
+    k: usize,
+    l: usize,
+    density: f64,
+    counter_width: CounterWidth,
+    ref_cap: Option<u64>,
+    reads_only: bool, // no reference: only the read k-min-mer spectrum
+    index_backend: IndexBackend,
+    spectra_cn: bool,
+    solid_threshold: Option<u64>,
+    dump_kminmers: Option<PathBuf>,
+    checkpoint_every: Option<u64>,
+    snapshot_every: Option<SnapshotEvery>,
+    resume: bool,
+    positional_bin: Option<usize>,
+    breadth_report: bool,
+    gc_histo: bool,
+    per_read_report: bool,
+    report_missing: bool,
+    report_novel: Option<u64>,
+    novel_read_ids: bool,
+    top_kminmers: Option<usize>,
+    top_read_ids: bool,
+    inspect_cell: Option<(usize, usize)>,
+    cell_read_ids: bool,
+    track_read_ids: Option<usize>,
+    contaminants: Option<PathBuf>,
+    classify_contaminant_reads: bool,
+    unmapped_reads: Option<PathBuf>,
+    unmapped_max_fraction: f64,
+    sparse: bool,
+    axis_order: AxisOrder,
+    annotated: bool,
+    cumulative: bool,
+    normalize: Option<Normalize>,
+    nb_mixture: Option<usize>,
+    estimate_ploidy: bool,
+    parquet: bool,
+    hdf5: bool,
+    sqlite: Option<PathBuf>,
+    html_report: bool,
+    distinct_per_read: bool,
+    quality_weights: bool,
+    trim5: usize,
+    trim3: usize,
+    trim_quality: Option<u8>,
+    min_entropy: Option<f64>,
+    mask_policy: Option<MaskPolicy>,
+    max_ref_count_filter: Option<u64>,
+    hash_filter: Option<Arc<hashfilter::HashFilter>>,
+    restrict_to_reference: bool,
+    regions: Option<Arc<regions::Regions>>,
+    exclude_regions: Option<Arc<regions::Regions>>,
+    respect_softmask: bool,
+    gfa_edges: bool,
+    mates: Option<PathBuf>,
+    interleaved: bool,
+    dedup_pairs: bool,
+    dedup_reads: bool,
+    window: Option<usize>,
+    syncmers: Option<SyncmerKind>,
+    syncmer_s: usize,
+    strobemers: Option<(usize, usize)>, // wmin, wmax
+    chunk_size: usize, // bytes of sequence file per chunk of records
+    max_memory: Option<usize>, // bytes
+    sketch: Option<(usize, usize)>, // width, depth
+    prefilter: Option<usize>, // bytes of the Bloom filters
+    deterministic: bool, // outputs independent of thread timing
+    threads: usize, // resolved --threads, also used to compute the histograms
+}
+
+impl Params {
+
+    // Parameters with the default options, for outputs derived from a histogram read back from a file.
+    pub fn new(k: usize, l: usize, density: f64) -> Self {
+        Params {
+            k, l, density,
+            counter_width: CounterWidth::U64,
+            ref_cap: None,
+            reads_only: false,
+            index_backend: IndexBackend::HashMap,
+            spectra_cn: false,
+            solid_threshold: None,
+            dump_kminmers: None,
+            checkpoint_every: None,
+            snapshot_every: None,
+            resume: false,
+            positional_bin: None,
+            breadth_report: false,
+            gc_histo: false,
+            per_read_report: false,
+            report_missing: false,
+            report_novel: None,
+            novel_read_ids: false,
+            top_kminmers: None,
+            top_read_ids: false,
+            inspect_cell: None,
+            cell_read_ids: false,
+            track_read_ids: None,
+            contaminants: None,
+            classify_contaminant_reads: false,
+            unmapped_reads: None,
+            unmapped_max_fraction: 0.1,
+            sparse: false,
+            axis_order: AxisOrder::ReadMajor,
+            annotated: false,
+            cumulative: false,
+            normalize: None,
+            nb_mixture: None,
+            estimate_ploidy: false,
+            parquet: false,
+            hdf5: false,
+            sqlite: None,
+            html_report: false,
+            distinct_per_read: false,
+            quality_weights: false,
+            trim5: 0,
+            trim3: 0,
+            trim_quality: None,
+            min_entropy: None,
+            mask_policy: None,
+            max_ref_count_filter: None,
+            hash_filter: None,
+            restrict_to_reference: false,
+            regions: None,
+            exclude_regions: None,
+            respect_softmask: false,
+            gfa_edges: false,
+            mates: None,
+            interleaved: false,
+            dedup_pairs: false,
+            dedup_reads: false,
+            window: None,
+            syncmers: None,
+            syncmer_s: 11,
+            strobemers: None,
+            chunk_size: 64 * 1024,
+            max_memory: None,
+            sketch: None,
+            prefilter: None,
+            deterministic: false,
+            threads: available_threads(),
+        }
+    }
+
+    // Parameters for indexing a reference other than the primary one (--reference2, contaminants): the BED regions
+    // and softmasking, which describe the primary reference, and its count cap don't apply to it.
+    pub fn for_other_reference(&self) -> Params {
+        Params {regions: None, exclude_regions: None, respect_softmask: false, ref_cap: None, ..self.clone()}
+    }
+}
+
+// Number of CPUs this process may use: std takes the affinity mask and cgroup CPU quotas into account.
+fn available_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Parse a size in bytes, with an optional K, M or G (binary) suffix, e.g. 64K.
+fn parse_size(s: &str) -> Result<usize, String> {
+    let (digits, shift) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 10),
+        Some('M') => (&s[..s.len() - 1], 20),
+        Some('G') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let value: usize = digits.parse().map_err(|_| format!("invalid size '{}' (expected e.g. 65536, 64K or 1M)", s))?;
+    value.checked_mul(1 << shift).ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+// Parse SAM flags, in decimal or hexadecimal (0x prefix), as samtools does, e.g. 0x900.
+fn parse_flags(s: &str) -> Result<u16, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }.map_err(|_| format!("invalid SAM flags '{}' (expected e.g. 4, 2304 or 0x900)", s))
+}
+
+// Parse a cell of the 2D histogram, as row,column (read abundance, reference abundance), e.g. 40,2.
+fn parse_cell(s: &str) -> Result<(usize, usize), String> {
+    let cell = s.split_once(',').and_then(|(i, j)| Some((i.trim().parse().ok()?, j.trim().parse().ok()?)));
+    match cell {
+        Some((i, j)) if i < histo::NB_READ_BINS && j < histo::NB_REF_BINS => Ok((i, j)),
+        _ => Err(format!("invalid histogram cell '{}' (expected row,column with a row below {} and a column below {}, e.g. 40,2)", s, histo::NB_READ_BINS, histo::NB_REF_BINS)),
+    }
+}
+
+/// Try to get memory usage (resident set size) in bytes using the `getrusage()` function from libc.
+// from https://github.com/digama0/mm0/blob/bebd670c5a77a1400913ebddec2c6248e76f90fe/mm0-rs/src/util.rs
+#[cfg(not(target_arch = "wasm32"))]
+fn get_memory_rusage() -> usize {
+  let usage = unsafe {
+    let mut usage = MaybeUninit::uninit();
+    assert_eq!(libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()), 0);
+    usage.assume_init()
+  };
+  usage.ru_maxrss as usize * 1024
+}
+
+// No getrusage in WebAssembly: the memory usage is reported as 0.
+#[cfg(target_arch = "wasm32")]
+fn get_memory_rusage() -> usize {
+    0
+}
+
+// Magic bytes of gzip streams and LZ4 frames.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+// Open a local file or URL, decompressing gzip (including bgzip) and LZ4 files, which are told by their magic
+// bytes whatever their extension (e.g. .bgz).
+fn get_reader(path: &PathBuf) -> Result<Box<dyn BufRead + Send>, Error> {
+    let filename_str = path.to_str().unwrap();
+    let file: Box<dyn std::io::Read + Send> = if remote::is_url(filename_str) {
+        remote::open(filename_str).input(path)?
+    }
+    else {
+        Box::new(File::open(path).input(path)?)
+    };
+    let mut file = BufReader::new(file);
+    let head = file.fill_buf().input(path)?;
+    let (is_gzip, is_lz4) = (head.starts_with(&GZIP_MAGIC), head.starts_with(&LZ4_MAGIC) || filename_str.ends_with(".lz4"));
+    // compressed files are decompressed on separate threads, overlapping with parsing
+    let reader: Box<dyn BufRead + Send> = if is_gzip {
+        // bgzip output is decompressed in parallel; other gzip files may also have several members
+        if bgzf::is_bgzf(head) {
+            let threads = available_threads().min(4);
+            Box::new(pipe::PipeReader::new(bgzf::BgzfReader::new(file, threads)))
+        }
+        else {
+            Box::new(pipe::PipeReader::new(MultiGzDecoder::new(file)))
+        }
+    }
+    else if is_lz4 {
+        lz4_reader(Box::new(file), path)?
+    }
+    else {
+        Box::new(file)
+    };
+    Ok(reader)
+}
+
+// Fail early on missing local inputs, or BAM and CRAM inputs without the "htslib" feature; URLs are only checked
+// when they are opened.
+fn check_input(path: &PathBuf) -> Result<(), Error> {
+    if aligned::is_aligned(path) {aligned::check_available()?;}
+    if !remote::is_url(path.to_str().unwrap()) {
+        fs::metadata(path).input(path)?;
+    }
+    Ok(())
+}
+
+// Replace directories by the sequence files they contain (by extension, in name order).
+fn expand_references(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
+    let extensions = ["fa", "fasta", "fna", "ffn", "fas", "fq", "fastq", "gfa"];
+    let mut res = Vec::new();
+    for path in paths {
+        if remote::is_url(path.to_str().unwrap()) || !path.is_dir() {
+            res.push(path);
+            continue;
+        }
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&path).input(&path)? {
+            let file = entry.input(&path)?.path();
+            let name = file.file_name().unwrap().to_string_lossy().to_string();
+            let name = name.trim_end_matches(".gz").trim_end_matches(".bgz").trim_end_matches(".lz4");
+            if file.is_file() && extensions.iter().any(|e| name.ends_with(&format!(".{}", e))) {
+                files.push(file);
+            }
+        }
+        if files.is_empty() {
+            return Err(Error::Usage(format!("No FASTA/FASTQ/GFA files in reference directory {}.", path.to_str().unwrap())));
+        }
+        files.sort();
+        res.extend(files);
+    }
+    Ok(res)
+}
+
+// Parse a manifest: one "name<TAB>file" line per sample (or contaminant), '#' lines are comments.
+fn read_manifest(path: &PathBuf) -> Result<Vec<(String, PathBuf)>, Error> {
+    let content = fs::read_to_string(path).input(path)?;
+    let mut samples = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {continue;}
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 2 {return Err(Error::corrupt(path, format!("expected a name and a file, got: {}", line)));}
+        samples.push((fields[0].to_string(), PathBuf::from(fields[1])));
+    }
+    Ok(samples)
+}
+
+// LZ4 streams, by the lz4 C library, which isn't built for WebAssembly.
+#[cfg(not(target_arch = "wasm32"))]
+fn lz4_reader(file: Box<dyn std::io::Read + Send>, path: &PathBuf) -> Result<Box<dyn BufRead + Send>, Error> {
+    Ok(Box::new(pipe::PipeReader::new(BufReadDecompressor::new(BufReader::new(file)).map_err(|e| Error::corrupt(path, e))?)))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn lz4_writer(file: File, path: &PathBuf) -> Result<Box<dyn Write + Send>, Error> {
+    Ok(Box::new(WriteCompressor::new(BufWriter::new(file), Preferences::default()).map_err(|e| Error::Output {path: path.to_str().unwrap().to_string(), source: std::io::Error::new(std::io::ErrorKind::Other, e.to_string())})?))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn lz4_reader(_file: Box<dyn std::io::Read + Send>, path: &PathBuf) -> Result<Box<dyn BufRead + Send>, Error> {
+    Err(Error::Usage(format!("{}: LZ4 files aren't supported in WebAssembly builds.", path.to_str().unwrap())))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn lz4_writer(_file: File, path: &PathBuf) -> Result<Box<dyn Write + Send>, Error> {
+    Err(Error::Usage(format!("{}: LZ4 files aren't supported in WebAssembly builds.", path.to_str().unwrap())))
+}
+
+fn get_writer(path: &PathBuf) -> Result<Box<dyn Write + Send>, Error> {
+    let filename_str = path.to_str().unwrap();
+    let file = File::create(path).output(path)?;
+    let writer :Box<dyn Write + Send> = if filename_str.ends_with(".gz") {
+        Box::new(GzEncoder::new(BufWriter::new(file), Compression::default()))
+    } else if filename_str.ends_with(".lz4") {
+        lz4_writer(file, path)?
+    } else {
+        Box::new(BufWriter::new(file))
+    };
+    Ok(writer)
+}
+#[derive(Debug, Parser)]
+#[command(name = "kminmer2Dhisto")]
+/// Minimizer-space 2D histograms of k-min-mer abundances in reads vs a reference.
+struct Cli {
+    #[command(flatten)]
+    opt: Opt,
+    #[command(flatten)]
+    log: LogOpt,
+    #[command(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Index the reference and count reads against it (also the default without a subcommand)
+    Count {
+        #[command(flatten)]
+        opt: Opt,
+    },
+    /// Build the reference k-min-mer index and save it to a file
+    Index {
+        /// Index file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        #[command(flatten)]
+        opt: Opt,
+    },
+    /// Count reads against a reference index saved by `index`
+    Histo {
+        /// Index file written by `index`
+        ///
+        /// k, l and density are those the index
+        /// was built with.
+        #[arg(long)]
+        index: PathBuf,
+        #[command(flatten)]
+        opt: Opt,
+    },
+    /// Simulate a reference and reads, and count them
+    ///
+    /// Writes prefix.ref.fa and prefix.reads.fq, then
+    /// runs as count on them (prefix defaults to
+    /// "simulated").
+    Simulate {
+        #[command(flatten)]
+        sim: simulate::Simulation,
+        #[command(flatten)]
+        opt: Opt,
+    },
+    /// Answer k-min-mer queries over HTTP from a reference index saved by `index`
+    Serve {
+        /// Index file written by `index`
+        #[arg(long)]
+        index: PathBuf,
+        /// Address to listen on
+        ///
+        /// GET /info describes the index, POST /query
+        /// takes {"sequences": [...]} and returns the
+        /// reference counts of their k-min-mers.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+    },
+    /// Draw a 2D histogram as an HTML report
+    Plot {
+        /// .hist2D file (dense or --sparse)
+        input: PathBuf,
+        /// Output prefix
+        ///
+        /// Writes prefix.report.html, as with
+        /// --html-report.
+        #[arg(short, long)]
+        prefix: PathBuf,
+    },
+    /// Sum 2D histograms of runs over shards of a read set
+    Merge {
+        /// .hist2D files to sum (dense or --sparse)
+        ///
+        /// They must have the same dimensions, and
+        /// the same k, l and density.
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+        /// Merged .hist2D file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Write the merged histogram in sparse format
+        #[arg(long)]
+        sparse: bool,
+    },
+    /// Compare two 2D histograms
+    Diff {
+        /// First .hist2D file
+        first: PathBuf,
+        /// Second .hist2D file
+        second: PathBuf,
+        /// Output prefix
+        ///
+        /// Writes prefix.diff.hist2D (second minus
+        /// first, cell by cell) and prefix.diff.stats.
+        #[arg(short, long)]
+        prefix: PathBuf,
+        /// Write the difference matrix in sparse format
+        #[arg(long)]
+        sparse: bool,
+    },
+    /// Estimate genome statistics from a 2D histogram
+    Stats {
+        /// .hist2D file (dense or --sparse)
+        input: PathBuf,
+        /// Output prefix
+        ///
+        /// Writes prefix.stats: genome size,
+        /// heterozygosity and repeat fraction, from
+        /// the read spectrum.
+        #[arg(short, long)]
+        prefix: PathBuf,
+    },
+    /// Print a shell completion script (bash, zsh or fish)
+    #[command(hide = true)]
+    Completions {
+        shell: clap_complete::Shell,
+    },
+}
+
+// What a run does: index the reference and count reads (count, or no subcommand), or only one of the two.
+enum Mode {
+    Full,
+    IndexOnly(PathBuf),
+    HistoOnly(PathBuf),
+}
+
+#[derive(Debug, Args)]
+struct Opt {
+    /// Input file (raw or gzip-/lz4-compressed FASTX)
+    ///
+    /// Input file can be FASTA/FASTQ, as well as gzip-compressed (.gz) or
+    /// lz4-compressed (.lz4). Lowercase and IUPAC bases are left to the
+    /// minimizer iterators unless --mask-policy is given; see documentation
+    /// for formatting.
+    reads: Option<PathBuf>,
+    /// Second read set (raw or gzip-/lz4-compressed FASTX)
+    ///
+    /// Counted against the same reference index. Writes
+    /// prefix.reads2.hist2D, and reads1 vs reads2 histograms
+    /// of k-min-mers present in / absent from the reference.
+    #[arg(long)]
+    reads2: Option<PathBuf>,
+    /// Second mates of paired-end reads
+    ///
+    /// File of the mates of the reads of the input file,
+    /// in the same order. Both mates are counted into
+    /// the same read index.
+    #[arg(long)]
+    mates: Option<PathBuf>,
+    /// Input file holds interleaved mate pairs
+    ///
+    /// Consecutive records are the two mates of a pair.
+    #[arg(long, conflicts_with = "mates")]
+    interleaved: bool,
+    /// Count k-min-mers at most once per read pair
+    ///
+    /// So that k-min-mers in the overlap of two mates
+    /// aren't counted twice. Needs --mates or --interleaved.
+    #[arg(long)]
+    dedup_pairs: bool,
+    /// Sample manifest for multi-sample mode
+    ///
+    /// Tab-separated file with one "sample_name reads_file"
+    /// line per sample. The reference is indexed once and
+    /// each sample is written to prefix.sample_name.hist2D.
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Count the read files appearing in this directory
+    ///
+    /// Watches it and its subdirectories for new FASTQ or
+    /// FASTA files, e.g. from MinKNOW during a run, and
+    /// rewrites the outputs as reads come in, until
+    /// interrupted (Ctrl-C) or --watch-idle.
+    #[arg(long)]
+    watch: Option<PathBuf>,
+    /// Minutes between output rewrites with --watch
+    ///
+    /// Outputs are only rewritten if new reads were counted
+    /// (default: 5).
+    #[arg(long)]
+    watch_interval: Option<f64>,
+    /// Stop --watch after this many minutes without a new file
+    #[arg(long)]
+    watch_idle: Option<f64>,
+    /// Run configuration file (TOML)
+    ///
+    /// Sets options by their long name (e.g. l = 31,
+    /// reference = ["ref.fa"], positional_histo = 1000)
+    /// and the input file (reads = "reads.fq"). Options
+    /// given on the command line override the file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Output prefix 
+    ///
+    #[arg(short, long)]
+    prefix: Option<PathBuf>,
+    /// k-min-mer length
+    ///
+    /// The length of each node of the mdBG. If
+    /// fewer l-mers than this value are obtained
+    /// from a read, they will be ignored. Several
+    /// comma-separated values (e.g. 3,5,8) are counted
+    /// in a single pass, with outputs in prefix.k<K>.*
+    #[arg(short, long, value_delimiter = ',')]
+    k: Vec<usize>,
+    /// l-mer (minimizer) length
+    ///
+    /// The length of each minimizer selected using
+    /// the minimizer scheme from base-space sequences.
+    #[arg(short, long)]
+    l: Option<usize>,
+    /// Density threshold for density-based selection scheme
+    /// 
+    /// The density threshold is analogous to the
+    /// fraction of l-mers that will be selected as
+    /// minimizers from a read.
+    #[arg(short, long)]
+    density: Option<f64>,
+    /// Choose the density for this many k-min-mers per kb
+    ///
+    /// Measures the k-min-mer yield on the first reads
+    /// (--tune-sample-mb) and adjusts the density to reach
+    /// it before the full run.
+    #[arg(long, conflicts_with = "density")]
+    target_kminmers_per_kb: Option<f64>,
+    /// Megabases of reads sampled by --target-kminmers-per-kb
+    #[arg(long, default_value = "10")]
+    tune_sample_mb: usize,
+    /// Window size of a (w,k) minimizer scheme
+    ///
+    /// Selects as minimizers the smallest l-mer of each
+    /// window of W consecutive l-mers, as in minimap2 or
+    /// mdBG tools using (w,k) minimizers, instead of the
+    /// density-based scheme. Needs l <= 32.
+    #[arg(long)]
+    window: Option<usize>,
+    /// Syncmers as seeds (open or closed)
+    ///
+    /// Selects l-mers whose smallest s-mer is in their
+    /// middle (open) or at either end (closed) instead of
+    /// density-based minimizers. Needs l <= 32.
+    #[arg(long, conflicts_with = "window")]
+    syncmers: Option<SyncmerKind>,
+    /// s-mer length of syncmers
+    ///
+    /// Smaller than l; l - s must be even for open
+    /// syncmers (default: 11).
+    #[arg(long)]
+    syncmer_s: Option<usize>,
+    /// Randstrobes instead of k-min-mers
+    ///
+    /// Seeds of k strobes, each next strobe picked among
+    /// the minimizers (or syncmers) --strobe-wmin to
+    /// --strobe-wmax positions further. Needs l <= 32.
+    #[arg(long)]
+    strobemers: bool,
+    /// Closest next strobe, in minimizers (default: 2)
+    #[arg(long)]
+    strobe_wmin: Option<usize>,
+    /// Farthest next strobe, in minimizers (default: 6)
+    #[arg(long)]
+    strobe_wmax: Option<usize>,
+    /// Reference genome input
+    ///
+    /// Reference to be indexed and mapped to. 
+    /// Allows multi-line FASTA and
+    /// doesn't filter any kminmers. Can be given
+    /// several times, or be a directory of FASTA/FASTQ
+    /// files, to index all of them together.
+    #[arg(long)]
+    reference: Vec<PathBuf>,
+    /// Reference k-mer counts
+    ///
+    /// Fills the reference index from a text dump of a
+    /// Jellyfish, KMC or meryl database of l-mers instead
+    /// of counting a reference. Needs k=1.
+    #[arg(long, conflicts_with = "reference")]
+    reference_counts: Option<PathBuf>,
+    /// Second reference, compared to the first one
+    ///
+    /// Writes prefix.ref-vs-ref2.hist2D, the matrix of
+    /// k-min-mer copy numbers in the reference (rows)
+    /// and in this one (columns), e.g. to compare two
+    /// assembly versions. Reads are optional then. Can
+    /// be given several times, or be a directory.
+    #[arg(long)]
+    reference2: Vec<PathBuf>,
+    /// Bin the reads by reference copy number
+    ///
+    /// Instead of the histograms, writes each read to
+    /// prefix.cn0, prefix.cn1, prefix.cn2 or prefix.repeat
+    /// (.fastq or .fasta, like the input), by the median
+    /// reference count of its k-min-mers, e.g. to isolate
+    /// repeat-derived or contaminant reads.
+    #[arg(long)]
+    bin_by_copy_number: bool,
+    /// Maternal reference, for trio binning
+    ///
+    /// With --paternal, replaces --reference: each read
+    /// is assigned to the parent whose specific k-min-mers
+    /// it contains most, in prefix.trio.tsv, and
+    /// prefix.trio.hist2D counts reads per number of
+    /// maternal (rows) and paternal (columns) ones. Can be
+    /// given several times, or be a directory.
+    #[arg(long)]
+    maternal: Vec<PathBuf>,
+    /// Paternal reference, for trio binning
+    #[arg(long)]
+    paternal: Vec<PathBuf>,
+    /// Pair heterozygous k-min-mers, as smudgeplot
+    ///
+    /// Instead of the histograms, pairs the read
+    /// k-min-mers whose minimizers differ at a single
+    /// position, and writes prefix.smudge.hist2D: pairs per
+    /// total coverage (rows) and minor allele fraction
+    /// (columns, from 0 to 0.5 by 0.01).
+    #[arg(long)]
+    smudge: bool,
+    /// First haplotype, for --smudge
+    ///
+    /// With --haplotype-b, pairs are made of a k-min-mer
+    /// specific to each haplotype instead, with their read
+    /// coverages. Can be given several times, or be a
+    /// directory.
+    #[arg(long)]
+    haplotype_a: Vec<PathBuf>,
+    /// Second haplotype, for --smudge
+    #[arg(long)]
+    haplotype_b: Vec<PathBuf>,
+    /// Number of threads
+    ///
+    /// 0 uses all available CPUs, which is also the
+    /// default. Within a container, only the CPUs of
+    /// its allocation (cgroup quota) are counted.
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Read chunks buffered for the worker threads
+    ///
+    /// Number of chunks of reads parsed ahead of the
+    /// worker threads (default: 200). Larger values help
+    /// on filesystems with high or irregular latency,
+    /// at the cost of memory (queue length x chunk size).
+    #[arg(long)]
+    queue_len: Option<usize>,
+    /// Reference chunks buffered for the worker threads
+    ///
+    /// Like --queue-len, for the reference (default: the
+    /// number of threads).
+    #[arg(long)]
+    ref_queue_len: Option<usize>,
+    /// Size of the chunks of sequences handed to threads
+    ///
+    /// In bytes, or with a K, M or G suffix (default:
+    /// 64K). Each worker thread processes the records of
+    /// one chunk at a time.
+    #[arg(long, value_parser = parse_size)]
+    chunk_size: Option<usize>,
+    /// Memory budget, e.g. 32G
+    ///
+    /// When the read k-min-mers approach the budget
+    /// left by the reference, they are spilled to sorted
+    /// runs on disk (prefix.spill*), merged at the end.
+    /// The budget is approximate.
+    #[arg(long, value_parser = parse_size)]
+    max_memory: Option<usize>,
+    /// Count read k-min-mers approximately
+    ///
+    /// Read abundances are tracked in a count-min sketch
+    /// of fixed size instead of a hash table, which may
+    /// over-count k-min-mers but uses much less memory on
+    /// large read sets.
+    #[arg(long)]
+    sketch: bool,
+    /// Counters per row of the --sketch (default: 16M)
+    #[arg(long, value_parser = parse_size)]
+    sketch_width: Option<usize>,
+    /// Rows of the --sketch (default: 4)
+    #[arg(long)]
+    sketch_depth: Option<usize>,
+    /// Leave read k-min-mers seen once out of the index
+    ///
+    /// Reads are read twice: a first pass records their
+    /// k-min-mers in Bloom filters, and the second one
+    /// only indexes those seen at least twice or present
+    /// in the reference, which saves memory on error-rich
+    /// long reads. Outputs are unchanged.
+    #[arg(long)]
+    prefilter: bool,
+    /// Memory of the --prefilter Bloom filters (default: 1G)
+    #[arg(long, value_parser = parse_size)]
+    prefilter_size: Option<usize>,
+    /// Only estimate the size of the run
+    ///
+    /// Streams the reference and the reads through
+    /// HyperLogLog sketches and a 1/256 sample, and
+    /// writes approximate distinct k-min-mer counts and
+    /// a coarse read abundance histogram to
+    /// prefix.estimate.json, without building indexes.
+    #[arg(long)]
+    estimate_only: bool,
+    /// Cap on reference k-min-mer counts
+    ///
+    /// Reference counters stop at this value, which
+    /// avoids contention on repeats and lets them fit in
+    /// narrow counters. The 2D histogram is unchanged
+    /// with a cap of 9 or more.
+    #[arg(long)]
+    ref_cap: Option<u64>,
+    /// Counter width in bits (8, 16, 32 or 64)
+    ///
+    /// Narrower counters reduce memory usage. Counts
+    /// beyond the maximum value of the width are kept
+    /// in a small overflow table (default: 64).
+    #[arg(long)]
+    counter_width: Option<CounterWidth>,
+    /// Hash table of the k-min-mer indexes (hashmap, compact or disk)
+    ///
+    /// compact stores 7-byte keys in open-addressing
+    /// tables, using less memory per k-min-mer than
+    /// the default hashmap on large indexes, at some
+    /// cost in speed. disk keeps the indexes in
+    /// databases under --index-dir, for k-min-mer sets
+    /// that don't fit in memory; it needs a build with
+    /// the "sled" feature (default: hashmap).
+    #[arg(long)]
+    index_backend: Option<IndexBackend>,
+    /// Directory of the --index-backend disk databases
+    ///
+    /// The databases are removed at the end of the run;
+    /// put it on a fast local disk with room for them.
+    #[arg(long)]
+    index_dir: Option<PathBuf>,
+    /// Also write copy-number spectra (KAT/Merqury spectra-cn format)
+    ///
+    /// Writes prefix.spectra-cn.mx (KAT matrix) and
+    /// prefix.spectra-cn.hist (Merqury), where the
+    /// reference axis is collapsed into 0, 1, 2, 3, 4+ copies.
+    #[arg(long)]
+    spectra_cn: bool,
+    /// Minimum read abundance of solid k-min-mers
+    ///
+    /// Used for the completeness estimate in prefix.qv.
+    /// By default, derived from the error valley of the
+    /// read spectrum.
+    #[arg(long)]
+    solid_threshold: Option<u64>,
+    /// Dump all k-min-mers to a TSV file
+    ///
+    /// Writes one "hash read_count ref_count" line per
+    /// k-min-mer present in the reads or the reference.
+    /// Compressed if the path ends with .gz or .lz4, and
+    /// a Parquet file if it ends with .parquet.
+    #[arg(long)]
+    dump_kminmers: Option<PathBuf>,
+    /// Checkpoint the read index every N reads
+    ///
+    /// Snapshots are written to prefix.ckpt and removed
+    /// once all reads are counted.
+    #[arg(long)]
+    checkpoint_every: Option<u64>,
+    /// Write the histogram every N reads, or N minutes (Nm)
+    ///
+    /// Snapshots of the reads counted so far go to
+    /// prefix.snapshot1.hist2D, ..., and how much the
+    /// histogram changed since the previous one is logged.
+    #[arg(long)]
+    snapshot_every: Option<SnapshotEvery>,
+    /// Resume from prefix.ckpt, if it exists
+    ///
+    /// Reads covered by the checkpoint are skipped.
+    /// Must be run with the same parameters and prefix.
+    #[arg(long)]
+    resume: bool,
+    /// Positional coverage histogram with bins of N bases
+    ///
+    /// Writes prefix.positional.hist2D: for each bin of
+    /// each reference sequence, the read abundances of
+    /// the reference k-min-mers starting in the bin.
+    #[arg(long)]
+    positional_histo: Option<usize>,
+    /// Per-reference breadth of coverage report
+    ///
+    /// Writes prefix.breadth.tsv: for each reference
+    /// sequence, the fraction of its k-min-mers seen in
+    /// at least 1, 3 and 5 reads.
+    #[arg(long)]
+    breadth_report: bool,
+    /// GC content vs abundance histogram
+    ///
+    /// Writes prefix.gc.hist2D: the number of distinct
+    /// read k-min-mers for each GC percentage of the
+    /// read window they span (rows) and abundance (columns).
+    #[arg(long)]
+    gc_histo: bool,
+    /// Per-read presence report
+    ///
+    /// Writes prefix.per_read.tsv: the ID, length, number of
+    /// k-min-mers of each read and the fraction of them found
+    /// in the reference, e.g. to screen for contaminant reads.
+    #[arg(long)]
+    per_read_report: bool,
+    /// Report reference k-min-mers missing from the reads
+    ///
+    /// Writes prefix.missing: the reference k-min-mers seen
+    /// fewer times in the reads than the solid threshold,
+    /// likely consensus errors or contamination in the
+    /// assembly. With --positional-histo, writes their
+    /// positions to prefix.missing.bed instead.
+    #[arg(long)]
+    report_missing: bool,
+    /// Report read k-min-mers of at least this abundance missing from the reference
+    ///
+    /// Writes prefix.novel: the read k-min-mers seen at
+    /// least this many times and absent from the
+    /// reference, by decreasing abundance, which flag
+    /// sequence missing from the assembly.
+    #[arg(long)]
+    report_novel: Option<u64>,
+    /// Add an example read ID to each --report-novel k-min-mer
+    ///
+    /// Reads the reads again to find the first one
+    /// containing each novel k-min-mer.
+    #[arg(long)]
+    novel_read_ids: bool,
+    /// Report the N most abundant read k-min-mers
+    ///
+    /// Writes prefix.top: the N read k-min-mers of
+    /// highest abundance, with their reference counts,
+    /// to find the adapters, phiX or plastid sequence
+    /// behind spikes in the spectrum.
+    #[arg(long)]
+    top_kminmers: Option<usize>,
+    /// Add an example read ID to each --top-kminmers k-min-mer
+    ///
+    /// Reads the reads again to find the first one
+    /// containing each top k-min-mer.
+    #[arg(long)]
+    top_read_ids: bool,
+    /// List the k-min-mers of a cell of the 2D histogram (row,column)
+    ///
+    /// Writes prefix.cell<i>_<j>: the k-min-mers of read
+    /// abundance i and reference abundance j (the last
+    /// row and column holding all higher abundances),
+    /// with their exact counts, e.g. --inspect-cell 40,2.
+    #[arg(long, value_parser = parse_cell)]
+    inspect_cell: Option<(usize, usize)>,
+    /// Add an example read ID to each --inspect-cell k-min-mer
+    ///
+    /// Reads the reads again to find the first one
+    /// containing each k-min-mer of the cell.
+    #[arg(long)]
+    cell_read_ids: bool,
+    /// Keep the IDs of up to M reads containing each read k-min-mer
+    ///
+    /// Fills the read_id columns of --report-novel,
+    /// --top-kminmers and --inspect-cell while counting,
+    /// without reading the reads again, at the cost of
+    /// about twice the memory of the read index.
+    #[arg(long)]
+    track_read_ids: Option<usize>,
+    /// Screen the reads against a panel of contaminants
+    ///
+    /// A directory with one sequence file per contaminant
+    /// (e.g. human, E. coli, phiX, vectors), or a manifest
+    /// of "name file" lines (tab-separated). Writes the
+    /// read k-min-mers found in each to
+    /// prefix.contaminants.tsv.
+    #[arg(long)]
+    contaminants: Option<PathBuf>,
+    /// Assign each read to its best matching contaminant
+    ///
+    /// Reads the reads again and writes the k-min-mers
+    /// each shares with every contaminant (and the
+    /// reference) to prefix.contaminants.reads.tsv, with
+    /// the one sharing the most.
+    #[arg(long)]
+    classify_contaminant_reads: bool,
+    /// Write the reads missing from the reference to this file
+    ///
+    /// Reads with less than --unmapped-max-fraction of
+    /// their k-min-mers in the reference are written as
+    /// they were read (FASTQ or FASTA), e.g. to assemble
+    /// the sequence missing from the reference.
+    #[arg(long = "extract-unmapped-reads")]
+    unmapped_reads: Option<PathBuf>,
+    /// Fraction of reference k-min-mers below which --extract-unmapped-reads writes a read (default: 0.1)
+    #[arg(long)]
+    unmapped_max_fraction: Option<f64>,
+    /// Sparse 2D histograms
+    ///
+    /// Writes the 2D histograms as "i<TAB>j<TAB>count" lines
+    /// for nonzero cells only, after a "# sparse rows=R cols=C"
+    /// header, instead of dense matrices.
+    #[arg(long)]
+    sparse: bool,
+    /// Orientation of the 2D histograms (read-major or ref-major)
+    ///
+    /// With ref-major, prefix.hist2D and the other read vs
+    /// reference histograms are transposed, with reference
+    /// abundances as rows, and record it in their header
+    /// (default: read-major, read abundances as rows).
+    #[arg(long)]
+    axis_order: Option<AxisOrder>,
+    /// Self-describing copy of the 2D histogram
+    ///
+    /// Also writes prefix.annotated.hist2D: the dense 2D
+    /// histogram with a header giving k, l, density and
+    /// the axes, a column of row sums, and a last row of
+    /// column sums and the grand total.
+    #[arg(long)]
+    annotated: bool,
+    /// Cumulative read abundance distributions per reference abundance
+    ///
+    /// Also writes prefix.cumulative.hist2D: for each
+    /// reference abundance column, the fraction of its
+    /// k-min-mers seen at most as many times as the row,
+    /// to choose solidity thresholds.
+    #[arg(long)]
+    cumulative: bool,
+    /// Normalized copy of the 2D histogram (per-million or probability)
+    ///
+    /// Also writes prefix.per-million.hist2D or
+    /// prefix.probability.hist2D: the cells divided by the
+    /// number of distinct k-min-mers, per million or as
+    /// joint probabilities, to compare datasets of
+    /// different depths.
+    #[arg(long)]
+    normalize: Option<Normalize>,
+    /// Fit a mixture of K negative binomials per reference copy number
+    ///
+    /// Writes prefix.nbfit.json: for each reference
+    /// abundance column of the 2D histogram, the weights,
+    /// means and dispersions of K negative binomial
+    /// components fitted to its read abundances by EM.
+    #[arg(long)]
+    nb_mixture: Option<usize>,
+    /// Estimate the ploidy from the read spectrum
+    ///
+    /// Writes prefix.ploidy.tsv: for ploidies 1 to 6, the
+    /// fit of coverage peaks at 1 to p times the coverage
+    /// of one haplotype, with the probability of each.
+    #[arg(long)]
+    estimate_ploidy: bool,
+    /// Parquet copy of the 2D histogram
+    ///
+    /// Also writes prefix.hist2D.parquet, with read_abundance,
+    /// ref_abundance and count columns for nonzero cells.
+    /// Needs a build with the "parquet" feature.
+    #[arg(long)]
+    parquet: bool,
+    /// HDF5 copy of the histograms
+    ///
+    /// Also writes prefix.h5, with the 2D histogram, its
+    /// marginals and the --positional-histo histograms as
+    /// datasets. Needs a build with the "hdf5" feature.
+    #[arg(long)]
+    hdf5: bool,
+    /// SQLite database of the k-min-mer counts
+    ///
+    /// Writes a kminmers table (hash, read_count,
+    /// ref_count), indexed on both counts, and a hist2d
+    /// table of the nonzero histogram cells. Needs a
+    /// build with the "sqlite" feature.
+    #[arg(long)]
+    sqlite: Option<PathBuf>,
+    /// Interactive HTML report
+    ///
+    /// Writes prefix.report.html: a heatmap of the 2D
+    /// histogram, the read and reference spectra and the run
+    /// parameters, in a single file that opens in a browser.
+    #[arg(long)]
+    html_report: bool,
+    /// Count each k-min-mer at most once per read
+    ///
+    /// So that long reads through tandem repeats don't
+    /// inflate read abundances.
+    #[arg(long)]
+    count_distinct_per_read: bool,
+    /// Weight read k-min-mers by base qualities
+    ///
+    /// Each occurrence adds the probability that all its
+    /// bases are correct, from the FASTQ qualities, and
+    /// abundances are the rounded sums: sharper peaks on
+    /// noisy reads. FASTA reads count 1 per occurrence.
+    #[arg(long)]
+    quality_weights: bool,
+    /// Bases trimmed from the start of each read
+    ///
+    /// Before extracting k-min-mers, e.g. to drop adapter
+    /// remnants.
+    #[arg(long, default_value_t = 0)]
+    trim5: usize,
+    /// Bases trimmed from the end of each read
+    #[arg(long, default_value_t = 0)]
+    trim3: usize,
+    /// Trim read ends of Phred quality below this
+    ///
+    /// After --trim5 and --trim3, bases below this
+    /// quality are trimmed from both ends of FASTQ reads,
+    /// so that low-quality tails don't add spurious
+    /// low-abundance k-min-mers.
+    #[arg(long)]
+    trim_quality: Option<u8>,
+    /// Skip low-complexity k-min-mers
+    ///
+    /// K-min-mers spanning sequence of normalized
+    /// trinucleotide entropy below this (0-1; 0 for a
+    /// homopolymer, 1/6 for a dinucleotide repeat) are
+    /// left out of the reads and the reference.
+    #[arg(long)]
+    min_entropy: Option<f64>,
+    /// Handling of lowercase and IUPAC bases (uppercase, skip or break)
+    ///
+    /// Uppercases bases, then resolves other codes
+    /// than ACGT to a base they stand for (uppercase),
+    /// skips them (skip), or never spans them with a
+    /// k-min-mer (break), in the reads and the reference.
+    #[arg(long)]
+    mask_policy: Option<MaskPolicy>,
+    /// Ignore k-min-mers seen more than this in the reference
+    ///
+    /// They are left out of both the reference and the
+    /// read indexes, e.g. to keep rDNA and centromeric
+    /// repeats from swamping the statistics.
+    #[arg(long)]
+    max_ref_count_filter: Option<u64>,
+    /// Only count the k-min-mers of this hash list
+    ///
+    /// One hash per line (first field; the outputs of
+    /// --dump-kminmers, --report-missing and
+    /// --report-novel work as is), or a binary set, e.g.
+    /// for a targeted panel. Applies to reads and reference.
+    #[arg(long)]
+    include_hashes: Option<PathBuf>,
+    /// Never count the k-min-mers of this hash list
+    ///
+    /// Same formats as --include-hashes, e.g. for known
+    /// artifacts.
+    #[arg(long)]
+    exclude_hashes: Option<PathBuf>,
+    /// Only count the read k-min-mers of the reference
+    ///
+    /// Saves the memory of read k-min-mers absent from the
+    /// reference, mostly sequencing errors: the histogram
+    /// is exact except for its reference abundance 0 column.
+    #[arg(long)]
+    restrict_to_reference: bool,
+    /// Only index the reference within these BED regions
+    ///
+    /// Reference k-min-mers are indexed if they start in
+    /// a region, e.g. for the spectrum of a targeted panel
+    /// or of one chromosome.
+    #[arg(long)]
+    regions: Option<PathBuf>,
+    /// Leave out the reference within these BED regions
+    ///
+    /// Reference k-min-mers overlapping a region aren't
+    /// indexed, e.g. for the ENCODE blacklist or rDNA
+    /// arrays.
+    #[arg(long)]
+    exclude_regions: Option<PathBuf>,
+    /// Leave out soft-masked reference sequence
+    ///
+    /// Reference k-min-mers overlapping lowercase
+    /// (repeat-masked) bases aren't indexed, for a
+    /// unique-sequence reference axis.
+    #[arg(long)]
+    respect_softmask: bool,
+    /// Also index the k-min-mers spanning GFA links
+    ///
+    /// For GFA references, k-min-mers crossing from a
+    /// segment to the next, in the orientations of the
+    /// link, besides those within segments.
+    #[arg(long)]
+    gfa_edges: bool,
+    /// Reference to decode CRAM reads with
+    ///
+    /// The FASTA the CRAM files were written against
+    /// (default: htslib's REF_PATH and REF_CACHE).
+    #[arg(long)]
+    cram_reference: Option<PathBuf>,
+    /// Only count the unmapped reads of BAM or CRAM input
+    ///
+    /// Records with flag 4, e.g. to look for sequence
+    /// missing from the reference the reads were mapped
+    /// to, or for contamination, against another one.
+    #[arg(long)]
+    unmapped_only: bool,
+    /// Only count BAM or CRAM records with this MAPQ or more
+    ///
+    /// As samtools view -q; unmapped records usually have
+    /// a MAPQ of 0.
+    #[arg(long)]
+    min_mapq: Option<u8>,
+    /// Only count BAM or CRAM records with all these flags
+    ///
+    /// As samtools view -f, in decimal or hexadecimal.
+    #[arg(long, value_parser = parse_flags)]
+    require_flags: Option<u16>,
+    /// Skip BAM or CRAM records with any of these flags
+    ///
+    /// As samtools view -F, in decimal or hexadecimal;
+    /// secondary and supplementary records (0x900) are
+    /// always skipped.
+    #[arg(long, value_parser = parse_flags)]
+    exclude_flags: Option<u16>,
+    /// Count duplicate reads once
+    ///
+    /// Skips reads (or pairs) whose prefix has the same
+    /// k-mer sketch as an earlier one: exact and most
+    /// near-duplicates from PCR or optical duplication.
+    #[arg(long)]
+    dedup_reads: bool,
+    /// Byte-identical outputs across runs and thread counts
+    ///
+    /// Writes k-min-mer dumps and index files in hash
+    /// order, leaves timings out of prefix.stats.json, and
+    /// counts the reads on one thread with --dedup-reads
+    /// or --gc-histo, which keep the first read seen.
+    #[arg(long)]
+    deterministic: bool,
+}
+
+// Options shared by all subcommands, given before or after the subcommand.
+#[derive(Debug, Args)]
+struct LogOpt {
+    /// Verbose output (-v: per-reference progress and
+    /// checkpoints, -vv: everything)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Only print errors
+    #[arg(short, long, conflicts_with = "verbose", global = true)]
+    quiet: bool,
+}
+
+// Log to stderr at the level set by -q/-v/-vv; RUST_LOG, if set, takes precedence.
+fn init_logging(opt: &LogOpt) {
+    let level = match (opt.quiet, opt.verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_target(false)
+        .parse_default_env()
+        .init();
+}
+
+// Report an error on stderr and exit with its exit code.
+fn exit_on_error(res: Result<(), Error>) {
+    if let Err(e) = res {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+// Parse the command line, exiting on invalid arguments.
+fn parse_cli(args: &[OsString]) -> (Cli, ArgMatches) {
+    let parse = || -> Result<(Cli, ArgMatches), clap::Error> {
+        let matches = Cli::command().try_get_matches_from(args)?;
+        Ok((Cli::from_arg_matches(&matches)?, matches))
+    };
+    parse().unwrap_or_else(|e| {
+        // --help goes to stdout and exits with 0, invalid arguments exit with the code of usage errors
+        if !e.use_stderr() {e.exit();}
+        let _ = e.print();
+        std::process::exit(Error::Usage(String::new()).exit_code());
+    })
+}
+
+// The counting options of the command line, if it has them.
+fn cli_opt(cli: &Cli) -> Option<&Opt> {
+    match &cli.cmd {
+        None => Some(&cli.opt),
+        Some(Command::Count {opt}) | Some(Command::Index {opt, ..}) | Some(Command::Histo {opt, ..}) | Some(Command::Simulate {opt, ..}) => Some(opt),
+        _ => None,
+    }
+}
+
+// Write the dataset of the simulate subcommand, and return the options to count it.
+fn simulated_opt(sim: &simulate::Simulation, mut opt: Opt) -> Result<Opt, Error> {
+    if opt.reads.is_some() || !opt.reference.is_empty() || opt.manifest.is_some() {return Err(Error::Usage("The simulate subcommand generates its reads and reference, it doesn't take any.".to_string()));}
+    sim.check()?;
+    let prefix = opt.prefix.get_or_insert_with(|| PathBuf::from("simulated")).clone();
+    let (ref_path, reads_path) = sim.write(&prefix)?;
+    opt.reference = vec![ref_path];
+    opt.reads = Some(reads_path);
+    Ok(opt)
+}
+
+// Command-line entry point, called by the binary (main.rs).
+pub fn main() {
+    let start = Instant::now();
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let (mut cli, matches) = parse_cli(&args);
+    // The settings of --config become arguments after those of the command line, for the options it doesn't give
+    if let Some(config) = cli_opt(&cli).and_then(|opt| opt.config.clone()) {
+        let (cmd, cmd_matches) = match matches.subcommand() {
+            Some((name, sub_matches)) => (Cli::command().find_subcommand(name).unwrap().clone(), sub_matches),
+            None => (Cli::command(), &matches),
+        };
+        match config::config_args(&config, &cmd, cmd_matches) {
+            Ok(config_args) => args.extend(config_args),
+            Err(e) => exit_on_error(Err(e)),
+        }
+        cli = parse_cli(&args).0;
+    }
+    init_logging(&cli.log);
+    let (opt, mode) = match cli.cmd {
+        None => (cli.opt, Mode::Full),
+        Some(Command::Count {opt}) => (opt, Mode::Full),
+        Some(Command::Index {output, opt}) => (opt, Mode::IndexOnly(output)),
+        Some(Command::Histo {index, opt}) => (opt, Mode::HistoOnly(index)),
+        Some(Command::Simulate {sim, opt}) => match simulated_opt(&sim, opt) {
+            Ok(opt) => (opt, Mode::Full),
+            Err(e) => return exit_on_error(Err(e)),
+        },
+        Some(Command::Serve {index, listen}) => return exit_on_error(serve::serve(&index, &listen)),
+        Some(Command::Plot {input, prefix}) => return exit_on_error(histfile::plot(&input, &prefix)),
+        Some(Command::Merge {inputs, output, sparse}) => return exit_on_error(histfile::merge(&inputs, &output, sparse)),
+        Some(Command::Diff {first, second, prefix, sparse}) => return exit_on_error(histfile::diff(&first, &second, &prefix, sparse)),
+        Some(Command::Stats {input, prefix}) => return exit_on_error(histfile::stats(&input, &prefix)),
+        Some(Command::Completions {shell}) => {
+            clap_complete::generate(shell, &mut Cli::command(), "rust-kminmer2Dhisto", &mut std::io::stdout());
+            return;
+        }
+    };
+    signals::install();
+    exit_on_error(run(opt, mode));
+    let duration = start.elapsed();
+    info!("Total execution time: {:?}", duration);
+    info!("Maximum RSS: {:?}GB", (get_memory_rusage() as f32) / 1024.0 / 1024.0 / 1024.0);
+}
+
+fn run(opt: Opt, mode: Mode) -> Result<(), Error> {
+    let mut provenance = provenance::Provenance::new();
+    let mut filename = PathBuf::new();
+    let mut output_prefix;
+    let mut k : usize = 5;
+    let mut l : usize = 31;
+    let mut density : f64 = 0.01;
+    if opt.reads.is_some() {filename = opt.reads.unwrap();} 
+    let counter_width = opt.counter_width.unwrap_or(CounterWidth::U64);
+    let index_backend = match (opt.index_backend.unwrap_or(IndexBackend::HashMap), &opt.index_dir) {
+        (IndexBackend::Disk(_), Some(dir)) => {
+            disk::check_available()?;
+            if opt.max_memory.is_some() {return Err(Error::Usage("--index-backend disk doesn't keep the indexes in memory, --max-memory can't be used with it.".to_string()));}
+            IndexBackend::Disk(dir.clone())
+        }
+        (IndexBackend::Disk(_), None) => return Err(Error::Usage("--index-backend disk needs an --index-dir.".to_string())),
+        (_, Some(_)) => return Err(Error::Usage("--index-dir needs --index-backend disk.".to_string())),
+        (backend, None) => backend,
+    };
+    let mut loaded_index = None;
+    let mut index_scheme = None;
+    let mut index_settings = None;
+    match &mode {
+        Mode::IndexOnly(_) => {
+            if !filename.as_os_str().is_empty() || opt.reads2.is_some() || opt.manifest.is_some() {return Err(Error::Usage("The index subcommand doesn't take reads.".to_string()));}
+            if !opt.reference2.is_empty() {return Err(Error::Usage("--reference2 can't be used with the index subcommand.".to_string()));}
+        }
+        _ => {
+            if filename.as_os_str().is_empty() && opt.manifest.is_none() && opt.reference2.is_empty() && opt.watch.is_none() {return Err(Error::Usage("Please specify an input file.".to_string()));}
+            if filename.as_os_str().is_empty() && opt.reads2.is_some() {return Err(Error::Usage("--reads2 requires a first input file.".to_string()));}
+        }
+    }
+    let mut ks = opt.k.clone(); // several values for a sweep
+    ks.sort_unstable();
+    ks.dedup();
+    if ks.len() > 1 && !matches!(mode, Mode::Full) {return Err(Error::Usage("Several k values can only be swept in a full run, not with the index and histo subcommands.".to_string()));}
+    let mut ref_filenames = Vec::new();
+    if let Mode::HistoOnly(index_path) = &mode {
+        if !opt.reference.is_empty() || opt.reference_counts.is_some() {return Err(Error::Usage("--reference can't be used with --index.".to_string()));}
+        if opt.positional_histo.is_some() || opt.breadth_report {return Err(Error::Usage("--positional-histo and --breadth-report need reference positions, which aren't stored in index files.".to_string()));}
+        let start = Instant::now();
+        let (index, p) = refindex::load(index_path, counter_width, &index_backend)?;
+        for (name, given, stored) in [("k", opt.k.first().map(|v| *v as f64), p.k as f64), ("l", opt.l.map(|v| v as f64), p.l as f64), ("density", opt.density, p.density)] {
+            if given.map_or(false, |v| v != stored) {
+                return Err(Error::Usage(format!("{} differs from the value the index was built with ({}).", name, stored)));
+            }
+        }
+        k = p.k; l = p.l; density = p.density;
+        index_scheme = Some(p.scheme);
+        index_settings = Some(p.settings);
+        info!("Loaded reference index {} in {:?}: {} k-min-mers (k={} l={} density={}).", index_path.to_str().unwrap(), start.elapsed(), index.len(), k, l, density);
+        loaded_index = Some((index, start.elapsed()));
+    }
+    else {
+        if opt.reference.is_empty() && opt.reference_counts.is_none() && opt.maternal.is_empty() && !opt.smudge {
+            if matches!(mode, Mode::IndexOnly(_)) {return Err(Error::Usage("Please specify a reference file.".to_string()));}
+            info!("No reference given, only the read k-min-mer spectrum will be computed.");
+        }
+        if opt.reference_counts.is_some() && (opt.positional_histo.is_some() || opt.breadth_report) {return Err(Error::Usage("--positional-histo and --breadth-report need reference positions, which k-mer counts don't have.".to_string()));}
+        ref_filenames = expand_references(opt.reference)?;
+        if let Some(first) = opt.k.first() {k = *first} else {warn!("Using default k value ({}).", k);}
+        if opt.l.is_some() {l = opt.l.unwrap()} else {warn!("Using default l value ({}).", l);}
+        if opt.density.is_some() {density = opt.density.unwrap()} else if opt.target_kminmers_per_kb.is_none() {warn!("Using default density value ({}%).", density * 100.0);}
+    }
+    let threads = match opt.threads {
+        Some(0) | None => available_threads(),
+        Some(n) => n,
+    };
+    if opt.threads.unwrap_or(0) == 0 {info!("Using all {} available CPUs.", threads);}
+    let mut params = Params { 
+        k,
+        l,
+        density,
+        counter_width,
+        ref_cap: opt.ref_cap,
+        reads_only: ref_filenames.is_empty() && opt.reference_counts.is_none() && matches!(mode, Mode::Full),
+        index_backend,
+        spectra_cn: opt.spectra_cn,
+        solid_threshold: opt.solid_threshold,
+        dump_kminmers: opt.dump_kminmers,
+        checkpoint_every: opt.checkpoint_every,
+        snapshot_every: opt.snapshot_every,
+        resume: opt.resume,
+        positional_bin: opt.positional_histo,
+        breadth_report: opt.breadth_report,
+        gc_histo: opt.gc_histo,
+        per_read_report: opt.per_read_report,
+        report_missing: opt.report_missing,
+        report_novel: opt.report_novel,
+        novel_read_ids: opt.novel_read_ids,
+        top_kminmers: opt.top_kminmers,
+        top_read_ids: opt.top_read_ids,
+        inspect_cell: opt.inspect_cell,
+        cell_read_ids: opt.cell_read_ids,
+        track_read_ids: opt.track_read_ids,
+        contaminants: opt.contaminants.clone(),
+        classify_contaminant_reads: opt.classify_contaminant_reads,
+        unmapped_reads: opt.unmapped_reads,
+        unmapped_max_fraction: opt.unmapped_max_fraction.unwrap_or(0.1),
+        sparse: opt.sparse,
+        axis_order: opt.axis_order.unwrap_or(AxisOrder::ReadMajor),
+        annotated: opt.annotated,
+        cumulative: opt.cumulative,
+        normalize: opt.normalize,
+        nb_mixture: opt.nb_mixture,
+        estimate_ploidy: opt.estimate_ploidy,
+        parquet: opt.parquet,
+        hdf5: opt.hdf5,
+        sqlite: opt.sqlite,
+        html_report: opt.html_report,
+        distinct_per_read: opt.count_distinct_per_read,
+        quality_weights: opt.quality_weights,
+        trim5: opt.trim5,
+        trim3: opt.trim3,
+        trim_quality: opt.trim_quality,
+        min_entropy: opt.min_entropy,
+        mask_policy: opt.mask_policy,
+        max_ref_count_filter: opt.max_ref_count_filter,
+        hash_filter: None,
+        restrict_to_reference: opt.restrict_to_reference,
+        regions: None,
+        exclude_regions: None,
+        respect_softmask: opt.respect_softmask,
+        gfa_edges: opt.gfa_edges,
+        mates: opt.mates,
+        interleaved: opt.interleaved,
+        dedup_pairs: opt.dedup_pairs,
+        dedup_reads: opt.dedup_reads,
+        window: opt.window,
+        syncmers: opt.syncmers,
+        syncmer_s: opt.syncmer_s.unwrap_or(11),
+        strobemers: if opt.strobemers {Some((opt.strobe_wmin.unwrap_or(2), opt.strobe_wmax.unwrap_or(6)))} else {None},
+        chunk_size: opt.chunk_size.unwrap_or(64 * 1024),
+        max_memory: opt.max_memory,
+        sketch: if opt.sketch {Some((opt.sketch_width.unwrap_or(16 << 20), opt.sketch_depth.unwrap_or(4)))} else {None},
+        prefilter: if opt.prefilter {Some(opt.prefilter_size.unwrap_or(1 << 30))} else {None},
+        deterministic: opt.deterministic,
+        threads,
+    };
+    if !filename.as_os_str().is_empty() {
+        check_input(&filename)?;
+    }
+    if let Some(path) = &opt.cram_reference {
+        check_input(path)?;
+        aligned::set_cram_reference(path.clone());
+    }
+    if opt.unmapped_only || opt.min_mapq.is_some() || opt.require_flags.is_some() || opt.exclude_flags.is_some() {
+        if !filename.as_os_str().is_empty() && !aligned::is_aligned(&filename) {return Err(Error::Usage("--unmapped-only, --min-mapq, --require-flags and --exclude-flags select records of BAM or CRAM reads.".to_string()));}
+        aligned::set_record_filter(aligned::RecordFilter {
+            require_flags: opt.require_flags.unwrap_or(0) | if opt.unmapped_only {aligned::UNMAPPED} else {0},
+            exclude_flags: opt.exclude_flags.unwrap_or(0),
+            min_mapq: opt.min_mapq.unwrap_or(0),
+        });
+    }
+    if let Some(filename2) = &opt.reads2 {
+        check_input(filename2)?;
+    }
+    if let Some(dir) = &opt.watch {
+        check_input(dir)?;
+    }
+    if let Some(mates) = &params.mates {
+        check_input(mates)?;
+    }
+    let mut samples = Vec::new();
+    if let Some(manifest) = &opt.manifest {
+        for (sample, sample_filename) in read_manifest(manifest)? {
+            check_input(&sample_filename)?;
+            samples.push((sample, sample_filename));
+        }
+        info!("Manifest: {} samples.", samples.len());
+    }
+    for ref_filename in ref_filenames.iter().chain(opt.reference_counts.iter()) {
+        check_input(ref_filename)?;
+    }
+    let ref_threads = threads;
+    let ref_queue_len = opt.ref_queue_len.unwrap_or(threads);
+    let queue_len = opt.queue_len.unwrap_or(200); // https://doc.rust-lang.org/std/sync/mpsc/fn.sync_channel.html
+                             // also: controls how many reads objects are buffered during fasta/fastq
+                             // parsing
+    // duplicate reads, the GC content of k-min-mers and their example read IDs are decided by the first reads seen,
+    // which depends on thread timing
+    let threads = if params.deterministic && (params.dedup_reads || params.gc_histo || params.track_read_ids.is_some()) && threads > 1 {
+        info!("Counting the reads on one thread, for --dedup-reads, --gc-histo and --track-read-ids to see them in file order with --deterministic.");
+        1
+    } else {threads};
+    if queue_len == 0 || ref_queue_len == 0 {return Err(Error::Usage("--queue-len and --ref-queue-len must be positive.".to_string()));}
+    if params.chunk_size < 1024 {return Err(Error::Usage("--chunk-size must be at least 1K.".to_string()));}
+
+    let paired = params.mates.is_some() || params.interleaved;
+    if params.dedup_pairs && !paired {return Err(Error::Usage("--dedup-pairs needs paired reads (--mates or --interleaved).".to_string()));}
+    if paired && filename.as_os_str().is_empty() {return Err(Error::Usage("--mates and --interleaved apply to the input file, which is missing.".to_string()));}
+    if paired && (params.checkpoint_every.is_some() || params.resume) {return Err(Error::Usage("Paired reads can't be checkpointed.".to_string()));}
+    if paired && params.per_read_report {return Err(Error::Usage("--per-read-report isn't available for paired reads.".to_string()));}
+    if params.quality_weights && (paired || params.checkpoint_every.is_some() || params.resume || params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || ks.len() > 1) {
+        return Err(Error::Usage("--quality-weights can't be used with paired reads, checkpoints, --max-memory, --sketch, --prefilter or several k values.".to_string()));
+    }
+    // weighted occurrences add about 1000 units each, which would overflow 8- and 16-bit counters after a few reads
+    if params.quality_weights && matches!(params.counter_width, CounterWidth::U8 | CounterWidth::U16) {return Err(Error::Usage("--quality-weights needs --counter-width 32 or 64.".to_string()));}
+    if params.trim_quality.is_some() && (paired || params.checkpoint_every.is_some() || params.resume) {return Err(Error::Usage("--trim-quality isn't available for paired reads or with checkpoints; --trim5 and --trim3 are.".to_string()));}
+    if params.dedup_reads && (params.checkpoint_every.is_some() || params.resume) {return Err(Error::Usage("--dedup-reads can't be used with checkpoints, the reads seen before a checkpoint aren't stored.".to_string()));}
+    if params.max_memory.is_some() && (paired || params.checkpoint_every.is_some() || params.resume || opt.reads2.is_some() || params.gc_histo || params.dump_kminmers.is_some()) {
+        return Err(Error::Usage("--max-memory can't be used with paired reads, checkpoints, --reads2, --gc-histo or --dump-kminmers, which need all read k-min-mers in memory.".to_string()));
+    }
+    if (opt.sketch_width.is_some() || opt.sketch_depth.is_some()) && !opt.sketch {return Err(Error::Usage("--sketch-width and --sketch-depth need --sketch.".to_string()));}
+    if let Some((width, depth)) = params.sketch {
+        if width == 0 || !(1..=16).contains(&depth) {return Err(Error::Usage("--sketch-width must be positive and --sketch-depth between 1 and 16.".to_string()));}
+        if params.max_memory.is_some() || paired || params.checkpoint_every.is_some() || params.resume || opt.reads2.is_some() || params.gc_histo || params.dump_kminmers.is_some() {
+            return Err(Error::Usage("--sketch can't be used with --max-memory, paired reads, checkpoints, --reads2, --gc-histo or --dump-kminmers.".to_string()));
+        }
+    }
+    if params.reads_only && (params.positional_bin.is_some() || params.breadth_report || params.spectra_cn || params.per_read_report || params.report_missing || params.report_novel.is_some() || params.unmapped_reads.is_some() || opt.reads2.is_some() || params.annotated || params.cumulative || params.normalize.is_some() || params.nb_mixture.is_some() || params.parquet || params.hdf5 || params.html_report) {
+        return Err(Error::Usage("Without a reference, --positional-histo, --breadth-report, --spectra-cn, --per-read-report, --report-missing, --report-novel, --extract-unmapped-reads, --reads2, --annotated, --cumulative, --normalize, --nb-mixture, --parquet, --hdf5 and --html-report aren't available.".to_string()));
+    }
+    let no_reads = filename.as_os_str().is_empty() && samples.is_empty();
+    if !opt.reference2.is_empty() {
+        if params.reads_only {return Err(Error::Usage("--reference2 is compared to --reference, which is missing.".to_string()));}
+        if ks.len() > 1 || opt.estimate_only {return Err(Error::Usage("--reference2 can't be used with several k values or --estimate-only.".to_string()));}
+        if no_reads && opt.target_kminmers_per_kb.is_some() {return Err(Error::Usage("--target-kminmers-per-kb needs reads.".to_string()));}
+    }
+    if params.min_entropy.map_or(false, |e| !(0.0..=1.0).contains(&e)) {return Err(Error::Usage("--min-entropy must be between 0 and 1.".to_string()));}
+    if params.min_entropy.is_some() && opt.reference_counts.is_some() {return Err(Error::Usage("--min-entropy needs the reference sequence, which k-mer counts don't have.".to_string()));}
+    if params.max_ref_count_filter.is_some() && (params.max_memory.is_some() || params.sketch.is_some() || params.positional_bin.is_some() || params.breadth_report) {
+        return Err(Error::Usage("--max-ref-count-filter can't be used with --max-memory, --sketch, --positional-histo or --breadth-report.".to_string()));
+    }
+    if params.restrict_to_reference && (params.reads_only || paired || params.checkpoint_every.is_some() || params.resume || params.report_novel.is_some() || ks.len() > 1) {
+        return Err(Error::Usage("--restrict-to-reference needs a reference, and can't be used with paired reads, checkpoints, --report-novel or several k values.".to_string()));
+    }
+    if params.snapshot_every.is_some() && (paired || params.checkpoint_every.is_some() || params.resume || params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || params.quality_weights || ks.len() > 1 || opt.watch.is_some() || params.deterministic) {
+        return Err(Error::Usage("--snapshot-every can't be used with paired reads, checkpoints, --max-memory, --sketch, --prefilter, --quality-weights, several k values, --watch or --deterministic.".to_string()));
+    }
+    if (opt.watch_interval.is_some() || opt.watch_idle.is_some()) && opt.watch.is_none() {return Err(Error::Usage("--watch-interval and --watch-idle need --watch.".to_string()));}
+    if opt.watch_interval.map_or(false, |m| !(m > 0.0)) || opt.watch_idle.map_or(false, |m| !(m > 0.0)) {return Err(Error::Usage("--watch-interval and --watch-idle must be positive.".to_string()));}
+    if opt.watch.is_some() && (!matches!(mode, Mode::Full) || !filename.as_os_str().is_empty() || opt.reads2.is_some() || !samples.is_empty() || paired || ks.len() > 1 || !opt.reference2.is_empty() || opt.target_kminmers_per_kb.is_some() || params.checkpoint_every.is_some() || params.resume
+        || params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || params.quality_weights || params.max_ref_count_filter.is_some() || params.per_read_report || params.unmapped_reads.is_some() || params.report_novel.is_some() || params.top_kminmers.is_some() || params.inspect_cell.is_some() || params.contaminants.is_some() || params.dump_kminmers.is_some() || params.sqlite.is_some()) {
+        return Err(Error::Usage("--watch counts the read files of a directory, without subcommands, a read file, --reads2, --manifest, paired reads, several k values, --reference2, --target-kminmers-per-kb, checkpoints, --max-memory, --sketch, --prefilter, --quality-weights, --max-ref-count-filter, --per-read-report, --extract-unmapped-reads, --report-novel, --top-kminmers, --inspect-cell, --contaminants, --dump-kminmers or --sqlite.".to_string()));
+    }
+    if (opt.include_hashes.is_some() || opt.exclude_hashes.is_some()) && ks.len() > 1 {return Err(Error::Usage("--include-hashes and --exclude-hashes list k-min-mers of a single k.".to_string()));}
+    params.hash_filter = hashfilter::HashFilter::load(opt.include_hashes.as_ref(), opt.exclude_hashes.as_ref())?.map(Arc::new);
+    if (opt.regions.is_some() || opt.exclude_regions.is_some() || opt.respect_softmask) && (params.reads_only || opt.reference_counts.is_some() || matches!(mode, Mode::HistoOnly(_))) {return Err(Error::Usage("--regions, --exclude-regions and --respect-softmask apply to reference sequences as they are indexed, not to --reference-counts or --index.".to_string()));}
+    if let Some(path) = &opt.regions {params.regions = Some(Arc::new(regions::Regions::load(path)?));}
+    if let Some(path) = &opt.exclude_regions {params.exclude_regions = Some(Arc::new(regions::Regions::load(path)?));}
+    if params.ref_cap == Some(0) {return Err(Error::Usage("--ref-cap must be positive.".to_string()));}
+    if params.ref_cap.is_some() && matches!(mode, Mode::HistoOnly(_)) {return Err(Error::Usage("--ref-cap applies when indexing the reference; cap the index with the index subcommand instead.".to_string()));}
+    if opt.prefilter_size.is_some() && !opt.prefilter {return Err(Error::Usage("--prefilter-size needs --prefilter.".to_string()));}
+    if params.prefilter == Some(0) {return Err(Error::Usage("--prefilter-size must be positive.".to_string()));}
+    if params.prefilter.is_some() && (params.max_memory.is_some() || params.sketch.is_some() || paired || params.checkpoint_every.is_some() || params.resume || opt.reads2.is_some() || params.gc_histo || params.dump_kminmers.is_some()) {
+        return Err(Error::Usage("--prefilter can't be used with --max-memory, --sketch, paired reads, checkpoints, --reads2, --gc-histo or --dump-kminmers.".to_string()));
+    }
+    if params.novel_read_ids && params.report_novel.is_none() {return Err(Error::Usage("--novel-read-ids needs --report-novel.".to_string()));}
+    if params.report_novel == Some(0) {return Err(Error::Usage("--report-novel must be positive.".to_string()));}
+    if params.nb_mixture == Some(0) {return Err(Error::Usage("--nb-mixture needs at least one component.".to_string()));}
+    if params.report_novel.is_some() && (params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || ks.len() > 1) {
+        return Err(Error::Usage("--report-novel needs all read k-min-mers in memory, it can't be used with --max-memory, --sketch, --prefilter or several k values.".to_string()));
+    }
+    if params.top_read_ids && params.top_kminmers.is_none() {return Err(Error::Usage("--top-read-ids needs --top-kminmers.".to_string()));}
+    if params.top_kminmers == Some(0) {return Err(Error::Usage("--top-kminmers must be positive.".to_string()));}
+    if params.top_kminmers.is_some() && (params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || ks.len() > 1) {
+        return Err(Error::Usage("--top-kminmers needs all read k-min-mers in memory, it can't be used with --max-memory, --sketch, --prefilter or several k values.".to_string()));
+    }
+    if params.cell_read_ids && params.inspect_cell.is_none() {return Err(Error::Usage("--cell-read-ids needs --inspect-cell.".to_string()));}
+    if params.inspect_cell.is_some() && (params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || ks.len() > 1) {
+        return Err(Error::Usage("--inspect-cell needs all read k-min-mers in memory, it can't be used with --max-memory, --sketch, --prefilter or several k values.".to_string()));
+    }
+    if params.classify_contaminant_reads && params.contaminants.is_none() {return Err(Error::Usage("--classify-contaminant-reads needs --contaminants.".to_string()));}
+    if params.contaminants.is_some() && (params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || ks.len() > 1) {
+        return Err(Error::Usage("--contaminants needs all read k-min-mers in memory, it can't be used with --max-memory, --sketch, --prefilter or several k values.".to_string()));
+    }
+    if params.track_read_ids == Some(0) {return Err(Error::Usage("--track-read-ids must be positive.".to_string()));}
+    if params.track_read_ids.is_some() && (paired || params.checkpoint_every.is_some() || params.resume || params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() || ks.len() > 1 || opt.watch.is_some()) {
+        return Err(Error::Usage("--track-read-ids can't be used with paired reads, checkpoints, --max-memory, --sketch, --prefilter, several k values or --watch.".to_string()));
+    }
+    if opt.unmapped_max_fraction.is_some() && params.unmapped_reads.is_none() {return Err(Error::Usage("--unmapped-max-fraction needs --extract-unmapped-reads.".to_string()));}
+    if !(params.unmapped_max_fraction > 0.0 && params.unmapped_max_fraction <= 1.0) {return Err(Error::Usage("--unmapped-max-fraction must be in (0, 1].".to_string()));}
+    if params.unmapped_reads.is_some() && (filename.as_os_str().is_empty() || paired || params.checkpoint_every.is_some() || params.resume || ks.len() > 1) {
+        return Err(Error::Usage("--extract-unmapped-reads takes the reads of the input file, without --manifest, paired reads, checkpoints or several k values.".to_string()));
+    }
+    if params.gc_histo && params.resume {return Err(Error::Usage("--gc-histo can't be used with --resume, the GC content of k-min-mers in the checkpoint isn't stored.".to_string()));}
+    if params.per_read_report && params.resume {return Err(Error::Usage("--per-read-report can't be used with --resume, the reads already counted in the checkpoint would be missing from it.".to_string()));}
+    if params.parquet || params.dump_kminmers.as_ref().map_or(false, columnar::is_parquet) {columnar::check_available()?;}
+    if params.hdf5 {h5::check_available()?;}
+    if params.sqlite.is_some() {sqlite::check_available()?;}
+    if params.sqlite.is_some() && (filename.as_os_str().is_empty() || !samples.is_empty() || ks.len() > 1 || params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some()) {
+        return Err(Error::Usage("--sqlite needs the input file, with all its k-min-mers in memory: it can't be used with the index subcommand, --manifest, several k values, --max-memory, --sketch or --prefilter.".to_string()));
+    }
+    if params.window == Some(0) {return Err(Error::Usage("--window must be positive.".to_string()));}
+    if params.window.is_some() && !(1..=32).contains(&params.l) {return Err(Error::Usage("--window needs l <= 32.".to_string()));}
+    if opt.syncmer_s.is_some() && params.syncmers.is_none() {return Err(Error::Usage("--syncmer-s needs --syncmers.".to_string()));}
+    if params.syncmers.is_some() && !(1..=32).contains(&params.l) {return Err(Error::Usage("--syncmers needs l <= 32.".to_string()));}
+    if params.syncmers.is_some() && !(1..params.l).contains(&params.syncmer_s) {return Err(Error::Usage(format!("--syncmer-s must be between 1 and l - 1 ({}).", params.l - 1)));}
+    if params.syncmers == Some(SyncmerKind::Open) && (params.l - params.syncmer_s) % 2 != 0 {return Err(Error::Usage("Open syncmers need l - s to be even, so that they have a middle s-mer.".to_string()));}
+    if (opt.strobe_wmin.is_some() || opt.strobe_wmax.is_some()) && !opt.strobemers {return Err(Error::Usage("--strobe-wmin and --strobe-wmax need --strobemers.".to_string()));}
+    if params.strobemers.is_some() && !(1..=32).contains(&params.l) {return Err(Error::Usage("--strobemers needs l <= 32.".to_string()));}
+    if let Some((wmin, wmax)) = params.strobemers {
+        if wmin == 0 || wmin > wmax {return Err(Error::Usage("--strobe-wmin must be positive and at most --strobe-wmax.".to_string()));}
+    }
+    if (params.window.is_some() || params.syncmers.is_some() || params.strobemers.is_some()) && opt.reference_counts.is_some() {return Err(Error::Usage("--reference-counts only supports the density-based scheme, not --window, --syncmers or --strobemers.".to_string()));}
+    if let Some(scheme) = index_scheme.filter(|s| *s != mers::scheme(&params)) {
+        return Err(Error::Usage(format!("The index was built with a different minimizer scheme ({}).", if scheme.is_empty() {"density-based"} else {&scheme})));
+    }
+    if let Some(settings) = index_settings.filter(|s| *s != refindex::settings(&params)) {
+        return Err(Error::Usage(format!("The index was built with different --mask-policy, --min-entropy or trimming settings ({}).", if settings.is_empty() {"none"} else {&settings})));
+    }
+    if params.positional_bin == Some(0) {return Err(Error::Usage("--positional-histo bin size must be positive.".to_string()));}
+    if let Some(target) = opt.target_kminmers_per_kb {
+        if !matches!(mode, Mode::Full) {return Err(Error::Usage("--target-kminmers-per-kb needs reads and can't change the density of an index, use it without the index and histo subcommands.".to_string()));}
+        if params.window.is_some() || params.syncmers.is_some() {return Err(Error::Usage("--target-kminmers-per-kb tunes the density, which --window and --syncmers don't use.".to_string()));}
+        if !(target > 0.0) {return Err(Error::Usage("--target-kminmers-per-kb must be positive.".to_string()));}
+        let sample_path = if !filename.as_os_str().is_empty() {filename.clone()}
+            else {samples.first().map(|sample| sample.1.clone()).ok_or_else(|| Error::Usage("--target-kminmers-per-kb needs reads to sample.".to_string()))?};
+        tune::tune_density(&sample_path, &mut params, target, opt.tune_sample_mb)?;
+    }
+    let k_name = if ks.len() > 1 {ks.iter().map(|k| k.to_string()).collect::<Vec<_>>().join(",")} else {params.k.to_string()};
+    output_prefix = PathBuf::from(format!("2DHisto-k{}-d{}-l{}", k_name, params.density, params.l));
+    if opt.prefix.is_some() {output_prefix = opt.prefix.unwrap();} else if !matches!(mode, Mode::IndexOnly(_)) {warn!("Using default output prefix ({}).", output_prefix.to_str().unwrap());}
+    if !filename.as_os_str().is_empty() {provenance.add_inputs("reads", [&filename]);}
+    provenance.add_inputs("reads2", opt.reads2.iter());
+    provenance.add_inputs("mates", params.mates.iter());
+    provenance.add_inputs("manifest", opt.manifest.iter());
+    provenance.add_inputs("contaminants", opt.contaminants.iter());
+    provenance.add_inputs("sample", samples.iter().map(|(_, path)| path));
+    provenance.add_inputs("reference", ref_filenames.iter());
+    provenance.add_inputs("reference_counts", opt.reference_counts.iter());
+    provenance.add_inputs("reference2", opt.reference2.iter());
+    provenance.add_inputs("cram_reference", opt.cram_reference.iter());
+    provenance.add_inputs("regions", opt.regions.iter());
+    provenance.add_inputs("exclude_regions", opt.exclude_regions.iter());
+    provenance.add_inputs("include_hashes", opt.include_hashes.iter());
+    provenance.add_inputs("exclude_hashes", opt.exclude_hashes.iter());
+    provenance.add_inputs("maternal", opt.maternal.iter());
+    provenance.add_inputs("paternal", opt.paternal.iter());
+    provenance.add_inputs("haplotype_a", opt.haplotype_a.iter());
+    provenance.add_inputs("haplotype_b", opt.haplotype_b.iter());
+    provenance.add_inputs("config", opt.config.iter());
+    let provenance_prefix = match &mode {
+        Mode::IndexOnly(index_path) => index_path.clone(), // index_path.provenance.json
+        Mode::HistoOnly(index_path) => {
+            provenance.add_inputs("index", [index_path]);
+            output_prefix.clone()
+        }
+        Mode::Full => output_prefix.clone(),
+    };
+    (|| -> Result<(), Error> {
+        if opt.estimate_only {
+            if !matches!(mode, Mode::Full) || ks.len() > 1 || filename.as_os_str().is_empty() || opt.reads2.is_some() || !samples.is_empty() || opt.reference_counts.is_some() || paired {
+                return Err(Error::Usage("--estimate-only takes one read file and reference files, without subcommands, several k values, --reads2, --manifest, --reference-counts or paired reads.".to_string()));
+            }
+            return estimate::run_estimate(&filename, &ref_filenames, &params, threads, queue_len, ref_queue_len, &output_prefix);
+        }
+        if opt.bin_by_copy_number && (matches!(mode, Mode::IndexOnly(_)) || params.reads_only || ks.len() > 1 || filename.as_os_str().is_empty() || opt.reads2.is_some() || !samples.is_empty() || !opt.reference2.is_empty() || paired) {
+            return Err(Error::Usage("--bin-by-copy-number takes one read file and a reference, without the index subcommand, several k values, --reads2, --manifest, --reference2 or paired reads.".to_string()));
+        }
+        if !opt.maternal.is_empty() || !opt.paternal.is_empty() {
+            if opt.maternal.is_empty() || opt.paternal.is_empty() {return Err(Error::Usage("Trio binning needs both --maternal and --paternal.".to_string()));}
+            if !matches!(mode, Mode::Full) || ks.len() > 1 || filename.as_os_str().is_empty() || opt.reads2.is_some() || !samples.is_empty() || !ref_filenames.is_empty() || opt.reference_counts.is_some() || !opt.reference2.is_empty() || paired {
+                return Err(Error::Usage("Trio binning takes one read file and the parental references, without subcommands, several k values, --reads2, --manifest, --reference, --reference-counts, --reference2 or paired reads.".to_string()));
+            }
+            return trio::run_trio(&filename, &expand_references(opt.maternal)?, &expand_references(opt.paternal)?, &params, threads, queue_len, ref_queue_len, &output_prefix);
+        }
+        if opt.smudge || !opt.haplotype_a.is_empty() || !opt.haplotype_b.is_empty() {
+            if !opt.smudge {return Err(Error::Usage("--haplotype-a and --haplotype-b are for --smudge.".to_string()));}
+            if opt.haplotype_a.is_empty() != opt.haplotype_b.is_empty() {return Err(Error::Usage("--smudge needs both --haplotype-a and --haplotype-b, or neither.".to_string()));}
+            if !matches!(mode, Mode::Full) || ks.len() > 1 || filename.as_os_str().is_empty() || opt.reads2.is_some() || !samples.is_empty() || !ref_filenames.is_empty() || opt.reference_counts.is_some() || !opt.reference2.is_empty() || paired || params.strobemers.is_some() {
+                return Err(Error::Usage("--smudge takes one read file, without subcommands, several k values, --reads2, --manifest, --reference, --reference-counts, --reference2, paired reads or --strobemers.".to_string()));
+            }
+            let haplotypes = if opt.haplotype_a.is_empty() {None} else {Some((expand_references(opt.haplotype_a)?, expand_references(opt.haplotype_b)?))};
+            return smudge::run_smudge(&filename, haplotypes.as_ref().map(|(a, b)| (&a[..], &b[..])), &params, threads, queue_len, &output_prefix);
+        }
+        if ks.len() > 1 {
+            if filename.as_os_str().is_empty() || opt.reads2.is_some() || !samples.is_empty() || opt.reference_counts.is_some() || paired || params.positional_bin.is_some() || params.breadth_report || params.gc_histo
+                || params.per_read_report || params.dedup_reads || params.dump_kminmers.is_some() || params.checkpoint_every.is_some() || params.resume || params.max_memory.is_some() || params.sketch.is_some() || params.prefilter.is_some() {
+                return Err(Error::Usage("A sweep over several k values takes one read file and reference files, without --reads2, --manifest, --reference-counts, paired reads, --positional-histo, --breadth-report, --gc-histo, --per-read-report, --dedup-reads, --dump-kminmers, checkpoints, --max-memory, --sketch or --prefilter.".to_string()));
+            }
+            return sweep::run_sweep(&filename, &ref_filenames, &ks, &params, threads, queue_len, ref_queue_len, &output_prefix);
+        }
+        let ref_positions = if params.positional_bin.is_some() || params.breadth_report {Some(RefPositions::new())} else {None};
+        let mut ref_summary = Summary::new();
+        let ref_mers_index = match loaded_index {
+            Some((index, duration)) => {
+                ref_summary.add_stage("load_index", duration);
+                if let Some(filter) = &params.hash_filter {
+                    index.retain(|h, _| filter.admit(h));
+                }
+                index
+            }
+            None => {
+                let start = Instant::now();
+                let (index, interrupted) = match &opt.reference_counts {
+                    Some(counts_path) => {
+                        let index = Index::with_backend(params.counter_width, &params.index_backend).with_filter(params.hash_filter.clone());
+                        let (nb_kmers, nb_kept) = kmerdump::import(counts_path, &params, &index)?;
+                        info!("Imported {} k-mer counts from {} in {:?}: {} k-min-mers.", nb_kmers, counts_path.to_str().unwrap(), start.elapsed(), nb_kept);
+                        (index, None)
+                    }
+                    None if params.reads_only => (Index::with_backend(params.counter_width, &params.index_backend), None),
+                    None => closures::index_reference(&ref_filenames, &params, ref_threads, ref_queue_len, ref_positions.as_ref())?,
+                };
+                ref_summary.add_stage("index_reference", start.elapsed());
+                if let Mode::IndexOnly(index_path) = &mode {
+                    if let Some(sig) = interrupted {std::process::exit(128 + sig);}
+                    refindex::save(index_path, &params, &index)?;
+                    info!("Reference index written to {}.", index_path.to_str().unwrap());
+                    return Ok(());
+                }
+                closures::exit_if_interrupted(interrupted, &Index::new(), &index, &params, &output_prefix)?;
+                index
+            }
+        };
+
+        if opt.bin_by_copy_number {
+            return cnbin::run_binning(&filename, &ref_mers_index, &params, threads, queue_len, &output_prefix);
+        }
+        if !opt.reference2.is_empty() {
+            let start = Instant::now();
+            closures::compare_references(&expand_references(opt.reference2)?, &ref_mers_index, &params, ref_threads, ref_queue_len, &output_prefix)?;
+            info!("Compared the references in {:?}.", start.elapsed());
+            if no_reads {return Ok(());}
+        }
+        if let Some(dir) = &opt.watch {
+            let interval = Duration::from_secs_f64(opt.watch_interval.unwrap_or(5.0) * 60.0);
+            return watch::run_watch(dir, interval, opt.watch_idle.map(|m| Duration::from_secs_f64(m * 60.0)), &ref_mers_index, ref_positions.as_ref(), &ref_summary, &params, threads, queue_len, &output_prefix);
+        }
+        let filename = if filename.as_os_str().is_empty() {None} else {Some(&filename)};
+        closures::run_mers(filename, opt.reads2.as_ref(), &samples, &ref_mers_index, ref_positions.as_ref(), &ref_summary, &params, threads, queue_len, &output_prefix)
+    })()?;
+    provenance.write(&params, threads, &provenance_prefix)
+}
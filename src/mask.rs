@@ -0,0 +1,93 @@
+// mask.rs
+// Handling of lowercase (soft-masked) bases and IUPAC ambiguity codes before k-min-mer extraction
+// (--mask-policy), rather than leaving them to the minimizer iterators. Bases are uppercased, line breaks of
+// multi-line records dropped, and bases other than ACGT either resolved pseudo-randomly to one of the bases they
+// stand for (uppercase: N to any base, R to A or G, ...; U to T), skipped as if they weren't in the sequence
+// (skip), or treated as sequence breaks that no k-min-mer spans (break). Resolution only depends on the position
+// of the base, so a sequence always gets the same k-min-mers. K-min-mers keep their positions in the original
+// sequence, for regions, positional histograms, GC content and qualities.
+
+use std::str::FromStr;
+use crate::mers::mix;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaskPolicy {
+    Uppercase,
+    Skip,
+    Break,
+}
+impl MaskPolicy {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaskPolicy::Uppercase => "uppercase",
+            MaskPolicy::Skip => "skip",
+            MaskPolicy::Break => "break",
+        }
+    }
+}
+impl FromStr for MaskPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uppercase" => Ok(MaskPolicy::Uppercase),
+            "skip" => Ok(MaskPolicy::Skip),
+            "break" => Ok(MaskPolicy::Break),
+            _ => Err(format!("invalid mask policy '{}' (expected uppercase, skip or break)", s)),
+        }
+    }
+}
+
+// A stretch of normalized sequence, with what it takes to map its positions back to the original sequence.
+pub struct Piece {
+    pub seq: Vec<u8>,
+    start: usize, // position of the first base in the original sequence
+    dropped: Vec<usize>, // for each base dropped from the stretch, the length of seq when it was dropped
+}
+impl Piece {
+
+    fn new(start: usize) -> Self {
+        Piece {seq: Vec::new(), start, dropped: Vec::new()}
+    }
+
+    // Position in the original sequence of base i of the piece.
+    pub fn original(&self, i: usize) -> usize {
+        self.start + i + self.dropped.partition_point(|&len| len <= i)
+    }
+}
+
+// One of the bases an IUPAC code stands for, picked from the position of the base.
+fn resolve(c: u8, pos: usize) -> u8 {
+    let bases: &[u8] = match c {
+        b'U' => b"T",
+        b'R' => b"AG",
+        b'Y' => b"CT",
+        b'S' => b"CG",
+        b'W' => b"AT",
+        b'K' => b"GT",
+        b'M' => b"AC",
+        b'B' => b"CGT",
+        b'D' => b"AGT",
+        b'H' => b"ACT",
+        b'V' => b"ACG",
+        _ => b"ACGT",
+    };
+    bases[(mix(pos as u64) % bases.len() as u64) as usize]
+}
+
+// The normalized pieces of seq under policy: one piece, or with MaskPolicy::Break, one per run of ACGT bases.
+pub fn pieces(seq: &[u8], policy: MaskPolicy) -> Vec<Piece> {
+    let mut pieces = vec![Piece::new(0)];
+    for (pos, &c) in seq.iter().enumerate() {
+        let piece = pieces.last_mut().unwrap();
+        let c = c.to_ascii_uppercase();
+        match (c, policy) {
+            (b'A' | b'C' | b'G' | b'T', _) => piece.seq.push(c),
+            (b'\n' | b'\r', _) | (_, MaskPolicy::Skip) => piece.dropped.push(piece.seq.len()),
+            (_, MaskPolicy::Uppercase) => piece.seq.push(resolve(c, pos)),
+            (_, MaskPolicy::Break) if piece.seq.is_empty() => *piece = Piece::new(pos + 1),
+            (_, MaskPolicy::Break) => pieces.push(Piece::new(pos + 1)),
+        }
+    }
+    pieces
+}
@@ -6,51 +6,466 @@ use std::cmp;
 use std::collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use dashmap::{DashMap, DashSet};
 use rust_seq2kminmers::KminmersIterator;
+use seq_io::BaseRecord;
+use crate::positional::RefPositions;
+use crate::gc::GcMap;
+use crate::readids::ReadIds;
+use crate::quality::{Weights, WEIGHT_SCALE};
+use crate::mask;
+use crate::regions;
+use crate::seeds;
+
+// A k-min-mer: the hash of its k minimizers and the span of sequence it covers.
+pub struct SeqKminmer {
+    hash: u64,
+    pub start: usize,
+    pub end: usize,
+}
+impl SeqKminmer {
+
+    pub fn new(hash: u64, start: usize, end: usize) -> Self {
+        SeqKminmer {hash, start, end}
+    }
+
+    pub fn get_hash_u64(&self) -> u64 {
+        self.hash
+    }
+}
+
+// k-min-mers of a sequence, from the density-based scheme of rust_seq2kminmers or from one implemented here.
+pub enum Kminmers<'a> {
+    Density(KminmersIterator<'a>),
+    Local(std::vec::IntoIter<SeqKminmer>),
+    Complex(Box<Kminmers<'a>>, &'a [u8], f64), // those spanning a window of the sequence of at least this entropy
+}
+impl<'a> Iterator for Kminmers<'a> {
+    type Item = SeqKminmer;
+
+    fn next(&mut self) -> Option<SeqKminmer> {
+        match self {
+            Kminmers::Density(iter) => iter.next().map(|km| SeqKminmer {hash: km.get_hash_u64(), start: km.start, end: km.end}),
+            Kminmers::Local(iter) => iter.next(),
+            Kminmers::Complex(iter, seq, min_entropy) => iter.find(|km| entropy(&seq[km.start.min(seq.len())..km.end.min(seq.len())]) >= *min_entropy),
+        }
+    }
+}
+
+// Shannon entropy of the trinucleotide composition of a window, normalized to [0, 1] by its maximum for the
+// window length: 0 for a homopolymer, 1/6 for a dinucleotide repeat, close to 1 for random sequence.
+// Trinucleotides with a non-ACGT base are left out.
+pub fn entropy(window: &[u8]) -> f64 {
+    let mut counts = [0u32; 64];
+    let mut nb = 0;
+    let code = |c: u8| match c {b'A' | b'a' => Some(0), b'C' | b'c' => Some(1), b'G' | b'g' => Some(2), b'T' | b't' => Some(3), _ => None};
+    for w in window.windows(3) {
+        if let (Some(a), Some(b), Some(c)) = (code(w[0]), code(w[1]), code(w[2])) {
+            counts[a * 16 + b * 4 + c] += 1;
+            nb += 1;
+        }
+    }
+    if nb < 2 {return 0.0;}
+    let h: f64 = counts.iter().filter(|&&c| c > 0).map(|&c| {let p = c as f64 / nb as f64; -p * p.log2()}).sum();
+    h / (nb.min(64) as f64).log2()
+}
+
+// Name and parameters of the minimizer scheme if it isn't the default density-based one, as recorded in
+// histogram headers, index files and checkpoints; empty for the density-based scheme.
+pub fn scheme(params: &Params) -> String {
+    let mut parts = Vec::new();
+    match (params.window, params.syncmers) {
+        (Some(w), _) => parts.push(format!("window={}", w)),
+        (None, Some(kind)) => parts.push(format!("syncmers={} s={}", kind.name(), params.syncmer_s)),
+        (None, None) => {}
+    }
+    if let Some((wmin, wmax)) = params.strobemers {
+        parts.push(format!("strobemers={}-{}", wmin, wmax));
+    }
+    parts.join(" ")
+}
+
+// Mix a 2-bit encoded l-mer into a uniformly distributed hash (murmur3 finalizer).
+pub fn mix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51afd7ed558ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ceb9fe1a85ec53);
+    x ^ (x >> 33)
+}
+
+// Inverse of mix.
+pub fn unmix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0x9cb4b2f8129337db);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0x4f74430c22a54005);
+    x ^ (x >> 33)
+}
+
+// Canonical hashes of the l-mers (l <= 32) of seq, with their start positions. l-mers with a non-ACGT base
+// are skipped; line breaks of multi-line FASTA records are not part of l-mers.
+pub fn lmer_hashes(seq: &[u8], l: usize) -> Vec<(u64, usize)> {
+    let mask = if l >= 32 {u64::MAX} else {(1u64 << (2 * l)) - 1};
+    let (mut fwd, mut rev) = (0u64, 0u64);
+    let mut positions = VecDeque::with_capacity(l + 1); // positions of the last l bases
+    let mut res = Vec::new();
+    for (i, c) in seq.iter().enumerate() {
+        let b: u64 = match c {
+            b'A' | b'a' => 0,
+            b'C' | b'c' => 1,
+            b'G' | b'g' => 2,
+            b'T' | b't' => 3,
+            b'\n' | b'\r' => continue,
+            _ => {positions.clear(); continue;}
+        };
+        fwd = ((fwd << 2) | b) & mask;
+        rev = (rev >> 2) | ((3 - b) << (2 * (l - 1)));
+        positions.push_back(i);
+        if positions.len() > l {positions.pop_front();}
+        if positions.len() == l {
+            res.push((mix(fwd.min(rev)), positions[0]));
+        }
+    }
+    res
+}
+
+// Minimizers of every window of w consecutive l-mers (the leftmost smallest hash), each reported once.
+pub fn window_minimizers(seq: &[u8], l: usize, w: usize) -> Vec<(u64, usize)> {
+    let lmers = lmer_hashes(seq, l);
+    let mut res: Vec<(u64, usize)> = Vec::new();
+    let mut window: VecDeque<usize> = VecDeque::new(); // indices in lmers of increasing hashes, front is the minimizer
+    for i in 0..lmers.len() {
+        while window.back().map_or(false, |&j| lmers[j].0 > lmers[i].0) {
+            window.pop_back();
+        }
+        window.push_back(i);
+        if window[0] + w <= i {window.pop_front();}
+        if i + 1 >= w {
+            let m = lmers[window[0]];
+            if res.last().map_or(true, |last| last.1 != m.1) {res.push(m);}
+        }
+    }
+    res
+}
+
+// l-mers selected with the density-based rule (hash at most density * 2^64), without rust_seq2kminmers.
+pub fn density_minimizers(seq: &[u8], l: usize, density: f64) -> Vec<(u64, usize)> {
+    let bound = (density * u64::MAX as f64) as u64;
+    lmer_hashes(seq, l).into_iter().filter(|m| m.0 <= bound).collect()
+}
+
+// Hash of a k-min-mer from the hashes of its minimizers in sequence order. A k-min-mer and its reverse
+// complement, which has the same minimizers in reverse order, get the same hash.
+pub fn canonical_hash(mers: &[u64]) -> u64 {
+    let rev: Vec<u64> = mers.iter().rev().copied().collect();
+    let mut hasher = DefaultHasher::new();
+    cmp::min(mers, &rev[..]).hash(&mut hasher);
+    hasher.finish()
+}
+
+// k-min-mers of k consecutive minimizers (hash, start), or other seeds such as syncmers.
+pub fn chain_minimizers(minimizers: &[(u64, usize)], k: usize, l: usize) -> Vec<SeqKminmer> {
+    if minimizers.len() < k {return Vec::new();}
+    minimizers.windows(k).map(|mers| {
+        let hashes: Vec<u64> = mers.iter().map(|m| m.0).collect();
+        SeqKminmer {hash: canonical_hash(&hashes), start: mers[0].1, end: mers[k - 1].1 + l}
+    }).collect()
+}
+
+// Minimizers of the schemes implemented here: (w,k) minimizers, syncmers, or density-based ones
+// (as strobes of --strobemers).
+fn local_minimizers(seq: &[u8], params: &Params) -> Vec<(u64, usize)> {
+    match (params.window, params.syncmers) {
+        (Some(w), _) => window_minimizers(seq, params.l, w),
+        (None, Some(kind)) => seeds::syncmers(seq, params.l, params.syncmer_s, kind),
+        (None, None) => density_minimizers(seq, params.l, params.density),
+    }
+}
+
+// Minimizer hashes of each k-min-mer of a sequence (k consecutive minimizers, in sequence order), with the schemes
+// implemented here: the density-based one uses density_minimizers, so canonical_hash of them may differ from the
+// hashes of rust_seq2kminmers.
+pub fn kminmer_minimizers(seq: &[u8], params: &Params) -> Vec<Vec<u64>> {
+    local_minimizers(seq, params).windows(params.k).map(|mers| mers.iter().map(|m| m.0).collect()).collect()
+}
 
 // Extract k-min-mers from reference. We don't store k-min-mer objects or hashes in a Vec, but rather immediately insert into the Index.
-pub fn ref_extract(seq_id: &str, inp_seq_raw: &[u8], params: &Params, ref_mers_index: &Index) -> usize {
-    let l = params.l;
-    let k = params.k;
-    if inp_seq_raw.len() < l+k-1 {
-        return 0;
+// If positions is given, the start and end positions and hash of each k-min-mer are also recorded there.
+// With params.regions, only the k-min-mers starting in a region are indexed; with params.exclude_regions, those
+// overlapping an excluded region are not, nor with params.respect_softmask those overlapping lowercase bases.
+pub fn ref_extract(seq_id: &str, inp_seq_raw: &[u8], params: &Params, ref_mers_index: &Index, positions: Option<&RefPositions>) -> usize {
+    let iter = match extract(seq_id, inp_seq_raw, params) {
+        Some(iter) => iter,
+        None => return 0,
+    };
+    let mut seq_positions = Vec::new();
+    let softmasked = if params.respect_softmask {regions::lowercase_runs(inp_seq_raw)} else {Vec::new()};
+    let count = index_ref_kminmers(seq_id, iter, &softmasked, params, ref_mers_index, positions.map(|_| &mut seq_positions));
+    if let Some(positions) = positions {
+        positions.add(seq_id, inp_seq_raw.len(), seq_positions);
     }
-    let density = params.density;
-    let iter = KminmersIterator::new(inp_seq_raw, l, k, density, false).unwrap();
+    count
+}
+
+// Reference sequences at least twice this long are split in chunks of this length by ref_extract_chunked.
+const REF_CHUNK_LEN: usize = 1 << 22;
+
+// Index the k-min-mers of a reference sequence like ref_extract, splitting it in chunks indexed on threads
+// threads if it is long, so that a few long chromosomes don't leave most threads idle. Each chunk gets the
+// k-min-mers starting in it, extracted from a window that extends past its end until it holds the next k-min-mer
+// too. The result is the same as without chunks with the density-based scheme and syncmers, which select l-mers
+// from their own bases; (w,k) minimizers and randstrobes depend on their neighbours, so sequences aren't split
+// with them.
+pub fn ref_extract_chunked(seq_id: &str, seq: &[u8], params: &Params, ref_mers_index: &Index, positions: Option<&RefPositions>, threads: usize) -> usize {
+    if threads <= 1 || seq.len() < 2 * REF_CHUNK_LEN || params.window.is_some() || params.strobemers.is_some() {
+        return ref_extract(seq_id, seq, params, ref_mers_index, positions);
+    }
+    let nb_chunks = (seq.len() + REF_CHUNK_LEN - 1) / REF_CHUNK_LEN;
+    let chunk_positions: Vec<Mutex<Vec<(usize, usize, u64)>>> = (0..nb_chunks).map(|_| Mutex::new(Vec::new())).collect();
+    let softmasked = if params.respect_softmask {regions::lowercase_runs(seq)} else {Vec::new()};
+    let (next, count) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    thread::scope(|s| {
+        for _ in 0..threads.min(nb_chunks) {
+            s.spawn(|| loop {
+                let chunk = next.fetch_add(1, Ordering::Relaxed);
+                if chunk >= nb_chunks {break;}
+                let (start, end) = (chunk * REF_CHUNK_LEN, ((chunk + 1) * REF_CHUNK_LEN).min(seq.len()));
+                let kminmers = complex(Kminmers::Local(chunk_kminmers(seq, start, end, params).into_iter()), seq, params);
+                let mut seq_positions = chunk_positions[chunk].lock().unwrap();
+                count.fetch_add(index_ref_kminmers(seq_id, kminmers, &softmasked, params, ref_mers_index, positions.map(|_| &mut *seq_positions)), Ordering::Relaxed);
+            });
+        }
+    });
+    if let Some(positions) = positions {
+        positions.add(seq_id, seq.len(), chunk_positions.into_iter().flat_map(|chunk| chunk.into_inner().unwrap()).collect());
+    }
+    count.into_inner()
+}
+
+// The k-min-mers of seq starting in start..end (before the low-complexity filter), at their positions in seq.
+// They are extracted from start to past end, over a margin of about 4 k-min-mer spans, doubled until a k-min-mer
+// starting at end or later shows that none of them was cut.
+fn chunk_kminmers(seq: &[u8], start: usize, end: usize, params: &Params) -> Vec<SeqKminmer> {
+    let mut margin = 4 * ((params.k as f64 / params.density) as usize + params.l);
+    loop {
+        let window_end = (end + margin).min(seq.len());
+        let mut kminmers = Vec::new();
+        let mut complete = window_end == seq.len();
+        for km in unfiltered_kminmers(&seq[start..window_end], params).into_iter().flatten() {
+            if start + km.start >= end {complete = true; break;}
+            kminmers.push(SeqKminmer {hash: km.hash, start: start + km.start, end: start + km.end});
+        }
+        if complete {return kminmers;}
+        margin *= 2;
+    }
+}
+
+// Add reference k-min-mers to the Index, but those outside of params.regions, overlapping params.exclude_regions
+// or the softmasked runs. Their positions and hashes are recorded in seq_positions if given. Returns the number
+// of k-min-mers indexed.
+fn index_ref_kminmers(seq_id: &str, iter: Kminmers, softmasked: &[(usize, usize)], params: &Params, ref_mers_index: &Index, mut seq_positions: Option<&mut Vec<(usize, usize, u64)>>) -> usize {
     let mut count = 0;
     for kminmer in iter {
+        if params.regions.as_ref().map_or(false, |regions| !regions.contains(seq_id, kminmer.start)) {continue;}
+        if params.exclude_regions.as_ref().map_or(false, |regions| regions.overlaps(seq_id, kminmer.start, kminmer.end)) {continue;}
+        if regions::overlaps(&softmasked, kminmer.start, kminmer.end) {continue;}
         // Add a reference k-min-mer to the Index.
-        ref_mers_index.increment(kminmer.get_hash_u64());
+        let h = kminmer.get_hash_u64();
+        match params.ref_cap {
+            Some(cap) => ref_mers_index.increment_capped(h, cap),
+            None => ref_mers_index.increment(h),
+        }
+        if let Some(seq_positions) = seq_positions.as_mut() {seq_positions.push((kminmer.start, kminmer.end, h));}
         count += 1;
     }
     count
 }
 
 // Extract k-min-mers from the query. We need to store Kminmer objects for the query in order to compute Hits.
-pub fn extract<'a>(seq_id: &str, inp_seq_raw: &'a [u8], params: &Params) -> Option<KminmersIterator<'a>> {
+// With params.mask_policy, the k-min-mers of the normalized pieces of the sequence are extracted, at their
+// positions in the original sequence.
+pub fn extract<'a>(seq_id: &str, inp_seq_raw: &'a [u8], params: &Params) -> Option<Kminmers<'a>> {
+    unfiltered_kminmers(inp_seq_raw, params).map(|kminmers| complex(kminmers, inp_seq_raw, params))
+}
+
+// k-min-mers of a sequence, before the low-complexity filter.
+fn unfiltered_kminmers<'a>(inp_seq_raw: &'a [u8], params: &Params) -> Option<Kminmers<'a>> {
+    if let Some(policy) = params.mask_policy {
+        let mut kminmers = Vec::new();
+        for piece in mask::pieces(inp_seq_raw, policy) {
+            if let Some(iter) = seeds_kminmers(&piece.seq, params) {
+                kminmers.extend(iter.map(|km| SeqKminmer {hash: km.hash, start: piece.original(km.start), end: piece.original(km.end - 1) + 1}));
+            }
+        }
+        return Some(Kminmers::Local(kminmers.into_iter()));
+    }
+    seeds_kminmers(inp_seq_raw, params)
+}
+
+// k-min-mers of a sequence taken as is, with the minimizer scheme of params.
+fn seeds_kminmers<'a>(inp_seq_raw: &'a [u8], params: &Params) -> Option<Kminmers<'a>> {
     let l = params.l;
     let k = params.k;
     if inp_seq_raw.len() < l+k-1 {
         return None;
     }
+    if params.window.is_some() || params.syncmers.is_some() || params.strobemers.is_some() {
+        let minimizers = local_minimizers(inp_seq_raw, params);
+        let kminmers = match params.strobemers {
+            Some((wmin, wmax)) => seeds::randstrobes(&minimizers, k, wmin, wmax, l),
+            None => chain_minimizers(&minimizers, k, l),
+        };
+        return Some(Kminmers::Local(kminmers.into_iter()));
+    }
     let density = params.density;
-    return Some(KminmersIterator::new(inp_seq_raw, l, k, density, false).unwrap());
+    return Some(Kminmers::Density(KminmersIterator::new(inp_seq_raw, l, k, density, false).unwrap()));
 }
 
-// populate the hashtable with read kminmers
-pub fn insert_kminmers(query_id: &str, query_it_raw: &mut Option<KminmersIterator>, index: &Index, params: &Params, q_len: usize)  {
+// Leave out the low-complexity k-min-mers with params.min_entropy.
+fn complex<'a>(kminmers: Kminmers<'a>, seq: &'a [u8], params: &Params) -> Kminmers<'a> {
+    match params.min_entropy {
+        Some(min_entropy) => Kminmers::Complex(Box::new(kminmers), seq, min_entropy),
+        None => kminmers,
+    }
+}
+
+// Hashes of the k-min-mers of a read, for when they are counted by another thread.
+// Their GC content is recorded right away if gc is given.
+pub fn read_hashes(q_id: &str, q_str: &[u8], params: &Params, gc: Option<&GcMap>) -> Vec<u64> {
+    let (q_str, _) = trim(q_str, None, params);
+    match extract(q_id, q_str, params) {
+        Some(it) => it.map(|q| {
+            let h = q.get_hash_u64();
+            if let Some(gc) = gc {
+                gc.record(h, &q_str[q.start..q.end.min(q_str.len())]);
+            }
+            h
+        }).collect(),
+        None => Vec::new(),
+    }
+}
+
+// populate the hashtable with read kminmers (and their GC content and read IDs, if gc and read_ids are given)
+// With params.distinct_per_read, a k-min-mer occurring several times in the read is only counted once.
+// With params.restrict_to_reference, only the k-min-mers in ref_mers_index are counted.
+// With params.quality_weights, each occurrence adds its weight from the read qualities (a FASTA read, without
+// qualities, adds a full weight), in quality::WEIGHT_SCALE units.
+// Returns the number of k-min-mers, and how many of them are in ref_mers_index if given.
+pub fn insert_kminmers(query_id: &str, query_it_raw: &mut Option<Kminmers>, index: &Index, params: &Params, q_str: &[u8], qual: Option<&[u8]>, gc: Option<&GcMap>, read_ids: Option<&ReadIds>, ref_mers_index: Option<&Index>) -> (usize, usize) {
     let l = params.l;
     let k = params.k;
-    if query_it_raw.is_none() {return;}
+    if query_it_raw.is_none() {return (0, 0);}
     let mut query_it = query_it_raw.as_mut().unwrap();
+    let (mut nb, mut nb_in_ref) = (0, 0);
+    let mut seen = HashSet::new();
+    let weights = qual.filter(|_| params.quality_weights).map(Weights::new);
+    let read_id: Option<Arc<str>> = read_ids.map(|_| Arc::from(query_id));
     while let Some(q) = query_it.next() {
-        index.increment(q.get_hash_u64());
+        let h = q.get_hash_u64();
+        let in_ref = ref_mers_index.map_or(false, |r| r.get(&h).is_some());
+        if (!params.restrict_to_reference || in_ref) && (!params.distinct_per_read || seen.insert(h)) {
+            match (&weights, params.quality_weights) {
+                (Some(weights), _) => index.increment_by(h, weights.get(q.start, q.end)),
+                (None, true) => index.increment_by(h, WEIGHT_SCALE),
+                (None, false) => index.increment(h),
+            }
+            // only for counted k-min-mers, so that --restrict-to-reference keeps the side maps small too
+            if let Some(gc) = gc {
+                gc.record(h, &q_str[q.start..q.end.min(q_str.len())]);
+            }
+            if let (Some(read_ids), Some(read_id)) = (read_ids, &read_id) {
+                read_ids.record(h, read_id);
+            }
+        }
+        if in_ref {nb_in_ref += 1;}
+        nb += 1;
+    }
+    (nb, nb_in_ref)
+}
+
+
+// populate the hashtable with the kminmers of both mates of a read pair
+// With params.dedup_pairs, a k-min-mer found in both mates (e.g. overlapping mates) is only counted once for the pair.
+pub fn process_pair(mate1: &[u8], mate2: &[u8], read_mers_index: &Index, params: &Params, gc: Option<&GcMap>) {
+    let hashes1 = read_hashes("", mate1, params, gc);
+    let hashes2 = read_hashes("", mate2, params, gc);
+    if params.dedup_pairs {
+        let pair: HashSet<u64> = hashes1.into_iter().chain(hashes2).collect();
+        for h in pair {
+            read_mers_index.increment(h);
+        }
+        return;
+    }
+    for hashes in [hashes1, hashes2] {
+        let mut seen = HashSet::new();
+        for h in hashes {
+            if !params.distinct_per_read || seen.insert(h) {
+                read_mers_index.increment(h);
+            }
+        }
     }
 }
 
+// Trim the ends of a read: params.trim5 and params.trim3 bases, then, given its qualities and
+// params.trim_quality, the bases of lower quality at either end. Returns the remaining sequence and qualities,
+// empty if nothing is left.
+pub fn trim<'a>(q_str: &'a [u8], qual: Option<&'a [u8]>, params: &Params) -> (&'a [u8], Option<&'a [u8]>) {
+    let mut start = params.trim5.min(q_str.len());
+    let mut end = q_str.len().saturating_sub(params.trim3).max(start);
+    if let (Some(qual), Some(min_quality)) = (qual, params.trim_quality) {
+        let end_qual = end.min(qual.len());
+        let low = |i: usize| qual[i].saturating_sub(33) < min_quality;
+        while start < end_qual && low(start) {start += 1;}
+        end = end_qual;
+        while end > start && low(end - 1) {end -= 1;}
+    }
+    (&q_str[start..end], qual.map(|qual| &qual[start.min(qual.len())..end.min(qual.len())]))
+}
 
-pub fn process_read(q_id: &str, q_len: usize, q_str: &[u8], ref_lens: &DashMap<String, usize>, read_mers_index: &Index, params: &Params) {
+pub fn process_read(q_id: &str, q_len: usize, q_str: &[u8], qual: Option<&[u8]>, ref_lens: &DashMap<String, usize>, read_mers_index: &Index, params: &Params, gc: Option<&GcMap>, read_ids: Option<&ReadIds>, ref_mers_index: Option<&Index>) -> (usize, usize) {
+    let (q_str, qual) = trim(q_str, qual, params);
     let mut kminmers = extract(q_id, q_str, params);
-    insert_kminmers(q_id, &mut kminmers, read_mers_index, params, q_len);
+    insert_kminmers(q_id, &mut kminmers, read_mers_index, params, q_str, qual, gc, read_ids, ref_mers_index)
+}
+
+// Count the k-min-mers of an in-memory sequence, as a read or as a reference sequence, for the C, Python and
+// WebAssembly bindings. Returns the number of k-min-mers.
+pub fn index_sequence(seq_id: &str, seq: &[u8], reference: bool, index: &Index, lens: &DashMap<String, usize>, params: &Params) -> usize {
+    if reference {
+        ref_extract(seq_id, seq, params, index, None)
+    }
+    else {
+        process_read(seq_id, seq.len(), seq, None, lens, index, params, None, None, None).0
+    }
+}
+
+// Count the records of an in-memory FASTA or FASTQ file, on the calling thread. Returns the number of records,
+// or None if the buffer isn't FASTA/FASTQ.
+pub fn index_fastx(buf: &[u8], reference: bool, index: &Index, lens: &DashMap<String, usize>, params: &Params) -> Option<usize> {
+    let mut nb_records = 0;
+    match buf.iter().find(|c| !c.is_ascii_whitespace()) {
+        None => {}
+        Some(b'>') => {
+            let mut reader = seq_io::fasta::Reader::new(buf);
+            while let Some(record) = reader.next() {
+                let record = record.ok()?;
+                index_sequence(record.id().unwrap_or(""), &record.seq(), reference, index, lens, params);
+                nb_records += 1;
+            }
+        }
+        Some(b'@') => {
+            let mut reader = seq_io::fastq::Reader::new(buf);
+            while let Some(record) = reader.next() {
+                let record = record.ok()?;
+                index_sequence(record.id().unwrap_or(""), &record.seq(), reference, index, lens, params);
+                nb_records += 1;
+            }
+        }
+        Some(_) => return None,
+    }
+    Some(nb_records)
 }
@@ -0,0 +1,117 @@
+// missing.rs
+// Lists of the k-min-mers behind the edges of the 2D histogram (--report-missing): reference k-min-mers without
+// solid read support (the hist[0][*] row and the rows below the solid threshold), which point at consensus
+// errors or contamination in the assembly. With --positional-histo, they are written as BED intervals of the
+// reference, otherwise as hashes. Conversely, read k-min-mers of high abundance absent from the reference
+// (--report-novel, the hist[*][0] column) flag sequence missing from the assembly; a second pass over the reads
+// can find an example read for each (--novel-read-ids).
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use seq_io::BaseRecord;
+use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
+use crate::closures::open_sequences;
+use crate::error::{Context, Error};
+use crate::index::Index;
+use crate::mers;
+use crate::positional::RefPositions;
+use crate::signals;
+use super::Params;
+use log::{info, warn};
+
+// Write prefix.missing, or prefix.missing.bed if reference positions were recorded: the reference k-min-mers
+// seen fewer than solid_threshold times in the reads.
+pub fn write_missing_ref(read_mers_index: &Index, ref_mers_index: &Index, ref_positions: Option<&RefPositions>, solid_threshold: u64, output_prefix: &PathBuf) -> Result<(), Error> {
+    let read_count = |h: &u64| read_mers_index.get(h).unwrap_or(0);
+    let mut nb_missing = 0;
+    let path = match ref_positions {
+        Some(positions) => {
+            let path = format!("{}{}", output_prefix.to_str().unwrap(), ".missing.bed");
+            let mut file = BufWriter::new(File::create(&path).output(&path)?);
+            for (seq_id, kminmers) in positions.sorted() {
+                for (start, end, h) in kminmers.iter().filter(|(_, _, h)| read_count(h) < solid_threshold) {
+                    writeln!(file, "{}\t{}\t{}\t{}\t{}", seq_id, start, end, h, read_count(h)).output(&path)?;
+                    nb_missing += 1;
+                }
+            }
+            file.flush().output(&path)?;
+            path
+        }
+        None => {
+            let mut missing: Vec<_> = ref_mers_index.iter().filter(|(h, _)| read_count(h) < solid_threshold).collect();
+            missing.sort_unstable();
+            let path = format!("{}{}", output_prefix.to_str().unwrap(), ".missing");
+            let mut file = BufWriter::new(File::create(&path).output(&path)?);
+            writeln!(file, "# hash\tref_count\tread_count").output(&path)?;
+            for (h, ref_count) in &missing {
+                writeln!(file, "{}\t{}\t{}", h, ref_count, read_count(h)).output(&path)?;
+            }
+            file.flush().output(&path)?;
+            nb_missing = missing.len();
+            path
+        }
+    };
+    info!("{} reference k-min-mer{} with a read abundance below {} written to {}.", nb_missing, if ref_positions.is_some() {" occurrences"} else {"s"}, solid_threshold, path);
+    Ok(())
+}
+
+// Read k-min-mers seen at least min_abundance times and absent from the reference, as (hash, read count),
+// by decreasing read count.
+pub fn novel_kminmers(read_mers_index: &Index, ref_mers_index: &Index, min_abundance: u64) -> Vec<(u64, u64)> {
+    let mut novel: Vec<_> = read_mers_index.iter()
+        .filter(|(h, count)| *count >= min_abundance && ref_mers_index.get(h).is_none())
+        .collect();
+    novel.sort_unstable_by_key(|&(h, count)| (std::cmp::Reverse(count), h));
+    novel
+}
+
+// Second pass over a read file, to find the first read containing each of the given k-min-mers (novel or top).
+pub fn example_reads(filename: &PathBuf, kminmers: &[(u64, u64)], params: &Params, threads: usize, queue_len: usize) -> Result<HashMap<u64, String>, Error> {
+    let mut examples = HashMap::new();
+    if kminmers.is_empty() {return Ok(examples);}
+    let wanted: HashSet<u64> = kminmers.iter().map(|(h, _)| *h).collect();
+    let find_hashes = |seq_id: &str, seq: &[u8]| -> Vec<u64> {
+        match mers::extract(seq_id, seq, params) {
+            Some(kminmers) => kminmers.map(|kminmer| kminmer.get_hash_u64()).filter(|h| wanted.contains(h)).collect(),
+            None => Vec::new(),
+        }
+    };
+    let mut main_thread = |seq_id: &str, found: &mut Vec<u64>| { // runs in main thread, in read order
+        for h in found.drain(..) {
+            examples.entry(h).or_insert_with(|| seq_id.to_string());
+        }
+        signals::pending()
+    };
+    let (buf, is_fasta) = open_sequences(filename)?;
+    let interrupted = if is_fasta {
+        let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fasta_records(reader, threads as u32, queue_len, |record, found: &mut Vec<u64>| {*found = find_hashes(record.id().unwrap_or(""), record.seq())}, |record, found| {main_thread(record.id().unwrap_or(""), found)})
+    }
+    else {
+        let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fastq_records(reader, threads as u32, queue_len, |record, found: &mut Vec<u64>| {*found = find_hashes(record.id().unwrap_or(""), record.seq())}, |record, found| {main_thread(record.id().unwrap_or(""), found)})
+    }.map_err(|e| Error::corrupt(filename, e))?;
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {} while looking for example reads.", sig);
+        std::process::exit(128 + sig);
+    }
+    Ok(examples)
+}
+
+// Write prefix.novel: the novel read k-min-mers, with the ID of a read containing each if examples are given.
+pub fn write_novel(novel: &[(u64, u64)], examples: Option<&HashMap<u64, String>>, min_abundance: u64, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".novel");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    writeln!(file, "# hash\tread_count{}", if examples.is_some() {"\tread_id"} else {""}).output(&path)?;
+    for (h, count) in novel {
+        match examples {
+            Some(examples) => writeln!(file, "{}\t{}\t{}", h, count, examples.get(h).map_or("NA", |id| id.as_str())),
+            None => writeln!(file, "{}\t{}", h, count),
+        }.output(&path)?;
+    }
+    file.flush().output(&path)?;
+    info!("{} read k-min-mers seen at least {} times and absent from the reference written to {}.", novel.len(), min_abundance, path);
+    Ok(())
+}
@@ -0,0 +1,103 @@
+// mmap.rs
+// Memory-mapped reading of uncompressed local FASTA references: records are found in the mapping and their
+// sequences handed to the indexing threads as slices of it, rather than copied by the parser into record sets
+// and then into a Vec per record. Sequences keep their line breaks, as those of seq_io records do, so k-min-mers
+// are the same either way. Compressed and remote files, FASTQ and WebAssembly builds go through seq_io.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use crate::error::Error;
+use crate::signals;
+
+// The memory mapping of a file, or nothing in WebAssembly builds.
+#[cfg(not(target_arch = "wasm32"))]
+pub type Mapping = memmap2::Mmap;
+#[cfg(target_arch = "wasm32")]
+pub type Mapping = Vec<u8>;
+
+// Whether a file can be mapped: a local file, without a compression extension.
+#[cfg(not(target_arch = "wasm32"))]
+fn is_mappable(path: &PathBuf) -> bool {
+    let name = path.to_string_lossy();
+    !crate::remote::is_url(&name) && !crate::aligned::is_aligned(path) && ![".gz", ".bgz", ".lz4"].iter().any(|ext| name.ends_with(ext))
+}
+
+// Map a reference file if it is an uncompressed local FASTA file.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn map_fasta(path: &PathBuf) -> Result<Option<Mapping>, Error> {
+    use crate::error::Context;
+    if !is_mappable(path) {return Ok(None);}
+    let file = std::fs::File::open(path).input(path)?;
+    if file.metadata().input(path)?.len() == 0 {return Ok(None);}
+    // the mapping is only read, while the file is open; it is undefined if another process truncates the file
+    let mapping = unsafe {memmap2::Mmap::map(&file)}.input(path)?;
+    let is_fasta = mapping.iter().find(|c| !c.is_ascii_whitespace()) == Some(&b'>');
+    Ok(if is_fasta {Some(mapping)} else {None})
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn map_fasta(_path: &PathBuf) -> Result<Option<Mapping>, Error> {
+    Ok(None)
+}
+
+// The records of a FASTA file in memory, as (id, sequence): the id is the first word of the header, and the
+// sequence spans its lines, with the line breaks between them.
+pub struct FastaRecords<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> FastaRecords<'a> {
+
+    pub fn new(data: &'a [u8]) -> Self {
+        let pos = data.iter().position(|&c| c == b'>').unwrap_or(data.len());
+        FastaRecords {data, pos}
+    }
+}
+impl<'a> Iterator for FastaRecords<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {return None;}
+        let rest = &self.data[self.pos + 1..];
+        let header_len = rest.iter().position(|&c| c == b'\n').unwrap_or(rest.len());
+        let header = &rest[..header_len];
+        let seq_start = (header_len + 1).min(rest.len());
+        // the next record starts at a '>' at the start of a line
+        let mut seq_end = seq_start;
+        while let Some(i) = rest[seq_end..].iter().position(|&c| c == b'>') {
+            seq_end += i;
+            if rest[seq_end - 1] == b'\n' {break;}
+            seq_end += 1;
+        }
+        if seq_end < rest.len() && rest[seq_end] == b'>' {self.pos += 1 + seq_end;}
+        else {seq_end = rest.len(); self.pos = self.data.len();}
+        let mut seq = &rest[seq_start..seq_end];
+        while let [head @ .., b'\n' | b'\r'] = seq {seq = head;}
+        let id_len = header.iter().position(|c| c.is_ascii_whitespace()).unwrap_or(header.len());
+        Some((&header[..id_len], seq))
+    }
+}
+
+// Call index_seq on the records of a mapped FASTA file, on threads threads. Returns the signal number if
+// indexing was interrupted.
+pub fn index_fasta(data: &[u8], threads: usize, index_seq: impl Fn(&str, &[u8]) + Sync) -> Option<i32> {
+    let records = Mutex::new(FastaRecords::new(data));
+    let interrupted = Mutex::new(None);
+    thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| loop {
+                let (id, seq) = match records.lock().unwrap().next() {
+                    Some(record) => record,
+                    None => break,
+                };
+                if let Some(sig) = signals::pending() {
+                    *interrupted.lock().unwrap() = Some(sig);
+                    break;
+                }
+                index_seq(&String::from_utf8_lossy(id), seq);
+            });
+        }
+    });
+    interrupted.into_inner().unwrap()
+}
@@ -0,0 +1,147 @@
+// nbfit.rs
+// Negative binomial mixture fits of the read abundance distribution conditioned on reference copy number
+// (--nb-mixture K): for each reference abundance column of the 2D histogram (1 to 9+), the read abundances of its
+// k-min-mers (row 0 included, for those missing from the reads) are fitted by an EM algorithm with K negative
+// binomial components, and the weights, means and dispersions are written to prefix.nbfit.json. The E step
+// computes the responsibility of each component for each abundance; the M step takes each component's weight and
+// mean from them, and its dispersion by matching its weighted variance (mean + mean^2/size), so that components
+// whose variance doesn't exceed their mean become Poisson.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use serde_json::{json, Value};
+use crate::error::{Context, Error};
+use crate::histo::{self, NB_REF_BINS};
+use crate::spectrum;
+use super::Params;
+use log::info;
+
+// Fewest k-min-mers in a column for it to be fitted.
+const MIN_KMINMERS: u64 = 10;
+// Largest number of EM iterations, and relative log-likelihood change at which the fit has converged.
+const MAX_ITERATIONS: usize = 500;
+const TOLERANCE: f64 = 1e-8;
+// Size (r) standing for a Poisson component.
+const POISSON_SIZE: f64 = 1e6;
+
+// A component of the mixture: its weight (fraction of the k-min-mers), mean and size (dispersion r: the variance
+// is mean + mean^2/size).
+#[derive(Clone, Copy, Debug)]
+pub struct Component {
+    pub weight: f64,
+    pub mean: f64,
+    pub size: f64,
+}
+
+// Result of fitting a mixture to a distribution.
+#[derive(Clone, Debug)]
+pub struct MixtureFit {
+    pub components: Vec<Component>, // by increasing mean
+    pub log_likelihood: f64,
+    pub iterations: usize,
+}
+
+// Log-probabilities of the abundances 0..=max_x under a component.
+fn log_pmf(c: &Component, max_x: usize) -> Vec<f64> {
+    spectrum::log_pmf(c.mean, 1.0 + c.mean / c.size, max_x)
+}
+
+// Fit a mixture of nb_components negative binomials to counts (counts[x] = number of k-min-mers seen x times)
+// with EM. Components start at the quantiles of the distribution, nearly Poisson.
+pub fn fit_mixture(counts: &[u64], nb_components: usize) -> Option<MixtureFit> {
+    let total: u64 = counts.iter().sum();
+    if total == 0 || nb_components == 0 {return None;}
+    let max_x = counts.iter().rposition(|&n| n > 0).unwrap();
+    let counts = &counts[..=max_x];
+    let mut components: Vec<Component> = (0..nb_components).map(|c| {
+        let target = (c as f64 + 0.5) / nb_components as f64 * total as f64;
+        let mut seen = 0;
+        let quantile = counts.iter().position(|&n| {seen += n; seen as f64 >= target}).unwrap_or(max_x);
+        Component {weight: 1.0 / nb_components as f64, mean: (quantile as f64).max(0.5), size: 10.0}
+    }).collect();
+    let mut log_likelihood = f64::NEG_INFINITY;
+    let mut iterations = 0;
+    let mut resp = vec![vec![0.0; max_x + 1]; nb_components];
+    while iterations < MAX_ITERATIONS {
+        iterations += 1;
+        // E step, with log-sum-exp over the components
+        let log_pmfs: Vec<Vec<f64>> = components.iter().map(|c| log_pmf(c, max_x)).collect();
+        let mut new_log_likelihood = 0.0;
+        for x in 0..=max_x {
+            let terms: Vec<f64> = (0..nb_components).map(|c| components[c].weight.ln() + log_pmfs[c][x]).collect();
+            let top = terms.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let sum: f64 = terms.iter().map(|t| (t - top).exp()).sum();
+            for c in 0..nb_components {resp[c][x] = (terms[c] - top).exp() / sum;}
+            new_log_likelihood += counts[x] as f64 * (top + sum.ln());
+        }
+        // M step
+        for (c, component) in components.iter_mut().enumerate() {
+            let n: f64 = (0..=max_x).map(|x| counts[x] as f64 * resp[c][x]).sum();
+            if n <= 0.0 {component.weight = 0.0; continue;}
+            let mean = (0..=max_x).map(|x| counts[x] as f64 * resp[c][x] * x as f64).sum::<f64>() / n;
+            let var = (0..=max_x).map(|x| counts[x] as f64 * resp[c][x] * (x as f64 - mean).powi(2)).sum::<f64>() / n;
+            component.weight = n / total as f64;
+            component.mean = mean.max(1e-6);
+            component.size = if var > mean {(mean * mean / (var - mean)).min(POISSON_SIZE)} else {POISSON_SIZE};
+        }
+        // a component left without k-min-mers can't come back
+        components.retain(|c| c.weight > 0.0);
+        resp.truncate(components.len());
+        let converged = (new_log_likelihood - log_likelihood).abs() <= TOLERANCE * new_log_likelihood.abs();
+        log_likelihood = new_log_likelihood;
+        if converged {break;}
+    }
+    components.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap());
+    Some(MixtureFit {components, log_likelihood, iterations})
+}
+
+// Read abundance distributions of the reference abundance columns 1 to NB_REF_BINS - 1 of the 2D histogram,
+// without the last row, which accumulates all higher abundances.
+fn columns(hist: &Vec<Vec<u64>>) -> Vec<(usize, Vec<u64>)> {
+    (1..NB_REF_BINS).map(|j| (j, hist[..hist.len() - 1].iter().map(|row| row[j]).collect())).collect()
+}
+
+// Fit the mixtures of all columns with enough k-min-mers, as (reference abundance, number of k-min-mers, fit).
+pub fn fit_columns(hist: &Vec<Vec<u64>>, nb_components: usize) -> Vec<(usize, u64, MixtureFit)> {
+    columns(hist).into_iter()
+        .filter_map(|(j, counts)| {
+            let total = counts.iter().sum::<u64>();
+            if total < MIN_KMINMERS {return None;}
+            fit_mixture(&counts, nb_components).map(|fit| (j, total, fit))
+        })
+        .collect()
+}
+
+// Write the mixture fits of the columns of the 2D histogram to prefix.nbfit.json.
+pub fn write_nbfit(hist: &Vec<Vec<u64>>, nb_components: usize, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let fits = fit_columns(hist, nb_components);
+    let columns: Vec<Value> = fits.iter().map(|(j, total, fit)| json!({
+        "ref_abundance": if *j == NB_REF_BINS - 1 {format!("{}+", j)} else {j.to_string()},
+        "nb_kminmers": total,
+        "log_likelihood": fit.log_likelihood,
+        "iterations": fit.iterations,
+        "components": fit.components.iter().map(|c| json!({
+            "weight": c.weight,
+            "mean": c.mean,
+            "size": c.size,
+            "variance": c.mean + c.mean * c.mean / c.size,
+        })).collect::<Vec<_>>(),
+    })).collect();
+    let doc = json!({
+        "metadata": histo::metadata(params),
+        "nb_components": nb_components,
+        "columns": columns,
+    });
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".nbfit.json");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    serde_json::to_writer_pretty(&mut file, &doc).map_err(std::io::Error::from).output(&path)?;
+    writeln!(file).output(&path)?;
+    file.flush().output(&path)?;
+    if let Some((_, _, fit)) = fits.iter().find(|(j, _, _)| *j == 1) {
+        let main = fit.components.iter().max_by(|a, b| a.weight.partial_cmp(&b.weight).unwrap()).unwrap();
+        info!("Single-copy reference k-min-mers: main component of mean {:.2} and size {:.2} ({:.1}% of them).", main.mean, main.size, main.weight * 100.0);
+    }
+    info!("Negative binomial mixture fits of {} reference abundance columns written to {}.", fits.len(), path);
+    Ok(())
+}
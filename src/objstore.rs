@@ -0,0 +1,261 @@
+// objstore.rs
+// Streaming of inputs stored in object stores (s3:// and gs:// URIs). Objects are read sequentially
+// with ranged GET requests, so that a dropped connection only costs a retry of the current range.
+//
+// S3 credentials are taken from AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN
+// (requests are unsigned without them), the region from AWS_REGION or AWS_DEFAULT_REGION, and
+// AWS_ENDPOINT_URL selects an S3-compatible server. GCS requests use GOOGLE_OAUTH_ACCESS_TOKEN if set.
+
+use std::env;
+use std::io::{self, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+
+// Bytes requested per ranged GET.
+const RANGE_SIZE: u64 = 64 * 1024 * 1024;
+// Attempts for each range before giving up.
+const MAX_ATTEMPTS: usize = 5;
+
+// Check whether an input path is an object store URI.
+pub fn is_uri(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("gs://")
+}
+
+// Open an object for streaming.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open(uri: &str) -> io::Result<Box<dyn Read + Send>> {
+    let (scheme, rest) = uri.split_once("://").unwrap();
+    let (bucket, key) = rest.split_once('/').ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no object key in {}", uri)))?;
+    let store = match scheme {
+        "s3" => Store::S3 {
+            region: env::var("AWS_REGION").or_else(|_| env::var("AWS_DEFAULT_REGION")).unwrap_or_else(|_| "us-east-1".to_string()),
+            endpoint: env::var("AWS_ENDPOINT_URL").ok().map(|e| e.trim_end_matches('/').to_string()),
+            credentials: match (env::var("AWS_ACCESS_KEY_ID"), env::var("AWS_SECRET_ACCESS_KEY")) {
+                (Ok(id), Ok(secret)) => Some((id, secret, env::var("AWS_SESSION_TOKEN").ok())),
+                _ => None,
+            },
+        },
+        _ => Store::Gcs {token: env::var("GOOGLE_OAUTH_ACCESS_TOKEN").ok()},
+    };
+    let mut reader = RangedReader {store, bucket: bucket.to_string(), key: key.to_string(), pos: 0, size: None, current: None};
+    // fail now rather than in the middle of parsing if the object can't be read
+    reader.next_range()?;
+    Ok(Box::new(reader))
+}
+
+// No HTTP client in WebAssembly builds (see remote.rs).
+#[cfg(target_arch = "wasm32")]
+pub fn open(uri: &str) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("object store URIs aren't supported in WebAssembly builds: {}", uri)))
+}
+
+enum Store {
+    S3 {region: String, endpoint: Option<String>, credentials: Option<(String, String, Option<String>)>},
+    Gcs {token: Option<String>},
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Store {
+
+    // GET the given byte range of an object.
+    fn get(&self, bucket: &str, key: &str, start: u64, end: u64) -> Result<ureq::Response, ureq::Error> {
+        let range = format!("bytes={}-{}", start, end);
+        match self {
+            Store::Gcs {token} => {
+                let request = ureq::get(&format!("https://storage.googleapis.com/{}/{}", bucket, uri_encode(key))).set("Range", &range);
+                match token {
+                    Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+                    None => request,
+                }.call()
+            }
+            Store::S3 {region, endpoint, credentials} => {
+                // path-style requests for custom endpoints, virtual-hosted style for AWS
+                let (base, path) = match endpoint {
+                    Some(endpoint) => (endpoint.clone(), format!("/{}/{}", bucket, uri_encode(key))),
+                    None => (format!("https://{}.s3.{}.amazonaws.com", bucket, region), format!("/{}", uri_encode(key))),
+                };
+                let request = ureq::get(&format!("{}{}", base, path)).set("Range", &range);
+                match credentials {
+                    None => request.call(),
+                    Some((id, secret, token)) => {
+                        let host = base.split_once("://").map_or(base.as_str(), |(_, h)| h);
+                        let mut request = request;
+                        for (name, value) in sign_v4(host, &path, &range, region, id, secret, token.as_deref()) {
+                            request = request.set(name, &value);
+                        }
+                        request.call()
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Sequential reader over an object, one range at a time.
+struct RangedReader {
+    store: Store,
+    bucket: String,
+    key: String,
+    pos: u64,          // offset of the next byte to return
+    size: Option<u64>, // object size, known after the first response
+    current: Option<(Box<dyn Read + Send>, u64)>, // body of the current range, and its end offset
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RangedReader {
+
+    // Request the range starting at pos. Leaves current empty at the end of the object.
+    fn next_range(&mut self) -> io::Result<()> {
+        self.current = None;
+        if self.size.map_or(false, |size| self.pos >= size) {return Ok(());}
+        let end = self.pos + RANGE_SIZE - 1;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.store.get(&self.bucket, &self.key, self.pos, end) {
+                Ok(response) => {
+                    // "Content-Range: bytes start-end/size"
+                    if let Some(size) = response.header("Content-Range").and_then(|r| r.rsplit('/').next()).and_then(|s| s.parse().ok()) {
+                        self.size = Some(size);
+                    }
+                    else if response.status() == 200 {
+                        // the server ignored the range and sent the whole object
+                        self.size = response.header("Content-Length").and_then(|s| s.parse().ok());
+                    }
+                    let range_end = self.size.map_or(end + 1, |size| (end + 1).min(size));
+                    self.current = Some((response.into_reader(), range_end));
+                    return Ok(());
+                }
+                // range starting at the end of the object, e.g. an empty object
+                Err(ureq::Error::Status(416, _)) => {
+                    self.size = Some(self.pos);
+                    return Ok(());
+                }
+                Err(ureq::Error::Status(code, response)) if code < 500 || attempt >= MAX_ATTEMPTS => {
+                    let msg = response.into_string().unwrap_or_default();
+                    let kind = if code == 404 {io::ErrorKind::NotFound} else if code == 403 {io::ErrorKind::PermissionDenied} else {io::ErrorKind::Other};
+                    return Err(io::Error::new(kind, format!("HTTP status {} {}", code, msg.trim())));
+                }
+                Err(e) if attempt >= MAX_ATTEMPTS => return Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Read for RangedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut attempt = 0;
+        loop {
+            let (body, range_end) = match self.current.as_mut() {
+                Some(c) => (&mut c.0, c.1),
+                None => return Ok(0),
+            };
+            match body.read(buf) {
+                Ok(0) if self.pos < range_end => {
+                    // connection closed early: request the rest again
+                    attempt += 1;
+                    if attempt >= MAX_ATTEMPTS {return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "object stream ended early"));}
+                    self.next_range()?;
+                }
+                Ok(0) => self.next_range()?,
+                Ok(n) => {
+                    self.pos += n as u64;
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    attempt += 1;
+                    if attempt >= MAX_ATTEMPTS {return Err(e);}
+                    self.next_range()?;
+                }
+            }
+        }
+    }
+}
+
+// Percent-encode an object key as required by SigV4 (unreserved characters and '/' are kept).
+fn uri_encode(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => res.push(b as char),
+            _ => res.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    res
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    }
+    else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let ipad: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let opad: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    let inner = Sha256::new().chain_update(&ipad).chain_update(msg).finalize();
+    Sha256::new().chain_update(&opad).chain_update(inner).finalize().to_vec()
+}
+
+// Current UTC time as (YYYYMMDD, YYYYMMDDTHHMMSSZ).
+fn amz_date() -> (String, String) {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let (year, month, day) = civil_date((secs / 86400) as i64);
+    let rem = secs % 86400;
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let datetime = format!("{}T{:02}{:02}{:02}Z", date, rem / 3600, rem % 3600 / 60, rem % 60);
+    (date, datetime)
+}
+
+// Days since 1970-01-01 to (year, month, day), with Howard Hinnant's algorithm.
+pub fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = yoe + era * 400 + if month <= 2 {1} else {0};
+    (year, month, day)
+}
+
+// AWS Signature Version 4 headers for a GET of path on host with the given Range header.
+fn sign_v4(host: &str, path: &str, range: &str, region: &str, id: &str, secret: &str, token: Option<&str>) -> Vec<(&'static str, String)> {
+    let (date, datetime) = amz_date();
+    let payload_hash = "UNSIGNED-PAYLOAD";
+    let mut headers = vec![
+        ("host", host.to_string()),
+        ("range", range.to_string()),
+        ("x-amz-content-sha256", payload_hash.to_string()),
+        ("x-amz-date", datetime.clone()),
+    ];
+    if let Some(token) = token {
+        headers.push(("x-amz-security-token", token.to_string()));
+    }
+    let canonical_headers: String = headers.iter().map(|(n, v)| format!("{}:{}\n", n, v.trim())).collect();
+    let signed_headers = headers.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(";");
+    let canonical_request = format!("GET\n{}\n\n{}\n{}\n{}", path, canonical_headers, signed_headers, payload_hash);
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", datetime, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+
+    let k_date = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, b"s3");
+    let k_signing = hmac_sha256(&k_service, b"aws4_request");
+    let signature = hex(&hmac_sha256(&k_signing, string_to_sign.as_bytes()));
+
+    // host is set by the HTTP client
+    let mut res: Vec<(&'static str, String)> = headers.into_iter().filter(|(n, _)| *n != "host" && *n != "range").collect();
+    res.push(("Authorization", format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", id, scope, signed_headers, signature)));
+    res
+}
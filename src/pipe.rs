@@ -0,0 +1,89 @@
+// pipe.rs
+// Reader that runs a decompressor on its own thread, so that decompression overlaps with FASTA/FASTQ parsing
+// instead of running inline on the parsing thread. Decompressed data is passed in chunks through a bounded
+// channel, which caps the memory used when the parser is slower than the decompressor.
+
+use std::io::{self, BufRead, Read};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+
+const CHUNK_SIZE: usize = 1 << 20; // bytes of decompressed data per chunk
+const QUEUE_CHUNKS: usize = 16;    // chunks buffered ahead of the parser
+
+pub struct PipeReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    buf: Vec<u8>, // current chunk
+    pos: usize,
+    eof: bool,
+}
+
+impl PipeReader {
+
+    // Start reading inner on a new thread. The thread stops at the end of inner, on an error (passed on to
+    // the reader), or when the PipeReader is dropped.
+    pub fn new<R: Read + Send + 'static>(mut inner: R) -> Self {
+        let (sender, receiver) = sync_channel(QUEUE_CHUNKS);
+        thread::spawn(move || {
+            loop {
+                let mut chunk = vec![0u8; CHUNK_SIZE];
+                let mut len = 0;
+                // fill the chunk, as decompressors often return less than asked for
+                let res = loop {
+                    match inner.read(&mut chunk[len..]) {
+                        Ok(0) => break Ok(()),
+                        Ok(n) => {
+                            len += n;
+                            if len == CHUNK_SIZE {break Ok(());}
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => break Err(e),
+                    }
+                };
+                chunk.truncate(len);
+                if len > 0 && sender.send(Ok(chunk)).is_err() {return;}
+                match res {
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        return;
+                    }
+                    Ok(()) if len < CHUNK_SIZE => return, // end of the stream
+                    Ok(()) => {}
+                }
+            }
+        });
+        PipeReader {receiver, buf: Vec::new(), pos: 0, eof: false}
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let data = self.fill_buf()?;
+            let n = data.len().min(out.len());
+            out[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for PipeReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos >= self.buf.len() && !self.eof {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.buf = chunk?;
+                    self.pos = 0;
+                }
+                // the thread is done once it has sent everything
+                Err(_) => self.eof = true,
+            }
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
@@ -0,0 +1,90 @@
+// ploidy.rs
+// Ploidy estimation from the read spectrum (--estimate-ploidy): a genome of ploidy p has coverage peaks at c, 2c,
+// ..., p*c, k-min-mers shared by i of its p haplotypes being seen about i*c times. For each p from 1 to MAX_PLOIDY,
+// peaks of negative binomials at these multiples are fitted to the spectrum past the error valley (as in
+// spectrum::fit, over the fano factor and c, near the lowest significant peak), and the fits are compared by their
+// BIC, so that extra peaks must explain the spectrum better to be chosen. The confidence of each ploidy is its BIC
+// weight (the posterior probability of the Schwarz approximation), written to prefix.ploidy.tsv.
+
+use std::cmp;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::histo;
+use crate::spectrum;
+use log::{info, warn};
+
+pub const MAX_PLOIDY: usize = 6;
+
+// Best fit of the peaks of a ploidy.
+#[derive(Clone, Debug)]
+pub struct PloidyFit {
+    pub ploidy: usize,
+    pub coverage: f64,        // c: coverage of sequence present in a single haplotype
+    pub fano: f64,
+    pub weights: Vec<f64>,    // distinct k-min-mers in each peak, shared by 1..=ploidy haplotypes
+    pub bic: f64,
+    pub probability: f64,     // BIC weight among the ploidies fitted
+}
+
+// Fit the peaks of each ploidy to a read spectrum (spectrum[x] = number of distinct k-min-mers seen x times),
+// over the abundances from the error valley to 3 times the highest peak. Returns the fits by ploidy, or nothing if
+// the spectrum has no peak.
+pub fn estimate(spectrum: &[u64]) -> Vec<PloidyFit> {
+    let valley = spectrum::error_threshold(spectrum);
+    let peaks = spectrum::significant_peaks(spectrum, valley);
+    let (main, lowest) = match (peaks.first(), peaks.iter().min()) {
+        (Some(&main), Some(&lowest)) => (main as f64, lowest as f64),
+        _ => return Vec::new(),
+    };
+    let lo = valley + 1;
+    let hi = cmp::min(spectrum.len() - 2, (3.0 * main).ceil() as usize);
+    if hi <= lo {return Vec::new();}
+    let n = (hi - lo + 1) as f64;
+    let nb_steps = 200;
+    let mut fits: Vec<PloidyFit> = (1..=MAX_PLOIDY).filter_map(|ploidy| {
+        let mut best: Option<(f64, f64, Vec<f64>, f64)> = None;
+        // the lowest peak is the first multiple of c: peaks of extra multiples hidden between the visible ones
+        // would otherwise be free to fit the shape of the spectrum
+        let (min_c, max_c) = (0.8 * lowest, 1.2 * lowest);
+        for step in 0..=nb_steps {
+            let c = min_c + (max_c - min_c) * step as f64 / nb_steps as f64;
+            for &fano in &[1.0, 1.25, 1.5, 2.0, 3.0, 5.0, 8.0] {
+                if let Some((weights, rss)) = spectrum::fit_weights(spectrum, lo, hi, c, fano, ploidy) {
+                    if best.as_ref().map_or(true, |b| rss < b.3) {
+                        best = Some((c, fano, weights, rss));
+                    }
+                }
+            }
+        }
+        let (coverage, fano, weights, rss) = best?;
+        // parameters: the weights, c and the fano factor
+        let bic = n * (rss.max(f64::MIN_POSITIVE) / n).ln() + (ploidy + 2) as f64 * n.ln();
+        Some(PloidyFit {ploidy, coverage, fano, weights, bic, probability: 0.0})
+    }).collect();
+    let min_bic = fits.iter().map(|f| f.bic).fold(f64::INFINITY, f64::min);
+    let total: f64 = fits.iter().map(|f| (-(f.bic - min_bic) / 2.0).exp()).sum();
+    for fit in fits.iter_mut() {
+        fit.probability = (-(fit.bic - min_bic) / 2.0).exp() / total;
+    }
+    fits
+}
+
+// Estimate the ploidy from the read marginal of the 2D histogram and write the fits to prefix.ploidy.tsv.
+pub fn write_ploidy(hist: &Vec<Vec<u64>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    let fits = estimate(&histo::read_marginal(hist));
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".ploidy.tsv");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    writeln!(file, "# ploidy\tprobability\tbic\tcoverage\tfano_factor\tpeak_weights").output(&path)?;
+    for fit in &fits {
+        let weights: Vec<String> = fit.weights.iter().map(|w| format!("{:.0}", w)).collect();
+        writeln!(file, "{}\t{:.4}\t{:.2}\t{:.3}\t{}\t{}", fit.ploidy, fit.probability, fit.bic, fit.coverage, fit.fano, weights.join(",")).output(&path)?;
+    }
+    file.flush().output(&path)?;
+    match fits.iter().max_by(|a, b| a.probability.partial_cmp(&b.probability).unwrap()) {
+        Some(best) => info!("Estimated ploidy: {} (confidence {:.2}), with a coverage of {:.1} per haplotype.", best.ploidy, best.probability, best.coverage),
+        None => warn!("Could not estimate the ploidy: the read spectrum has no coverage peak."),
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+// positional.rs
+// Positional coverage histogram: for each bin of reference positions, the distribution of read
+// abundances of the reference k-min-mers starting in that bin. Coverage dropouts show up as bins
+// dominated by low abundances, collapsed repeats as bins shifted to high abundances.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use crate::error::{Context, Error};
+use crate::index::Index;
+
+pub const NB_ABUNDANCE_BINS: usize = 256; // columns: read abundance 0..255 (last bin holds all higher abundances)
+
+// Positions and hashes of the k-min-mers of each reference sequence, recorded during indexing.
+pub struct RefPositions {
+    seqs: Mutex<Vec<(String, usize, Vec<(usize, usize, u64)>)>>, // (sequence id, length, [(start, end, hash)])
+}
+impl RefPositions {
+
+    pub fn new() -> Self {
+        RefPositions {seqs: Mutex::new(Vec::new())}
+    }
+
+    // Record the k-min-mers of a reference sequence.
+    pub fn add(&self, seq_id: &str, seq_len: usize, positions: Vec<(usize, usize, u64)>) {
+        self.seqs.lock().unwrap().push((seq_id.to_string(), seq_len, positions));
+    }
+
+    // (sequence id, [(start, end, hash)]) of each reference sequence, sorted by id. Sequences sharing an id are
+    // recorded in an order that depends on thread timing, and sorted by contents.
+    pub fn sorted(&self) -> Vec<(String, Vec<(usize, usize, u64)>)> {
+        let mut seqs = self.seqs.lock().unwrap();
+        seqs.sort_unstable();
+        seqs.iter().map(|(seq_id, _, kminmers)| (seq_id.clone(), kminmers.clone())).collect()
+    }
+}
+
+// Produce the positional histogram of each reference sequence, sorted by id: one row per bin of bin_size
+// bases, giving the number of reference k-min-mers starting in the bin for each read abundance.
+pub fn compute(positions: &RefPositions, read_mers_index: &Index, bin_size: usize) -> Vec<(String, Vec<Vec<u64>>)> {
+    let mut seqs = positions.seqs.lock().unwrap();
+    seqs.sort_unstable();
+    seqs.iter().map(|(seq_id, seq_len, kminmers)| {
+        let nb_bins = (seq_len + bin_size - 1) / bin_size;
+        let mut hist = vec![vec![0u64; NB_ABUNDANCE_BINS]; nb_bins.max(1)];
+        for (start, _, h) in kminmers {
+            let abundance = read_mers_index.get(h).unwrap_or(0) as usize;
+            hist[start / bin_size][abundance.min(NB_ABUNDANCE_BINS - 1)] += 1;
+        }
+        (seq_id.clone(), hist)
+    }).collect()
+}
+
+// Read support thresholds of the breadth report: the fraction of the reference k-min-mers seen in at least
+// this many reads is given for each.
+pub const BREADTH_THRESHOLDS: [u64; 3] = [1, 3, 5];
+
+// Write prefix.breadth.tsv: for each reference sequence, sorted by id, its length, number of k-min-mers and the
+// fraction of them with a read abundance of at least each of BREADTH_THRESHOLDS, a breadth of coverage in
+// minimizer space.
+pub fn write_breadth(positions: &RefPositions, read_mers_index: &Index, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".breadth.tsv");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    write!(file, "# ref_id\tlength\tnb_kminmers").output(&path)?;
+    for threshold in BREADTH_THRESHOLDS {
+        write!(file, "\tbreadth_{}", threshold).output(&path)?;
+    }
+    writeln!(file).output(&path)?;
+    let mut seqs = positions.seqs.lock().unwrap();
+    seqs.sort_unstable();
+    for (seq_id, seq_len, kminmers) in seqs.iter() {
+        let mut supported = [0usize; BREADTH_THRESHOLDS.len()];
+        for (_, _, h) in kminmers {
+            let abundance = read_mers_index.get(h).unwrap_or(0);
+            for (count, threshold) in supported.iter_mut().zip(BREADTH_THRESHOLDS) {
+                if abundance >= threshold {*count += 1;}
+            }
+        }
+        write!(file, "{}\t{}\t{}", seq_id, seq_len, kminmers.len()).output(&path)?;
+        for count in supported {
+            write!(file, "\t{:.4}", count as f64 / kminmers.len().max(1) as f64).output(&path)?;
+        }
+        writeln!(file).output(&path)?;
+    }
+    file.flush().output(&path)
+}
+
+// Write prefix.positional.hist2D: the positional histograms of all reference sequences, one row per bin.
+pub fn write_positional_hist2d(positions: &RefPositions, read_mers_index: &Index, bin_size: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".positional.hist2D");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    writeln!(file, "# ref_id\tbin_start\tcounts for read abundances 0..{} (last column: {} and more)", NB_ABUNDANCE_BINS - 1, NB_ABUNDANCE_BINS - 1).output(&path)?;
+
+    for (seq_id, hist) in compute(positions, read_mers_index, bin_size) {
+        for (bin, row) in hist.iter().enumerate() {
+            write!(file, "{}\t{}", seq_id, bin * bin_size).output(&path)?;
+            for count in row {
+                write!(file, "\t{}", count).output(&path)?;
+            }
+            writeln!(file).output(&path)?;
+        }
+    }
+    file.flush().output(&path)
+}
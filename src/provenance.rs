@@ -0,0 +1,123 @@
+// provenance.rs
+// Provenance of a run (prefix.provenance.json), to trace results back to how they were made, e.g. in a LIMS:
+// version and git commit of the program, full command line, the resolved parameters that change the results
+// (input files aside, which are listed with the inputs), start and end times (UTC), and each
+// input file with its size and the SHA-256 of its first megabyte, which tells files apart without reading them
+// whole. Remote inputs are listed without size or checksum. With --deterministic, the command line, thread count
+// and times are null, so that the file is the same across runs and thread counts.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use crate::error::{Context, Error};
+use crate::histo;
+use crate::objstore::{civil_date, hex};
+use crate::remote;
+use super::{expand_references, Params};
+
+const CHECKSUM_BYTES: u64 = 1 << 20; // prefix of each input file covered by its checksum
+
+pub struct Provenance {
+    started: SystemTime,
+    inputs: Vec<(&'static str, PathBuf)>, // role, path (a reference directory stands for its files)
+}
+impl Provenance {
+
+    pub fn new() -> Self {
+        Provenance {started: SystemTime::now(), inputs: Vec::new()}
+    }
+
+    pub fn add_inputs<'a>(&mut self, role: &'static str, paths: impl IntoIterator<Item = &'a PathBuf>) {
+        self.inputs.extend(paths.into_iter().map(|path| (role, path.clone())));
+    }
+
+    // Write prefix.provenance.json, ending the run now.
+    pub fn write(&self, params: &Params, threads: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+        let unless_deterministic = |value: Value| if params.deterministic {Value::Null} else {value};
+        let mut inputs = Vec::new();
+        for (role, path) in &self.inputs {
+            for path in expand_references(vec![path.clone()])? {
+                inputs.push(input_entry(role, &path)?);
+            }
+        }
+        let doc = json!({
+            "program": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "git_commit": option_env!("GIT_COMMIT").filter(|commit| !commit.is_empty()),
+            "command_line": unless_deterministic(json!(std::env::args().collect::<Vec<_>>())),
+            "parameters": parameters(params, unless_deterministic(json!(threads))),
+            "inputs": inputs,
+            "started_at": unless_deterministic(json!(utc_datetime(self.started))),
+            "finished_at": unless_deterministic(json!(utc_datetime(SystemTime::now()))),
+        });
+        let path = format!("{}{}", output_prefix.to_str().unwrap(), ".provenance.json");
+        let mut file = BufWriter::new(File::create(&path).output(&path)?);
+        serde_json::to_writer_pretty(&mut file, &doc).map_err(std::io::Error::from).output(&path)?;
+        writeln!(file).output(&path)?;
+        file.flush().output(&path)
+    }
+}
+
+// The resolved parameters of a run, after --config, defaults and --target-kminmers-per-kb: enough, with the
+// inputs, to run it again.
+fn parameters(params: &Params, threads: Value) -> Value {
+    json!({
+        "k": params.k,
+        "l": params.l,
+        "density": params.density,
+        "metadata": histo::metadata(params), // as in the .hist2D header
+        "threads": threads,
+        "window": params.window,
+        "syncmers": params.syncmers.map(|kind| kind.name()),
+        "syncmer_s": params.syncmers.map(|_| params.syncmer_s),
+        "strobemers": params.strobemers.map(|(wmin, wmax)| json!({"wmin": wmin, "wmax": wmax})),
+        "counter_width": params.counter_width.bits(),
+        "ref_cap": params.ref_cap,
+        "reads_only": params.reads_only,
+        "mask_policy": params.mask_policy.map(|policy| policy.name()),
+        "min_entropy": params.min_entropy,
+        "trim5": params.trim5,
+        "trim3": params.trim3,
+        "trim_quality": params.trim_quality,
+        "respect_softmask": params.respect_softmask,
+        "restrict_to_reference": params.restrict_to_reference,
+        "max_ref_count_filter": params.max_ref_count_filter,
+        "distinct_per_read": params.distinct_per_read,
+        "quality_weights": params.quality_weights,
+        "interleaved": params.interleaved,
+        "dedup_reads": params.dedup_reads,
+        "dedup_pairs": params.dedup_pairs,
+        "solid_threshold": params.solid_threshold,
+        "max_memory": params.max_memory,
+        "sketch": params.sketch.map(|(width, depth)| json!({"width": width, "depth": depth})),
+        "prefilter": params.prefilter,
+        "axis_order": params.axis_order.name(),
+        "sparse": params.sparse,
+        "cumulative": params.cumulative,
+        "normalize": params.normalize.map(|normalize| normalize.name()),
+        "deterministic": params.deterministic,
+    })
+}
+
+// Role, path, size and checksum of the first CHECKSUM_BYTES of an input file.
+fn input_entry(role: &str, path: &PathBuf) -> Result<Value, Error> {
+    if remote::is_url(path.to_str().unwrap()) {
+        return Ok(json!({"role": role, "path": path, "size": null, "sha256_first_mb": null}));
+    }
+    let file = File::open(path).input(path)?;
+    let size = file.metadata().input(path)?.len();
+    let mut head = Vec::new();
+    file.take(CHECKSUM_BYTES).read_to_end(&mut head).input(path)?;
+    Ok(json!({"role": role, "path": path, "size": size, "sha256_first_mb": hex(&Sha256::digest(&head))}))
+}
+
+// ISO 8601 UTC time, to the second.
+fn utc_datetime(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let (year, month, day) = civil_date((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
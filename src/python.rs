@@ -0,0 +1,141 @@
+// python.rs
+// Python bindings (the kminmer2dhisto module, built by maturin with the "python" feature), so that analysis
+// notebooks can call the counting core directly: indexes of read or reference files, or of in-memory sequences,
+// and their 2D histogram as a NumPy array, h[read abundance][reference abundance] as in prefix.hist2D.
+//
+//     import kminmer2dhisto as kh
+//     ref = kh.index_reference(["ref.fa"], k=5, l=31, density=0.01)
+//     reads = kh.count_reads(["reads.fq"], k=5, l=31, density=0.01)
+//     h = kh.histogram(reads, ref)  # numpy.ndarray of shape (10000, 10)
+
+use std::path::PathBuf;
+use dashmap::DashMap;
+use numpy::PyArray2;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use crate::closures;
+use crate::error::Error;
+use crate::histo;
+use crate::index::Index;
+use crate::mers;
+use super::{available_threads, Params};
+
+// Python exception for an error of the core: I/O errors are OSErrors, bad parameters and inputs ValueErrors.
+fn to_pyerr(e: Error) -> PyErr {
+    match e {
+        Error::Input {..} | Error::Output {..} => PyOSError::new_err(e.to_string()),
+        Error::Usage(_) | Error::Corrupt {..} => PyValueError::new_err(e.to_string()),
+    }
+}
+
+// Index of k-min-mer counts, with the parameters it was built with.
+#[pyclass(name = "Index")]
+pub struct PyIndex {
+    index: Index,
+    params: Params,
+}
+
+#[pymethods]
+impl PyIndex {
+
+    // Number of distinct k-min-mers.
+    fn __len__(&self) -> usize {
+        self.index.len()
+    }
+
+    // Count of a k-min-mer hash (0 if absent).
+    fn get(&self, h: u64) -> u64 {
+        self.index.get(&h).unwrap_or(0)
+    }
+
+    // (hash, count) pairs, in hash order.
+    fn items(&self) -> Vec<(u64, u64)> {
+        self.index.iter_ordered(true).collect()
+    }
+
+    #[getter]
+    fn k(&self) -> usize {self.params.k}
+    #[getter]
+    fn l(&self) -> usize {self.params.l}
+    #[getter]
+    fn density(&self) -> f64 {self.params.density}
+
+    fn __repr__(&self) -> String {
+        format!("Index({} k-min-mers, {})", self.index.len(), histo::metadata(&self.params))
+    }
+}
+
+fn check_params(k: usize, l: usize, density: f64) -> PyResult<Params> {
+    if k == 0 || l == 0 || !(density > 0.0 && density <= 1.0) {
+        return Err(PyValueError::new_err("k and l must be positive, and density in (0, 1]."));
+    }
+    Ok(Params::new(k, l, density))
+}
+
+fn nb_threads(threads: usize) -> usize {
+    if threads == 0 {available_threads()} else {threads}
+}
+
+// Index the k-min-mers of reference files (FASTA/FASTQ, raw or compressed), as for --reference.
+#[pyfunction]
+#[pyo3(signature = (paths, k=5, l=31, density=0.01, threads=0))]
+fn index_reference(py: Python<'_>, paths: Vec<PathBuf>, k: usize, l: usize, density: f64, threads: usize) -> PyResult<PyIndex> {
+    let params = check_params(k, l, density)?;
+    let threads = nb_threads(threads);
+    let (index, _) = py.detach(|| closures::index_reference(&paths, &params, threads, threads, None)).map_err(to_pyerr)?;
+    Ok(PyIndex {index, params})
+}
+
+// Count the k-min-mers of read files into a single index, as for the input file.
+#[pyfunction]
+#[pyo3(signature = (paths, k=5, l=31, density=0.01, threads=0))]
+fn count_reads(py: Python<'_>, paths: Vec<PathBuf>, k: usize, l: usize, density: f64, threads: usize) -> PyResult<PyIndex> {
+    let params = check_params(k, l, density)?;
+    let threads = nb_threads(threads);
+    let index = py.detach(|| -> Result<Index, Error> {
+        let index = Index::new();
+        let lens = DashMap::new();
+        for path in &paths {
+            closures::index_reads(path, threads, 200, &params, &lens, &index, None, None, None, None, None, None, None, None)?;
+        }
+        Ok(index)
+    }).map_err(to_pyerr)?;
+    Ok(PyIndex {index, params})
+}
+
+// Index in-memory sequences, as reads or, with reference=True, as reference sequences.
+#[pyfunction]
+#[pyo3(signature = (seqs, k=5, l=31, density=0.01, reference=false))]
+fn index_sequences(py: Python<'_>, seqs: Vec<String>, k: usize, l: usize, density: f64, reference: bool) -> PyResult<PyIndex> {
+    let params = check_params(k, l, density)?;
+    let index = py.detach(|| {
+        let index = Index::new();
+        let lens = DashMap::new();
+        for (i, seq) in seqs.iter().enumerate() {
+            mers::index_sequence(&format!("seq{}", i), seq.as_bytes(), reference, &index, &lens, &params);
+        }
+        index
+    });
+    Ok(PyIndex {index, params})
+}
+
+// 2D histogram of a read and a reference index built with the same parameters, as a NumPy array of shape
+// (10000, 10): h[x][y] is the number of k-min-mers seen x times in the reads and y times in the reference.
+#[pyfunction]
+fn histogram<'py>(py: Python<'py>, reads: &PyIndex, reference: &PyIndex) -> PyResult<Bound<'py, PyArray2<u64>>> {
+    if histo::metadata(&reads.params) != histo::metadata(&reference.params) {
+        return Err(PyValueError::new_err(format!("The indexes were built with different parameters ({} and {}).", histo::metadata(&reads.params), histo::metadata(&reference.params))));
+    }
+    let hist = py.detach(|| histo::compute(&reads.index, &reference.index, reads.params.threads));
+    PyArray2::from_vec2(py, &hist).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+#[pymodule]
+fn kminmer2dhisto(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyIndex>()?;
+    m.add_function(wrap_pyfunction!(index_reference, m)?)?;
+    m.add_function(wrap_pyfunction!(count_reads, m)?)?;
+    m.add_function(wrap_pyfunction!(index_sequences, m)?)?;
+    m.add_function(wrap_pyfunction!(histogram, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,54 @@
+// quality.rs
+// Quality-weighted read counting (--quality-weights): each read k-min-mer adds the probability that all the bases
+// it spans are correct, the product of (1 - error probability) of their Phred qualities, rather than 1. Weights
+// are accumulated in fixed point, WEIGHT_SCALE units per error-free occurrence, so that the Index keeps integer
+// counters, and rounded back to abundances once the reads are counted. K-min-mers spanning low-quality bases,
+// which are most often erroneous, then add little to the spectrum, which sharpens its peaks on noisy long reads.
+
+use crate::index::Index;
+
+// Counter units of a k-min-mer occurrence of weight 1.
+pub const WEIGHT_SCALE: u64 = 1000;
+
+// Phred scores above this are treated as this (Sanger encoding, offset 33).
+const MAX_PHRED: usize = 93;
+
+// Log-probability standing for a base of quality 0 (error probability 1): low enough for any k-min-mer spanning
+// it to get a zero weight, while keeping the prefix sums finite.
+const LOG_ZERO: f64 = -1000.0;
+
+// Weights of the k-min-mers of one read, from prefix sums of the log-probabilities that its bases are correct,
+// so that each weight takes constant time however many bases a k-min-mer spans.
+pub struct Weights(Vec<f64>);
+impl Weights {
+
+    pub fn new(qual: &[u8]) -> Self {
+        let mut sums = Vec::with_capacity(qual.len() + 1);
+        let mut sum = 0.0;
+        sums.push(sum);
+        for &q in qual {
+            let phred = (q.saturating_sub(33) as usize).min(MAX_PHRED);
+            sum += if phred == 0 {LOG_ZERO} else {(1.0 - 10f64.powf(-(phred as f64) / 10.0)).ln()};
+            sums.push(sum);
+        }
+        Weights(sums)
+    }
+
+    // Fixed-point weight of the k-min-mer spanning bases start..end.
+    pub fn get(&self, start: usize, end: usize) -> u64 {
+        let end = end.min(self.0.len() - 1);
+        let start = start.min(end);
+        ((self.0[end] - self.0[start]).exp() * WEIGHT_SCALE as f64).round() as u64
+    }
+}
+
+// Turn the fixed-point weights of a read Index back into abundances, rounded to the nearest integer. K-min-mers
+// whose total weight rounds to 0 are kept with an abundance of 1, as they were seen. A drained shard only gets
+// back its own hashes, so each entry is rescaled once.
+pub fn rescale(index: &Index) {
+    for shard in 0..index.nb_shards() {
+        for (h, weight) in index.drain_shard(shard) {
+            index.add(h, ((weight + WEIGHT_SCALE / 2) / WEIGHT_SCALE).max(1));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// qv.rs
+// Merqury-style assembly evaluation in minimizer space: k-min-mer completeness of the reference
+// and consensus quality value (QV) from reference k-min-mers that are never seen in the reads.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::index::Index;
+use super::{histo, spectrum, Params};
+use log::info;
+
+#[derive(Clone, Debug)]
+pub struct QvReport {
+    pub solid_threshold: u64,
+    pub solid_read_kminmers: u64,
+    pub solid_read_kminmers_in_ref: u64,
+    pub completeness: f64,
+    pub ref_kminmers: u64,         // total occurrences of k-min-mers in the reference
+    pub ref_kminmers_missing: u64, // occurrences of reference k-min-mers absent from the reads
+    pub error_rate: f64,
+    pub qv: f64,
+}
+
+// Compute completeness (% of solid read k-min-mers found in the reference) and QV.
+// Read k-min-mers are solid when seen at least solid_threshold times.
+pub fn evaluate(read_mers_index: &Index, ref_mers_index: &Index, solid_threshold: u64, params: &Params) -> QvReport {
+    let mut solid_read_kminmers = 0;
+    let mut solid_read_kminmers_in_ref = 0;
+    for (node, read_abundance) in read_mers_index.iter() {
+        if read_abundance < solid_threshold {continue;}
+        solid_read_kminmers += 1;
+        if ref_mers_index.get(&node).is_some() {solid_read_kminmers_in_ref += 1;}
+    }
+    evaluate_solid(solid_read_kminmers, solid_read_kminmers_in_ref, read_mers_index, ref_mers_index, solid_threshold, params)
+}
+
+// Compute completeness and QV given the number of solid read k-min-mers and of those in the reference.
+// read_mers_index is only looked up for reference k-min-mers, so it may hold just the read k-min-mers
+// present in the reference.
+pub fn evaluate_solid(solid_read_kminmers: u64, solid_read_kminmers_in_ref: u64, read_mers_index: &Index, ref_mers_index: &Index, solid_threshold: u64, params: &Params) -> QvReport {
+    let mut ref_kminmers = 0;
+    let mut ref_kminmers_missing = 0;
+    for (node, ref_abundance) in ref_mers_index.iter() {
+        ref_kminmers += ref_abundance;
+        if read_mers_index.get(&node).is_none() {ref_kminmers_missing += ref_abundance;}
+    }
+
+    let completeness = if solid_read_kminmers > 0 {100.0 * solid_read_kminmers_in_ref as f64 / solid_read_kminmers as f64} else {0.0};
+    // a consensus error only breaks a k-min-mer if it falls within one of its k minimizers (k*l bases)
+    let span = (params.k * params.l) as f64;
+    let error_rate = if ref_kminmers > 0 {1.0 - (1.0 - ref_kminmers_missing as f64 / ref_kminmers as f64).powf(1.0 / span)} else {0.0};
+    let qv = -10.0 * error_rate.log10();
+    QvReport {solid_threshold, solid_read_kminmers, solid_read_kminmers_in_ref, completeness, ref_kminmers, ref_kminmers_missing, error_rate, qv}
+}
+
+// Solidity threshold of read k-min-mers: unless given, derived from the error valley of the read spectrum.
+pub fn solid_threshold(hist: &Vec<Vec<u64>>, params: &Params) -> u64 {
+    match params.solid_threshold {
+        Some(t) => t,
+        None => spectrum::error_threshold(&histo::read_marginal(hist)) as u64 + 1,
+    }
+}
+
+// Write the evaluation of the reference against the reads to prefix.qv.
+pub fn write_qv(r: &QvReport, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let qv_path = format!("{}{}", output_prefix.to_str().unwrap(), ".qv");
+    let mut qv_file = File::create(&qv_path).output(&qv_path)?;
+    writeln!(qv_file, "# k={} l={} density={}", params.k, params.l, params.density).output(&qv_path)?;
+    writeln!(qv_file, "solid_threshold\t{}", r.solid_threshold).output(&qv_path)?;
+    writeln!(qv_file, "solid_read_kminmers\t{}", r.solid_read_kminmers).output(&qv_path)?;
+    writeln!(qv_file, "solid_read_kminmers_in_ref\t{}", r.solid_read_kminmers_in_ref).output(&qv_path)?;
+    writeln!(qv_file, "completeness\t{:.4}", r.completeness).output(&qv_path)?;
+    writeln!(qv_file, "ref_kminmers\t{}", r.ref_kminmers).output(&qv_path)?;
+    writeln!(qv_file, "ref_kminmers_missing_from_reads\t{}", r.ref_kminmers_missing).output(&qv_path)?;
+    writeln!(qv_file, "error_rate\t{:.3e}", r.error_rate).output(&qv_path)?;
+    writeln!(qv_file, "qv\t{:.2}", r.qv).output(&qv_path)?;
+    info!("k-min-mer completeness: {:.2}%, QV: {:.2}.", r.completeness, r.qv);
+    Ok(())
+}
@@ -0,0 +1,38 @@
+// readids.rs
+// Example reads of each read k-min-mer (--track-read-ids M): while the reads are counted, the IDs of the first
+// M reads (in processing order, file order with --deterministic) containing each distinct k-min-mer are kept next to the read Index, as GcMap keeps
+// GC contents. The read_id columns of --report-novel, --top-kminmers and --inspect-cell are then filled from
+// them, without reading the reads a second time. Each read ID is stored once and shared by its k-min-mers, but
+// every distinct read k-min-mer, sequencing errors included, holds at least one: this roughly doubles the
+// memory of the read Index.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use dashmap::DashMap;
+
+// IDs of up to max reads containing each read k-min-mer.
+pub struct ReadIds {
+    max: usize,
+    map: DashMap<u64, Vec<Arc<str>>>,
+}
+impl ReadIds {
+
+    pub fn new(max: usize) -> Self {
+        ReadIds {max, map: DashMap::new()}
+    }
+
+    // Record that read id contains the k-min-mer of hash h, unless max reads are already recorded for it.
+    pub fn record(&self, h: u64, id: &Arc<str>) {
+        let mut ids = self.map.entry(h).or_default();
+        if ids.len() < self.max && !ids.iter().any(|other| Arc::ptr_eq(other, id)) {
+            ids.push(Arc::clone(id));
+        }
+    }
+
+    // The recorded reads of each of the given k-min-mers (hash, count), as comma-separated IDs.
+    pub fn examples(&self, kminmers: &[(u64, u64)]) -> HashMap<u64, String> {
+        kminmers.iter()
+            .filter_map(|(h, _)| self.map.get(h).map(|ids| (*h, ids.join(","))))
+            .collect()
+    }
+}
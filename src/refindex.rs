@@ -0,0 +1,112 @@
+// refindex.rs
+// Reference index files, written by the `index` subcommand and loaded by `histo`, so that a shared
+// reference is indexed once and then queried against many read sets.
+// The file stores the parameters the index was built with and all (hash, count) pairs.
+// Version 2 also stores the minimizer scheme; version 1 files have the density-based one. Version 3 also stores
+// the sequence settings (--mask-policy, --min-entropy and the read trimming), which queries must use too.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use crate::checkpoint::{read_str, read_u64, write_str, write_u64};
+use crate::error::{Context, Error};
+use crate::index::{CounterWidth, Index, IndexBackend};
+use super::Params;
+use super::mers;
+
+const MAGIC_V1: &[u8; 8] = b"KM2DIDX1";
+const MAGIC_V2: &[u8; 8] = b"KM2DIDX2";
+const MAGIC: &[u8; 8] = b"KM2DIDX3";
+
+// Parameters a reference index was built with.
+pub struct IndexParams {
+    pub k: usize,
+    pub l: usize,
+    pub density: f64,
+    pub scheme: String, // as given by mers::scheme
+    pub settings: String, // as given by settings
+}
+
+// Sequence settings other than the defaults, as recorded in index files; empty if all are the defaults.
+pub fn settings(params: &Params) -> String {
+    let mut parts = Vec::new();
+    if let Some(policy) = params.mask_policy {parts.push(format!("mask_policy={}", policy.name()));}
+    if let Some(min_entropy) = params.min_entropy {parts.push(format!("min_entropy={}", min_entropy));}
+    if params.trim5 > 0 {parts.push(format!("trim5={}", params.trim5));}
+    if params.trim3 > 0 {parts.push(format!("trim3={}", params.trim3));}
+    if let Some(q) = params.trim_quality {parts.push(format!("trim_quality={}", q));}
+    parts.join(" ")
+}
+
+impl IndexParams {
+
+    // Parameters to extract k-min-mers as the index did, for queries against it.
+    pub fn params(&self, path: &PathBuf) -> Result<Params, Error> {
+        let mut params = Params::new(self.k, self.l, self.density);
+        for field in self.scheme.split_whitespace() {
+            let parsed = match field.split_once('=') {
+                Some(("window", w)) => w.parse().ok().map(|w| params.window = Some(w)),
+                Some(("syncmers", kind)) => kind.parse().ok().map(|kind| params.syncmers = Some(kind)),
+                Some(("s", s)) => s.parse().ok().map(|s| params.syncmer_s = s),
+                Some(("strobemers", w)) => w.split_once('-').and_then(|(wmin, wmax)| Some((wmin.parse().ok()?, wmax.parse().ok()?))).map(|w| params.strobemers = Some(w)),
+                _ => None,
+            };
+            if parsed.is_none() {return Err(Error::corrupt(path, format!("unknown minimizer scheme {}", self.scheme)));}
+        }
+        for field in self.settings.split_whitespace() {
+            let parsed = match field.split_once('=') {
+                Some(("mask_policy", policy)) => policy.parse().ok().map(|policy| params.mask_policy = Some(policy)),
+                Some(("min_entropy", e)) => e.parse().ok().map(|e| params.min_entropy = Some(e)),
+                Some(("trim5", n)) => n.parse().ok().map(|n| params.trim5 = n),
+                Some(("trim3", n)) => n.parse().ok().map(|n| params.trim3 = n),
+                Some(("trim_quality", q)) => q.parse().ok().map(|q| params.trim_quality = Some(q)),
+                _ => None,
+            };
+            if parsed.is_none() {return Err(Error::corrupt(path, format!("unknown sequence settings {}", self.settings)));}
+        }
+        Ok(params)
+    }
+}
+
+fn write_index(w: &mut impl Write, params: &Params, ref_mers_index: &Index) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_u64(w, params.k as u64)?;
+    write_u64(w, params.l as u64)?;
+    write_u64(w, params.density.to_bits())?;
+    write_str(w, &mers::scheme(params))?;
+    write_str(w, &settings(params))?;
+    write_u64(w, ref_mers_index.len() as u64)?;
+    for (h, count) in ref_mers_index.iter_ordered(params.deterministic) {
+        write_u64(w, h)?;
+        write_u64(w, count)?;
+    }
+    w.flush()
+}
+
+// Save the reference Index to path.
+pub fn save(path: &PathBuf, params: &Params, ref_mers_index: &Index) -> Result<(), Error> {
+    let file = File::create(path).output(path)?;
+    write_index(&mut BufWriter::new(file), params, ref_mers_index).output(path)
+}
+
+// Load a reference index into a new Index with counters of the given width.
+pub fn load(path: &PathBuf, counter_width: CounterWidth, backend: &IndexBackend) -> Result<(Index, IndexParams), Error> {
+    let file = File::open(path).input(path)?;
+    let mut r = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic).corrupt(path)?;
+    if &magic != MAGIC && &magic != MAGIC_V2 && &magic != MAGIC_V1 {return Err(Error::corrupt(path, "not a reference index file"));}
+    let k = read_u64(&mut r).corrupt(path)? as usize;
+    let l = read_u64(&mut r).corrupt(path)? as usize;
+    let density = f64::from_bits(read_u64(&mut r).corrupt(path)?);
+    let scheme = if &magic != MAGIC_V1 {read_str(&mut r).corrupt(path)?} else {String::new()};
+    let settings = if &magic == MAGIC {read_str(&mut r).corrupt(path)?} else {String::new()};
+    let nb_entries = read_u64(&mut r).corrupt(path)?;
+    let ref_mers_index = Index::with_backend(counter_width, backend);
+    for _ in 0..nb_entries {
+        let h = read_u64(&mut r).corrupt(path)?;
+        let count = read_u64(&mut r).corrupt(path)?;
+        ref_mers_index.add(h, count);
+    }
+    Ok((ref_mers_index, IndexParams {k, l, density, scheme, settings}))
+}
@@ -0,0 +1,86 @@
+// regions.rs
+// Reference regions from a BED file (--regions), so that only the reference k-min-mers starting within them are
+// indexed, e.g. for the spectrum of a targeted panel or a single chromosome; or blacklisted regions
+// (--exclude-regions), e.g. the ENCODE blacklist or rDNA arrays, so that the k-min-mers overlapping them are
+// left out. Intervals are 0-based and half-open, as in BED, on the sequence ids of the reference (the first
+// word of their header); header lines (track, browser, #) are skipped, and columns after the third are ignored.
+// Also the soft-masked (lowercase) runs of a reference sequence (--respect-softmask), left out in the same way,
+// so that repeat-masked assemblies give a unique-sequence reference without hard-masking them first.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::get_reader;
+use log::info;
+
+pub struct Regions {
+    intervals: HashMap<String, Vec<(usize, usize)>>, // per sequence id, sorted and merged
+}
+impl Regions {
+
+    pub fn load(path: &PathBuf) -> Result<Self, Error> {
+        let mut intervals: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        for (i, line) in get_reader(path)?.lines().enumerate() {
+            let line = line.input(path)?;
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {continue;}
+            let fields: Vec<&str> = line.split('\t').collect();
+            let interval = match fields.as_slice() {
+                [seq_id, start, end, ..] => start.trim().parse::<usize>().ok().zip(end.trim().parse::<usize>().ok()).filter(|(start, end)| start <= end).map(|interval| (seq_id.to_string(), interval)),
+                _ => None,
+            };
+            let (seq_id, interval) = interval.ok_or_else(|| Error::corrupt(path, format!("line {}: not a BED interval", i + 1)))?;
+            intervals.entry(seq_id).or_default().push(interval);
+        }
+        let mut nb = 0;
+        for list in intervals.values_mut() {
+            list.sort_unstable();
+            let mut merged: Vec<(usize, usize)> = Vec::with_capacity(list.len());
+            for &(start, end) in list.iter() {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            nb += merged.len();
+            *list = merged;
+        }
+        info!("Loaded {} regions on {} sequences from {}.", nb, intervals.len(), path.to_str().unwrap());
+        Ok(Regions {intervals})
+    }
+
+    // Whether position pos of sequence seq_id is in a region.
+    pub fn contains(&self, seq_id: &str, pos: usize) -> bool {
+        match self.intervals.get(seq_id) {
+            Some(list) => {
+                let i = list.partition_point(|&(start, _)| start <= pos);
+                i > 0 && pos < list[i - 1].1
+            }
+            None => false,
+        }
+    }
+
+    // Whether the interval start..end of sequence seq_id overlaps a region.
+    pub fn overlaps(&self, seq_id: &str, start: usize, end: usize) -> bool {
+        self.intervals.get(seq_id).map_or(false, |list| overlaps(list, start, end))
+    }
+}
+
+// Whether the interval start..end overlaps one of a sorted list of disjoint intervals.
+pub fn overlaps(list: &[(usize, usize)], start: usize, end: usize) -> bool {
+    let i = list.partition_point(|&(region_start, _)| region_start < end);
+    i > 0 && start < list[i - 1].1
+}
+
+// Runs of lowercase bases of a sequence, sorted.
+pub fn lowercase_runs(seq: &[u8]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (pos, c) in seq.iter().enumerate() {
+        if !c.is_ascii_lowercase() {continue;}
+        match runs.last_mut() {
+            Some(last) if last.1 == pos => last.1 = pos + 1,
+            _ => runs.push((pos, pos + 1)),
+        }
+    }
+    runs
+}
@@ -0,0 +1,115 @@
+// remote.rs
+// Streaming of inputs given as HTTP(S)/FTP URLs or object store URIs, so that remote FASTA/FASTQ files
+// don't have to be downloaded first. The returned stream goes through the same decompression as local files.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use crate::objstore;
+
+// Check whether an input path is a URL rather than a local file.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://") || path.starts_with("ftp://") || objstore::is_uri(path)
+}
+
+// Open a URL for streaming.
+pub fn open(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    if objstore::is_uri(url) {
+        return objstore::open(url);
+    }
+    if url.starts_with("ftp://") {
+        return Ok(Box::new(FtpStream::open(url)?));
+    }
+    http_get(url)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn http_get(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    match ureq::get(url).call() {
+        Ok(response) => Ok(Box::new(response.into_reader())),
+        Err(ureq::Error::Status(code, _)) => Err(io::Error::new(io::ErrorKind::NotFound, format!("HTTP status {}", code))),
+        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
+    }
+}
+
+// The HTTP client needs sockets and a TLS library, which aren't available in WebAssembly.
+#[cfg(target_arch = "wasm32")]
+fn http_get(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("URLs aren't supported in WebAssembly builds: {}", url)))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Download of a single file over anonymous, passive-mode FTP.
+struct FtpStream {
+    data: TcpStream,
+    _control: TcpStream, // the transfer is aborted if the control connection is closed
+}
+
+impl FtpStream {
+
+    fn open(url: &str) -> io::Result<Self> {
+        let rest = &url["ftp://".len()..];
+        let (host, path) = rest.split_once('/').ok_or_else(|| invalid(format!("no file path in {}", url)))?;
+        let addr = if host.contains(':') {host.to_string()} else {format!("{}:21", host)};
+        let mut control = TcpStream::connect(&addr)?;
+        let mut replies = BufReader::new(control.try_clone()?);
+
+        expect_reply(&mut replies, &[220])?;
+        if command(&mut control, &mut replies, "USER anonymous", &[230, 331])? == 331 {
+            command(&mut control, &mut replies, "PASS anonymous@", &[230])?;
+        }
+        command(&mut control, &mut replies, "TYPE I", &[200])?;
+        write!(control, "PASV\r\n")?;
+        let (_, msg) = expect_reply(&mut replies, &[227])?;
+        let data = TcpStream::connect(parse_pasv(&msg)?)?;
+        command(&mut control, &mut replies, &format!("RETR /{}", path), &[125, 150])?;
+        Ok(FtpStream {data, _control: control})
+    }
+}
+
+impl Read for FtpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+// Send a command and check that its reply code is one of expected. Returns the reply code.
+fn command(control: &mut TcpStream, replies: &mut impl BufRead, cmd: &str, expected: &[u32]) -> io::Result<u32> {
+    write!(control, "{}\r\n", cmd)?;
+    expect_reply(replies, expected).map(|(code, _)| code)
+}
+
+// Read a (possibly multi-line) reply and check that its code is one of expected.
+fn expect_reply(replies: &mut impl BufRead, expected: &[u32]) -> io::Result<(u32, String)> {
+    let mut line = String::new();
+    if replies.read_line(&mut line)? == 0 || line.len() < 4 {
+        return Err(invalid("connection closed by the FTP server".to_string()));
+    }
+    let code: u32 = line[..3].parse().map_err(|_| invalid(format!("unexpected FTP reply: {}", line.trim_end())))?;
+    if &line[3..4] == "-" {
+        // multi-line reply, ends with a line starting with "<code> "
+        let end = format!("{} ", code);
+        loop {
+            let mut next = String::new();
+            if replies.read_line(&mut next)? == 0 {break;}
+            if next.starts_with(&end) {break;}
+        }
+    }
+    if !expected.contains(&code) {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("FTP server replied: {}", line.trim_end())));
+    }
+    Ok((code, line))
+}
+
+// Data connection address from a "227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)" reply.
+fn parse_pasv(msg: &str) -> io::Result<(String, u16)> {
+    let start = msg.find('(').ok_or_else(|| invalid(format!("unexpected PASV reply: {}", msg.trim_end())))?;
+    let end = msg[start..].find(')').map(|i| start + i).ok_or_else(|| invalid(format!("unexpected PASV reply: {}", msg.trim_end())))?;
+    let fields: Vec<u16> = msg[start + 1..end].split(',').filter_map(|f| f.trim().parse().ok()).collect();
+    if fields.len() != 6 {
+        return Err(invalid(format!("unexpected PASV reply: {}", msg.trim_end())));
+    }
+    Ok((format!("{}.{}.{}.{}", fields[0], fields[1], fields[2], fields[3]), fields[4] * 256 + fields[5]))
+}
@@ -0,0 +1,45 @@
+// report.rs
+// Per-read presence report: for each read, how many of its k-min-mers are in the reference Index.
+// This is a containment screen in minimizer space, e.g. to triage contaminated reads.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::index::Index;
+
+pub struct ReadReport<'a> {
+    pub ref_mers_index: &'a Index,
+    path: String,
+    file: BufWriter<File>,
+}
+impl<'a> ReadReport<'a> {
+
+    // Create prefix.per_read.tsv and write its header.
+    pub fn create(ref_mers_index: &'a Index, output_prefix: &PathBuf) -> Result<Self, Error> {
+        let path = format!("{}{}", output_prefix.to_str().unwrap(), ".per_read.tsv");
+        let mut file = BufWriter::new(File::create(&path).output(&path)?);
+        writeln!(file, "read_id\tlength\tnb_kminmers\tfraction_in_ref").output(&path)?;
+        Ok(ReadReport {ref_mers_index, path, file})
+    }
+
+    // Write the line of a read, given its number of k-min-mers and how many of them are in the reference.
+    // The fraction is NA for reads too short to have any k-min-mer.
+    pub fn write(&mut self, read_id: &str, length: usize, nb_kminmers: usize, nb_in_ref: usize) -> Result<(), Error> {
+        if nb_kminmers == 0 {
+            writeln!(self.file, "{}\t{}\t0\tNA", read_id, length).output(&self.path)
+        }
+        else {
+            writeln!(self.file, "{}\t{}\t{}\t{:.4}", read_id, length, nb_kminmers, nb_in_ref as f64 / nb_kminmers as f64).output(&self.path)
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.file.flush().output(&self.path)
+    }
+}
+
+// Number of bases of a record sequence, which may span several lines in FASTA files.
+pub fn seq_len(seq: &[u8]) -> usize {
+    seq.iter().filter(|&&c| c != b'\n' && c != b'\r').count()
+}
@@ -0,0 +1,100 @@
+// seeds.rs
+// Syncmer selection, an alternative to density-based minimizers: an l-mer is a syncmer depending only on its
+// own sequence (the position of its smallest s-mer), not on its neighbours, so that seeds are more evenly
+// spaced and conserved under mutations outside of them. Selected syncmers are chained into k-min-mers like
+// minimizers, and feed the same Index.
+// Also randstrobes, an alternative to chaining k consecutive minimizers: each next strobe is picked pseudo-randomly
+// within a window of following minimizers, so that a seed can skip over minimizers destroyed by sequencing errors.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use crate::mers::{canonical_hash, lmer_hashes, SeqKminmer};
+
+// Open syncmers have their smallest s-mer in the middle of the l-mer (so l - s must be even), closed syncmers
+// at either end. Both are strand-independent, since s-mer hashes are canonical.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncmerKind {
+    Open,
+    Closed,
+}
+impl SyncmerKind {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyncmerKind::Open => "open",
+            SyncmerKind::Closed => "closed",
+        }
+    }
+}
+impl FromStr for SyncmerKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(SyncmerKind::Open),
+            "closed" => Ok(SyncmerKind::Closed),
+            _ => Err(format!("invalid syncmer kind '{}' (expected open or closed)", s)),
+        }
+    }
+}
+
+// Syncmers (canonical hash, start) of seq: l-mers whose smallest s-mer (s < l <= 32) is at the position
+// required by kind. About 1/(l-s+1) of the l-mers are open syncmers, and 2/(l-s+1) closed syncmers.
+pub fn syncmers(seq: &[u8], l: usize, s: usize, kind: SyncmerKind) -> Vec<(u64, usize)> {
+    let lmers = lmer_hashes(seq, l);
+    let smers = lmer_hashes(seq, s);
+    let nb_smers = l - s + 1; // s-mers per l-mer
+    let mut res = Vec::new();
+    let mut j = 0; // index in smers of the first s-mer of the current l-mer
+    for &(h, start) in &lmers {
+        // the s-mers of a valid l-mer are consecutive in smers, the first one starting where the l-mer does
+        while smers[j].1 != start {j += 1;}
+        // leftmost and rightmost positions of the smallest s-mer, which differ if it occurs several times;
+        // checking both keeps the selection strand-independent
+        let window = &smers[j..j + nb_smers];
+        let min = window.iter().map(|m| m.0).min().unwrap();
+        let first = window.iter().position(|m| m.0 == min).unwrap();
+        let last = window.iter().rposition(|m| m.0 == min).unwrap();
+        let selected = match kind {
+            SyncmerKind::Open => first == (nb_smers - 1) / 2 && last == first,
+            SyncmerKind::Closed => first == 0 || last == nb_smers - 1,
+        };
+        if selected {res.push((h, start));}
+    }
+    res
+}
+
+// Strobe indices of the randstrobe of the given order starting at strobe first: each next strobe is the one,
+// among those wmin to wmax strobes further (to the right if forward, else to the left), whose hash is closest
+// in Hamming distance to the previous strobe's, the nearest one on ties. None if a window is empty.
+fn link(strobes: &[(u64, usize)], first: usize, order: usize, wmin: usize, wmax: usize, forward: bool) -> Option<Vec<usize>> {
+    let mut indices = vec![first];
+    for _ in 1..order {
+        let prev = *indices.last().unwrap();
+        let next = (wmin..=wmax)
+            .filter_map(|d| if forward {Some(prev + d).filter(|&j| j < strobes.len())} else {prev.checked_sub(d)})
+            .min_by_key(|&j| (strobes[prev].0 ^ strobes[j].0).count_ones())?;
+        indices.push(next);
+    }
+    Some(indices)
+}
+
+// Randstrobes of the given order (the number of strobes of a seed, k) from strobes (hash, start) in sequence order.
+// Seeds are linked in both directions and each distinct set of strobes is kept once, so that a sequence and its
+// reverse complement have the same seeds.
+pub fn randstrobes(strobes: &[(u64, usize)], order: usize, wmin: usize, wmax: usize, l: usize) -> Vec<SeqKminmer> {
+    let mut seeds: HashSet<Vec<usize>> = HashSet::new();
+    for first in 0..strobes.len() {
+        for forward in [true, false] {
+            if let Some(mut indices) = link(strobes, first, order, wmin, wmax, forward) {
+                indices.sort_unstable();
+                seeds.insert(indices);
+            }
+        }
+    }
+    let mut res: Vec<SeqKminmer> = seeds.into_iter().map(|indices| {
+        let hashes: Vec<u64> = indices.iter().map(|&i| strobes[i].0).collect();
+        SeqKminmer::new(canonical_hash(&hashes), strobes[indices[0]].1, strobes[*indices.last().unwrap()].1 + l)
+    }).collect();
+    res.sort_unstable_by_key(|km| (km.start, km.end, km.get_hash_u64()));
+    res
+}
@@ -0,0 +1,118 @@
+// serve.rs
+// HTTP query server (the `serve` subcommand): a reference index saved by `index` is loaded once and queried by
+// interactive tools, each connection on its own thread. The API is JSON over HTTP/1.1, one request per
+// connection:
+//   GET /info     parameters of the index and its number of distinct k-min-mers
+//   POST /query   {"sequences": ["ACGT...", {"id": "read1", "seq": "ACGT..."}]} gives, for each sequence, the
+//                 reference count of each of its k-min-mers, the number found in the reference and the
+//                 containment (the fraction found).
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Instant;
+use serde_json::{json, Value};
+use crate::error::Error;
+use crate::index::{CounterWidth, Index, IndexBackend};
+use crate::mers;
+use crate::refindex;
+use super::Params;
+use log::{debug, info, warn};
+
+const MAX_BODY_BYTES: usize = 64 << 20;
+
+// Load the index and answer queries until the process is killed.
+pub fn serve(index_path: &PathBuf, listen: &str) -> Result<(), Error> {
+    let start = Instant::now();
+    let (index, p) = refindex::load(index_path, CounterWidth::U64, &IndexBackend::HashMap)?;
+    let params = p.params(index_path)?;
+    info!("Loaded reference index {} in {:?}: {} k-min-mers (k={} l={} density={}).", index_path.to_str().unwrap(), start.elapsed(), index.len(), p.k, p.l, p.density);
+    let listener = TcpListener::bind(listen).map_err(|e| Error::Usage(format!("Cannot listen on {}: {}.", listen, e)))?;
+    info!("Listening on http://{}", listener.local_addr().map_or(listen.to_string(), |addr| addr.to_string()));
+    thread::scope(|s| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let (index, params) = (&index, &params);
+                    s.spawn(move || {
+                        if let Err(e) = handle(stream, index, params) {debug!("Connection error: {}", e);}
+                    });
+                }
+                Err(e) => warn!("Cannot accept a connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+// Read one request and send its response.
+fn handle(stream: TcpStream, index: &Index, params: &Params) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {break;}
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {content_length = value.trim().parse().unwrap_or(0);}
+        }
+    }
+    let mut fields = request_line.split_whitespace();
+    let (method, path) = (fields.next().unwrap_or(""), fields.next().unwrap_or(""));
+    let (status, body) = match (method, path) {
+        _ if content_length > MAX_BODY_BYTES => (413, json!({"error": format!("request body larger than {} bytes", MAX_BODY_BYTES)})),
+        ("GET", "/info") => (200, json!({
+            "k": params.k,
+            "l": params.l,
+            "density": params.density,
+            "scheme": mers::scheme(params),
+            "kminmers": index.len(),
+        })),
+        ("POST", "/query") => {
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body)?;
+            match serde_json::from_slice(&body).map_err(|e| e.to_string()).and_then(|request| query(&request, index, params)) {
+                Ok(response) => (200, response),
+                Err(msg) => (400, json!({"error": msg})),
+            }
+        }
+        (_, "/info") | (_, "/query") => (405, json!({"error": format!("method {} not allowed", method)})),
+        _ => (404, json!({"error": format!("no such endpoint {}", path)})),
+    };
+    debug!("{} {} {}", method, path, status);
+    let body = body.to_string();
+    let reason = match status {200 => "OK", 400 => "Bad Request", 404 => "Not Found", 405 => "Method Not Allowed", _ => "Payload Too Large"};
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, reason, body.len(), body)?;
+    stream.flush()
+}
+
+// Counts and containment of the sequences of a query.
+fn query(request: &Value, index: &Index, params: &Params) -> Result<Value, String> {
+    let sequences = request.get("sequences").and_then(Value::as_array).ok_or("expected {\"sequences\": [...]}")?;
+    let mut results = Vec::new();
+    for (i, sequence) in sequences.iter().enumerate() {
+        let (id, seq) = match sequence {
+            Value::String(seq) => (format!("seq{}", i), seq.as_str()),
+            _ => match (sequence.get("id").and_then(Value::as_str), sequence.get("seq").and_then(Value::as_str)) {
+                (id, Some(seq)) => (id.map_or_else(|| format!("seq{}", i), str::to_string), seq),
+                (_, None) => return Err(format!("sequence {} is neither a string nor an object with a \"seq\" string", i)),
+            },
+        };
+        let counts: Vec<u64> = match mers::extract(&id, seq.as_bytes(), params) {
+            Some(kminmers) => kminmers.map(|kminmer| index.get(&kminmer.get_hash_u64()).unwrap_or(0)).collect(),
+            None => Vec::new(),
+        };
+        let found = counts.iter().filter(|&&count| count > 0).count();
+        results.push(json!({
+            "id": id,
+            "kminmers": counts.len(),
+            "found": found,
+            "containment": if counts.is_empty() {None} else {Some(found as f64 / counts.len() as f64)},
+            "counts": counts,
+        }));
+    }
+    Ok(json!({"results": results}))
+}
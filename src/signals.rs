@@ -0,0 +1,36 @@
+// signals.rs
+// SIGINT/SIGUSR1 handling: the signal is recorded, and the main thread stops processing at the next read,
+// flushes a partial histogram and exits. A second Ctrl-C exits immediately. WebAssembly has no signals.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use signal_hook::consts::{SIGINT, SIGUSR1};
+
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+// Install the signal handlers.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn install() {
+    for &sig in &[SIGINT, SIGUSR1] {
+        // only async-signal-safe operations in the handler: an atomic swap and _exit
+        let res = unsafe {
+            signal_hook::low_level::register(sig, move || {
+                if PENDING.swap(sig as usize, Ordering::SeqCst) != 0 && sig == SIGINT {
+                    signal_hook::low_level::exit(130);
+                }
+            })
+        };
+        res.expect("Error installing signal handler.");
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn install() {}
+
+// The signal received so far, if any.
+pub fn pending() -> Option<i32> {
+    match PENDING.load(Ordering::SeqCst) {
+        0 => None,
+        sig => Some(sig as i32),
+    }
+}
@@ -0,0 +1,162 @@
+// simulate.rs
+// Synthetic datasets (the `simulate` subcommand), to explore parameters on data of known properties: a random
+// reference, part of which is made of copies of repeat elements, and reads sampled uniformly from both strands
+// at a given coverage, with substitution, insertion and deletion errors. The reference is written to
+// prefix.ref.fa and the reads to prefix.reads.fq, which are then counted as in a full run. The same seed
+// gives the same dataset.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use clap::Args;
+use crate::error::{Context, Error};
+use log::info;
+
+const BASES: &[u8; 4] = b"ACGT";
+
+#[derive(Debug, Args)]
+pub struct Simulation {
+    /// Reference length (bp)
+    #[arg(long, default_value_t = 1_000_000)]
+    genome_size: usize,
+    /// Read coverage
+    #[arg(long, default_value_t = 30.0)]
+    coverage: f64,
+    /// Read length (bp)
+    #[arg(long, default_value_t = 10_000)]
+    read_length: usize,
+    /// Per-base error rate of the reads
+    ///
+    /// Errors are substitutions, insertions and
+    /// deletions in equal proportions.
+    #[arg(long, default_value_t = 0.01)]
+    error_rate: f64,
+    /// Fraction of the reference made of repeats
+    ///
+    /// Copies of --repeat-families random elements of
+    /// --repeat-length bp, at random positions.
+    #[arg(long, default_value_t = 0.0)]
+    repeat_fraction: f64,
+    /// Length of the repeat elements (bp)
+    #[arg(long, default_value_t = 5000)]
+    repeat_length: usize,
+    /// Number of distinct repeat elements
+    #[arg(long, default_value_t = 1)]
+    repeat_families: usize,
+    /// Random seed
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+}
+
+// Small, fast pseudo-random generator (splitmix64): the datasets only need to be reproducible, not cryptographic.
+struct Rng(u64);
+impl Rng {
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..n.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    // Uniform in [0, 1).
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn base(&mut self) -> u8 {
+        BASES[self.below(4)]
+    }
+}
+
+fn complement(base: u8) -> u8 {
+    match base {b'A' => b'T', b'C' => b'G', b'G' => b'C', _ => b'A'}
+}
+
+impl Simulation {
+
+    pub fn check(&self) -> Result<(), Error> {
+        if self.genome_size == 0 || self.read_length == 0 || self.read_length > self.genome_size {return Err(Error::Usage("--genome-size and --read-length must be positive, and reads at most as long as the genome.".to_string()));}
+        if !(self.coverage > 0.0) {return Err(Error::Usage("--coverage must be positive.".to_string()));}
+        if !(0.0..1.0).contains(&self.error_rate) {return Err(Error::Usage("--error-rate must be in [0, 1).".to_string()));}
+        if !(0.0..1.0).contains(&self.repeat_fraction) {return Err(Error::Usage("--repeat-fraction must be in [0, 1).".to_string()));}
+        if self.repeat_fraction > 0.0 && (self.repeat_length == 0 || self.repeat_length > self.genome_size || self.repeat_families == 0) {return Err(Error::Usage("--repeat-length must be positive and at most --genome-size, and --repeat-families positive.".to_string()));}
+        Ok(())
+    }
+
+    // Random reference, with copies of the repeat elements pasted at random positions until they make up
+    // repeat_fraction of it (copies may overlap, so the actual fraction can be a little lower).
+    fn reference(&self, rng: &mut Rng) -> Vec<u8> {
+        let mut genome: Vec<u8> = (0..self.genome_size).map(|_| rng.base()).collect();
+        if self.repeat_fraction > 0.0 {
+            let families: Vec<Vec<u8>> = (0..self.repeat_families).map(|_| (0..self.repeat_length).map(|_| rng.base()).collect()).collect();
+            let nb_copies = ((self.repeat_fraction * self.genome_size as f64) / self.repeat_length as f64).ceil() as usize;
+            for i in 0..nb_copies {
+                let start = rng.below(self.genome_size - self.repeat_length + 1);
+                genome[start..start + self.repeat_length].copy_from_slice(&families[i % families.len()]);
+            }
+        }
+        genome
+    }
+
+    // A read of read_length bases of the genome, from a random position and strand, with errors. Returns the
+    // read, its start and whether it is from the forward strand.
+    fn read(&self, genome: &[u8], rng: &mut Rng) -> (Vec<u8>, usize, bool) {
+        let start = rng.below(genome.len() - self.read_length + 1);
+        let forward = rng.below(2) == 0;
+        let fragment: Vec<u8> = if forward {genome[start..start + self.read_length].to_vec()}
+            else {genome[start..start + self.read_length].iter().rev().map(|&b| complement(b)).collect()};
+        let mut read = Vec::with_capacity(fragment.len() + fragment.len() / 10);
+        for &base in &fragment {
+            if rng.unit() >= self.error_rate {
+                read.push(base);
+                continue;
+            }
+            match rng.below(3) {
+                0 => read.push(BASES[(BASES.iter().position(|&b| b == base).unwrap() + 1 + rng.below(3)) % 4]), // substitution
+                1 => {read.push(rng.base()); read.push(base);} // insertion
+                _ => {} // deletion
+            }
+        }
+        (read, start, forward)
+    }
+
+    // Write the reference and the reads. Returns their paths.
+    pub fn write(&self, prefix: &PathBuf) -> Result<(PathBuf, PathBuf), Error> {
+        let start = Instant::now();
+        let mut rng = Rng(self.seed);
+        let ref_path = PathBuf::from(format!("{}.ref.fa", prefix.to_str().unwrap()));
+        let reads_path = PathBuf::from(format!("{}.reads.fq", prefix.to_str().unwrap()));
+        let genome = self.reference(&mut rng);
+        let mut file = BufWriter::new(File::create(&ref_path).output(&ref_path)?);
+        writeln!(file, ">simulated_ref length={} repeat_fraction={}", genome.len(), self.repeat_fraction).output(&ref_path)?;
+        for line in genome.chunks(80) {
+            file.write_all(line).output(&ref_path)?;
+            writeln!(file).output(&ref_path)?;
+        }
+        file.flush().output(&ref_path)?;
+
+        // constant qualities matching the error rate
+        let phred = if self.error_rate > 0.0 {(-10.0 * self.error_rate.log10()).round().min(93.0) as u8} else {93};
+        let nb_reads = ((self.coverage * genome.len() as f64) / self.read_length as f64).ceil() as usize;
+        let mut file = BufWriter::new(File::create(&reads_path).output(&reads_path)?);
+        for i in 0..nb_reads {
+            let (read, pos, forward) = self.read(&genome, &mut rng);
+            writeln!(file, "@read{} pos={} strand={}", i, pos, if forward {'+'} else {'-'}).output(&reads_path)?;
+            file.write_all(&read).output(&reads_path)?;
+            writeln!(file, "\n+").output(&reads_path)?;
+            file.write_all(&vec![33 + phred; read.len()]).output(&reads_path)?;
+            writeln!(file).output(&reads_path)?;
+        }
+        file.flush().output(&reads_path)?;
+        info!("Simulated a {} bp reference ({}) and {} reads ({}) in {:?}.", genome.len(), ref_path.to_str().unwrap(), nb_reads, reads_path.to_str().unwrap(), start.elapsed());
+        Ok((ref_path, reads_path))
+    }
+}
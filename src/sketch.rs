@@ -0,0 +1,98 @@
+// sketch.rs
+// Count-min sketch of read k-min-mer abundances (--sketch), an approximate alternative to the hash table of an
+// Index: depth rows of width counters, where a k-min-mer increments one counter per row and its abundance is
+// estimated by the smallest of them, so that collisions can only over-count. Memory doesn't depend on the number
+// of distinct k-min-mers. As k-min-mers can't be enumerated from the sketch, the abundance spectrum of distinct
+// k-min-mers is tracked while counting: each increment moves a k-min-mer from the bin of its previous estimate
+// to the bin of the new one.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering};
+use crate::histo::{ReadCounts, NB_READ_BINS};
+use crate::index::{CounterWidth, Index};
+use crate::mers::mix;
+
+const NB_STRIPES: usize = 4096; // locks serializing the increments of a same hash
+
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    cells: Vec<AtomicU32>,    // depth rows of width counters, saturating
+    spectrum: Vec<AtomicI64>, // distinct k-min-mers per estimated abundance (capped like the histogram rows)
+    total: AtomicU64,
+    stripes: Vec<Mutex<()>>,
+}
+impl CountMinSketch {
+
+    pub fn new(width: usize, depth: usize) -> Self {
+        CountMinSketch {
+            width,
+            depth,
+            cells: (0..width * depth).map(|_| AtomicU32::new(0)).collect(),
+            spectrum: (0..NB_READ_BINS).map(|_| AtomicI64::new(0)).collect(),
+            total: AtomicU64::new(0),
+            stripes: (0..NB_STRIPES).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    // Counter of the hash h in a row; each row hashes h with a different seed.
+    fn cell(&self, h: u64, row: usize) -> &AtomicU32 {
+        let seed = (row as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+        &self.cells[row * self.width + (mix(h ^ seed) % self.width as u64) as usize]
+    }
+
+    // Add n to the abundance of the hash h.
+    pub fn increment_by(&self, h: u64, n: u64) {
+        if n == 0 {return;}
+        // the estimate before this increment is the smallest previous value of its counters; concurrent
+        // increments of h would see the same estimate if their counter updates interleaved
+        let _guard = self.stripes[(h % NB_STRIPES as u64) as usize].lock().unwrap();
+        self.add(h, n);
+    }
+
+    // Add one to the abundance of the hash h unless its estimate already reached cap.
+    pub fn increment_capped(&self, h: u64, cap: u64) {
+        let _guard = self.stripes[(h % NB_STRIPES as u64) as usize].lock().unwrap();
+        if self.estimate(h) < cap {self.add(h, 1);}
+    }
+
+    // Add n to the abundance of the hash h; the caller holds the stripe lock of h.
+    fn add(&self, h: u64, n: u64) {
+        let n = n.min(u32::MAX as u64) as u32;
+        let prev = (0..self.depth)
+            .map(|row| self.cell(h, row).fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some(v.saturating_add(n))).unwrap())
+            .min().unwrap();
+        let bin = |c: u32| (c as usize).min(NB_READ_BINS - 1);
+        if prev > 0 {
+            self.spectrum[bin(prev)].fetch_sub(1, Ordering::Relaxed);
+        }
+        self.spectrum[bin(prev.saturating_add(n))].fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    // Estimated abundance of the hash h (0 if it was never counted, unless all its counters collide).
+    pub fn estimate(&self, h: u64) -> u64 {
+        (0..self.depth).map(|row| self.cell(h, row).load(Ordering::Relaxed)).min().unwrap() as u64
+    }
+
+    // Estimated read counts against the reference Index: reference k-min-mers get their estimated abundance
+    // in the reads, and the other read k-min-mers are what remains of the tracked spectrum. Over-counted
+    // k-min-mers move up the spectrum, and collisions with reference k-min-mers can make them look present.
+    pub fn read_counts(&self, ref_mers_index: &Index, width: CounterWidth, threads: usize) -> ReadCounts {
+        let read_in_ref = Index::with_backend(width, &ref_mers_index.backend);
+        let mut spectrum: Vec<i64> = self.spectrum.iter().map(|n| n.load(Ordering::Relaxed)).collect();
+        for (h, _) in ref_mers_index.iter() {
+            let count = self.estimate(h);
+            if count > 0 {
+                read_in_ref.increment_by(h, count);
+                spectrum[(count as usize).min(NB_READ_BINS - 1)] -= 1;
+            }
+        }
+        let not_in_ref: BTreeMap<u64, u64> = spectrum.into_iter().enumerate()
+            .filter(|&(_, n)| n > 0)
+            .map(|(count, n)| (count as u64, n as u64))
+            .collect();
+        ReadCounts::new(read_in_ref, not_in_ref, self.total.load(Ordering::Relaxed), ref_mers_index, threads)
+    }
+}
@@ -0,0 +1,159 @@
+// smudge.rs
+// Heterozygous k-min-mer pairs (--smudge), as smudgeplot pairs k-mers: two k-min-mers whose minimizers differ at a
+// single position usually come from the two alleles of a heterozygous site, and the coverages a <= b of a pair
+// tell its structure, the minor allele being seen a/(a+b) = 1/2 of the time for AB pairs, 1/3 for AAB, 1/4 for
+// AAAB, etc. The reads are read once to count their k-min-mers with their minimizers (from the schemes computed in
+// mers.rs, density_minimizers for the density-based one), and the k-min-mers past the error valley are grouped by
+// their minimizers with one position left out: groups of exactly two make the pairs. With --haplotype-a and
+// --haplotype-b, pairs are instead made of a k-min-mer specific to each haplotype, from their sequences, with the
+// read coverages of both. Writes prefix.smudge.hist2D, the number of pairs per total coverage a+b (rows) and minor
+// fraction a/(a+b) (columns, in bins of 0.01 from 0 to 0.5).
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Instant;
+use dashmap::DashMap;
+use seq_io::BaseRecord;
+use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
+use crate::closures::{open_sequences, SeqReader};
+use crate::error::Error;
+use crate::histo::{self, NB_PAIR_BINS, NB_READ_BINS};
+use crate::mers;
+use crate::signals;
+use crate::spectrum;
+use super::Params;
+use log::{info, warn};
+
+const NB_FRACTION_BINS: usize = 50; // columns of prefix.smudge.hist2D: minor fraction 0..0.5 by 0.01 (0.5 in the last bin)
+
+// Read counts of k-min-mers, with their minimizers if they are to be paired.
+type Counts = DashMap<u64, (u64, Vec<u64>)>;
+
+// Keys of the minimizers of a k-min-mer with each position left out in turn. Two k-min-mers differing at a single
+// position share one key, whichever strand they were seen on.
+fn masked_keys(mers: &[u64]) -> Vec<u64> {
+    (0..mers.len()).map(|i| {
+        let mut masked = mers.to_vec();
+        masked[i] = u64::MAX;
+        mers::canonical_hash(&masked)
+    }).collect()
+}
+
+// Pairs of k-min-mers (hash, minimizers, haplotype) whose minimizers differ at a single position: groups of exactly
+// two k-min-mers sharing all their other minimizers, from both haplotypes unless haplotypes are unknown (0).
+fn pairs(kminmers: &[(u64, &[u64], usize)]) -> Vec<(u64, u64)> {
+    let mut groups: HashMap<u64, Vec<(u64, usize)>> = HashMap::new();
+    for (h, mers, haplotype) in kminmers {
+        for key in masked_keys(mers) {
+            let group = groups.entry(key).or_default();
+            if group.len() < 3 {group.push((*h, *haplotype));}
+        }
+    }
+    let mut pairs: Vec<(u64, u64)> = groups.into_values()
+        .filter(|group| group.len() == 2 && (group[0].1 == 0 || group[0].1 != group[1].1))
+        .map(|group| (group[0].0.min(group[1].0), group[0].0.max(group[1].0)))
+        .collect();
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+// The k-min-mers of haplotype sequences, as hash -> minimizers.
+fn haplotype_kminmers(filenames: &[PathBuf], params: &Params) -> Result<HashMap<u64, Vec<u64>>, Error> {
+    let mut kminmers = HashMap::new();
+    for path in filenames {
+        let mut reader = SeqReader::open(path)?;
+        while let Some(seq) = reader.next_seq(path)? {
+            for mers in mers::kminmer_minimizers(&seq, params) {
+                kminmers.entry(mers::canonical_hash(&mers)).or_insert(mers);
+            }
+        }
+    }
+    Ok(kminmers)
+}
+
+// Count the k-min-mers of the reads, only those of keep if given, in which case their minimizers aren't kept.
+// Returns the counts, and the signal number if reading was interrupted.
+fn count_reads(reads: &PathBuf, keep: Option<&HashSet<u64>>, params: &Params, threads: usize, queue_len: usize) -> Result<(Counts, Option<i32>), Error> {
+    let counts = Counts::new();
+    let count_read = |seq: &[u8]| {
+        for mers in mers::kminmer_minimizers(seq, params) {
+            let h = mers::canonical_hash(&mers);
+            if keep.map_or(false, |keep| !keep.contains(&h)) {continue;}
+            counts.entry(h).or_insert_with(|| (0, if keep.is_some() {Vec::new()} else {mers})).0 += 1;
+        }
+    };
+    let (buf, is_fasta) = open_sequences(reads)?;
+    let interrupted = if is_fasta {
+        let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fasta_records(reader, threads as u32, queue_len, |record, _: &mut Option<()>| {count_read(record.seq())}, |_, _| {signals::pending()})
+    }
+    else {
+        let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fastq_records(reader, threads as u32, queue_len, |record, _: &mut Option<()>| {count_read(record.seq())}, |_, _| {signals::pending()})
+    }.map_err(|e| Error::corrupt(reads, e))?;
+    Ok((counts, interrupted))
+}
+
+// Pair the k-min-mers of the reads, or of the haplotypes (a, b) if given, and write prefix.smudge.hist2D.
+pub fn run_smudge(reads: &PathBuf, haplotypes: Option<(&[PathBuf], &[PathBuf])>, params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let start = Instant::now();
+    let specific = match haplotypes {
+        Some((a_filenames, b_filenames)) => {
+            let (a, b) = (haplotype_kminmers(a_filenames, params)?, haplotype_kminmers(b_filenames, params)?);
+            let a_only: Vec<(u64, Vec<u64>)> = a.iter().filter(|(h, _)| !b.contains_key(h)).map(|(h, mers)| (*h, mers.clone())).collect();
+            let b_only: Vec<(u64, Vec<u64>)> = b.into_iter().filter(|(h, _)| !a.contains_key(h)).collect();
+            info!("{} k-min-mers specific to haplotype A and {} to haplotype B.", a_only.len(), b_only.len());
+            if a_only.is_empty() || b_only.is_empty() {
+                return Err(Error::Usage("The haplotypes have no specific k-min-mers to pair.".to_string()));
+            }
+            Some((a_only, b_only))
+        }
+        None => None,
+    };
+    let keep: Option<HashSet<u64>> = specific.as_ref().map(|(a_only, b_only)| a_only.iter().chain(b_only.iter()).map(|(h, _)| *h).collect());
+    let (counts, interrupted) = count_reads(reads, keep.as_ref(), params, threads, queue_len)?;
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {} while counting the reads.", sig);
+        std::process::exit(128 + sig);
+    }
+    info!("Counted {} k-min-mers of {} in {:?}.", counts.len(), reads.to_str().unwrap(), start.elapsed());
+
+    let pairs = match &specific {
+        Some((a_only, b_only)) => {
+            let kminmers: Vec<(u64, &[u64], usize)> = a_only.iter().map(|(h, mers)| (*h, &mers[..], 1))
+                .chain(b_only.iter().map(|(h, mers)| (*h, &mers[..], 2)))
+                .collect();
+            pairs(&kminmers)
+        }
+        None => {
+            // k-min-mers with a sequencing error pair with the correct ones, so only those past the error valley are paired
+            let mut spectrum = vec![0u64; NB_READ_BINS];
+            for entry in counts.iter() {spectrum[(entry.value().0 as usize).min(NB_READ_BINS - 1)] += 1;}
+            let valley = spectrum::error_threshold(&spectrum) as u64;
+            let solid: Vec<_> = counts.iter().filter(|entry| entry.value().0 > valley).map(|entry| (*entry.key(), entry.value().1.clone())).collect();
+            info!("Pairing the {} k-min-mers seen more than {} times.", solid.len(), valley);
+            pairs(&solid.iter().map(|(h, mers)| (*h, &mers[..], 0)).collect::<Vec<_>>())
+        }
+    };
+
+    let coverage = |h: &u64| counts.get(h).map_or(0, |entry| entry.value().0);
+    let mut hist = vec![vec![0u64; NB_FRACTION_BINS]; NB_PAIR_BINS];
+    let mut nb_pairs = 0;
+    for (h1, h2) in &pairs {
+        let (c1, c2) = (coverage(h1), coverage(h2));
+        let total = c1 + c2;
+        if total == 0 {continue;}
+        let fraction = c1.min(c2) as f64 / total as f64;
+        hist[(total as usize).min(NB_PAIR_BINS - 1)][((fraction * 100.0) as usize).min(NB_FRACTION_BINS - 1)] += 1;
+        nb_pairs += 1;
+    }
+    let column_sums: Vec<u64> = (0..NB_FRACTION_BINS).map(|j| hist.iter().map(|row| row[j]).sum()).collect();
+    match (0..NB_FRACTION_BINS).filter(|&j| column_sums[j] > 0).max_by_key(|&j| column_sums[j]) {
+        Some(j) => info!("{} heterozygous k-min-mer pairs, most with a minor fraction of {:.2}-{:.2} (1/2 for AB pairs, 1/3 for AAB, 1/4 for AAAB).", nb_pairs, j as f64 / 100.0, (j + 1) as f64 / 100.0),
+        None => warn!("No heterozygous k-min-mer pairs found."),
+    }
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".smudge.hist2D");
+    let axes = "rows: total coverage of the pair, columns: minor fraction in bins of 0.01 from 0 to 0.5".to_string();
+    histo::write_matrix(&hist, &path, params.sparse, &[histo::metadata(params), axes])
+}
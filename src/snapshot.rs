@@ -0,0 +1,77 @@
+// snapshot.rs
+// Intermediate histograms (--snapshot-every): while the reads are counted, the 2D histogram of those counted so
+// far is recomputed every N reads (--snapshot-every 1000000) or N minutes (--snapshot-every 10m) and written to
+// prefix.snapshot<i>.hist2D, numbered from 1, with the number of reads in its header. Snapshots are taken on the
+// main thread while the workers keep counting, so a snapshot holds at least that many reads, and maybe some of the
+// next ones: they aren't reproducible, hence not available with --deterministic. Each snapshot logs how much
+// the histogram changed since the previous one, as half the L1 distance between the two histograms normalized to
+// a sum of 1 (0 if the same, 1 if disjoint), so that a run can be stopped once the spectrum has converged.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use crate::error::Error;
+use crate::histo;
+use crate::index::Index;
+use crate::spill;
+use super::Params;
+use log::info;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapshotEvery {
+    Reads(u64),
+    Minutes(f64),
+}
+impl FromStr for SnapshotEvery {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let every = match s.strip_suffix('m') {
+            Some(minutes) => minutes.parse().ok().filter(|&m: &f64| m > 0.0).map(SnapshotEvery::Minutes),
+            None => s.parse().ok().filter(|&n| n > 0).map(SnapshotEvery::Reads),
+        };
+        every.ok_or_else(|| format!("invalid snapshot interval '{}' (expected a number of reads, e.g. 1000000, or of minutes, e.g. 10m)", s))
+    }
+}
+
+pub struct Snapshots<'a> {
+    every: SnapshotEvery,
+    ref_mers_index: &'a Index,
+    params: &'a Params,
+    prefix: String,
+    last: Instant, // time of the last snapshot, or of the start
+    previous: Option<Vec<Vec<u64>>>,
+    nb: usize,
+}
+impl<'a> Snapshots<'a> {
+
+    pub fn new(every: SnapshotEvery, ref_mers_index: &'a Index, params: &'a Params, output_prefix: &PathBuf) -> Self {
+        Snapshots {every, ref_mers_index, params, prefix: output_prefix.to_str().unwrap().to_string(), last: Instant::now(), previous: None, nb: 0}
+    }
+
+    // Write a snapshot of read_mers_index if one is due, nb_reads reads having been counted.
+    pub fn check(&mut self, nb_reads: u64, read_mers_index: &Index) -> Result<(), Error> {
+        let due = match self.every {
+            SnapshotEvery::Reads(n) => nb_reads % n == 0,
+            SnapshotEvery::Minutes(m) => nb_reads % spill::CHECK_EVERY == 0 && self.last.elapsed() >= Duration::from_secs_f64(m * 60.0),
+        };
+        if !due {return Ok(());}
+        let hist = histo::compute(read_mers_index, self.ref_mers_index, self.params.threads);
+        self.nb += 1;
+        let path = format!("{}.snapshot{}.hist2D", self.prefix, self.nb);
+        histo::write_oriented(&hist, &path, self.params, &[histo::metadata(self.params), format!("snapshot {} after at least {} reads", self.nb, nb_reads)])?;
+        match &self.previous {
+            Some(previous) => info!("Snapshot {} after at least {} reads written to {}: {:.2}% of the histogram changed since the previous one.", self.nb, nb_reads, path, 100.0 * change(previous, &hist)),
+            None => info!("Snapshot {} after at least {} reads written to {}.", self.nb, nb_reads, path),
+        }
+        self.previous = Some(hist);
+        self.last = Instant::now();
+        Ok(())
+    }
+}
+
+// Half the L1 distance between two histograms normalized to a sum of 1.
+fn change(previous: &[Vec<u64>], hist: &[Vec<u64>]) -> f64 {
+    let total = |h: &[Vec<u64>]| h.iter().flatten().sum::<u64>().max(1) as f64;
+    let (total_previous, total_hist) = (total(previous), total(hist));
+    previous.iter().flatten().zip(hist.iter().flatten()).map(|(&a, &b)| (a as f64 / total_previous - b as f64 / total_hist).abs()).sum::<f64>() / 2.0
+}
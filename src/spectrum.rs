@@ -0,0 +1,263 @@
+// spectrum.rs
+// GenomeScope-style analysis of the read k-min-mer abundance spectrum: a mixture of an error
+// component and 1/2/3/4-copy coverage peaks is fitted to the marginal read histogram, from which
+// genome size, heterozygosity and repeat fraction are estimated. The error valley and the haploid
+// and diploid coverage peaks are also located directly, without a model, to suggest count thresholds.
+
+use std::cmp;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use super::Params;
+use super::histo;
+use log::{info, warn};
+
+// Number of copy-number components in the model (het, hom, and two repeat peaks).
+const NB_COMPONENTS: usize = 4;
+
+// Result of fitting the mixture model to a read spectrum.
+#[derive(Clone, Debug)]
+pub struct SpectrumModel {
+    pub error_threshold: usize, // abundances up to this value are attributed to sequencing errors
+    pub kcov: f64,              // mean abundance of single-copy (heterozygous) k-min-mers
+    pub fano: f64,              // variance/mean ratio of each coverage peak
+    pub weights: [f64; NB_COMPONENTS], // distinct k-min-mers in each copy-number component
+    pub genome_size: f64,
+    pub heterozygosity: f64,
+    pub repeat_fraction: f64,
+    pub fit_error: f64,         // relative residual of the fit over the fitted range
+}
+
+// Log-probability mass function of a negative binomial of mean mu and variance fano*mu,
+// (a Poisson when fano <= 1) evaluated at 0..=max_x.
+pub fn log_pmf(mu: f64, fano: f64, max_x: usize) -> Vec<f64> {
+    let mut res = Vec::with_capacity(max_x + 1);
+    if fano <= 1.0 {
+        let mut lp = -mu;
+        res.push(lp);
+        for x in 1..=max_x {
+            lp += (mu / x as f64).ln();
+            res.push(lp);
+        }
+    } else {
+        let r = mu / (fano - 1.0);
+        let mut lp = r * (r / (r + mu)).ln();
+        res.push(lp);
+        for x in 1..=max_x {
+            lp += ((x as f64 - 1.0 + r) / x as f64).ln() + (mu / (r + mu)).ln();
+            res.push(lp);
+        }
+    }
+    res
+}
+
+// Solve the (small) linear system a.x = b by Gaussian elimination with partial pivoting.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())?;
+        if a[pivot][col].abs() < 1e-12 {return None;}
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let f = a[row][col] / a[col][col];
+            for c in col..n {a[row][c] -= f * a[col][c];}
+            b[row] -= f * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let s: f64 = (row + 1..n).map(|c| a[row][c] * x[c]).sum();
+        x[row] = (b[row] - s) / a[row][row];
+    }
+    Some(x)
+}
+
+// Least-squares fit of the weights of nb_components components of means kcov, 2*kcov, ... for a given kcov
+// and fano factor over x in [lo, hi]. Returns the weights and the residual sum of squares.
+pub fn fit_weights(spectrum: &[u64], lo: usize, hi: usize, kcov: f64, fano: f64, nb_components: usize) -> Option<(Vec<f64>, f64)> {
+    let pmfs: Vec<Vec<f64>> = (0..nb_components)
+        .map(|c| log_pmf(kcov * (c + 1) as f64, fano, hi).iter().map(|lp| lp.exp()).collect())
+        .collect();
+    let mut ata = vec![vec![0.0; nb_components]; nb_components];
+    let mut atb = vec![0.0; nb_components];
+    for x in lo..=hi {
+        for i in 0..nb_components {
+            atb[i] += pmfs[i][x] * spectrum[x] as f64;
+            for j in 0..nb_components {
+                ata[i][j] += pmfs[i][x] * pmfs[j][x];
+            }
+        }
+    }
+    // regularize so that components vanishing over the fitted range don't make the system singular
+    for i in 0..nb_components {ata[i][i] += 1e-9;}
+    let mut w = solve(ata, atb)?;
+    for wi in w.iter_mut() {if *wi < 0.0 {*wi = 0.0;}}
+    let rss = (lo..=hi).map(|x| {
+        let m: f64 = (0..nb_components).map(|c| w[c] * pmfs[c][x]).sum();
+        (m - spectrum[x] as f64).powi(2)
+    }).sum();
+    Some((w, rss))
+}
+
+// Abundance at the bottom of the error valley: the first local minimum of the spectrum.
+// Returns 0 if the spectrum has no error component (e.g. error-free data), which is also assumed
+// when most k-min-mer occurrences lie before the valley.
+pub fn error_threshold(spectrum: &[u64]) -> usize {
+    let mut x = 1;
+    while x + 1 < spectrum.len() && spectrum[x + 1] <= spectrum[x] {
+        x += 1;
+    }
+    if x + 1 >= spectrum.len() {return 0;}
+    let occurrences = |r: std::ops::Range<usize>| -> f64 {r.map(|i| i as f64 * spectrum[i] as f64).sum()};
+    if occurrences(1..x + 1) > occurrences(x + 1..spectrum.len()) {0} else {x}
+}
+
+// Coverage peaks of a read spectrum, located without a model.
+#[derive(Clone, Copy, Debug)]
+pub struct Peaks {
+    pub valley: usize,                // bottom of the error valley (0 without an error component)
+    pub haploid_peak: Option<usize>,  // abundance of the heterozygous peak, if there is one
+    pub diploid_peak: usize,          // abundance of the homozygous peak, or of the single peak
+    pub depth: f64,                   // k-min-mer sequencing depth of the diploid genome
+    pub min_count: usize,             // suggested lowest abundance of solid k-min-mers
+    pub max_count: usize,             // suggested highest abundance of single-copy k-min-mers
+}
+
+// Smallest height of a secondary peak, relative to the main one.
+const MIN_PEAK_RATIO: f64 = 0.1;
+
+// Locate the error valley and the coverage peaks of a read spectrum: the highest local maximum past the valley
+// (on a 3-bin moving average), and a secondary one near half or twice its abundance, which makes them the
+// haploid and diploid peaks. A lone peak is taken as the diploid one (a homozygous genome). The suggested
+// thresholds keep the abundances past the valley, up to 1.5 times the diploid peak for single-copy k-min-mers.
+pub fn find_peaks(spectrum: &[u64]) -> Option<Peaks> {
+    let valley = error_threshold(spectrum);
+    let peaks = significant_peaks(spectrum, valley);
+    let main = *peaks.first()?;
+    // the most significant peak within 20% of an abundance
+    let peak_near = |target: f64| -> Option<usize> {
+        let (lo, hi) = ((target * 0.8).floor() as usize, (target * 1.2).ceil() as usize);
+        peaks.iter().cloned().find(|&x| x >= lo && x <= hi)
+    };
+    let (haploid_peak, diploid_peak) = match (peak_near(main as f64 / 2.0), peak_near(main as f64 * 2.0)) {
+        (Some(half), _) => (Some(half), main),
+        (None, Some(double)) => (Some(main), double),
+        (None, None) => (None, main),
+    };
+    // the haploid peak, when there is one, locates the diploid coverage more precisely on noisy spectra
+    let depth = haploid_peak.map_or(diploid_peak as f64, |h| (2 * h + diploid_peak) as f64 / 2.0);
+    Some(Peaks {valley, haploid_peak, diploid_peak, depth, min_count: valley + 1, max_count: (1.5 * diploid_peak as f64).round() as usize})
+}
+
+// The local maxima of a read spectrum past the error valley, after smoothing over 3 bins, whose height is at least
+// MIN_PEAK_RATIO times the highest one, by decreasing height.
+pub fn significant_peaks(spectrum: &[u64], valley: usize) -> Vec<usize> {
+    if spectrum.len() < 4 {return Vec::new();}
+    let last = spectrum.len() - 2; // the last bin accumulates all higher abundances
+    let smoothed: Vec<f64> = (0..=last).map(|x| {
+        let window = &spectrum[x.saturating_sub(1)..=(x + 1).min(last)];
+        window.iter().sum::<u64>() as f64 / window.len() as f64
+    }).collect();
+    let is_peak = |x: usize| smoothed[x] >= smoothed[x - 1] && smoothed[x] >= smoothed[x + 1] && smoothed[x] > 0.0;
+    let mut peaks: Vec<usize> = (valley + 1..last).filter(|&x| is_peak(x)).collect();
+    peaks.sort_by(|&a, &b| smoothed[b].partial_cmp(&smoothed[a]).unwrap().then(a.cmp(&b)));
+    let highest = peaks.first().map_or(0.0, |&x| smoothed[x]);
+    peaks.retain(|&x| smoothed[x] >= MIN_PEAK_RATIO * highest);
+    peaks
+}
+
+// Fit the mixture model to a read spectrum (spectrum[x] = number of distinct k-min-mers seen x times).
+// The last bin is not used since it accumulates all higher abundances.
+pub fn fit(spectrum: &[u64], params: &Params) -> Option<SpectrumModel> {
+    if spectrum.len() < 4 {return None;}
+    let last = spectrum.len() - 2;
+    let error_threshold = error_threshold(spectrum);
+    let lo = error_threshold + 1;
+    let peak = (lo..=last).max_by_key(|&x| spectrum[x])?;
+    if spectrum[peak] == 0 {return None;}
+
+    // the main peak is either the heterozygous (kcov) or the homozygous (2*kcov) one
+    let mut best: Option<(f64, f64, Vec<f64>, f64)> = None;
+    let nb_steps = 200;
+    for step in 0..=nb_steps {
+        let kcov = peak as f64 * (0.4 + 0.7 * step as f64 / nb_steps as f64);
+        let hi = cmp::min(last, (kcov * (NB_COMPONENTS as f64 + 1.5)).ceil() as usize);
+        if hi < lo {continue;}
+        for &fano in &[1.0, 1.25, 1.5, 2.0, 3.0, 5.0, 8.0] {
+            if let Some((w, rss)) = fit_weights(spectrum, lo, hi, kcov, fano, NB_COMPONENTS) {
+                // normalize by the size of the fitted range, which grows with kcov
+                let rss = rss / (hi - lo + 1) as f64;
+                if best.as_ref().map_or(true, |b| rss < b.3) {
+                    best = Some((kcov, fano, w, rss));
+                }
+            }
+        }
+    }
+    let (kcov, fano, weights, rss) = best?;
+    let weights: [f64; NB_COMPONENTS] = weights.try_into().unwrap();
+
+    // k-min-mer occurrences that aren't sequencing errors
+    let solid_occurrences: f64 = (lo..spectrum.len()).map(|x| x as f64 * spectrum[x] as f64).sum();
+    let unique_occurrences = weights[0] * kcov + weights[1] * 2.0 * kcov;
+    // one k-min-mer per selected minimizer, i.e. roughly one every 1/density bases
+    let genome_size = solid_occurrences / (2.0 * kcov) / params.density;
+    let repeat_fraction = if solid_occurrences > 0.0 {(1.0 - unique_occurrences / solid_occurrences).max(0.0)} else {0.0};
+
+    // a heterozygous site yields two distinct single-copy k-min-mers, and only alters a k-min-mer
+    // when it falls within one of its k minimizers (k*l bases)
+    let het_positions = weights[0] / 2.0;
+    let het_kminmer_fraction = if het_positions + weights[1] > 0.0 {het_positions / (het_positions + weights[1])} else {0.0};
+    let span = (params.k * params.l) as f64;
+    let heterozygosity = 1.0 - (1.0 - het_kminmer_fraction).powf(1.0 / span);
+
+    let mean_sq: f64 = (lo..=last).map(|x| (spectrum[x] as f64).powi(2)).sum::<f64>() / (last - lo + 1) as f64;
+    let fit_error = if mean_sq > 0.0 {(rss / mean_sq).sqrt()} else {0.0};
+
+    Some(SpectrumModel {error_threshold, kcov, fano, weights, genome_size, heterozygosity, repeat_fraction, fit_error})
+}
+
+// Fit the model to the read marginal of the 2D histogram and write the estimates to prefix.stats.
+pub fn write_stats(hist: &Vec<Vec<u64>>, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let spectrum = histo::read_marginal(hist);
+    let model = fit(&spectrum, params);
+
+    let stats_path = format!("{}{}", output_prefix.to_str().unwrap(), ".stats");
+    let mut stats_file = File::create(&stats_path).output(&stats_path)?;
+    writeln!(stats_file, "# k={} l={} density={}", params.k, params.l, params.density).output(&stats_path)?;
+    match model {
+        Some(m) => {
+            writeln!(stats_file, "error_threshold\t{}", m.error_threshold).output(&stats_path)?;
+            writeln!(stats_file, "kcov\t{:.3}", m.kcov).output(&stats_path)?;
+            writeln!(stats_file, "fano_factor\t{}", m.fano).output(&stats_path)?;
+            for (c, w) in m.weights.iter().enumerate() {
+                writeln!(stats_file, "weight_{}copy\t{:.0}", c + 1, w).output(&stats_path)?;
+            }
+            writeln!(stats_file, "genome_size\t{:.0}", m.genome_size).output(&stats_path)?;
+            writeln!(stats_file, "heterozygosity\t{:.6}", m.heterozygosity).output(&stats_path)?;
+            writeln!(stats_file, "repeat_fraction\t{:.4}", m.repeat_fraction).output(&stats_path)?;
+            writeln!(stats_file, "fit_error\t{:.4}", m.fit_error).output(&stats_path)?;
+            info!("Estimated genome size: {:.0} bp, heterozygosity: {:.4}%, repeat fraction: {:.2}%.", m.genome_size, m.heterozygosity * 100.0, m.repeat_fraction * 100.0);
+        }
+        None => {
+            writeln!(stats_file, "model_fit\tfailed").output(&stats_path)?;
+            warn!("Could not fit a model to the read spectrum.");
+        }
+    }
+    match find_peaks(&spectrum) {
+        Some(p) => {
+            writeln!(stats_file, "error_valley\t{}", p.valley).output(&stats_path)?;
+            writeln!(stats_file, "haploid_peak\t{}", p.haploid_peak.map_or("NA".to_string(), |h| h.to_string())).output(&stats_path)?;
+            writeln!(stats_file, "diploid_peak\t{}", p.diploid_peak).output(&stats_path)?;
+            writeln!(stats_file, "depth\t{:.1}", p.depth).output(&stats_path)?;
+            writeln!(stats_file, "suggested_min_count\t{}", p.min_count).output(&stats_path)?;
+            writeln!(stats_file, "suggested_max_count\t{}", p.max_count).output(&stats_path)?;
+            info!("Estimated k-min-mer depth: {:.1}x; suggested count thresholds: {} (errors below) to {} (repeats above).", p.depth, p.min_count, p.max_count);
+        }
+        None => {
+            writeln!(stats_file, "peaks\tnot_found").output(&stats_path)?;
+        }
+    }
+    Ok(())
+}
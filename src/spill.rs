@@ -0,0 +1,147 @@
+// spill.rs
+// External counting of read k-min-mers under a memory budget (--max-memory): when the read Index grows past the
+// budget, its entries are moved to a run on disk, a file of (hash, count) pairs sorted by hash, and counting
+// goes on in the emptied Index. Once all reads are counted, the runs are merged into a single sorted stream,
+// from which the histogram and statistics are computed without holding all read k-min-mers in memory (see
+// histo::ReadCounts).
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use crate::checkpoint::{read_u64, write_u64};
+use crate::error::{Context, Error};
+use crate::histo::ReadCounts;
+use crate::index::Index;
+use log::{debug, info};
+
+pub const CHECK_EVERY: u64 = 1000; // reads between two checks of the memory used by the read Index
+
+// Runs of a read Index spilled to disk.
+pub struct Spill {
+    budget: usize, // bytes the read Index may use
+    prefix: String,
+    runs: Vec<(PathBuf, usize)>, // path and number of pairs of each run
+}
+impl Spill {
+
+    // Budget the read Index with what max_memory leaves after the reference Index. Half of it is kept free
+    // for the pairs being sorted and written while a run is spilled.
+    pub fn new(max_memory: usize, ref_mers_index: &Index, output_prefix: &PathBuf) -> Result<Self, Error> {
+        let ref_bytes = ref_mers_index.memory_usage();
+        if ref_bytes >= max_memory {
+            return Err(Error::Usage(format!("--max-memory leaves no room for the reads: the reference index alone takes about {:.1} MB.", ref_bytes as f64 / (1 << 20) as f64)));
+        }
+        let budget = (max_memory - ref_bytes) / 2;
+        debug!("Read k-min-mers are spilled to disk above {:.1} MB.", budget as f64 / (1 << 20) as f64);
+        Ok(Spill {budget, prefix: output_prefix.to_str().unwrap().to_string(), runs: Vec::new()})
+    }
+
+    pub fn nb_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    // Spill index to a new run if it exceeds the budget. The Index can be filled by other threads meanwhile.
+    pub fn check(&mut self, index: &Index) -> Result<(), Error> {
+        if index.memory_usage() < self.budget {return Ok(());}
+        let start = Instant::now();
+        let mut pairs: Vec<(u64, u64)> = (0..index.nb_shards()).flat_map(|shard| index.drain_shard(shard)).collect();
+        self.write_run(&mut pairs)?;
+        info!("Spilled {} read k-min-mers to disk (run {}) in {:?}.", pairs.len(), self.runs.len(), start.elapsed());
+        Ok(())
+    }
+
+    // Sort pairs by hash and write them to a new run.
+    fn write_run(&mut self, pairs: &mut Vec<(u64, u64)>) -> Result<(), Error> {
+        pairs.sort_unstable();
+        let path = PathBuf::from(format!("{}.spill{}", self.prefix, self.runs.len()));
+        let mut w = BufWriter::new(File::create(&path).output(&path)?);
+        for &(h, count) in pairs.iter() {
+            write_u64(&mut w, h).output(&path)?;
+            write_u64(&mut w, count).output(&path)?;
+        }
+        w.flush().output(&path)?;
+        self.runs.push((path, pairs.len()));
+        Ok(())
+    }
+
+    // Spill what remains in index, then merge all runs against the reference Index. The runs are removed.
+    pub fn finish(&mut self, index: &Index, ref_mers_index: &Index, threads: usize) -> Result<ReadCounts, Error> {
+        let start = Instant::now();
+        self.write_run(&mut index.drain())?;
+        let merged = merge(&self.runs, ref_mers_index, index, threads)?;
+        info!("Merged {} runs of read k-min-mers in {:?}.", self.runs.len(), start.elapsed());
+        self.remove_runs();
+        Ok(merged)
+    }
+
+    fn remove_runs(&mut self) {
+        for (path, _) in self.runs.drain(..) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+impl Drop for Spill {
+    fn drop(&mut self) {
+        self.remove_runs();
+    }
+}
+
+// Reader over the (hash, count) pairs of a run.
+struct Run {
+    path: PathBuf,
+    reader: BufReader<File>,
+    remaining: usize,
+}
+impl Run {
+
+    fn open(path: &PathBuf, len: usize) -> Result<Self, Error> {
+        Ok(Run {path: path.clone(), reader: BufReader::new(File::open(path).input(path)?), remaining: len})
+    }
+
+    fn next(&mut self) -> Result<Option<(u64, u64)>, Error> {
+        if self.remaining == 0 {return Ok(None);}
+        self.remaining -= 1;
+        let h = read_u64(&mut self.reader).corrupt(&self.path)?;
+        let count = read_u64(&mut self.reader).corrupt(&self.path)?;
+        Ok(Some((h, count)))
+    }
+}
+
+// Merge runs into a stream of (hash, count) pairs with distinct hashes, and count them against the reference Index,
+// into an Index like read_mers_index.
+fn merge(runs: &[(PathBuf, usize)], ref_mers_index: &Index, read_mers_index: &Index, threads: usize) -> Result<ReadCounts, Error> {
+    let mut readers = runs.iter().map(|(path, len)| Run::open(path, *len)).collect::<Result<Vec<_>, _>>()?;
+    let mut heap = BinaryHeap::new(); // next pair of each run, smallest hash first
+    for (i, reader) in readers.iter_mut().enumerate() {
+        if let Some((h, count)) = reader.next()? {heap.push(Reverse((h, count, i)));}
+    }
+    let read_in_ref = read_mers_index.empty_like();
+    let mut not_in_ref = BTreeMap::new(); // distinct read k-min-mers absent from the reference, per abundance
+    let mut total = 0;
+    let mut current: Option<(u64, u64)> = None;
+    let mut add = |(h, count): (u64, u64)| {
+        total += count;
+        if ref_mers_index.get(&h).is_some() {
+            read_in_ref.increment_by(h, count);
+        }
+        else {
+            *not_in_ref.entry(count).or_insert(0) += 1;
+        }
+    };
+    while let Some(Reverse((h, count, i))) = heap.pop() {
+        if let Some(next) = readers[i].next()? {heap.push(Reverse((next.0, next.1, i)));}
+        current = match current {
+            Some((prev, sum)) if prev == h => Some((h, sum + count)),
+            Some(pair) => {
+                add(pair);
+                Some((h, count))
+            }
+            None => Some((h, count)),
+        };
+    }
+    if let Some(pair) = current {add(pair);}
+    Ok(ReadCounts::new(read_in_ref, not_in_ref, total, ref_mers_index, threads))
+}
@@ -0,0 +1,93 @@
+// sqlite.rs
+// SQLite output (--sqlite out.db), for QC dashboards that query the counts directly: a kminmers table of
+// hash, read_count and ref_count rows for every k-min-mer of the reads or the reference, indexed on both
+// counts, the nonzero cells of the 2D histogram in a hist2d table, and the parameters in a metadata table.
+// SQLite integers are signed, so hashes are stored as the i64 with the same bits.
+// Only available when built with the "sqlite" feature.
+
+use std::path::PathBuf;
+use crate::error::Error;
+use crate::index::Index;
+use super::Params;
+
+// Fail early if SQLite output was asked for but isn't compiled in.
+pub fn check_available() -> Result<(), Error> {
+    if cfg!(feature = "sqlite") {Ok(())}
+    else {Err(Error::Usage("--sqlite needs a build with the \"sqlite\" feature (cargo build --release --features sqlite).".to_string()))}
+}
+
+#[cfg(feature = "sqlite")]
+mod imp {
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+    use rusqlite::{params, Connection};
+    use crate::error::{Context, Error};
+    use crate::histo;
+    use crate::index::Index;
+    use crate::Params;
+
+    fn other(e: rusqlite::Error) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+
+    fn write(conn: &mut Connection, read_mers_index: &Index, ref_mers_index: &Index, hist: &Vec<Vec<u64>>, params: &Params) -> rusqlite::Result<()> {
+        // the database is only read once written: no journal, and no sync until it is closed
+        conn.execute_batch("
+            PRAGMA journal_mode = OFF;
+            PRAGMA synchronous = OFF;
+            CREATE TABLE metadata (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE kminmers (hash INTEGER PRIMARY KEY, read_count INTEGER NOT NULL, ref_count INTEGER NOT NULL);
+            CREATE TABLE hist2d (read_abundance INTEGER NOT NULL, ref_abundance INTEGER NOT NULL, count INTEGER NOT NULL, PRIMARY KEY (read_abundance, ref_abundance));
+        ")?;
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT INTO metadata VALUES (?1, ?2)")?;
+            insert.execute(params!["parameters", histo::metadata(params)])?;
+            insert.execute(params!["version", env!("CARGO_PKG_VERSION")])?;
+            let mut insert = tx.prepare("INSERT INTO kminmers VALUES (?1, ?2, ?3)")?;
+            for (node, read_abundance) in read_mers_index.iter_ordered(params.deterministic) {
+                insert.execute(params![node as i64, read_abundance as i64, ref_mers_index.get(&node).unwrap_or(0) as i64])?;
+            }
+            // reference k-min-mers absent from the reads
+            for (node, ref_abundance) in ref_mers_index.iter_ordered(params.deterministic) {
+                if read_mers_index.get(&node).is_none() {
+                    insert.execute(params![node as i64, 0, ref_abundance as i64])?;
+                }
+            }
+            let mut insert = tx.prepare("INSERT INTO hist2d VALUES (?1, ?2, ?3)")?;
+            for (i, row) in hist.iter().enumerate() {
+                for (j, cell) in row.iter().enumerate() {
+                    if *cell > 0 {
+                        insert.execute(params![i as i64, j as i64, *cell as i64])?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        // indexes are faster to build once the rows are in
+        conn.execute_batch("
+            CREATE INDEX kminmers_read_count ON kminmers (read_count);
+            CREATE INDEX kminmers_ref_count ON kminmers (ref_count);
+        ")
+    }
+
+    pub fn write_sqlite(read_mers_index: &Index, ref_mers_index: &Index, hist: &Vec<Vec<u64>>, params: &Params, path: &PathBuf) -> Result<(), Error> {
+        // replace any previous database rather than adding to it
+        if path.exists() {fs::remove_file(path).output(path)?;}
+        let mut conn = Connection::open(path).map_err(other).output(path)?;
+        write(&mut conn, read_mers_index, ref_mers_index, hist, params).map_err(other).output(path)?;
+        conn.close().map_err(|(_, e)| other(e)).output(path)
+    }
+}
+
+// Write the k-min-mer counts and the 2D histogram to a new SQLite database at path.
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(read_mers_index: &Index, ref_mers_index: &Index, hist: &Vec<Vec<u64>>, params: &Params, path: &PathBuf) -> Result<(), Error> {
+    imp::write_sqlite(read_mers_index, ref_mers_index, hist, params, path)
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn write_sqlite(read_mers_index: &Index, ref_mers_index: &Index, hist: &Vec<Vec<u64>>, params: &Params, path: &PathBuf) -> Result<(), Error> {
+    check_available()
+}
@@ -0,0 +1,83 @@
+// summary.rs
+// Machine-readable summary of a run (prefix.stats.json): input sizes, k-min-mer counts, peak memory
+// and wall-clock time of each stage, for benchmarking and workflow tracking. With --deterministic, the memory
+// and times are null, so that the file is the same across runs.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use serde_json::json;
+use crate::error::{Context, Error};
+use crate::get_memory_rusage;
+use crate::index::Index;
+use crate::histo::Similarity;
+use super::Params;
+
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    pub nb_reads: u64,
+    pub nb_duplicates: u64, // duplicate reads skipped
+    stages: Vec<(String, Duration)>, // in the order they were run
+}
+impl Summary {
+
+    pub fn new() -> Self {
+        Summary::default()
+    }
+
+    // Record the wall-clock time of a stage.
+    pub fn add_stage(&mut self, name: &str, duration: Duration) {
+        self.stages.push((name.to_string(), duration));
+    }
+}
+
+// Total and distinct number of k-min-mers in an Index.
+fn totals(index: &Index) -> (u64, u64) {
+    index.iter().fold((0, 0), |(total, distinct), (_, count)| (total + count, distinct + 1))
+}
+
+// Write the summary of a run to prefix.stats.json.
+pub fn write_summary(summary: &Summary, read_mers_index: &Index, ref_mers_index: &Index, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let shared = read_mers_index.iter().filter(|(h, _)| ref_mers_index.get(h).is_some()).count();
+    write_summary_counts(summary, totals(read_mers_index), shared, ref_mers_index, params, output_prefix)
+}
+
+// Write the summary of a run given the total and distinct number of read k-min-mers, and the number of them
+// in the reference, when the read k-min-mers aren't all in an Index.
+pub fn write_summary_counts(summary: &Summary, (read_total, read_distinct): (u64, u64), shared: usize, ref_mers_index: &Index, params: &Params, output_prefix: &PathBuf) -> Result<(), Error> {
+    let (ref_total, ref_distinct) = totals(ref_mers_index);
+    let similarity = Similarity::new(read_distinct, ref_distinct, shared as u64);
+    let stages: Vec<_> = summary.stages.iter()
+        .map(|(name, d)| json!({"stage": name, "seconds": if params.deterministic {None} else {Some(d.as_secs_f64())}}))
+        .collect();
+    let doc = json!({
+        "k": params.k,
+        "l": params.l,
+        "density": params.density,
+        "reads": {
+            "nb_reads": summary.nb_reads,
+            "nb_duplicates": summary.nb_duplicates,
+            "total_kminmers": read_total,
+            "distinct_kminmers": read_distinct,
+        },
+        "reference": {
+            "total_kminmers": ref_total,
+            "distinct_kminmers": ref_distinct,
+        },
+        "shared_kminmers": shared,
+        "similarity": {
+            "jaccard": similarity.jaccard,
+            "containment_reads_in_ref": similarity.reads_in_ref,
+            "containment_ref_in_reads": similarity.ref_in_reads,
+        },
+        "peak_rss_bytes": if params.deterministic {None} else {Some(get_memory_rusage())},
+        "stages": stages,
+    });
+
+    let summary_path = format!("{}{}", output_prefix.to_str().unwrap(), ".stats.json");
+    let mut file = BufWriter::new(File::create(&summary_path).output(&summary_path)?);
+    serde_json::to_writer_pretty(&mut file, &doc).map_err(std::io::Error::from).output(&summary_path)?;
+    writeln!(file).output(&summary_path)?;
+    file.flush().output(&summary_path)
+}
@@ -0,0 +1,104 @@
+// sweep.rs
+// Parameter sweep over several k values (-k 3,5,8) in a single pass: each reference and read sequence is parsed
+// once and its k-min-mers are counted for every k into separate Indexes, so that the I/O and parsing are shared
+// by the whole sweep. One set of outputs is written per k, to prefix.k<K>.*.
+
+use std::path::PathBuf;
+use std::time::Instant;
+use dashmap::DashMap;
+use seq_io::BaseRecord;
+use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
+use crate::closures::{self, open_sequences};
+use crate::error::Error;
+use crate::histo;
+use crate::index::Index;
+use crate::mers;
+use crate::signals;
+use crate::summary::{self, Summary};
+use super::Params;
+use log::{debug, info, warn};
+
+// Count the k-min-mers of all sequences of files, for each of params_list into the Index of the same rank.
+// Reference sequences are counted like with mers::ref_extract, reads like with mers::process_read.
+// Returns the number of sequences, and the signal number if counting was interrupted.
+fn count_files(files: &[PathBuf], params_list: &[Params], indexes: &[Index], is_reference: bool, threads: usize, queue_len: usize) -> Result<(u64, Option<i32>), Error> {
+    let lens: DashMap<String, usize> = DashMap::new();
+    let count_seq = |seq_id: &str, seq: &[u8]| {
+        for (params, index) in params_list.iter().zip(indexes) {
+            if is_reference {
+                mers::ref_extract(seq_id, seq, params, index, None);
+            }
+            else {
+                mers::process_read(seq_id, seq.len(), seq, None, &lens, index, params, None, None, None);
+            }
+        }
+    };
+    let mut nb_seqs: u64 = 0;
+    let mut main_thread = || { // runs in main thread
+        nb_seqs += 1;
+        signals::pending()
+    };
+    for path in files {
+        let (buf, is_fasta) = open_sequences(path)?;
+        let interrupted = if is_fasta {
+            let reader = seq_io::fasta::Reader::with_capacity(buf, params_list[0].chunk_size);
+            read_process_fasta_records(reader, threads as u32, queue_len, |record, _: &mut ()| {count_seq(record.id().unwrap_or(""), record.seq())}, |_, _| {main_thread()})
+        }
+        else {
+            let reader = seq_io::fastq::Reader::with_capacity(buf, params_list[0].chunk_size);
+            read_process_fastq_records(reader, threads as u32, queue_len, |record, _: &mut ()| {count_seq(record.id().unwrap_or(""), record.seq())}, |_, _| {main_thread()})
+        }.map_err(|e| Error::corrupt(path, e))?;
+        if interrupted.is_some() {return Ok((nb_seqs, interrupted));}
+    }
+    Ok((nb_seqs, None))
+}
+
+// Output prefix of the results for one k.
+fn k_prefix(output_prefix: &PathBuf, k: usize) -> PathBuf {
+    PathBuf::from(format!("{}.k{}", output_prefix.to_str().unwrap(), k))
+}
+
+// If a signal was received, flush a partial histogram per k of what was counted so far and exit.
+fn exit_if_interrupted(interrupted: Option<i32>, params_list: &[Params], read_indexes: &[Index], ref_indexes: &[Index], output_prefix: &PathBuf) -> Result<(), Error> {
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {}, writing partial histograms.", sig);
+        for ((params, read_index), ref_index) in params_list.iter().zip(read_indexes).zip(ref_indexes) {
+            let hist = histo::compute(read_index, ref_index, params.threads);
+            histo::write_partial_hist2d(&hist, sig, params, &k_prefix(output_prefix, params.k))?;
+        }
+        std::process::exit(128 + sig);
+    }
+    Ok(())
+}
+
+// Count the reference and the reads once for all values of ks, and write the outputs of each k.
+pub fn run_sweep(reads: &PathBuf, ref_filenames: &[PathBuf], ks: &[usize], params: &Params, threads: usize, queue_len: usize, ref_queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let params_list: Vec<Params> = ks.iter().map(|&k| Params {k, ..params.clone()}).collect();
+    let ref_indexes: Vec<Index> = ks.iter().map(|_| Index::with_backend(params.counter_width, &params.index_backend)).collect();
+    let read_indexes: Vec<Index> = ks.iter().map(|_| Index::with_backend(params.counter_width, &params.index_backend)).collect();
+    let mut summary = Summary::new();
+
+    let start = Instant::now();
+    let (_, interrupted) = count_files(ref_filenames, &params_list, &ref_indexes, true, threads, ref_queue_len)?;
+    exit_if_interrupted(interrupted, &params_list, &read_indexes, &ref_indexes, output_prefix)?;
+    info!("Indexed {} reference file(s) for k={:?} in {:?}.", ref_filenames.len(), ks, start.elapsed());
+    summary.add_stage("index_reference", start.elapsed());
+
+    let start = Instant::now();
+    let (nb_reads, interrupted) = count_files(&[reads.clone()], &params_list, &read_indexes, false, threads, queue_len)?;
+    exit_if_interrupted(interrupted, &params_list, &read_indexes, &ref_indexes, output_prefix)?;
+    info!("Counted {} reads of {} for k={:?} in {:?}.", nb_reads, reads.to_str().unwrap(), ks, start.elapsed());
+    summary.nb_reads = nb_reads;
+    summary.add_stage("count_reads", start.elapsed());
+
+    for ((params, read_index), ref_index) in params_list.iter().zip(&read_indexes).zip(&ref_indexes) {
+        let prefix = k_prefix(output_prefix, params.k);
+        debug!("k={}: {} distinct k-min-mers in the reads, {} in the reference.", params.k, read_index.len(), ref_index.len());
+        let mut k_summary = summary.clone();
+        let start = Instant::now();
+        closures::write_outputs(read_index, ref_index, None, None, params, &prefix)?;
+        k_summary.add_stage("write_outputs", start.elapsed());
+        summary::write_summary(&k_summary, read_index, ref_index, params, &prefix)?;
+    }
+    Ok(())
+}
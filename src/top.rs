@@ -0,0 +1,44 @@
+// top.rs
+// Report of the most abundant read k-min-mers (--top-kminmers N): the N k-min-mers with the highest read counts,
+// with their reference counts, written to prefix.top. Those driving spikes far above the coverage peak of the
+// spectrum usually come from adapters, a spike-in such as phiX, or plastid and mitochondrial sequence; a
+// reference count of 0 tells they are missing from the reference. With --top-read-ids, the reads are read again
+// to add the ID of a read containing each.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::index::Index;
+use log::info;
+
+// The n read k-min-mers of highest read count, as (hash, read count), by decreasing read count (then by hash).
+pub fn top_kminmers(read_mers_index: &Index, n: usize) -> Vec<(u64, u64)> {
+    // min-heap of the n best so far, so that memory stays proportional to n
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for (h, count) in read_mers_index.iter() {
+        heap.push(Reverse((count, Reverse(h))));
+        if heap.len() > n {heap.pop();}
+    }
+    heap.into_sorted_vec().into_iter().map(|Reverse((count, Reverse(h)))| (h, count)).collect()
+}
+
+// Write prefix.top: the top k-min-mers with their reference counts, and the ID of a read containing each if
+// examples are given.
+pub fn write_top(top: &[(u64, u64)], ref_mers_index: &Index, examples: Option<&HashMap<u64, String>>, output_prefix: &PathBuf) -> Result<(), Error> {
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".top");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    writeln!(file, "# hash\tread_count\tref_count{}", if examples.is_some() {"\tread_id"} else {""}).output(&path)?;
+    for (h, count) in top {
+        let ref_count = ref_mers_index.get(h).unwrap_or(0);
+        match examples {
+            Some(examples) => writeln!(file, "{}\t{}\t{}\t{}", h, count, ref_count, examples.get(h).map_or("NA", |id| id.as_str())),
+            None => writeln!(file, "{}\t{}\t{}", h, count, ref_count),
+        }.output(&path)?;
+    }
+    file.flush().output(&path)?;
+    info!("{} most abundant read k-min-mers written to {}.", top.len(), path);
+    Ok(())
+}
@@ -0,0 +1,107 @@
+// trio.rs
+// Trio binning in minimizer space (--maternal, --paternal): both parental references are indexed, and each read
+// is assigned to the parent whose haplotype-specific k-min-mers (present in one parent and absent from the other)
+// it contains most, as in TrioCanu. Counts are scaled by the number of specific k-min-mers of each parent, so that
+// a parent with more of them isn't favored. Writes prefix.trio.tsv, one line per read, and prefix.trio.hist2D,
+// the number of reads per count of maternal (rows) and paternal (columns) specific k-min-mers.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use seq_io::BaseRecord;
+use seq_io::parallel::{read_process_fasta_records, read_process_fastq_records};
+use crate::closures::{index_reference, open_sequences};
+use crate::error::{Context, Error};
+use crate::histo;
+use crate::index::Index;
+use crate::mers;
+use crate::report;
+use crate::signals;
+use super::Params;
+use log::{info, warn};
+
+const NB_TRIO_BINS: usize = 100; // rows and columns of prefix.trio.hist2D: 0..99 specific k-min-mers (last bin holds more)
+
+// Haplotype of a read, from its scaled counts of maternal and paternal specific k-min-mers.
+fn classify(maternal: f64, paternal: f64) -> &'static str {
+    if maternal > paternal {"maternal"}
+    else if paternal > maternal {"paternal"}
+    else if maternal > 0.0 {"ambiguous"}
+    else {"unknown"}
+}
+
+// Number of k-min-mers of index absent from other.
+fn nb_specific(index: &Index, other: &Index) -> u64 {
+    index.iter().filter(|(h, _)| other.get(h).is_none()).count() as u64
+}
+
+// Index both parents, then classify each read of reads.
+pub fn run_trio(reads: &PathBuf, maternal_filenames: &[PathBuf], paternal_filenames: &[PathBuf], params: &Params, threads: usize, queue_len: usize, ref_queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let mut parents = Vec::new();
+    for filenames in [maternal_filenames, paternal_filenames] {
+        let (index, interrupted) = index_reference(filenames, params, threads, ref_queue_len, None)?;
+        if let Some(sig) = interrupted {
+            warn!("Interrupted by signal {} while indexing the parents.", sig);
+            std::process::exit(128 + sig);
+        }
+        parents.push(index);
+    }
+    let (maternal_index, paternal_index) = (&parents[0], &parents[1]);
+    let (nb_maternal, nb_paternal) = (nb_specific(maternal_index, paternal_index), nb_specific(paternal_index, maternal_index));
+    info!("{} maternal and {} paternal specific k-min-mers.", nb_maternal, nb_paternal);
+    if nb_maternal == 0 || nb_paternal == 0 {
+        return Err(Error::Usage("The parents have no specific k-min-mers to tell them apart.".to_string()));
+    }
+
+    // Each record yields (length, number of k-min-mers, maternal and paternal specific ones)
+    let count_read = |seq: &[u8], seq_id: &str| -> (usize, usize, usize, usize) {
+        let (mut nb, mut maternal, mut paternal) = (0, 0, 0);
+        if let Some(kminmers) = mers::extract(seq_id, seq, params) {
+            for kminmer in kminmers {
+                let h = kminmer.get_hash_u64();
+                match (maternal_index.get(&h).is_some(), paternal_index.get(&h).is_some()) {
+                    (true, false) => maternal += 1,
+                    (false, true) => paternal += 1,
+                    _ => {}
+                }
+                nb += 1;
+            }
+        }
+        (report::seq_len(seq), nb, maternal, paternal)
+    };
+
+    let path = format!("{}{}", output_prefix.to_str().unwrap(), ".trio.tsv");
+    let mut file = BufWriter::new(File::create(&path).output(&path)?);
+    writeln!(file, "read_id\tlength\tnb_kminmers\tmaternal\tpaternal\thaplotype").output(&path)?;
+    let mut hist = vec![vec![0u64; NB_TRIO_BINS]; NB_TRIO_BINS];
+    let mut nb_reads = [0u64; 4]; // maternal, paternal, ambiguous, unknown
+    let mut main_thread = |seq_id: &str, found: &mut Option<(usize, usize, usize, usize)>| { // runs in main thread
+        let (length, nb, maternal, paternal) = found.unwrap();
+        let haplotype = classify(maternal as f64 / nb_maternal as f64, paternal as f64 / nb_paternal as f64);
+        nb_reads[["maternal", "paternal", "ambiguous", "unknown"].iter().position(|&c| c == haplotype).unwrap()] += 1;
+        hist[maternal.min(NB_TRIO_BINS - 1)][paternal.min(NB_TRIO_BINS - 1)] += 1;
+        if let Err(e) = writeln!(file, "{}\t{}\t{}\t{}\t{}\t{}", seq_id, length, nb, maternal, paternal, haplotype).output(&path) {
+            return Some(Err(e));
+        }
+        signals::pending().map(Ok)
+    };
+    let start = Instant::now();
+    let (buf, is_fasta) = open_sequences(reads)?;
+    let interrupted = if is_fasta {
+        let reader = seq_io::fasta::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fasta_records(reader, threads as u32, queue_len, |record, found: &mut Option<_>| {*found = Some(count_read(record.seq(), record.id().unwrap_or("")))}, |record, found| {main_thread(record.id().unwrap_or(""), found)})
+    }
+    else {
+        let reader = seq_io::fastq::Reader::with_capacity(buf, params.chunk_size);
+        read_process_fastq_records(reader, threads as u32, queue_len, |record, found: &mut Option<_>| {*found = Some(count_read(record.seq(), record.id().unwrap_or("")))}, |record, found| {main_thread(record.id().unwrap_or(""), found)})
+    }.map_err(|e| Error::corrupt(reads, e))?.transpose()?;
+    file.flush().output(&path)?;
+    if let Some(sig) = interrupted {
+        warn!("Interrupted by signal {}, the read classification is partial.", sig);
+        std::process::exit(128 + sig);
+    }
+    info!("Binned {} maternal, {} paternal, {} ambiguous and {} unknown reads of {} in {:?}.", nb_reads[0], nb_reads[1], nb_reads[2], nb_reads[3], reads.to_str().unwrap(), start.elapsed());
+    let hist_path = format!("{}{}", output_prefix.to_str().unwrap(), ".trio.hist2D");
+    histo::write_matrix(&hist, &hist_path, params.sparse, &[histo::metadata(params)])
+}
@@ -0,0 +1,61 @@
+// tune.rs
+// Automatic choice of the density (--target-kminmers-per-kb), so that users don't have to hand-tune it per
+// dataset: the k-min-mer yield is measured on the first reads, and the density scaled until the yield is close
+// to the target. The yield is about proportional to the density, so this takes a few rounds.
+
+use std::path::PathBuf;
+use crate::closures::SeqReader;
+use crate::error::Error;
+use crate::mers;
+use crate::report;
+use super::Params;
+use log::{debug, info, warn};
+
+const MAX_ROUNDS: usize = 10;
+const TOLERANCE: f64 = 0.02; // relative distance to the target at which to stop
+
+// Whole reads from the start of a file, up to sample_bases bases.
+fn sample_reads(path: &PathBuf, sample_bases: usize) -> Result<Vec<Vec<u8>>, Error> {
+    let mut reader = SeqReader::open(path)?;
+    let (mut sample, mut nb_bases) = (Vec::new(), 0);
+    while nb_bases < sample_bases {
+        match reader.next_seq(path)? {
+            Some(seq) => {
+                nb_bases += report::seq_len(&seq);
+                sample.push(seq);
+            }
+            None => break,
+        }
+    }
+    Ok(sample)
+}
+
+// k-min-mers per kilobase of the sample, with the current parameters.
+fn kminmers_per_kb(sample: &[Vec<u8>], params: &Params) -> f64 {
+    let nb_kminmers: usize = sample.iter().map(|seq| mers::extract("", seq, params).map_or(0, |it| it.count())).sum();
+    let nb_bases: usize = sample.iter().map(|seq| report::seq_len(seq)).sum();
+    nb_kminmers as f64 * 1000.0 / nb_bases.max(1) as f64
+}
+
+// Set params.density so that the first sample_mb megabases of reads yield about target k-min-mers per kilobase.
+pub fn tune_density(path: &PathBuf, params: &mut Params, target: f64, sample_mb: usize) -> Result<(), Error> {
+    let sample = sample_reads(path, sample_mb * 1_000_000)?;
+    if sample.is_empty() {
+        return Err(Error::corrupt(path, "no reads to tune the density on"));
+    }
+    let mut per_kb = kminmers_per_kb(&sample, params);
+    for _ in 0..MAX_ROUNDS {
+        debug!("Density {}: {:.3} k-min-mers per kb.", params.density, per_kb);
+        if (per_kb - target).abs() <= TOLERANCE * target {break;}
+        if per_kb < target && params.density >= 1.0 {
+            warn!("Reads yield at most {:.3} k-min-mers per kb (with density 1), less than the target.", per_kb);
+            break;
+        }
+        let density = if per_kb > 0.0 {params.density * target / per_kb} else {params.density * 10.0};
+        // 3 significant digits are plenty, and keep the default output prefix readable
+        params.density = format!("{:.2e}", density.min(1.0)).parse().unwrap();
+        per_kb = kminmers_per_kb(&sample, params);
+    }
+    info!("Tuned density to {} on {} reads: {:.3} k-min-mers per kb (target {}).", params.density, sample.len(), per_kb, target);
+    Ok(())
+}
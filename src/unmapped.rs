@@ -0,0 +1,61 @@
+// unmapped.rs
+// Extraction of the reads the reference doesn't explain (--extract-unmapped-reads): reads whose fraction of
+// k-min-mers present in the reference falls below a threshold are written out as they were read, FASTQ or FASTA,
+// e.g. as raw material for assembling the sequence missing from the reference without mapping the reads first.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use crate::error::{Context, Error};
+use crate::index::Index;
+
+pub struct UnmappedReads<'a> {
+    pub ref_mers_index: &'a Index,
+    max_fraction: f64,
+    path: String,
+    file: BufWriter<File>,
+    nb_reads: u64,
+}
+impl<'a> UnmappedReads<'a> {
+
+    pub fn create(ref_mers_index: &'a Index, path: &PathBuf, max_fraction: f64) -> Result<Self, Error> {
+        let path = path.to_str().unwrap().to_string();
+        let file = BufWriter::new(File::create(&path).output(&path)?);
+        Ok(UnmappedReads {ref_mers_index, max_fraction, path, file, nb_reads: 0})
+    }
+
+    // Write a read (FASTQ if it has qualities) if less than max_fraction of its k-min-mers are in the reference.
+    // Reads too short to have any k-min-mer are left out.
+    pub fn write(&mut self, head: &[u8], seq: &[u8], qual: Option<&[u8]>, nb_kminmers: usize, nb_in_ref: usize) -> Result<(), Error> {
+        if nb_kminmers == 0 || nb_in_ref as f64 >= self.max_fraction * nb_kminmers as f64 {return Ok(());}
+        self.nb_reads += 1;
+        write_record(&mut self.file, head, seq, qual).output(&self.path)
+    }
+
+    // Flush the file and return the number of reads written to it.
+    pub fn finish(mut self) -> Result<u64, Error> {
+        self.file.flush().output(&self.path)?;
+        Ok(self.nb_reads)
+    }
+}
+
+// Write a record as FASTQ if it has qualities, as FASTA otherwise.
+pub fn write_record(file: &mut impl Write, head: &[u8], seq: &[u8], qual: Option<&[u8]>) -> io::Result<()> {
+    match qual {
+        Some(qual) => {
+            file.write_all(b"@")?;
+            file.write_all(head)?;
+            file.write_all(b"\n")?;
+            file.write_all(seq)?;
+            file.write_all(b"\n+\n")?;
+            file.write_all(qual)?;
+        }
+        None => {
+            file.write_all(b">")?;
+            file.write_all(head)?;
+            file.write_all(b"\n")?;
+            file.write_all(seq)?;
+        }
+    }
+    file.write_all(b"\n")
+}
@@ -0,0 +1,68 @@
+// wasm.rs
+// WebAssembly bindings (the "wasm" feature, built with wasm-pack), for the browser demo in demo/: FASTA/FASTQ
+// files dropped on the page are counted client-side, on the calling thread, and their 2D histogram is drawn.
+//
+//     import init, {Index, histogram} from "./pkg/kminmer2dhisto.js";
+//     await init();
+//     const reads = new Index(5, 31, 0.01), ref = new Index(5, 31, 0.01);
+//     reads.add_fastx(new Uint8Array(await readsFile.arrayBuffer()), false);
+//     ref.add_fastx(new Uint8Array(await refFile.arrayBuffer()), true);
+//     const h = histogram(reads, ref, 100, 10); // Float64Array, h[x * 10 + y]
+
+use dashmap::DashMap;
+use wasm_bindgen::prelude::*;
+use crate::histo;
+use crate::index::Index;
+use crate::mers;
+use super::Params;
+
+// Index of k-min-mer counts, with the parameters it was built with.
+#[wasm_bindgen(js_name = Index)]
+pub struct WasmIndex {
+    index: Index,
+    params: Params,
+    lens: DashMap<String, usize>, // read lengths, as in closures::index_reads
+}
+
+#[wasm_bindgen(js_class = Index)]
+impl WasmIndex {
+
+    #[wasm_bindgen(constructor)]
+    pub fn new(k: usize, l: usize, density: f64) -> Result<WasmIndex, JsError> {
+        if k == 0 || l == 0 || !(density > 0.0 && density <= 1.0) {
+            return Err(JsError::new("k and l must be positive, and density in (0, 1]."));
+        }
+        Ok(WasmIndex {index: Index::new(), params: Params::new(k, l, density), lens: DashMap::new()})
+    }
+
+    // Count the records of a FASTA or FASTQ file (uncompressed), as reads or reference sequences. Returns the
+    // number of records.
+    pub fn add_fastx(&self, buf: &[u8], reference: bool) -> Result<usize, JsError> {
+        mers::index_fastx(buf, reference, &self.index, &self.lens, &self.params).ok_or_else(|| JsError::new("Not a FASTA or FASTQ file."))
+    }
+
+    // Number of distinct k-min-mers.
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.index.len()
+    }
+
+    // Count of a k-min-mer hash (a BigInt), 0 if absent.
+    pub fn get(&self, hash: u64) -> u64 {
+        self.index.get(&hash).unwrap_or(0)
+    }
+}
+
+// 2D histogram of a read and a reference index built with the same parameters, as a row-major rows x cols
+// matrix: h[x * cols + y] is the number of k-min-mers seen x times in the reads and y times in the reference, the
+// last row and column holding all higher abundances. Counts are numbers rather than BigInts, for plotting.
+#[wasm_bindgen]
+pub fn histogram(reads: &WasmIndex, reference: &WasmIndex, rows: usize, cols: usize) -> Result<Vec<f64>, JsError> {
+    if rows == 0 || cols == 0 {
+        return Err(JsError::new("The histogram needs at least one row and one column."));
+    }
+    if histo::metadata(&reads.params) != histo::metadata(&reference.params) {
+        return Err(JsError::new(&format!("The indexes were built with different parameters ({} and {}).", histo::metadata(&reads.params), histo::metadata(&reference.params))));
+    }
+    Ok(histo::compute_binned(&reads.index, &reference.index, rows, cols, reads.params.threads).into_iter().map(|count| count as f64).collect())
+}
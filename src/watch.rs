@@ -0,0 +1,109 @@
+// watch.rs
+// Watch-directory mode (--watch dir/), for QC during a live sequencing run: the read files appearing in a
+// directory or its subdirectories (e.g. the fastq_pass/ batches MinKNOW writes) are counted into the read Index
+// as they are completed, and the outputs are rewritten every --watch-interval minutes if new reads were counted.
+// A file is taken as complete once its size is the same at two successive scans; hidden files are ignored.
+// A file that fails to parse is skipped with a warning, the reads before the error staying counted.
+// Watching stops on SIGINT or SIGUSR1, or after --watch-idle minutes without a new file, and the outputs are then
+// written a last time, with the summary.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use crate::aligned;
+use crate::closures;
+use crate::dedup::Duplicates;
+use crate::error::{Context, Error};
+use crate::gc::GcMap;
+use crate::index::Index;
+use crate::positional::RefPositions;
+use crate::signals;
+use crate::summary::{self, Summary};
+use super::Params;
+use log::{info, warn};
+
+// Time between two scans of the watched directory.
+const POLL: Duration = Duration::from_secs(5);
+
+// Whether a file is a read file, from its name: FASTA or FASTQ, possibly compressed, or BAM and CRAM files if
+// they can be read.
+fn is_read_file(path: &PathBuf) -> bool {
+    let name = path.to_string_lossy();
+    let name = name.trim_end_matches(".gz").trim_end_matches(".bgz").trim_end_matches(".lz4");
+    ["fq", "fastq", "fa", "fasta", "fna"].iter().any(|ext| name.ends_with(&format!(".{}", ext)))
+        || (aligned::is_aligned(path) && aligned::check_available().is_ok())
+}
+
+// Add the read files under dir, with their sizes, to files.
+fn scan(dir: &PathBuf, files: &mut Vec<(PathBuf, u64)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {continue;}
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {scan(&path, files)?;}
+        else if is_read_file(&path) {files.push((path, metadata.len()));}
+    }
+    Ok(())
+}
+
+// Count the read files of dir as they appear, rewriting the outputs every interval, until a signal is received or
+// no new file has appeared for idle.
+pub fn run_watch(dir: &PathBuf, interval: Duration, idle: Option<Duration>, ref_mers_index: &Index, ref_positions: Option<&RefPositions>, ref_summary: &Summary, params: &Params, threads: usize, queue_len: usize, output_prefix: &PathBuf) -> Result<(), Error> {
+    let read_mers_index = closures::new_read_index(params);
+    let read_gc = if params.gc_histo {Some(GcMap::new())} else {None};
+    let read_dups = if params.dedup_reads {Some(Duplicates::new())} else {None};
+    let lens: DashMap<String, usize> = DashMap::new();
+    let restrict = Some(ref_mers_index).filter(|_| params.restrict_to_reference);
+    let mut summary = ref_summary.clone();
+    let mut sizes: HashMap<PathBuf, u64> = HashMap::new(); // sizes at the last scan of the files not counted yet
+    let mut counted: HashSet<PathBuf> = HashSet::new();
+    let mut skipped: HashSet<PathBuf> = HashSet::new(); // files that failed to parse
+    let (mut nb_reads, mut counting_time) = (0, Duration::ZERO);
+    let (mut last_file, mut last_write, mut new_reads) = (Instant::now(), Instant::now(), false);
+    info!("Watching {} for read files.", dir.to_str().unwrap());
+    loop {
+        let mut files = Vec::new();
+        scan(dir, &mut files).input(dir)?;
+        files.sort();
+        let mut interrupted = None;
+        for (path, size) in files {
+            if counted.contains(&path) || skipped.contains(&path) || sizes.insert(path.clone(), size) != Some(size) {continue;}
+            sizes.remove(&path);
+            let start = Instant::now();
+            let (nb, file_interrupted) = match closures::index_reads(&path, threads, queue_len, params, &lens, &read_mers_index, restrict, read_gc.as_ref(), None, None, None, read_dups.as_ref(), None, None) {
+                Ok(res) => res,
+                Err(e) => {
+                    // a bad file doesn't end the session; its reads before the error stay counted
+                    warn!("Skipping the rest of {}: {}", path.to_str().unwrap(), e);
+                    skipped.insert(path);
+                    continue;
+                }
+            };
+            counting_time += start.elapsed();
+            nb_reads += nb;
+            counted.insert(path);
+            (last_file, new_reads, interrupted) = (Instant::now(), true, file_interrupted);
+            if interrupted.is_some() {break;}
+        }
+        if interrupted.is_some() || signals::pending().is_some() || idle.map_or(false, |idle| last_file.elapsed() >= idle) {break;}
+        if new_reads && last_write.elapsed() >= interval {
+            info!("{} reads of {} files counted, {} distinct k-min-mers.", nb_reads, counted.len(), read_mers_index.len());
+            closures::write_outputs(&read_mers_index, ref_mers_index, ref_positions, read_gc.as_ref(), params, output_prefix)?;
+            (last_write, new_reads) = (Instant::now(), false);
+        }
+        thread::sleep(POLL);
+    }
+    info!("Stopped watching {}: {} reads of {} files counted, {} distinct k-min-mers.", dir.to_str().unwrap(), nb_reads, counted.len(), read_mers_index.len());
+    summary.nb_reads = nb_reads;
+    summary.nb_duplicates = read_dups.map_or(0, |dups| dups.nb_duplicates());
+    summary.add_stage("count_reads", counting_time);
+    let start = Instant::now();
+    closures::write_outputs(&read_mers_index, ref_mers_index, ref_positions, read_gc.as_ref(), params, output_prefix)?;
+    summary.add_stage("write_outputs", start.elapsed());
+    summary::write_summary(&summary, &read_mers_index, ref_mers_index, params, output_prefix)
+}
@@ -0,0 +1,17 @@
+// common/mod.rs
+// Synthetic inputs for the end-to-end tests, so that they need no data files.
+
+// Random bases from a linear congruential generator.
+pub fn random_seq(len: usize, mut seed: u64) -> String {
+    (0..len).map(|_| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        b"ACGT"[(seed >> 62) as usize] as char
+    }).collect()
+}
+
+// Reads tiling seq every step bases, as FASTQ records.
+pub fn tiled_reads(seq: &str, name: &str, len: usize, step: usize) -> String {
+    (0..seq.len() - len).step_by(step)
+        .map(|start| format!("@{}_{}\n{}\n+\n{}\n", name, start, &seq[start..start + len], "I".repeat(len)))
+        .collect()
+}
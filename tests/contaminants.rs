@@ -0,0 +1,49 @@
+// contaminants.rs
+// End-to-end check of --contaminants: the panel is indexed on its own, so the BED regions of the primary
+// reference (--regions) must not empty it.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use common::{random_seq, tiled_reads};
+
+// The fields of the line of a contaminant in prefix.contaminants.tsv.
+fn contaminant_line(prefix: &Path, name: &str) -> Vec<String> {
+    let tsv = fs::read_to_string(format!("{}.contaminants.tsv", prefix.to_str().unwrap())).unwrap();
+    tsv.lines().map(|line| line.split('\t').map(String::from).collect::<Vec<_>>()).find(|fields| fields[0] == name).unwrap()
+}
+
+fn run(dir: &Path, prefix: &str, extra: &[&str]) -> PathBuf {
+    let prefix = dir.join(prefix);
+    let status = Command::new(env!("CARGO_BIN_EXE_rust-kminmer2Dhisto"))
+        .args(["--reference", dir.join("ref.fa").to_str().unwrap(), "--contaminants", dir.join("panel").to_str().unwrap()])
+        .args(["-k", "5", "-l", "15", "-d", "0.05", "--threads", "1", "-p", prefix.to_str().unwrap()])
+        .args(extra)
+        .arg(dir.join("reads.fq"))
+        .status().unwrap();
+    assert!(status.success());
+    prefix
+}
+
+#[test]
+fn contaminants_ignore_reference_regions() {
+    let dir = std::env::temp_dir().join(format!("kminmer2dhisto-contaminants-{}", std::process::id()));
+    fs::create_dir_all(dir.join("panel")).unwrap();
+    let (reference, phix) = (random_seq(60000, 1), random_seq(20000, 2));
+    fs::write(dir.join("ref.fa"), format!(">ref\n{}\n", reference)).unwrap();
+    fs::write(dir.join("panel").join("phix.fa"), format!(">phix\n{}\n", phix)).unwrap();
+    fs::write(dir.join("reads.fq"), tiled_reads(&reference, "ref", 2000, 200) + &tiled_reads(&phix, "phix", 2000, 500)).unwrap();
+    fs::write(dir.join("regions.bed"), "ref\t0\t10000\n").unwrap();
+
+    let all = contaminant_line(&run(&dir, "all", &[]), "phix");
+    let regions = contaminant_line(&run(&dir, "regions", &["--regions", dir.join("regions.bed").to_str().unwrap()]), "phix");
+    fs::remove_dir_all(&dir).unwrap();
+    assert_ne!(all[1], "0", "the contaminant has k-min-mers");
+    assert_ne!(all[2], "0", "reads of the contaminant are found");
+    // kminmers, shared_kminmers and contaminant_coverage; not_in_reference depends on the reference regions
+    for i in [1, 2, 6] {
+        assert_eq!(all[i], regions[i], "--regions doesn't apply to the contaminants");
+    }
+}
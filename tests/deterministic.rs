@@ -0,0 +1,42 @@
+// deterministic.rs
+// End-to-end check of --deterministic: every output, provenance included, is the same byte for byte across runs
+// with different thread counts.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use common::{random_seq, tiled_reads};
+
+fn run(dir: &Path, prefix: &str, threads: &str) {
+    let status = Command::new(env!("CARGO_BIN_EXE_rust-kminmer2Dhisto"))
+        .args(["--reference", dir.join("ref.fa").to_str().unwrap(), "-k", "5", "-l", "15", "-d", "0.05", "--deterministic"])
+        .args(["--threads", threads, "-p", dir.join(prefix).to_str().unwrap()])
+        .args(["--dump-kminmers", dir.join(format!("{}.dump.tsv", prefix)).to_str().unwrap()])
+        .arg(dir.join("reads.fq"))
+        .status().unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn deterministic_outputs_across_thread_counts() {
+    let dir = std::env::temp_dir().join(format!("kminmer2dhisto-deterministic-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let reference = random_seq(60000, 3);
+    fs::write(dir.join("ref.fa"), format!(">ref\n{}\n", reference)).unwrap();
+    fs::write(dir.join("reads.fq"), tiled_reads(&reference, "ref", 2000, 150)).unwrap();
+    run(&dir, "one", "1");
+    run(&dir, "four", "4");
+
+    let mut nb_outputs = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let name = entry.unwrap().file_name().to_string_lossy().to_string();
+        let Some(suffix) = name.strip_prefix("one.") else {continue};
+        let (one, four) = (fs::read(dir.join(&name)).unwrap(), fs::read(dir.join(format!("four.{}", suffix))).unwrap());
+        assert!(one == four, "{} differs between 1 and 4 threads", suffix);
+        nb_outputs += 1;
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(nb_outputs >= 4, "only {} outputs compared", nb_outputs);
+}